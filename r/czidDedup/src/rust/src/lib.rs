@@ -0,0 +1,57 @@
+use std::convert::TryFrom;
+
+use extendr_api::prelude::*;
+
+use czid_dedup::clusters::ClusterOptions;
+use czid_dedup::memory::{self, DedupConfig};
+
+fn optional(path: &str) -> Option<&str> {
+    Some(path).filter(|path| !path.is_empty())
+}
+
+/// Deduplicates reads for `dedup_fastq`, empty r2 paths and a prefix length
+/// of 0 meaning none
+/// @keywords internal
+#[extendr]
+fn dedup_fastq_impl(
+    input: &str,
+    output: &str,
+    input_r2: &str,
+    output_r2: &str,
+    prefix_length: i32,
+    revcomp: bool,
+) -> Result<List> {
+    let config = DedupConfig {
+        options: ClusterOptions {
+            prefix_length_opt: usize::try_from(prefix_length)
+                .ok()
+                .filter(|prefix_length| *prefix_length > 0),
+            ..ClusterOptions::default()
+        },
+        use_revcomp: revcomp,
+        skip_invalid: false,
+    };
+    let (summary, cluster_sizes) = memory::dedup_files(
+        (input, optional(input_r2)),
+        (output, optional(output_r2)),
+        &config,
+    )
+    .map_err(|err| Error::Other(err.to_string()))?;
+    // R has no 64 bit integers, so counts are doubles
+    let (read_ids, sizes): (Vec<String>, Vec<f64>) = cluster_sizes
+        .into_iter()
+        .map(|(id, size)| (id, size as f64))
+        .unzip();
+    let summary = list!(
+        total_records = summary.total_records as f64,
+        unique_records = summary.unique_records as f64,
+        duplicate_records = summary.duplicate_records as f64,
+        duplication_rate = summary.duplication_rate
+    );
+    Ok(list!(read_id = read_ids, size = sizes, summary = summary))
+}
+
+extendr_module! {
+    mod czidDedup;
+    fn dedup_fastq_impl;
+}
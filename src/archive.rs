@@ -0,0 +1,309 @@
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use zip::CompressionMethod;
+
+use super::fastx;
+
+/// Separates an archive path from the member to read in an input like `sample.tar.gz::R1.fastq`
+pub const MEMBER_SEPARATOR: &str = "::";
+
+pub fn is_tar(path: &str) -> bool {
+    path.ends_with(".tar") || path.ends_with(".tar.gz") || path.ends_with(".tgz")
+}
+
+pub fn is_zip(path: &str) -> bool {
+    path.ends_with(".zip")
+}
+
+pub fn is_archive(path: &str) -> bool {
+    is_tar(path) || is_zip(path)
+}
+
+fn is_fastx_name(name: &str) -> bool {
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    [".fastq", ".fq", ".fasta", ".fa", ".fna"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+/// Splits `archive::member` into its archive path and member name
+pub fn split_member(input: &str) -> Option<(&str, &str)> {
+    let index = input.find(MEMBER_SEPARATOR)?;
+    Some((&input[..index], &input[index + MEMBER_SEPARATOR.len()..]))
+}
+
+fn open_tar(path: &str) -> Result<tar::Archive<Box<dyn Read>>, Error> {
+    let file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read> = if path.ends_with(".tar") {
+        Box::new(file)
+    } else {
+        Box::new(flate2::read::MultiGzDecoder::new(file))
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// Lists the FASTA/FASTQ members of a tar archive in name order
+pub fn tar_fastx_members(path: &str) -> Result<Vec<String>, Error> {
+    let mut archive = open_tar(path)?;
+    let mut members = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            let name = entry.path()?.to_string_lossy().into_owned();
+            if is_fastx_name(&name) {
+                members.push(name);
+            }
+        }
+    }
+    members.sort();
+    Ok(members)
+}
+
+/// Streams a single member of a tar archive without extracting it
+pub fn open_tar_member(path: &str, member: &str) -> Result<Box<dyn Read>, Error> {
+    let mut archive = open_tar(path)?;
+    let mut size_opt = None;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.path()?.to_string_lossy() == member {
+            size_opt = Some(entry.size());
+            break;
+        }
+    }
+    match size_opt {
+        // the underlying reader is positioned at the start of the member's data
+        // since the entry's header has been read but none of its contents
+        Some(size) => Ok(fastx::decode_gz(member, archive.into_inner().take(size))),
+        None => Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} not found in {}", member, path),
+        )),
+    }
+}
+
+fn zip_error(path: &str, err: zip::result::ZipError) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{}: {}", path, err))
+}
+
+/// Lists the FASTA/FASTQ members of a zip archive in name order
+pub fn zip_fastx_members(path: &str) -> Result<Vec<String>, Error> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))
+        .map_err(|err| zip_error(path, err))?;
+    let mut members = Vec::new();
+    for index in 0..archive.len() {
+        let file = archive
+            .by_index(index)
+            .map_err(|err| zip_error(path, err))?;
+        if file.is_file() && is_fastx_name(file.name()) {
+            members.push(file.name().to_owned());
+        }
+    }
+    members.sort();
+    Ok(members)
+}
+
+/// Streams a single member of a zip archive without extracting it
+///
+/// The member's data is read straight from the file at its offset so the
+/// returned reader does not borrow from the archive's index.
+pub fn open_zip_member(path: &str, member: &str) -> Result<Box<dyn Read>, Error> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))
+        .map_err(|err| zip_error(path, err))?;
+    let (data_start, compressed_size, compression) = match archive.by_name(member) {
+        Ok(file) => (
+            file.data_start(),
+            file.compressed_size(),
+            file.compression(),
+        ),
+        Err(zip::result::ZipError::FileNotFound) => {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} not found in {}", member, path),
+            ))
+        }
+        Err(err) => return Err(zip_error(path, err)),
+    };
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(data_start))?;
+    let data = BufReader::new(file).take(compressed_size);
+    let reader: Box<dyn Read> = match compression {
+        CompressionMethod::Stored => Box::new(data),
+        CompressionMethod::Deflated => Box::new(flate2::read::DeflateDecoder::new(data)),
+        other => {
+            let message = format!(
+                "{} in {} uses unsupported compression {}",
+                member, path, other
+            );
+            return Err(Error::new(ErrorKind::InvalidData, message));
+        }
+    };
+    Ok(fastx::decode_gz(member, reader))
+}
+
+/// Lists the FASTA/FASTQ members of a tar or zip archive in name order
+pub fn fastx_members(path: &str) -> Result<Vec<String>, Error> {
+    if is_zip(path) {
+        zip_fastx_members(path)
+    } else {
+        tar_fastx_members(path)
+    }
+}
+
+/// Streams a single member of a tar or zip archive without extracting it
+pub fn open_member(path: &str, member: &str) -> Result<Box<dyn Read>, Error> {
+    if is_zip(path) {
+        open_zip_member(path, member)
+    } else {
+        open_tar_member(path, member)
+    }
+}
+
+/// Replaces each input that is an archive without an explicit member with
+/// `archive::member` inputs for every FASTA/FASTQ member it contains
+pub fn expand_inputs<'a, I: IntoIterator<Item = &'a str>>(inputs: I) -> Result<Vec<String>, Error> {
+    let mut expanded = Vec::new();
+    for input in inputs {
+        if split_member(input).is_none() && is_archive(input) {
+            let members = fastx_members(input)?;
+            if members.is_empty() {
+                let message = format!("no FASTA or FASTQ files found in {}", input);
+                return Err(Error::new(ErrorKind::InvalidData, message));
+            }
+            for member in members {
+                expanded.push(format!("{}{}{}", input, MEMBER_SEPARATOR, member));
+            }
+        } else {
+            expanded.push(input.to_owned());
+        }
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    fn write_tar(path: &std::path::Path, members: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(File::create(path).expect("don't break"));
+        for (name, contents) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, *contents)
+                .expect("don't break");
+        }
+        builder.finish().expect("don't break");
+    }
+
+    #[test]
+    fn test_open_tar_member() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sample.tar");
+        write_tar(
+            &path,
+            &[
+                ("R1.fastq", b"@id_a\nACGT\n+\nIIII\n"),
+                ("R2.fastq", b"@id_a\nTTTT\n+\nIIII\n"),
+            ],
+        );
+        let path = path.to_str().unwrap();
+
+        let mut contents = String::new();
+        open_tar_member(path, "R2.fastq")
+            .expect("don't break")
+            .read_to_string(&mut contents)
+            .expect("don't break");
+        assert_eq!(contents, "@id_a\nTTTT\n+\nIIII\n");
+
+        let error = open_tar_member(path, "R3.fastq")
+            .err()
+            .expect("should error on a missing member");
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        dir.close().expect("don't break");
+    }
+
+    fn write_zip(path: &std::path::Path, members: &[(&str, &[u8], CompressionMethod)]) {
+        let mut writer = zip::ZipWriter::new(File::create(path).expect("don't break"));
+        for (name, contents, compression) in members {
+            let options = zip::write::SimpleFileOptions::default().compression_method(*compression);
+            writer.start_file(*name, options).expect("don't break");
+            std::io::Write::write_all(&mut writer, contents).expect("don't break");
+        }
+        writer.finish().expect("don't break");
+    }
+
+    #[test]
+    fn test_open_zip_member() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sample.zip");
+        write_zip(
+            &path,
+            &[
+                (
+                    "R1.fastq",
+                    b"@id_a\nACGT\n+\nIIII\n",
+                    CompressionMethod::Stored,
+                ),
+                (
+                    "R2.fastq",
+                    b"@id_a\nTTTT\n+\nIIII\n",
+                    CompressionMethod::Deflated,
+                ),
+            ],
+        );
+        let path = path.to_str().unwrap();
+        assert_eq!(
+            zip_fastx_members(path).expect("don't break"),
+            vec!["R1.fastq", "R2.fastq"]
+        );
+
+        for (member, expected) in [
+            ("R1.fastq", "@id_a\nACGT\n+\nIIII\n"),
+            ("R2.fastq", "@id_a\nTTTT\n+\nIIII\n"),
+        ]
+        .iter()
+        {
+            let mut contents = String::new();
+            open_zip_member(path, member)
+                .expect("don't break")
+                .read_to_string(&mut contents)
+                .expect("don't break");
+            assert_eq!(&contents, expected);
+        }
+
+        let error = open_zip_member(path, "R3.fastq")
+            .err()
+            .expect("should error on a missing member");
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_expand_inputs() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sample.tar");
+        write_tar(
+            &path,
+            &[
+                ("notes.txt", b"hello"),
+                ("R2.fastq.gz", b""),
+                ("R1.fastq.gz", b""),
+            ],
+        );
+        let path = path.to_str().unwrap();
+        let expanded = expand_inputs(vec![path, "other.fastq"]).expect("don't break");
+        assert_eq!(
+            expanded,
+            vec![
+                format!("{}::R1.fastq.gz", path),
+                format!("{}::R2.fastq.gz", path),
+                "other.fastq".to_owned(),
+            ]
+        );
+        dir.close().expect("don't break");
+    }
+}
@@ -0,0 +1,111 @@
+/// Number of reads sampled from each input by default
+pub const DEFAULT_SAMPLE_SIZE: usize = 10_000;
+
+/// The chosen prefix is no longer than this fraction of sampled reads
+pub const SHORT_READ_FRACTION: f64 = 0.1;
+
+/// The chosen prefix holds at most this many expected sequencing errors
+pub const MAX_EXPECTED_ERRORS: f64 = 1.0;
+
+/// Chooses a prefix length from a sample of reads
+///
+/// The prefix is capped at the length that all but `SHORT_READ_FRACTION` of
+/// reads reach, so most reads are compared on the same number of bases, then
+/// shortened until the bases it covers hold at most `MAX_EXPECTED_ERRORS`
+/// expected errors. Errors are estimated from quality scores for FASTQ and
+/// from `N` calls for FASTA.
+#[derive(Debug, Default)]
+pub struct PrefixSampler {
+    lengths: Vec<usize>,
+    /// per position, the summed error probability of every base sampled there
+    error_sums: Vec<f64>,
+    /// per position, the number of bases sampled there
+    base_counts: Vec<u64>,
+}
+
+/// The probability a base is wrong given its Phred+33 quality
+fn error_probability(quality: u8) -> f64 {
+    10f64.powf(-(quality.saturating_sub(33) as f64) / 10.0)
+}
+
+impl PrefixSampler {
+    pub fn add(&mut self, seq: &[u8], qual_opt: Option<&[u8]>) {
+        self.lengths.push(seq.len());
+        if self.error_sums.len() < seq.len() {
+            self.error_sums.resize(seq.len(), 0.0);
+            self.base_counts.resize(seq.len(), 0);
+        }
+        for (i, base) in seq.iter().enumerate() {
+            let error = match qual_opt {
+                Some(qual) => error_probability(qual[i]),
+                None if matches!(base, b'N' | b'n') => 1.0,
+                None => 0.0,
+            };
+            self.error_sums[i] += error;
+            self.base_counts[i] += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lengths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lengths.is_empty()
+    }
+
+    /// The chosen prefix length, `None` if no reads were sampled
+    pub fn prefix_length(&self) -> Option<usize> {
+        let mut lengths = self.lengths.clone();
+        lengths.sort_unstable();
+        let max_length = *lengths.get((lengths.len() as f64 * SHORT_READ_FRACTION) as usize)?;
+        let mut expected_errors = 0.0;
+        let mut prefix_length = 0;
+        while prefix_length < max_length {
+            expected_errors +=
+                self.error_sums[prefix_length] / self.base_counts[prefix_length] as f64;
+            if expected_errors > MAX_EXPECTED_ERRORS {
+                break;
+            }
+            prefix_length += 1;
+        }
+        // always compare at least one base
+        Some(prefix_length.max(1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prefix_length_short_reads() {
+        let mut sampler = PrefixSampler::default();
+        for _ in 0..9 {
+            sampler.add(&[b'A'; 150], None);
+        }
+        sampler.add(&[b'A'; 35], None);
+        assert_eq!(sampler.prefix_length(), Some(150));
+        for _ in 0..5 {
+            sampler.add(&[b'A'; 35], None);
+        }
+        assert_eq!(sampler.prefix_length(), Some(35));
+    }
+
+    #[test]
+    fn test_prefix_length_errors() {
+        let mut sampler = PrefixSampler::default();
+        // Q40 bases have a 1 in 10,000 error rate, Q10 bases 1 in 10
+        let mut qual = vec![b'I'; 100];
+        for quality in qual[50..].iter_mut() {
+            *quality = b'+';
+        }
+        sampler.add(&[b'A'; 100], Some(&qual));
+        assert_eq!(sampler.prefix_length(), Some(59));
+    }
+
+    #[test]
+    fn test_prefix_length_empty() {
+        assert_eq!(PrefixSampler::default().prefix_length(), None);
+    }
+}
@@ -0,0 +1,374 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+
+use super::bgzf::{self, BgzfReader, BgzfWriter};
+
+const MAGIC: &[u8; 4] = b"BAM\x01";
+
+/// Read flags, from the SAM specification
+pub const FLAG_PAIRED: u16 = 0x1;
+pub const FLAG_UNMAPPED: u16 = 0x4;
+pub const FLAG_MATE_UNMAPPED: u16 = 0x8;
+pub const FLAG_REVERSE: u16 = 0x10;
+pub const FLAG_MATE_REVERSE: u16 = 0x20;
+pub const FLAG_SECONDARY: u16 = 0x100;
+pub const FLAG_SUPPLEMENTARY: u16 = 0x800;
+
+/// CIGAR operations in the order BAM numbers them
+const CIGAR_OPS: &[u8; 9] = b"MIDNSHP=X";
+
+/// Whether a file is BAM, a BGZF file starting with the BAM magic
+pub fn is_bam<P: AsRef<std::path::Path>>(path: P) -> Result<bool, Error> {
+    let mut header = Vec::new();
+    File::open(&path)?.take(18).read_to_end(&mut header)?;
+    if !bgzf::is_bgzf(&header) {
+        return Ok(false);
+    }
+    let mut magic = Vec::new();
+    BgzfReader::new(BufReader::new(File::open(path)?))
+        .take(4)
+        .read_to_end(&mut magic)?;
+    Ok(magic == MAGIC)
+}
+
+/// One alignment record, its bytes after the block size
+pub struct BamRecord {
+    data: Vec<u8>,
+}
+
+impl BamRecord {
+    /// A record with no sequence, qualities, or tags other than `tags`,
+    /// given as the bytes of their BAM encoding
+    pub fn new(
+        name: &str,
+        flag: u16,
+        (ref_id, pos): (i32, i32),
+        cigar: &[(u8, u32)],
+        (next_ref_id, next_pos): (i32, i32),
+        tags: &[u8],
+    ) -> Self {
+        let mut data = Vec::new();
+        data.extend_from_slice(&ref_id.to_le_bytes());
+        data.extend_from_slice(&pos.to_le_bytes());
+        data.push(name.len() as u8 + 1);
+        // mapping quality and bin
+        data.extend_from_slice(&[255, 0, 0]);
+        data.extend_from_slice(&(cigar.len() as u16).to_le_bytes());
+        data.extend_from_slice(&flag.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&next_ref_id.to_le_bytes());
+        data.extend_from_slice(&next_pos.to_le_bytes());
+        data.extend_from_slice(&0i32.to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        for (op, len) in cigar {
+            let code = CIGAR_OPS.iter().position(|known| known == op).unwrap_or(0) as u32;
+            data.extend_from_slice(&(len << 4 | code).to_le_bytes());
+        }
+        data.extend_from_slice(tags);
+        BamRecord { data }
+    }
+
+    /// Gives a record made with `new` Phred qualities, and as many bases,
+    /// all `=`
+    pub fn with_quals(mut self, quals: &[u8]) -> Self {
+        let start = self.tags_start();
+        let mut seq_and_quals = vec![0; quals.len().div_ceil(2)];
+        seq_and_quals.extend_from_slice(quals);
+        self.data.splice(start..start, seq_and_quals);
+        self.data[16..20].copy_from_slice(&(quals.len() as u32).to_le_bytes());
+        self
+    }
+
+    fn i32_at(&self, offset: usize) -> i32 {
+        i32::from_le_bytes([
+            self.data[offset],
+            self.data[offset + 1],
+            self.data[offset + 2],
+            self.data[offset + 3],
+        ])
+    }
+
+    fn u16_at(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    pub fn ref_id(&self) -> i32 {
+        self.i32_at(0)
+    }
+
+    /// 0-based leftmost mapped position
+    pub fn pos(&self) -> i32 {
+        self.i32_at(4)
+    }
+
+    pub fn flag(&self) -> u16 {
+        self.u16_at(14)
+    }
+
+    pub fn next_ref_id(&self) -> i32 {
+        self.i32_at(20)
+    }
+
+    pub fn next_pos(&self) -> i32 {
+        self.i32_at(24)
+    }
+
+    fn name_len(&self) -> usize {
+        self.data[8] as usize
+    }
+
+    fn cigar_len(&self) -> usize {
+        self.u16_at(12) as usize
+    }
+
+    fn seq_len(&self) -> usize {
+        self.i32_at(16).max(0) as usize
+    }
+
+    /// The read name, without its NUL terminator
+    pub fn name(&self) -> &[u8] {
+        &self.data[32..32 + self.name_len() - 1]
+    }
+
+    /// (operation, length) pairs, operations as SAM writes them
+    pub fn cigar(&self) -> Vec<(u8, u32)> {
+        let start = 32 + self.name_len();
+        (0..self.cigar_len())
+            .map(|i| {
+                let offset = start + 4 * i;
+                let value = u32::from_le_bytes([
+                    self.data[offset],
+                    self.data[offset + 1],
+                    self.data[offset + 2],
+                    self.data[offset + 3],
+                ]);
+                let op = CIGAR_OPS
+                    .get((value & 0xf) as usize)
+                    .copied()
+                    .unwrap_or(b'?');
+                (op, value >> 4)
+            })
+            .collect()
+    }
+
+    /// Phred qualities, empty if the record has none
+    pub fn quals(&self) -> &[u8] {
+        let seq_len = self.seq_len();
+        let start = self.tags_start() - seq_len;
+        let quals = &self.data[start..start + seq_len];
+        // missing qualities are stored as 0xff
+        if quals.first() == Some(&0xff) {
+            &[]
+        } else {
+            quals
+        }
+    }
+
+    fn tags_start(&self) -> usize {
+        let seq_len = self.seq_len();
+        32 + self.name_len() + 4 * self.cigar_len() + seq_len.div_ceil(2) + seq_len
+    }
+
+    /// The value of a string (`Z`) tag, `None` if the record doesn't have it
+    pub fn string_tag(&self, tag: &[u8; 2]) -> Option<&str> {
+        let mut offset = self.tags_start();
+        while offset + 3 <= self.data.len() {
+            let name = &self.data[offset..offset + 2];
+            let value_type = self.data[offset + 2];
+            offset += 3;
+            let value_len = match value_type {
+                b'A' | b'c' | b'C' => 1,
+                b's' | b'S' => 2,
+                b'i' | b'I' | b'f' => 4,
+                b'Z' | b'H' => {
+                    let end = self.data[offset..].iter().position(|byte| *byte == 0)?;
+                    if name == tag && value_type == b'Z' {
+                        return std::str::from_utf8(&self.data[offset..offset + end]).ok();
+                    }
+                    end + 1
+                }
+                b'B' => {
+                    let element_len = match *self.data.get(offset)? {
+                        b'c' | b'C' => 1,
+                        b's' | b'S' => 2,
+                        _ => 4,
+                    };
+                    let count =
+                        i32::from_le_bytes(self.data.get(offset + 1..offset + 5)?.try_into().ok()?);
+                    5 + element_len * count as usize
+                }
+                _ => return None,
+            };
+            offset += value_len;
+        }
+        None
+    }
+}
+
+/// Parses a SAM CIGAR string, like the `MC` tag's
+pub fn parse_cigar(cigar: &str) -> Option<Vec<(u8, u32)>> {
+    let mut ops = Vec::new();
+    let mut len: u32 = 0;
+    for byte in cigar.bytes() {
+        if byte.is_ascii_digit() {
+            len = len.checked_mul(10)?.checked_add((byte - b'0') as u32)?;
+        } else if CIGAR_OPS.contains(&byte) {
+            ops.push((byte, len));
+            len = 0;
+        } else {
+            return None;
+        }
+    }
+    Some(ops)
+}
+
+/// Encodes a BAM header from its SAM text and (name, length) references
+pub fn encode_header(text: &str, references: &[(&str, u32)]) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    header.extend_from_slice(text.as_bytes());
+    header.extend_from_slice(&(references.len() as u32).to_le_bytes());
+    for (name, len) in references {
+        header.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&len.to_le_bytes());
+    }
+    header
+}
+
+/// Reads a BAM file's header and then its records
+pub struct BamReader<R: Read> {
+    inner: BgzfReader<R>,
+    header: Vec<u8>,
+}
+
+impl<R: Read> BamReader<R> {
+    pub fn new(inner: R) -> Result<Self, Error> {
+        let mut inner = BgzfReader::new(inner);
+        let mut header = vec![0; 8];
+        inner.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a BAM file"));
+        }
+        let text_len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        (&mut inner)
+            .take(text_len as u64 + 4)
+            .read_to_end(&mut header)?;
+        let references_start = header.len() - 4;
+        let references = u32::from_le_bytes([
+            header[references_start],
+            header[references_start + 1],
+            header[references_start + 2],
+            header[references_start + 3],
+        ]);
+        for _ in 0..references {
+            let mut name_len = [0; 4];
+            inner.read_exact(&mut name_len)?;
+            header.extend_from_slice(&name_len);
+            // the name and the reference length
+            (&mut inner)
+                .take(u32::from_le_bytes(name_len) as u64 + 4)
+                .read_to_end(&mut header)?;
+        }
+        Ok(BamReader { inner, header })
+    }
+
+    /// The header's bytes, as they're written back to a BAM file
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// The next record, `None` at the end of the file
+    pub fn read_record(&mut self) -> Result<Option<BamRecord>, Error> {
+        let mut block_size = [0; 4];
+        match self.inner.read_exact(&mut block_size) {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let mut data = vec![0; u32::from_le_bytes(block_size) as usize];
+        self.inner.read_exact(&mut data)?;
+        let record = BamRecord { data };
+        // the fields before the tags, which the accessors index into
+        if record.data.len() < 32
+            || record.data.len() < record.tags_start()
+            || record.name_len() == 0
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "truncated BAM record"));
+        }
+        Ok(Some(record))
+    }
+}
+
+/// Writes a BAM header and records
+pub struct BamWriter<W: Write> {
+    inner: BgzfWriter<W>,
+}
+
+impl<W: Write> BamWriter<W> {
+    pub fn new(inner: W, header: &[u8], threads: usize) -> Result<Self, Error> {
+        let mut inner = BgzfWriter::new(inner, threads);
+        inner.write_all(header)?;
+        Ok(BamWriter { inner })
+    }
+
+    pub fn write_record(&mut self, record: &BamRecord) -> Result<(), Error> {
+        self.inner
+            .write_all(&(record.data.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&record.data)
+    }
+
+    pub fn finish(self) -> Result<W, Error> {
+        self.inner.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bam_round_trip() {
+        let header = encode_header("@HD\tVN:1.6\n", &[("chr1", 1000), ("chr2", 500)]);
+        let mut tags = b"NMC\x01".to_vec();
+        tags.extend_from_slice(b"MCZ10S40M\0");
+        let records = [
+            BamRecord::new(
+                "read_a",
+                FLAG_PAIRED,
+                (0, 10),
+                &[(b'M', 50)],
+                (1, 20),
+                &tags,
+            )
+            .with_quals(&[30; 5]),
+            BamRecord::new("read_b", FLAG_UNMAPPED, (-1, -1), &[], (-1, -1), &[]),
+        ];
+        let mut writer = BamWriter::new(Vec::new(), &header, 1).expect("don't break");
+        for record in records.iter() {
+            writer.write_record(record).expect("don't break");
+        }
+        let data = writer.finish().expect("don't break");
+
+        let mut reader = BamReader::new(&data[..]).expect("don't break");
+        assert_eq!(reader.header(), &header[..]);
+        let record = reader.read_record().expect("don't break").unwrap();
+        assert_eq!(record.name(), b"read_a");
+        assert_eq!(
+            (record.ref_id(), record.pos(), record.flag()),
+            (0, 10, FLAG_PAIRED)
+        );
+        assert_eq!((record.next_ref_id(), record.next_pos()), (1, 20));
+        assert_eq!(record.cigar(), vec![(b'M', 50)]);
+        assert_eq!(record.string_tag(b"MC"), Some("10S40M"));
+        assert_eq!(record.string_tag(b"RG"), None);
+        assert_eq!(record.quals(), &[30; 5]);
+        let record = reader.read_record().expect("don't break").unwrap();
+        assert_eq!(record.name(), b"read_b");
+        assert_eq!(record.quals(), b"");
+        assert!(reader.read_record().expect("don't break").is_none());
+        assert!(BamReader::new(&bgzf::EOF_BLOCK[..]).is_err());
+    }
+}
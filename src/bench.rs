@@ -0,0 +1,120 @@
+use bio::io::fasta;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+use super::clusters::Clusters;
+use super::paired::PairedRecord;
+use super::simulate::synthetic_reads;
+
+/// A throughput measurement of one clustering mode
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchResult {
+    pub mode: &'static str,
+    /// reads, or pairs in paired mode, clustered
+    pub reads: u64,
+    pub unique_reads: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    pub fn reads_per_second(&self) -> f64 {
+        self.reads as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+fn records<'a, I: Iterator<Item = &'a [u8]>>(seqs: I) -> Vec<fasta::Record> {
+    seqs.enumerate()
+        .map(|(i, seq)| fasta::Record::with_attrs(&i.to_string(), None, seq))
+        .collect()
+}
+
+/// Clusters `reads` synthetic reads single end, single end with reverse
+/// complements, and as pairs, timing each
+///
+/// Reads are generated up front so only clustering is timed. Pairs are cut
+/// from fragments of twice the read length, so both mates of a duplicate
+/// fragment are duplicates.
+pub fn run(reads: usize, length: usize, duplication: f64, seed: u64) -> Vec<BenchResult> {
+    let fragments = synthetic_reads(reads, length * 2, duplication, seed);
+    let records_r1 = records(fragments.iter().map(|fragment| &fragment[..length]));
+    let records_r2 = records(fragments.iter().map(|fragment| &fragment[length..]));
+    drop(fragments);
+
+    let mut results = Vec::new();
+    for (mode, use_revcomp) in [("hashing", false), ("revcomp", true)].iter() {
+        let mut clusters = new_clusters(reads);
+        let start = Instant::now();
+        for record in records_r1.iter() {
+            clusters
+                .insert_single(record, *use_revcomp)
+                .expect("clusters without outputs don't fail");
+        }
+        results.push(result(mode, &clusters, start));
+    }
+
+    let pairs = records_r1
+        .into_iter()
+        .zip(records_r2)
+        .map(PairedRecord::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .expect("mates share ids");
+    let mut clusters = new_clusters(reads);
+    let start = Instant::now();
+    for pair in pairs.iter() {
+        clusters
+            .insert_pair(pair, false)
+            .expect("clusters without outputs don't fail");
+    }
+    results.push(result("paired", &clusters, start));
+    results
+}
+
+fn new_clusters(capacity: usize) -> Clusters<std::io::Sink> {
+    Clusters::from_writer(None, None, capacity).expect("clusters without outputs don't fail")
+}
+
+fn result(mode: &'static str, clusters: &Clusters<std::io::Sink>, start: Instant) -> BenchResult {
+    BenchResult {
+        mode,
+        reads: clusters.total_records(),
+        unique_reads: clusters.unique_records(),
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Parses a count with an optional `K`, `M`, or `G` suffix, like `10M`
+pub fn parse_count(s: &str) -> Result<usize, String> {
+    let (digits, multiplier) = match s.chars().last() {
+        Some('k') | Some('K') => (&s[..s.len() - 1], 1_000),
+        Some('m') | Some('M') => (&s[..s.len() - 1], 1_000_000),
+        Some('g') | Some('G') => (&s[..s.len() - 1], 1_000_000_000),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<usize>()
+        .map(|n| n * multiplier)
+        .map_err(|_| format!("invalid count: {}", s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let results = run(500, 30, 0.5, 1);
+        let modes = results.iter().map(|result| result.mode).collect::<Vec<_>>();
+        assert_eq!(modes, vec!["hashing", "revcomp", "paired"]);
+        assert!(results.iter().all(|result| result.reads == 500));
+        assert_eq!(results[0].unique_reads, results[2].unique_reads);
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("10M"), Ok(10_000_000));
+        assert_eq!(parse_count("5k"), Ok(5_000));
+        assert_eq!(parse_count("123"), Ok(123));
+        assert!(parse_count("M").is_err());
+        assert!(parse_count("ten").is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::io::{BufRead, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+/// Uncompressed bytes per block, small enough that any block compresses to
+/// less than BGZF's 64 KiB limit
+pub const BLOCK_SIZE: usize = 0xff00;
+
+/// The empty block that marks the end of a BGZF file
+pub const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+const HEADER_SIZE: usize = 18;
+const FOOTER_SIZE: usize = 8;
+const MAX_BLOCK_SIZE: usize = 0x10000;
+
+/// Whether data starts with a BGZF block header, rather than any other gzip member
+pub fn is_bgzf(data: &[u8]) -> bool {
+    data.len() >= HEADER_SIZE && data[..4] == [0x1f, 0x8b, 0x08, 0x04] && &data[12..14] == b"BC"
+}
+
+/// Compresses one block as a gzip member with the BGZF `BC` extra field
+pub fn compress_block(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut cdata = deflate(data, Compression::default())?;
+    if HEADER_SIZE + cdata.len() + FOOTER_SIZE > MAX_BLOCK_SIZE {
+        // incompressible data is stored, which always fits
+        cdata = deflate(data, Compression::none())?;
+    }
+    let block_size = HEADER_SIZE + cdata.len() + FOOTER_SIZE;
+    let mut block = Vec::with_capacity(block_size);
+    block.extend_from_slice(&[
+        0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02,
+        0x00,
+    ]);
+    block.extend_from_slice(&((block_size - 1) as u16).to_le_bytes());
+    block.extend_from_slice(&cdata);
+    let mut crc = Crc::new();
+    crc.update(data);
+    block.extend_from_slice(&crc.sum().to_le_bytes());
+    block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    Ok(block)
+}
+
+fn deflate(data: &[u8], level: Compression) -> Result<Vec<u8>, Error> {
+    let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len()), level);
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Writes BGZF, gzip made of independently compressed blocks that tools like
+/// tabix can seek into
+///
+/// Blocks are compressed `threads` at a time on scoped threads and written in
+/// order. The end of file marker is written by `finish`, or on drop.
+pub struct BgzfWriter<W: Write> {
+    inner: Option<W>,
+    buffer: Vec<u8>,
+    pending: Vec<Vec<u8>>,
+    threads: usize,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W, threads: usize) -> Self {
+        BgzfWriter {
+            inner: Some(inner),
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            pending: Vec::new(),
+            threads: threads.max(1),
+        }
+    }
+
+    fn write_pending(&mut self) -> Result<(), Error> {
+        let blocks = if self.threads == 1 || self.pending.len() == 1 {
+            self.pending
+                .iter()
+                .map(|data| compress_block(data))
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            std::thread::scope(|scope| {
+                let handles = self
+                    .pending
+                    .iter()
+                    .map(|data| scope.spawn(move || compress_block(data)))
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("compression thread panicked"))
+                    .collect::<Result<Vec<_>, _>>()
+            })?
+        };
+        self.pending.clear();
+        // only taken by finish
+        let inner = self.inner.as_mut().unwrap();
+        for block in blocks {
+            inner.write_all(&block)?;
+        }
+        Ok(())
+    }
+
+    fn end_block(&mut self) -> Result<(), Error> {
+        if !self.buffer.is_empty() {
+            let data = std::mem::replace(&mut self.buffer, Vec::with_capacity(BLOCK_SIZE));
+            self.pending.push(data);
+        }
+        if self.pending.len() >= self.threads {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Writes any buffered data and the end of file marker, returning the inner writer
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.try_finish()?;
+        // set until finished
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn try_finish(&mut self) -> Result<(), Error> {
+        if self.inner.is_none() {
+            return Ok(());
+        }
+        self.end_block()?;
+        self.write_pending()?;
+        // set until finished
+        let inner = self.inner.as_mut().unwrap();
+        inner.write_all(&EOF_BLOCK)?;
+        inner.flush()
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let len = std::cmp::min(buf.len(), BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == BLOCK_SIZE {
+            self.end_block()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.end_block()?;
+        self.write_pending()?;
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.try_finish();
+        self.inner.take();
+    }
+}
+
+/// Reads BGZF one block at a time, tracking the virtual offset of the next
+/// byte read
+///
+/// A virtual offset is the compressed offset of a block's start shifted left
+/// 16 bits, or'd with the uncompressed offset within that block.
+pub struct BgzfReader<R: Read> {
+    inner: R,
+    block_start: u64,
+    next_block_start: u64,
+    data: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> BgzfReader<R> {
+    pub fn new(inner: R) -> Self {
+        BgzfReader {
+            inner,
+            block_start: 0,
+            next_block_start: 0,
+            data: Vec::new(),
+            pos: 0,
+            eof: false,
+        }
+    }
+
+    fn read_block(&mut self) -> Result<(), Error> {
+        let mut header = [0; HEADER_SIZE];
+        match self.inner.read_exact(&mut header) {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                self.eof = true;
+                self.data.clear();
+                self.pos = 0;
+                return Ok(());
+            }
+            result => result?,
+        }
+        if !is_bgzf(&header) {
+            return Err(Error::new(ErrorKind::InvalidData, "not a BGZF block"));
+        }
+        let block_size = u16::from_le_bytes([header[16], header[17]]) as usize + 1;
+        if block_size < HEADER_SIZE + FOOTER_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "BGZF block too small"));
+        }
+        let mut rest = vec![0; block_size - HEADER_SIZE];
+        self.inner.read_exact(&mut rest)?;
+        let (cdata, footer) = rest.split_at(rest.len() - FOOTER_SIZE);
+        self.data.clear();
+        DeflateDecoder::new(cdata).read_to_end(&mut self.data)?;
+        let mut crc = Crc::new();
+        crc.update(&self.data);
+        if footer[..4] != crc.sum().to_le_bytes() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "BGZF block CRC mismatch",
+            ));
+        }
+        self.block_start = self.next_block_start;
+        self.next_block_start += block_size as u64;
+        self.pos = 0;
+        Ok(())
+    }
+
+    /// Skips past exhausted and empty blocks so the position is in the block
+    /// holding the next byte
+    fn fill_block(&mut self) -> Result<(), Error> {
+        while self.pos == self.data.len() && !self.eof {
+            self.read_block()?;
+        }
+        Ok(())
+    }
+
+    /// The virtual offset of the next byte read
+    pub fn virtual_offset(&mut self) -> Result<u64, Error> {
+        self.fill_block()?;
+        if self.eof {
+            return Ok(self.next_block_start << 16);
+        }
+        Ok((self.block_start << 16) | self.pos as u64)
+    }
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Moves to a virtual offset, as returned by `virtual_offset`
+    pub fn seek_virtual(&mut self, offset: u64) -> Result<(), Error> {
+        let block_start = offset >> 16;
+        self.inner.seek(SeekFrom::Start(block_start))?;
+        self.next_block_start = block_start;
+        self.eof = false;
+        self.read_block()?;
+        self.pos = std::cmp::min((offset & 0xffff) as usize, self.data.len());
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let available = self.fill_buf()?;
+        let len = std::cmp::min(buf.len(), available.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: Read> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> Result<&[u8], Error> {
+        self.fill_block()?;
+        Ok(&self.data[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = std::cmp::min(self.pos + amt, self.data.len());
+    }
+}
+
+/// The id and virtual offset of every read in a BGZF FASTA or FASTQ file, in
+/// file order
+///
+/// FASTQ records are expected on four lines each, as czid-dedup writes them.
+pub fn index_reads<R: Read>(reader: &mut BgzfReader<R>) -> Result<Vec<(String, u64)>, Error> {
+    let mut index = Vec::new();
+    let mut line = Vec::new();
+    let mut fastq_opt = None;
+    let mut line_number = 0;
+    loop {
+        let offset = reader.virtual_offset()?;
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(index);
+        }
+        let fastq = *fastq_opt.get_or_insert(line[0] == b'@');
+        let is_header = if fastq {
+            line_number % 4 == 0
+        } else {
+            line[0] == b'>'
+        };
+        line_number += 1;
+        if is_header {
+            let id = line[1..]
+                .split(|byte| byte.is_ascii_whitespace())
+                .next()
+                .unwrap_or(&[]);
+            index.push((String::from_utf8_lossy(id).into_owned(), offset));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    fn round_trip(data: &[u8], threads: usize) -> Vec<u8> {
+        let mut writer = BgzfWriter::new(Vec::new(), threads);
+        writer.write_all(data).expect("don't break");
+        let compressed = writer.finish().expect("don't break");
+        assert!(compressed.ends_with(&EOF_BLOCK));
+        assert_eq!(&compressed[12..14], b"BC");
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .expect("don't break");
+        decompressed
+    }
+
+    #[test]
+    fn test_bgzf_round_trip() {
+        let data = (0..(BLOCK_SIZE * 5 + 17))
+            .map(|i| b"ACGT"[(i * i + i / 7) % 4])
+            .collect::<Vec<_>>();
+        assert_eq!(round_trip(&data, 1), data);
+        assert_eq!(round_trip(&data, 4), data);
+    }
+
+    #[test]
+    fn test_bgzf_seek_virtual() {
+        let data = (0..(BLOCK_SIZE * 2 + 17))
+            .map(|i| b"ACGT"[(i * i + i / 7) % 4])
+            .collect::<Vec<_>>();
+        let mut writer = BgzfWriter::new(Vec::new(), 1);
+        writer.write_all(&data).expect("don't break");
+        let compressed = writer.finish().expect("don't break");
+        assert!(is_bgzf(&compressed));
+        let mut reader = BgzfReader::new(std::io::Cursor::new(compressed));
+        let mut skip = vec![0; BLOCK_SIZE + 5];
+        reader.read_exact(&mut skip).expect("don't break");
+        let offset = reader.virtual_offset().expect("don't break");
+        reader.read_to_end(&mut Vec::new()).expect("don't break");
+        reader.seek_virtual(offset).expect("don't break");
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).expect("don't break");
+        assert_eq!(rest, &data[BLOCK_SIZE + 5..]);
+    }
+
+    #[test]
+    fn test_bgzf_empty() {
+        let compressed = BgzfWriter::new(Vec::new(), 1)
+            .finish()
+            .expect("don't break");
+        assert_eq!(compressed, EOF_BLOCK.to_vec());
+    }
+
+    #[test]
+    fn test_bgzf_block_size() {
+        let block = compress_block(b"ACGTACGT").expect("don't break");
+        let bsize = u16::from_le_bytes([block[16], block[17]]) as usize;
+        assert_eq!(bsize + 1, block.len());
+    }
+
+    #[test]
+    fn test_bgzf_virtual_offsets() {
+        let mut fasta = Vec::new();
+        for i in 0..2000 {
+            fasta.extend_from_slice(format!(">id_{} comment\n", i).as_bytes());
+            fasta.extend(
+                (0..100)
+                    .map(|j| b"ACGT"[(i * j + j / 3) % 4])
+                    .chain(std::iter::once(b'\n')),
+            );
+        }
+        let mut writer = BgzfWriter::new(Vec::new(), 2);
+        writer.write_all(&fasta).expect("don't break");
+        let compressed = writer.finish().expect("don't break");
+
+        let index = index_reads(&mut BgzfReader::new(&compressed[..])).expect("don't break");
+        assert_eq!(index.len(), 2000);
+        for (i, (id, offset)) in [0, 1, 777, 1999].iter().map(|&i| (i, &index[i])) {
+            assert_eq!(id, &format!("id_{}", i));
+            let block_start = (offset >> 16) as usize;
+            let mut reader = BgzfReader::new(&compressed[block_start..]);
+            let mut skip = vec![0; (offset & 0xffff) as usize];
+            reader.read_exact(&mut skip).expect("don't break");
+            let mut line = String::new();
+            reader.read_line(&mut line).expect("don't break");
+            assert_eq!(line, format!(">id_{} comment\n", i));
+        }
+    }
+
+    #[test]
+    fn test_index_reads_fastq() {
+        let fastq = b"@a\nACGT\n+\n@@@@\n@b\nACGT\n+\nIIII\n";
+        let mut writer = BgzfWriter::new(Vec::new(), 1);
+        writer.write_all(fastq).expect("don't break");
+        let compressed = writer.finish().expect("don't break");
+        let index = index_reads(&mut BgzfReader::new(&compressed[..])).expect("don't break");
+        assert_eq!(index, vec![("a".to_owned(), 0), ("b".to_owned(), 15)]);
+    }
+
+    #[test]
+    fn test_bgzf_reader_rejects_plain_gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b">a\nACGT\n").expect("don't break");
+        let compressed = encoder.finish().expect("don't break");
+        assert!(index_reads(&mut BgzfReader::new(&compressed[..])).is_err());
+    }
+}
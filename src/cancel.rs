@@ -0,0 +1,82 @@
+//! Cancelling a run part way through its inputs
+//!
+//! A service embedding deduplication keeps a clone of the token it gives
+//! `Clusters::set_cancellation` and cancels it when its user gives up on
+//! the job. The record loops check `Clusters::should_stop` between records,
+//! so the run stops cleanly with the records read so far, and its outputs
+//! and state are still flushed.
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How often `watch_file` checks for its file
+pub const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A flag shared between a run and whoever may cancel it, clones share it
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Cancels the token once `path` exists, for --cancel-file
+///
+/// A file that's already there cancels the token before this returns.
+/// Otherwise the watching thread stops once the token is cancelled, or
+/// runs until the process exits.
+pub fn watch_file(token: CancellationToken, path: PathBuf) -> thread::JoinHandle<()> {
+    if path.exists() {
+        token.cancel();
+    }
+    thread::spawn(move || {
+        while !token.is_cancelled() {
+            if path.exists() {
+                token.cancel();
+                break;
+            }
+            thread::sleep(WATCH_INTERVAL);
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        clone.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn test_watch_file() {
+        let dir = tempdir().expect("don't break");
+        let path = dir.path().join("cancel");
+        let token = CancellationToken::new();
+        let watcher = watch_file(token.clone(), path.clone());
+        assert!(!token.is_cancelled());
+        std::fs::write(&path, "").expect("don't break");
+        watcher.join().expect("don't break");
+        assert!(token.is_cancelled());
+        dir.close().expect("don't break");
+    }
+}
@@ -1,294 +1,4365 @@
+use bio::alphabets::dna::revcomp;
 use core::hash::Hash;
 use core::hash::Hasher;
 use std::cmp;
-use std::collections::hash_map::DefaultHasher;
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io;
-use bio::alphabets::dna::revcomp;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::time::Instant;
 
+use super::cancel;
 use super::fastx;
-use super::paired::PairedRecord;
+use super::filter::RecordFilter;
+use super::hll::HyperLogLog;
+use super::iupac;
+use super::keyhash::StableHasher;
+use super::kmerset;
+use super::lsh;
+use super::overlap;
+use super::paired::{Mate, MateSet, PairedRecord};
+use super::summary::{
+    self, ClusterOutputMeta, DedupParameters, DedupSummary, LengthBin, RunStatus, UniqueEstimate,
+};
+use super::warnings::{self, Warning, WarningCallback, WarningKind, Warnings};
 
 pub struct Cluster {
     id: String,
     size: u64,
+    /// members that joined reverse complemented, `true` in the cluster
+    /// output's `revcomp` column
+    revcomp_members: u64,
+    hash: u128,
+    full_hash: u64,
+    /// whether the representative's sequence is stored reverse complemented,
+    /// members stored the other way round are its reverse complements
+    is_revcomp: bool,
+    /// the representative's score under the representative strategy, a
+    /// member with a higher score replaces it
+    score: f64,
+    /// index of the most recent record in this cluster, tracked in window mode
+    last_seen: u64,
+    /// the representative's compared bases, kept when a compare region is set
+    compare_bytes_opt: Option<Vec<u8>>,
+    /// the representative's second, independent key hash, kept with
+    /// `double_hash`
+    check_hash_opt: Option<u64>,
+    /// index of the lane the representative came from
+    lane: usize,
+    /// Phred scores aggregated over the cluster's reads for each canonical
+    /// sequence, kept when a quality aggregation is set
+    quals_opt: Option<Vec<Vec<u8>>>,
+    /// whether a read whose full sequence differs from the representative's
+    /// joined only by sharing its prefix
+    has_prefix_duplicate: bool,
+    /// read ids of every record in the cluster, kept with `keep_members`
+    members_opt: Option<Vec<String>>,
+    /// the representative's r1 and r2 record offsets, 0 for r2 of single
+    /// reads, kept when offsets are tracked
+    offsets_opt: Option<[u64; 2]>,
+}
+
+impl Cluster {
+    /// The read id of this cluster's representative
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// How many of the cluster's members joined it reverse complemented
+    pub fn revcomp_members(&self) -> u64 {
+        self.revcomp_members
+    }
+
+    /// The hash the cluster is stored under, written to the cluster output's
+    /// `cluster hash` column
+    pub fn hash(&self) -> u128 {
+        self.hash
+    }
+
+    /// The read ids of every record in the cluster, representative first,
+    /// if members are kept
+    pub fn members(&self) -> Option<&[String]> {
+        self.members_opt.as_deref()
+    }
+}
+
+struct RecordKey {
+    seq_hash: u128,
+    full_hash: u64,
+    is_revcomp: bool,
+    compare_bytes_opt: Option<Vec<u8>>,
+    check_hash_opt: Option<u64>,
+    /// Phred scores of the canonical sequences, set when qualities are aggregated
+    quals_opt: Option<Vec<Vec<u8>>>,
+}
+
+/// Hashed after the key salt by the second hasher of `double_hash`, so
+/// its hash is independent of the first
+const CHECK_HASH_DOMAIN: &[u8] = b"czid-dedup check hash";
+
+/// Feeds everything a record's key hashes to a second hasher too, with
+/// `double_hash`
+struct KeyHasher {
+    hasher: StableHasher,
+    check_hasher_opt: Option<StableHasher>,
+}
+
+impl Hasher for KeyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.hasher.write(bytes);
+        if let Some(check_hasher) = self.check_hasher_opt.as_mut() {
+            check_hasher.write(bytes);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.hasher.finish()
+    }
+}
+
+/// How a read in the cluster output relates to its cluster's representative
+///
+/// A read whose full sequence differs from the representative's only matched
+/// because of prefix truncation and is a prefix duplicate even if it was also
+/// reverse complemented. A read that only joined within the mismatches
+/// allowed in near-duplicate mode is a near duplicate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DuplicateType {
+    Representative,
+    Exact,
+    Prefix,
+    Revcomp,
+    Near,
+}
+
+impl DuplicateType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DuplicateType::Representative => "representative",
+            DuplicateType::Exact => "exact",
+            DuplicateType::Prefix => "prefix",
+            DuplicateType::Revcomp => "revcomp",
+            DuplicateType::Near => "near",
+        }
+    }
+}
+
+/// Called with (representative read id, duplicate read id, is revcomp) for every duplicate
+pub type DuplicateCallback = Box<dyn FnMut(&str, &str, bool)>;
+
+/// Whether a run clusters reads with their reverse complements, picked once
+/// per run with `Forward` or `Revcomp`
+///
+/// The insert paths are generic over it, so a run without reverse
+/// complementing is compiled without any of its per-read branches or
+/// allocations.
+pub trait Orientation: Copy {
+    const USE_REVCOMP: bool;
+}
+
+/// Keys reads as sequenced
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Forward;
+
+impl Orientation for Forward {
+    const USE_REVCOMP: bool = false;
+}
+
+/// Keys reads by the lesser of their sequence and its reverse complement,
+/// and pairs as set by `PairCanonical`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Revcomp;
+
+impl Orientation for Revcomp {
+    const USE_REVCOMP: bool = true;
+}
+
+/// How read pairs are reverse complemented when clustering with revcomp
+///
+/// - `Lexmin` compares (r1, r2) with (rc(r1), rc(r2)), flipping each mate in place
+/// - `Lexmax` flips each mate in place like `Lexmin` but keeps the larger
+///   orientation, as the original czid-dedup did
+/// - `Fragment` compares (r1, r2) with (rc(r2), rc(r1)), the same fragment
+///   sequenced from its other strand
+/// - `R2Only` canonicalizes r2 on its own and keeps r1 as sequenced, for
+///   protocols where only r2 is reverse oriented relative to the fragment
+/// - `Off` never reverse complements pairs
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PairCanonical {
+    #[default]
+    Lexmin,
+    Lexmax,
+    Fragment,
+    R2Only,
+    Off,
+}
+
+impl std::str::FromStr for PairCanonical {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lexmin" => Ok(PairCanonical::Lexmin),
+            "lexmax" => Ok(PairCanonical::Lexmax),
+            "fragment" => Ok(PairCanonical::Fragment),
+            "r2-only" => Ok(PairCanonical::R2Only),
+            "off" => Ok(PairCanonical::Off),
+            _ => Err(format!("invalid pair canonicalization: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for PairCanonical {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PairCanonical::Lexmin => "lexmin",
+            PairCanonical::Lexmax => "lexmax",
+            PairCanonical::Fragment => "fragment",
+            PairCanonical::R2Only => "r2-only",
+            PairCanonical::Off => "off",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How a mate shorter than the prefix length is keyed in a read pair
+///
+/// - `Truncate` keys the whole short mate, the same as its prefix
+/// - `Pad` pads the short mate with `N` up to the prefix length
+/// - `Flag` also keys whether each mate is short, so pairs with a short mate
+///   only cluster with pairs whose same mate is short
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ShortMate {
+    #[default]
+    Truncate,
+    Pad,
+    Flag,
+}
+
+impl std::str::FromStr for ShortMate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "truncate" => Ok(ShortMate::Truncate),
+            "pad" => Ok(ShortMate::Pad),
+            "flag" => Ok(ShortMate::Flag),
+            _ => Err(format!("invalid short mate behavior: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for ShortMate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ShortMate::Truncate => "truncate",
+            ShortMate::Pad => "pad",
+            ShortMate::Flag => "flag",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The bases of a read used for hashing or comparing it
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Region {
+    /// the first `prefix_length` bases, or the whole read without a prefix length
+    #[default]
+    Prefix,
+    Full,
+}
+
+impl std::str::FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefix" => Ok(Region::Prefix),
+            "full" => Ok(Region::Full),
+            _ => Err(format!("invalid region: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Region::Prefix => "prefix",
+            Region::Full => "full",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Bytes of cluster output rows held before writing with `ClusterFlush::OnExit`
+pub const ON_EXIT_BUFFER_CAPACITY: usize = 16 * 1024 * 1024;
+
+/// When rows of the cluster output are written to it
+///
+/// - `PerRecord` writes every row as its read is clustered, so the output
+///   can be followed during a run
+/// - `Buffered` writes rows in small batches
+/// - `OnExit` holds up to `ON_EXIT_BUFFER_CAPACITY` bytes of rows, writing
+///   them when the buffer fills and when the run ends
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ClusterFlush {
+    PerRecord,
+    #[default]
+    Buffered,
+    OnExit,
+}
+
+impl std::str::FromStr for ClusterFlush {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per-record" => Ok(ClusterFlush::PerRecord),
+            "buffered" => Ok(ClusterFlush::Buffered),
+            "on-exit" => Ok(ClusterFlush::OnExit),
+            _ => Err(format!("invalid cluster flush policy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for ClusterFlush {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ClusterFlush::PerRecord => "per-record",
+            ClusterFlush::Buffered => "buffered",
+            ClusterFlush::OnExit => "on-exit",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// What happens to reads with no bases, which would otherwise all collapse
+/// into one cluster
+///
+/// - `Error` fails the run
+/// - `Drop` skips them with a warning
+/// - `Keep` clusters them like any other read
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EmptyReads {
+    Error,
+    #[default]
+    Drop,
+    Keep,
+}
+
+impl std::str::FromStr for EmptyReads {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(EmptyReads::Error),
+            "drop" => Ok(EmptyReads::Drop),
+            "keep" => Ok(EmptyReads::Keep),
+            _ => Err(format!("invalid empty read policy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for EmptyReads {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            EmptyReads::Error => "error",
+            EmptyReads::Drop => "drop",
+            EmptyReads::Keep => "keep",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// What happens when a record has the same id as an earlier one, which
+/// makes the cluster output ambiguous
+///
+/// - `Warn` keeps the record with a warning
+/// - `Error` fails the run
+/// - `Ignore` doesn't check, so read ids aren't kept in memory
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicateIds {
+    Warn,
+    Error,
+    #[default]
+    Ignore,
+}
+
+impl std::str::FromStr for DuplicateIds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(DuplicateIds::Warn),
+            "error" => Ok(DuplicateIds::Error),
+            "ignore" => Ok(DuplicateIds::Ignore),
+            _ => Err(format!("invalid duplicate id policy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for DuplicateIds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            DuplicateIds::Warn => "warn",
+            DuplicateIds::Error => "error",
+            DuplicateIds::Ignore => "ignore",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Which read represents a cluster
+///
+/// - `First` keeps the first read seen, written out as it's read
+/// - `Longest` replaces it with any later member with more bases, so reads
+///   truncated by trimming don't represent their cluster
+/// - `BestQuality` replaces it with any later member with a higher mean
+///   Phred score
+///
+/// Except with `First`, the outputs can only be written once every read is
+/// seen, with a second pass over the inputs.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Representative {
+    #[default]
+    First,
+    Longest,
+    BestQuality,
+}
+
+impl std::str::FromStr for Representative {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(Representative::First),
+            "longest" => Ok(Representative::Longest),
+            "best-quality" => Ok(Representative::BestQuality),
+            _ => Err(format!("invalid representative: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Representative {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Representative::First => "first",
+            Representative::Longest => "longest",
+            Representative::BestQuality => "best-quality",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How IUPAC ambiguity codes other than `N`, like `R` or `Y`, are keyed
+///
+/// - `Keep` keys them as distinct bases
+/// - `Normalize` keys them as `N`
+/// - `Match` joins a read to the first existing cluster of any sequence its
+///   codes are compatible with, up to `iupac::MAX_EXPANSIONS` of them, and
+///   keys it as `Normalize` does otherwise. Reads with codes never join a
+///   later read's cluster without them.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Ambiguity {
+    #[default]
+    Keep,
+    Normalize,
+    Match,
+}
+
+impl std::str::FromStr for Ambiguity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Ambiguity::Keep),
+            "normalize" => Ok(Ambiguity::Normalize),
+            "match" => Ok(Ambiguity::Match),
+            _ => Err(format!("invalid ambiguity handling: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Ambiguity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Ambiguity::Keep => "keep",
+            Ambiguity::Normalize => "normalize",
+            Ambiguity::Match => "match",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The highest Phred score an aggregated quality is capped at, `~` in Phred+33
+pub const MAX_AGGREGATED_QUALITY: u8 = 93;
+
+/// How a cluster's per-base qualities are combined into its representative's
+///
+/// - `Max` keeps the highest quality of any read at each position
+/// - `Sum` adds the qualities of every read at each position, capped at
+///   `MAX_AGGREGATED_QUALITY`
+///
+/// Qualities are aligned from the start of each read's canonical sequence.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QualityAggregation {
+    Max,
+    Sum,
+}
+
+impl QualityAggregation {
+    fn combine(&self, aggregated: &mut [u8], quals: &[u8]) {
+        for (aggregated, qual) in aggregated.iter_mut().zip(quals.iter()) {
+            *aggregated = match self {
+                QualityAggregation::Max => cmp::max(*aggregated, *qual),
+                QualityAggregation::Sum => {
+                    aggregated.saturating_add(*qual).min(MAX_AGGREGATED_QUALITY)
+                }
+            };
+        }
+    }
+}
+
+impl std::str::FromStr for QualityAggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(QualityAggregation::Max),
+            "sum" => Ok(QualityAggregation::Sum),
+            _ => Err(format!("invalid quality aggregation: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for QualityAggregation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            QualityAggregation::Max => "max",
+            QualityAggregation::Sum => "sum",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// What a read's cluster hash is computed from
+///
+/// - `Sequence` hashes the read's bases in order
+/// - `KmerSet` hashes the read's multiset of canonical k-mers, collapsing
+///   cyclic rotations of a read (experimental)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum KeyMode {
+    #[default]
+    Sequence,
+    KmerSet,
+}
+
+impl std::str::FromStr for KeyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequence" => Ok(KeyMode::Sequence),
+            "kmerset" => Ok(KeyMode::KmerSet),
+            _ => Err(format!("invalid key: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for KeyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            KeyMode::Sequence => "sequence",
+            KeyMode::KmerSet => "kmerset",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ClusterOptions {
+    pub prefix_length_opt: Option<usize>,
+    /// Keys overlapping pairs by their merged fragment rather than by each mate
+    pub merge_overlaps: bool,
+    /// Collapses byte-identical reads first, then clusters them by prefix,
+    /// reporting each read's exact representative alongside its cluster's
+    pub two_tier: bool,
+    /// Seeds every random choice so identical invocations give identical results
+    pub seed: u64,
+    pub pair_canonical: PairCanonical,
+    /// Reports read ids without `/1`, `/2`, or Casava comment suffixes
+    pub strip_read_suffix: bool,
+    /// Only detects duplicates among this many most recent records, forgetting
+    /// older clusters to keep memory bounded
+    pub window_opt: Option<u64>,
+    /// Input is sorted so duplicates are adjacent, only the current cluster is kept
+    pub assume_sorted: bool,
+    /// Adds each cluster's sequence hash to the cluster and cluster size
+    /// outputs so clusters can be matched across runs and joined across outputs
+    pub cluster_hash: bool,
+    /// Adds a column to the cluster size output counting each cluster's
+    /// members that joined reverse complemented, a per-cluster strand bias
+    /// signal
+    pub size_revcomp_counts: bool,
+    /// Adds a read group column to the cluster output, the group of the lane
+    /// each read came from, set with `set_read_group`
+    pub read_groups: bool,
+    pub short_mate: ShortMate,
+    /// The bases hashed to find a read's cluster
+    pub hash_region: Region,
+    /// The bases compared byte for byte with a cluster's representative before
+    /// a read joins it, guarding against false merges. Reads that differ are
+    /// placed in another cluster.
+    pub compare_region_opt: Option<Region>,
+    pub key: KeyMode,
+    /// k-mer length for `KeyMode::KmerSet`
+    pub kmer_size: usize,
+    /// Mixed into every hash, so the key dump's and `cluster hash` column's
+    /// hashes can't be matched against hashes of guessed sequences without
+    /// it. Reads cluster the same with or without one.
+    pub key_salt_opt: Option<Vec<u8>>,
+    /// Near-duplicate mode, reads of the same length within this many
+    /// mismatches of a cluster's representative join its cluster
+    pub max_mismatches_opt: Option<usize>,
+    /// Combines the qualities of each cluster's reads, read back with
+    /// `single_quals` and `pair_quals`. Pairs that merge with
+    /// `merge_overlaps` keep their representative's qualities.
+    pub quality_aggregation_opt: Option<QualityAggregation>,
+    /// What happens to single reads, or pairs, with no bases at all
+    pub empty_reads: EmptyReads,
+    /// What happens to records whose id was already seen, after filtering
+    /// and suffix stripping
+    pub duplicate_ids: DuplicateIds,
+    /// Which member represents each cluster. With `Representative::Longest`
+    /// the cluster, edge, and id map outputs written as records are inserted
+    /// may name a read that was later replaced.
+    pub representative: Representative,
+    /// Drops reads, or pairs with a mate, whose fraction of `N` bases is
+    /// above this before they're clustered
+    pub max_n_fraction_opt: Option<f64>,
+    pub ambiguity: Ambiguity,
+    /// Upper bounds, in ascending order, of the read length bins the summary
+    /// breaks duplication down by, no breakdown if empty. Pairs and mate sets
+    /// are binned by their longest mate.
+    pub length_bins: Vec<usize>,
+    /// Separates the cluster and cluster size outputs' fields, `,` if not set
+    pub cluster_delimiter_opt: Option<u8>,
+    /// Leaves the header row out of the cluster and cluster size outputs
+    pub omit_cluster_header: bool,
+    /// Appends to an existing cluster output file opened with
+    /// `from_file_with_options`, without repeating its header
+    pub append_cluster_output: bool,
+    /// Leaves the duplicate type column out of the cluster output, which
+    /// the original czid-dedup didn't write
+    pub legacy_cluster_output: bool,
+    /// Marks reverse complemented reads by appending ` (rc)` to their read
+    /// id in the cluster output, as the original czid-dedup did, rather than
+    /// in a `revcomp` column. Implied by `legacy_cluster_output`.
+    pub legacy_rc_suffix: bool,
+    /// Keeps every cluster's member read ids in memory, read back with
+    /// `Cluster::members`
+    pub keep_members: bool,
+    /// Also estimates the number of unique reads in fixed memory, reported
+    /// in the summary even when window or sorted mode forget clusters
+    pub estimate_unique: bool,
+    pub cluster_flush: ClusterFlush,
+    /// Also keys records by a second, independent hash, so a record joins a
+    /// cluster only when both of its hashes match the representative's
+    pub double_hash: bool,
+}
+
+/// A cluster hash as written to the cluster output's `cluster hash` column
+pub fn format_hash(hash: u128) -> String {
+    format!("{:032x}", hash)
+}
+
+/// How the `cluster hash` column's values are computed, reported in the
+/// cluster outputs' metadata
+pub const HASH_ALGORITHM: &str = "SipHash-1-3, 128 bit, keys (0, 0), over the key salt then the \
+     hashed bases, integers as little endian bytes and lengths as u64, as 32 lowercase hex digits";
+
+/// The cluster output's columns with `options`
+fn cluster_columns(options: &ClusterOptions) -> Vec<&'static str> {
+    let mut columns = vec!["representative read id", "read id"];
+    if !options.legacy_cluster_output {
+        columns.push("duplicate type");
+    }
+    if has_revcomp_column(options) {
+        columns.push("revcomp");
+    }
+    if options.two_tier {
+        columns.push("exact representative read id");
+    }
+    if options.cluster_hash {
+        columns.push("cluster hash");
+    }
+    if options.read_groups {
+        columns.push("read group");
+    }
+    columns
+}
+
+/// Fails unless the first line of the cluster file at `path`, appended to
+/// with --cluster-append, is the header a run with `options` would write
+fn check_appended_header(
+    path: &std::path::Path,
+    options: &ClusterOptions,
+) -> Result<(), csv::Error> {
+    let mut builder = csv::WriterBuilder::new();
+    builder.delimiter(options.cluster_delimiter_opt.unwrap_or(b','));
+    let mut header_writer = builder.from_writer(Vec::new());
+    header_writer.write_record(cluster_columns(options))?;
+    // only fails if the header couldn't be written, which it just was
+    let header = header_writer.into_inner().unwrap();
+    let mut first_line = Vec::new();
+    io::BufReader::new(File::open(path)?).read_until(b'\n', &mut first_line)?;
+    if first_line == header {
+        return Ok(());
+    }
+    let message = format!(
+        "can't append to {}: its header {:?} doesn't match this run's {:?}",
+        path.display(),
+        String::from_utf8_lossy(&first_line).trim_end(),
+        String::from_utf8_lossy(&header).trim_end()
+    );
+    Err(io::Error::new(io::ErrorKind::InvalidData, message).into())
+}
+
+/// Whether the cluster output marks reverse complemented reads in a
+/// `revcomp` column rather than with an ` (rc)` read id suffix
+fn has_revcomp_column(options: &ClusterOptions) -> bool {
+    !options.legacy_cluster_output && !options.legacy_rc_suffix
+}
+
+/// The cluster size output's columns with `options`
+fn size_columns(options: &ClusterOptions) -> Vec<&'static str> {
+    let mut columns = vec!["representative read id", "cluster size"];
+    if options.size_revcomp_counts {
+        columns.push("revcomp members");
+    }
+    if options.cluster_hash {
+        columns.push("cluster hash");
+    }
+    columns
+}
+
+/// The options that decide a single read's cluster hash, see `key_for`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyConfig {
+    pub prefix_length_opt: Option<usize>,
+    pub hash_region: Region,
+    pub key: KeyMode,
+    pub kmer_size: usize,
+    pub ambiguity: Ambiguity,
+    pub key_salt_opt: Option<Vec<u8>>,
+    pub use_revcomp: bool,
+}
+
+impl KeyConfig {
+    /// The key options of a run clustering with `options`
+    pub fn from_options(options: &ClusterOptions, use_revcomp: bool) -> Self {
+        KeyConfig {
+            prefix_length_opt: options.prefix_length_opt,
+            hash_region: options.hash_region,
+            key: options.key,
+            kmer_size: options.kmer_size,
+            ambiguity: options.ambiguity,
+            key_salt_opt: options.key_salt_opt.clone(),
+            use_revcomp,
+        }
+    }
+}
+
+/// The hash a single read is clustered by, the same as `Clusters::single_hash`
+/// gives and the cluster output's `cluster hash` column shows, so services
+/// can check a read against a run's clusters without running the deduper
+///
+/// With the default sequence key the hash is SipHash-1-3 with 128 bit output
+/// and keys (0, 0), see `keyhash`, of the key salt's bytes followed by the
+/// hashed region of the read or, with `use_revcomp`, of whichever of the read
+/// and its reverse complement sorts first. It's the same on every build and
+/// machine. With `Ambiguity::Match` a read with ambiguity codes is keyed as
+/// if no compatible cluster existed, like `Ambiguity::Normalize`.
+pub fn key_for(seq: &[u8], config: &KeyConfig) -> u128 {
+    let options = ClusterOptions {
+        prefix_length_opt: config.prefix_length_opt,
+        hash_region: config.hash_region,
+        key: config.key,
+        kmer_size: config.kmer_size,
+        ambiguity: config.ambiguity,
+        key_salt_opt: config.key_salt_opt.clone(),
+        ..ClusterOptions::default()
+    };
+    let clusters = Clusters::<io::Sink>::from_writer_with_options(None, options, 0)
+        .expect("no cluster output to write a header to");
+    clusters.single_key(seq, config.use_revcomp, false).seq_hash
+}
+
+/// Identifies a cluster state file written by `Clusters::save_state`,
+/// raised when the hashes clusters are stored under change
+const STATE_MAGIC: &[u8; 8] = b"CZDSTAT2";
+
+fn write_u64<W: io::Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u128<W: io::Write>(writer: &mut W, value: u128) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_bytes<W: io::Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_u64(writer, bytes.len() as u64)?;
+    writer.write_all(bytes)
+}
+
+fn read_u64<R: io::Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_u128<R: io::Read>(reader: &mut R) -> io::Result<u128> {
+    let mut buf = [0u8; 16];
+    reader.read_exact(&mut buf)?;
+    Ok(u128::from_le_bytes(buf))
+}
+
+fn read_bytes<R: io::Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    // a truncated file can't claim more bytes than it has
+    reader.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn invalid_state(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
 }
 
 pub struct Clusters<T: io::Write> {
-    cluster_map: HashMap<u64, Cluster>,
-    cluster_order: Vec<u64>,
+    cluster_map: HashMap<u128, Cluster>,
+    cluster_order: Vec<u128>,
     cluster_csv_writer: Option<csv::Writer<T>>,
     total_records: u64,
-    prefix_length_opt: Option<usize>,
+    unique_records: u64,
+    options: ClusterOptions,
+    /// (cluster hash, record index) for each of the most recent records in window mode
+    window: VecDeque<(u128, u64)>,
+    /// sizes of clusters forgotten in window mode
+    evicted_size_histogram: BTreeMap<u64, u64>,
+    /// full sequence hash -> first read id with that sequence, in two-tier mode
+    exact_ids_opt: Option<HashMap<u64, String>>,
+    exact_duplicate_records: u64,
+    /// pairs with a mate shorter than the prefix length
+    short_mate_pairs: u64,
+    /// clusters with reads whose full sequences differ from their representative's
+    prefix_divergent_clusters: u64,
+    on_duplicate: Option<DuplicateCallback>,
+    edges_csv_writer: Option<csv::Writer<T>>,
+    /// a sample of duplicates next to their representatives, with the
+    /// fraction of clusters sampled and their representatives' sequences
+    audit_csv_writer: Option<csv::Writer<T>>,
+    audit_fraction: f64,
+    audit_seqs: HashMap<u128, Vec<Vec<u8>>>,
+    key_dump_csv_writer: Option<csv::Writer<T>>,
+    id_map_csv_writer: Option<csv::Writer<T>>,
+    warnings: Warnings,
+    on_warning: Option<WarningCallback>,
+    /// representatives' compared bases by band, in near-duplicate mode
+    band_index_opt: Option<lsh::BandIndex>,
+    filter_opt: Option<Box<dyn RecordFilter>>,
+    /// records dropped by the filter before clustering
+    filtered_records: u64,
+    /// records with no bases, whatever the empty read policy
+    empty_records: u64,
+    /// records dropped for having too many `N` bases
+    n_filtered_records: u64,
+    /// every record's id, to detect duplicate ids
+    seen_ids: HashSet<String>,
+    /// records with IUPAC ambiguity codes other than `N`, whatever the
+    /// ambiguity handling
+    ambiguous_records: u64,
+    /// (records, duplicates) in each read length bin
+    length_bin_counts: Vec<(u64, u64)>,
+    /// every record's hash, to estimate unique reads
+    unique_estimator_opt: Option<HyperLogLog>,
+    /// offsets of the records being inserted in each mate's input
+    record_offsets: Vec<fastx::RecordOffsets>,
+    /// lane index and read group of the records being inserted
+    lane: usize,
+    read_group_opt: Option<String>,
+    /// (representative's lane, duplicate's lane) -> number of duplicates
+    lane_duplicates: BTreeMap<(usize, usize), u64>,
+    /// when to stop reading inputs, with --time-limit
+    deadline_opt: Option<Instant>,
+    deadline_reached: bool,
+    /// cancels the run from outside, checked between records
+    cancellation_opt: Option<cancel::CancellationToken>,
+    cancelled: bool,
+    /// hashes of every pair's r1 keyed on its own, kept for --single-input
+    r1_keys_opt: Option<HashSet<u128>>,
+    /// hashes of pairs' r1s, single reads keyed the same are their duplicates
+    paired_keys: HashSet<u128>,
+    paired_duplicate_records: u64,
+    /// reads left without their r2 and r1 respectively, counted on the
+    /// clusters orphans are inserted into
+    mate_orphan_records: [u64; 2],
+    /// sequence bytes, and those other than `ACGTN`, in the chunk of input
+    /// being checked for corruption
+    chunk_bytes: usize,
+    chunk_non_acgtn: usize,
 }
 
 impl<T: std::io::Write> Clusters<T> {
-    fn insert_record(&mut self, seq_hash: u64, id: String, is_revcomp: bool) -> Result<bool, csv::Error> {
+    fn record_id(&self, id: &str) -> String {
+        if self.options.strip_read_suffix {
+            fastx::strip_read_suffix(id).to_owned()
+        } else {
+            id.to_owned()
+        }
+    }
+
+    fn insert_record(
+        &mut self,
+        key: RecordKey,
+        id: String,
+        seqs: &[&[u8]],
+        score: f64,
+    ) -> Result<bool, csv::Error> {
+        let lengths = seqs.iter().map(|seq| seq.len()).collect::<Vec<_>>();
+        let unique_records = self.unique_records;
+        if let Some(unique_estimator) = self.unique_estimator_opt.as_mut() {
+            // the low bits are as well mixed as the rest
+            unique_estimator.insert(key.seq_hash as u64);
+        }
+        let (seq_hash, found) = self.probe(&key);
+        let (seq_hash, found, is_near) = match (found, key.compare_bytes_opt.as_ref()) {
+            (false, Some(compare_bytes)) => match self.near_cluster(compare_bytes) {
+                Some(slot) => (slot, true, true),
+                None => (seq_hash, false, false),
+            },
+            _ => (seq_hash, found, false),
+        };
+        if let Some(key_dump_csv_writer) = self.key_dump_csv_writer.as_mut() {
+            key_dump_csv_writer.write_record([id.as_str(), &format_hash(seq_hash)])?;
+        }
+        let RecordKey {
+            full_hash,
+            is_revcomp,
+            compare_bytes_opt,
+            check_hash_opt,
+            quals_opt,
+            ..
+        } = key;
         self.total_records += 1;
-        match self.cluster_map.get_mut(&seq_hash) {
+        // the first read with this exact sequence, if it isn't this one
+        let exact_id_opt =
+            self.exact_ids_opt
+                .as_mut()
+                .and_then(|exact_ids| match exact_ids.entry(full_hash) {
+                    Entry::Occupied(entry) => Some(entry.get().clone()),
+                    Entry::Vacant(entry) => {
+                        entry.insert(id.clone());
+                        None
+                    }
+                });
+        if self.options.assume_sorted && !found {
+            // sorted input never returns to an earlier cluster
+            for (_, cluster) in self.cluster_map.drain() {
+                *self.evicted_size_histogram.entry(cluster.size).or_insert(0) += 1;
+            }
+        }
+        let total_records = self.total_records;
+        let offsets_opt = self.current_offsets();
+        let result = match self.cluster_map.get_mut(&seq_hash) {
             Some(cluster) => {
+                // relative to the representative, not to the canonical sequence
+                let is_revcomp_duplicate = is_revcomp != cluster.is_revcomp;
                 cluster.size += 1;
+                if is_revcomp_duplicate {
+                    cluster.revcomp_members += 1;
+                }
+                cluster.last_seen = total_records;
+                if let Some(members) = cluster.members_opt.as_mut() {
+                    members.push(id.clone());
+                }
+                if let (Some(aggregation), Some(aggregated), Some(quals)) = (
+                    self.options.quality_aggregation_opt,
+                    cluster.quals_opt.as_mut(),
+                    quals_opt.as_ref(),
+                ) {
+                    for (aggregated, quals) in aggregated.iter_mut().zip(quals.iter()) {
+                        aggregation.combine(aggregated, quals);
+                    }
+                }
+                *self
+                    .lane_duplicates
+                    .entry((cluster.lane, self.lane))
+                    .or_insert(0) += 1;
+                if let Some(on_duplicate) = self.on_duplicate.as_mut() {
+                    on_duplicate(&cluster.id, &id, is_revcomp_duplicate);
+                }
+                let is_exact = if self.exact_ids_opt.is_some() {
+                    exact_id_opt.is_some()
+                } else {
+                    full_hash == cluster.full_hash
+                };
+                let duplicate_type = if is_near {
+                    DuplicateType::Near
+                } else if !is_exact {
+                    DuplicateType::Prefix
+                } else if is_revcomp_duplicate {
+                    DuplicateType::Revcomp
+                } else {
+                    DuplicateType::Exact
+                };
+                if is_exact {
+                    self.exact_duplicate_records += 1;
+                } else if !is_near && !cluster.has_prefix_duplicate {
+                    cluster.has_prefix_duplicate = true;
+                    self.prefix_divergent_clusters += 1;
+                }
+                if self.options.representative != Representative::First && score > cluster.score {
+                    cluster.id = id.clone();
+                    cluster.full_hash = full_hash;
+                    if is_revcomp_duplicate {
+                        // the members that weren't reverse complements of the
+                        // old representative are of this one
+                        cluster.revcomp_members = cluster.size - cluster.revcomp_members;
+                    }
+                    cluster.is_revcomp = is_revcomp;
+                    cluster.score = score;
+                    cluster.offsets_opt = offsets_opt;
+                }
+                if let Some(edges_csv_writer) = self.edges_csv_writer.as_mut() {
+                    edges_csv_writer.write_record([cluster.id.as_str(), &id, "1"])?;
+                }
+                if let (Some(audit_csv_writer), Some(representative_seqs)) = (
+                    self.audit_csv_writer.as_mut(),
+                    self.audit_seqs.get(&seq_hash),
+                ) {
+                    audit_csv_writer.write_record([
+                        cluster.id.as_bytes(),
+                        id.as_bytes(),
+                        duplicate_type.as_str().as_bytes(),
+                        &representative_seqs.join(&b'+'),
+                        &seqs.join(&b'+'),
+                    ])?;
+                }
+                if let Some(id_map_csv_writer) = self.id_map_csv_writer.as_mut() {
+                    id_map_csv_writer.write_record([id.as_str(), &cluster.id])?;
+                }
+                let legacy_cluster_output = self.options.legacy_cluster_output;
+                let revcomp_column = has_revcomp_column(&self.options);
+                let two_tier = self.options.two_tier;
+                let cluster_hash = self.options.cluster_hash;
+                let read_groups = self.options.read_groups;
+                let read_group = self.read_group_opt.as_deref().unwrap_or_default();
                 self.cluster_csv_writer
                     .as_mut()
                     .map(|cluster_csv_writer| {
-                        let id_entry = if is_revcomp {
+                        let id_entry = if is_revcomp_duplicate && !revcomp_column {
                             format!("{} (rc)", id) // Mark revcomp sequences
                         } else {
                             id.clone()
                         };
-                        cluster_csv_writer
-                            .write_record(vec![&cluster.id, &id_entry])
-                            .map(|_| false)
+                        let hash_entry = format_hash(seq_hash);
+                        let mut row = vec![cluster.id.as_str(), &id_entry];
+                        if !legacy_cluster_output {
+                            row.push(duplicate_type.as_str());
+                        }
+                        if revcomp_column {
+                            row.push(if is_revcomp_duplicate {
+                                "true"
+                            } else {
+                                "false"
+                            });
+                        }
+                        if two_tier {
+                            row.push(exact_id_opt.as_deref().unwrap_or(&id));
+                        }
+                        if cluster_hash {
+                            row.push(&hash_entry);
+                        }
+                        if read_groups {
+                            row.push(read_group);
+                        }
+                        cluster_csv_writer.write_record(row).map(|_| false)
                     })
                     .unwrap_or(Ok(false))
             }
-            None => {
-                let res_opt = self.cluster_csv_writer.as_mut().map(|cluster_csv_writer| {
-                    cluster_csv_writer
-                        .write_record(vec![&id, &id])
-                        .map(|_| true)
-                });
-                self.cluster_map.insert(seq_hash, Cluster { id, size: 1 });
-                self.cluster_order.push(seq_hash);
-                res_opt.unwrap_or(Ok(true))
+            None => {
+                let legacy_cluster_output = self.options.legacy_cluster_output;
+                let revcomp_column = has_revcomp_column(&self.options);
+                let two_tier = self.options.two_tier;
+                let cluster_hash = self.options.cluster_hash;
+                let read_groups = self.options.read_groups;
+                let read_group = self.read_group_opt.as_deref().unwrap_or_default();
+                let res_opt = self.cluster_csv_writer.as_mut().map(|cluster_csv_writer| {
+                    let hash_entry = format_hash(seq_hash);
+                    let mut row = vec![id.as_str(), &id];
+                    if !legacy_cluster_output {
+                        row.push(DuplicateType::Representative.as_str());
+                    }
+                    if revcomp_column {
+                        row.push("false");
+                    }
+                    if two_tier {
+                        row.push(&id);
+                    }
+                    if cluster_hash {
+                        row.push(&hash_entry);
+                    }
+                    if read_groups {
+                        row.push(read_group);
+                    }
+                    cluster_csv_writer.write_record(row).map(|_| true)
+                });
+                if let Some(id_map_csv_writer) = self.id_map_csv_writer.as_mut() {
+                    id_map_csv_writer.write_record([id.as_str(), &id])?;
+                }
+                // the slot may have held a cluster evicted from the window
+                self.audit_seqs.remove(&seq_hash);
+                if self.is_audited(seq_hash) {
+                    let seqs = seqs.iter().map(|seq| seq.to_vec()).collect();
+                    self.audit_seqs.insert(seq_hash, seqs);
+                }
+                if let (Some(band_index), Some(compare_bytes)) =
+                    (self.band_index_opt.as_mut(), compare_bytes_opt.as_ref())
+                {
+                    band_index.insert(compare_bytes, seq_hash);
+                }
+                let members_opt = if self.options.keep_members {
+                    Some(vec![id.clone()])
+                } else {
+                    None
+                };
+                self.cluster_map.insert(
+                    seq_hash,
+                    Cluster {
+                        id,
+                        size: 1,
+                        revcomp_members: 0,
+                        hash: seq_hash,
+                        full_hash,
+                        is_revcomp,
+                        score,
+                        last_seen: total_records,
+                        compare_bytes_opt,
+                        check_hash_opt,
+                        lane: self.lane,
+                        quals_opt,
+                        has_prefix_duplicate: false,
+                        members_opt,
+                        offsets_opt,
+                    },
+                );
+                self.unique_records += 1;
+                if self.options.window_opt.is_none() && !self.options.assume_sorted {
+                    self.cluster_order.push(seq_hash);
+                }
+                res_opt.unwrap_or(Ok(true))
+            }
+        };
+        if let Some(window) = self.options.window_opt {
+            self.window.push_back((seq_hash, total_records));
+            self.evict(window);
+        }
+        if self.options.cluster_flush == ClusterFlush::PerRecord {
+            if let Some(cluster_csv_writer) = self.cluster_csv_writer.as_mut() {
+                cluster_csv_writer.flush()?;
+            }
+        }
+        let length = lengths.iter().copied().max().unwrap_or(0);
+        self.count_length(length, self.unique_records == unique_records);
+        result
+    }
+
+    /// Counts a record, and whether it was a duplicate, in its read length bin
+    fn count_length(&mut self, length: usize, is_duplicate: bool) {
+        let bin = self
+            .options
+            .length_bins
+            .iter()
+            .filter(|bound| length >= **bound)
+            .count();
+        if let Some((records, duplicates)) = self.length_bin_counts.get_mut(bin) {
+            *records += 1;
+            if is_duplicate {
+                *duplicates += 1;
+            }
+        }
+    }
+
+    /// Duplication in each read length bin, from shortest to longest
+    fn length_bins(&self) -> Vec<LengthBin> {
+        let bounds = &self.options.length_bins;
+        self.length_bin_counts
+            .iter()
+            .enumerate()
+            .map(|(i, (records, duplicates))| LengthBin {
+                min_length: if i == 0 { 0 } else { bounds[i - 1] },
+                max_length: bounds.get(i).map(|bound| bound - 1),
+                total_records: *records,
+                duplicate_records: *duplicates,
+                duplication_rate: summary::duplication_rate(*duplicates, *records),
+            })
+            .collect()
+    }
+
+    /// Writes out every buffered row of the cluster, edge, key dump, and id
+    /// map outputs
+    pub fn flush(&mut self) -> Result<(), csv::Error> {
+        let mut writers = [
+            self.cluster_csv_writer.as_mut(),
+            self.edges_csv_writer.as_mut(),
+            self.audit_csv_writer.as_mut(),
+            self.key_dump_csv_writer.as_mut(),
+            self.id_map_csv_writer.as_mut(),
+        ];
+        for csv_writer in writers.iter_mut().flatten() {
+            csv_writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// The slot of a record's cluster in the cluster map and whether the
+    /// cluster exists yet
+    ///
+    /// Without a compare region a record's slot is its hash. With one, a
+    /// record whose compared bases differ from the cluster in its slot moves
+    /// on to the next slot in a probe sequence seeded by its hash.
+    fn probe(&self, key: &RecordKey) -> (u128, bool) {
+        let mut slot = key.seq_hash;
+        let mut attempt: u64 = 0;
+        loop {
+            match self.cluster_map.get(&slot) {
+                None => return (slot, false),
+                Some(cluster)
+                    if cluster.compare_bytes_opt == key.compare_bytes_opt
+                        && cluster.check_hash_opt == key.check_hash_opt =>
+                {
+                    return (slot, true)
+                }
+                Some(_) => {
+                    attempt += 1;
+                    let mut hasher = StableHasher::default();
+                    Hash::hash(&(key.seq_hash, attempt), &mut hasher);
+                    slot = hasher.finish128();
+                }
+            }
+        }
+    }
+
+    /// The slot of the first cluster whose representative is within the
+    /// allowed mismatches, in near-duplicate mode
+    fn near_cluster(&self, compare_bytes: &[u8]) -> Option<u128> {
+        let band_index = self.band_index_opt.as_ref()?;
+        // guaranteed to be set with the band index
+        let max_mismatches = self.options.max_mismatches_opt.unwrap();
+        band_index
+            .candidates(compare_bytes)
+            .into_iter()
+            .find(|slot| {
+                // clusters forgotten in window mode stay in the band index
+                self.cluster_map
+                    .get(slot)
+                    .and_then(|cluster| cluster.compare_bytes_opt.as_ref())
+                    .map(|bytes| lsh::within_mismatches(bytes, compare_bytes, max_mismatches))
+                    .unwrap_or(false)
+            })
+    }
+
+    /// Forgets clusters that have no records among the most recent `window` records
+    fn evict(&mut self, window: u64) {
+        while let Some(&(seq_hash, index)) = self.window.front() {
+            if self.total_records - index < window {
+                break;
+            }
+            self.window.pop_front();
+            let is_stale = self
+                .cluster_map
+                .get(&seq_hash)
+                .map(|cluster| cluster.last_seen == index)
+                .unwrap_or(false);
+            if is_stale {
+                // guaranteed to be present
+                let cluster = self.cluster_map.remove(&seq_hash).unwrap();
+                *self.evicted_size_histogram.entry(cluster.size).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Records a warning, only building its message if it will be seen
+    fn warn<M: FnOnce() -> String>(&mut self, kind: WarningKind, read_id: &str, message: M) {
+        if self.on_warning.is_none() && self.warnings.count(kind) >= warnings::MAX_EXAMPLES as u64 {
+            self.warnings.push(Warning {
+                kind,
+                read_id: String::new(),
+                message: String::new(),
+            });
+            return;
+        }
+        let warning = Warning {
+            kind,
+            read_id: read_id.to_owned(),
+            message: message(),
+        };
+        if let Some(on_warning) = self.on_warning.as_mut() {
+            on_warning(&warning);
+        }
+        self.warnings.push(warning);
+    }
+
+    /// Warns about reads whose sequences were truncated by the prefix length
+    /// or are mostly `N`, and counts reads with ambiguity codes
+    fn check_seqs(&mut self, read_id: &str, seqs: &[&[u8]]) {
+        if seqs.iter().any(|seq| iupac::has_ambiguous(seq)) {
+            self.ambiguous_records += 1;
+        }
+        if let Some(prefix_length) = self.options.prefix_length_opt {
+            if let Some(seq) = seqs.iter().find(|seq| seq.len() > prefix_length) {
+                let ignored = seq.len() - prefix_length;
+                self.warn(WarningKind::PrefixTruncated, read_id, || {
+                    format!(
+                        "{} bases past the prefix length of {} were ignored",
+                        ignored, prefix_length
+                    )
+                });
+            }
+        }
+        if seqs.iter().any(|seq| warnings::is_n_heavy(seq)) {
+            self.warn(WarningKind::NHeavy, read_id, || {
+                format!(
+                    "more than {}% of bases are N",
+                    warnings::N_HEAVY_FRACTION * 100.0
+                )
+            });
+        }
+    }
+
+    /// Fails once a chunk of sequence is mostly bytes other than `ACGTN`,
+    /// rather than clustering the garbage of a corrupt or mis-decoded input
+    fn check_composition(&mut self, seqs: &[&[u8]]) -> Result<(), csv::Error> {
+        for seq in seqs {
+            self.chunk_bytes += seq.len();
+            self.chunk_non_acgtn += warnings::non_acgtn_bytes(seq);
+        }
+        if self.chunk_bytes < warnings::COMPOSITION_CHUNK_BYTES {
+            return Ok(());
+        }
+        let fraction = self.chunk_non_acgtn as f64 / self.chunk_bytes as f64;
+        self.chunk_bytes = 0;
+        self.chunk_non_acgtn = 0;
+        if fraction > warnings::CORRUPT_FRACTION {
+            let message = format!(
+                "input appears corrupt/binary at record {}: {:.0}% of recent sequence bytes aren't A, C, G, T or N",
+                self.records_read() + 1,
+                fraction * 100.0
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message).into());
+        }
+        Ok(())
+    }
+
+    /// Records that an invalid record was skipped rather than clustered
+    pub fn skip_invalid(&mut self, read_id: &str, reason: &str) {
+        self.skip_anomaly(WarningKind::InvalidRecord, read_id, reason);
+    }
+
+    /// Records that a read was skipped, or its input cut short, because of an
+    /// anomaly in the input
+    pub fn skip_anomaly(&mut self, kind: WarningKind, read_id: &str, reason: &str) {
+        self.warn(kind, read_id, || reason.to_owned());
+    }
+
+    pub fn warnings(&self) -> &Warnings {
+        &self.warnings
+    }
+
+    pub fn on_warning<F: FnMut(&Warning) + 'static>(&mut self, callback: F) {
+        self.on_warning = Some(Box::new(callback));
+    }
+
+    fn get_prefix<'a>(&self, seq: &'a [u8]) -> &'a [u8] {
+        let seq_length = seq.len();
+        let prefix_length = self
+            .options
+            .prefix_length_opt
+            .map(|prefix_length| cmp::min(prefix_length, seq_length))
+            .unwrap_or(seq_length);
+        &seq[..prefix_length]
+    }
+
+    fn get_region<'a>(&self, seq: &'a [u8], region: Region) -> &'a [u8] {
+        match region {
+            Region::Prefix => self.get_prefix(seq),
+            Region::Full => seq,
+        }
+    }
+
+    /// The compared bases of a record's canonical sequences, separated by `0`
+    /// and padded or flagged like they are hashed
+    fn compare_bytes(&self, canonical_seqs: &[&[u8]], merged: bool, region: Region) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for seq in canonical_seqs.iter() {
+            bytes.extend_from_slice(self.get_region(seq, region));
+            if let (true, Region::Prefix, Some(prefix_length)) = (
+                canonical_seqs.len() > 1,
+                region,
+                self.options.prefix_length_opt,
+            ) {
+                let missing = prefix_length.saturating_sub(seq.len());
+                match self.options.short_mate {
+                    ShortMate::Truncate => (),
+                    ShortMate::Pad => bytes.extend(std::iter::repeat_n(b'N', missing)),
+                    ShortMate::Flag => bytes.push((missing > 0) as u8),
+                }
+            }
+            bytes.push(0);
+        }
+        bytes.push(merged as u8);
+        bytes
+    }
+
+    /// A hasher that has already hashed the key salt, if there is one
+    fn salted_hasher(&self) -> StableHasher {
+        let mut hasher = StableHasher::default();
+        if let Some(key_salt) = self.options.key_salt_opt.as_ref() {
+            Hash::hash_slice(key_salt, &mut hasher);
+        }
+        hasher
+    }
+
+    /// A hasher for a record's key, that also feeds a second salted hasher
+    /// under its own domain with `double_hash`
+    fn key_hasher(&self) -> KeyHasher {
+        let check_hasher_opt = if self.options.double_hash {
+            let mut check_hasher = self.salted_hasher();
+            Hash::hash_slice(CHECK_HASH_DOMAIN, &mut check_hasher);
+            Some(check_hasher)
+        } else {
+            None
+        };
+        KeyHasher {
+            hasher: self.salted_hasher(),
+            check_hasher_opt,
+        }
+    }
+
+    /// Hashes the hash region of a record's canonical sequences, and the full
+    /// sequences too when a prefix length is set
+    fn record_key(&self, canonical_seqs: &[&[u8]], merged: bool, is_revcomp: bool) -> RecordKey {
+        let hash_region = self.options.hash_region;
+        let mut seq_hasher = self.key_hasher();
+        for (i, seq) in canonical_seqs.iter().enumerate() {
+            if i > 0 {
+                Hash::hash(&0, &mut seq_hasher);
+            }
+            let region = self.get_region(seq, hash_region);
+            match self.options.key {
+                KeyMode::Sequence => Hash::hash_slice(region, &mut seq_hasher),
+                KeyMode::KmerSet => {
+                    kmerset::hash_kmer_multiset(region, self.options.kmer_size, &mut seq_hasher)
+                }
+            }
+            if let (true, Region::Prefix, Some(prefix_length)) = (
+                canonical_seqs.len() > 1,
+                hash_region,
+                self.options.prefix_length_opt,
+            ) {
+                let missing = prefix_length.saturating_sub(seq.len());
+                match self.options.short_mate {
+                    ShortMate::Truncate => (),
+                    ShortMate::Pad => {
+                        for _ in 0..missing {
+                            Hash::hash(&b'N', &mut seq_hasher);
+                        }
+                    }
+                    ShortMate::Flag => Hash::hash(&(missing > 0), &mut seq_hasher),
+                }
+            }
+        }
+        if merged {
+            // keeps merged fragments apart from pairs that could not be merged
+            Hash::hash(&1, &mut seq_hasher);
+        }
+        let seq_hash = seq_hasher.hasher.finish128();
+        let check_hash_opt = seq_hasher.check_hasher_opt.map(|hasher| hasher.finish());
+
+        let full_hash = if self.options.prefix_length_opt.is_some() {
+            let mut full_hasher = self.salted_hasher();
+            Hash::hash(&canonical_seqs, &mut full_hasher);
+            Hash::hash(&merged, &mut full_hasher);
+            full_hasher.finish()
+        } else {
+            seq_hash as u64
+        };
+        // near-duplicate mode compares the hashed bases unless told otherwise
+        let compare_region_opt = self.options.compare_region_opt.or_else(|| {
+            self.options
+                .max_mismatches_opt
+                .map(|_| self.options.hash_region)
+        });
+        let compare_bytes_opt =
+            compare_region_opt.map(|region| self.compare_bytes(canonical_seqs, merged, region));
+        RecordKey {
+            seq_hash,
+            full_hash,
+            is_revcomp,
+            compare_bytes_opt,
+            check_hash_opt,
+            quals_opt: None,
+        }
+    }
+
+    pub fn insert_single<R: fastx::Record>(
+        &mut self,
+        record: &R,
+        use_revcomp: bool,
+    ) -> Result<bool, csv::Error> {
+        if use_revcomp {
+            self.insert_single_oriented(record, Revcomp)
+        } else {
+            self.insert_single_oriented(record, Forward)
+        }
+    }
+
+    /// `insert_single` with the orientation fixed for the run, for callers
+    /// that insert every read the same way
+    pub fn insert_single_oriented<O: Orientation, R: fastx::Record>(
+        &mut self,
+        record: &R,
+        _orientation: O,
+    ) -> Result<bool, csv::Error> {
+        if self.is_filtered(&[record]) {
+            return Ok(false);
+        }
+        let id = self.record_id(record.id());
+        self.check_duplicate_id(&id)?;
+        self.check_composition(&[record.seq()])?;
+        if self.skip_empty(&id, &[record.seq()])? || self.is_n_filtered(&[record.seq()]) {
+            return Ok(false);
+        }
+        self.check_seqs(&id, &[record.seq()]);
+        let mut key = self.oriented_single_key::<O>(record.seq(), false);
+        if self.paired_keys.contains(&key.seq_hash) {
+            self.total_records += 1;
+            self.paired_duplicate_records += 1;
+            return Ok(false);
+        }
+        if self.options.quality_aggregation_opt.is_some() {
+            key.quals_opt = self.canonical_quals(&[record], key.is_revcomp);
+        }
+        let score = self.representative_score(&[record]);
+        self.insert_record(key, id, &[record.seq()], score)
+    }
+
+    /// Phred scores of a record's mates in the orientation of its canonical
+    /// sequences, `None` for FASTA records
+    fn canonical_quals<R: fastx::Record>(
+        &self,
+        records: &[&R],
+        is_revcomp: bool,
+    ) -> Option<Vec<Vec<u8>>> {
+        let mut quals = records
+            .iter()
+            .map(|record| {
+                record.qual().map(|qual| {
+                    qual.iter()
+                        .map(|q| q.saturating_sub(33))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if is_revcomp {
+            self.flip_quals(&mut quals);
+        }
+        Some(quals)
+    }
+
+    /// Reverses mates' qualities the way their sequences are reverse
+    /// complemented, which undoes itself
+    fn flip_quals(&self, quals: &mut [Vec<u8>]) {
+        match (quals.len(), self.options.pair_canonical) {
+            (1, _) | (_, PairCanonical::Lexmin) | (_, PairCanonical::Lexmax) => {
+                quals.iter_mut().for_each(|q| q.reverse())
+            }
+            (_, PairCanonical::Fragment) => {
+                quals.swap(0, 1);
+                quals.iter_mut().for_each(|q| q.reverse());
+            }
+            (_, PairCanonical::R2Only) => quals[1].reverse(),
+            (_, PairCanonical::Off) => (),
+        }
+    }
+
+    /// Phred+33 qualities of each of a record's mates aggregated over its
+    /// cluster, oriented like the record
+    fn aggregated_quals(&self, key: &RecordKey) -> Option<Vec<Vec<u8>>> {
+        let mut quals = self.find_cluster(key)?.quals_opt.clone()?;
+        if key.is_revcomp {
+            self.flip_quals(&mut quals);
+        }
+        for qual in quals.iter_mut() {
+            qual.iter_mut().for_each(|q| *q += 33);
+        }
+        Some(quals)
+    }
+
+    /// The qualities of a single record's cluster aggregated into the
+    /// record's orientation, if qualities are aggregated and it was inserted
+    pub fn single_quals<R: fastx::Record>(&self, record: &R, use_revcomp: bool) -> Option<Vec<u8>> {
+        let key = self.single_key(record.seq(), use_revcomp, false);
+        self.aggregated_quals(&key)?.pop()
+    }
+
+    fn single_key(&self, seq: &[u8], use_revcomp: bool, merged: bool) -> RecordKey {
+        if use_revcomp {
+            self.oriented_single_key::<Revcomp>(seq, merged)
+        } else {
+            self.oriented_single_key::<Forward>(seq, merged)
+        }
+    }
+
+    fn oriented_single_key<O: Orientation>(&self, seq: &[u8], merged: bool) -> RecordKey {
+        self.ambiguity_key(&[seq], |seqs| {
+            self.canonical_single_key::<O>(seqs[0], merged)
+        })
+    }
+
+    /// The key of a record's sequences with their ambiguity codes handled
+    /// as set, `key` canonicalizes and hashes the sequences
+    fn ambiguity_key<F: Fn(&[&[u8]]) -> RecordKey>(&self, seqs: &[&[u8]], key: F) -> RecordKey {
+        if self.options.ambiguity == Ambiguity::Keep
+            || !seqs.iter().any(|seq| iupac::has_ambiguous(seq))
+        {
+            return key(seqs);
+        }
+        if self.options.ambiguity == Ambiguity::Match {
+            let existing_opt = iupac::expansions(seqs, iupac::MAX_EXPANSIONS)
+                .into_iter()
+                .flatten()
+                .map(|expanded| key(&expanded.iter().map(Vec::as_slice).collect::<Vec<_>>()))
+                .find(|expanded_key| self.find_cluster(expanded_key).is_some());
+            if let Some(existing) = existing_opt {
+                return existing;
+            }
+        }
+        let normalized = seqs
+            .iter()
+            .map(|seq| iupac::normalize(seq))
+            .collect::<Vec<_>>();
+        key(&normalized.iter().map(Vec::as_slice).collect::<Vec<_>>())
+    }
+
+    fn canonical_single_key<O: Orientation>(&self, seq: &[u8], merged: bool) -> RecordKey {
+        if !O::USE_REVCOMP {
+            return self.record_key(&[seq], merged, false); // Use original sequence
+        }
+
+        // determine the canonical sequence (either original or reverse complement)
+        let rev_seq = revcomp(seq);
+        if seq <= rev_seq.as_slice() {
+            self.record_key(&[seq], merged, false) // Original sequence is canonical
+        } else {
+            self.record_key(&[rev_seq.as_slice()], merged, true) // Reverse complement is canonical
+        }
+    }
+
+    /// The hash identifying a single record's cluster, as used by `insert_single`
+    pub fn single_hash<R: fastx::Record>(&self, record: &R, use_revcomp: bool) -> u128 {
+        self.single_key(record.seq(), use_revcomp, false).seq_hash
+    }
+
+    fn find_cluster(&self, key: &RecordKey) -> Option<&Cluster> {
+        match self.probe(key) {
+            (slot, true) => self.cluster_map.get(&slot),
+            (_, false) => None,
+        }
+    }
+
+    /// The cluster a single record belongs to, if it has been inserted
+    pub fn single_cluster<R: fastx::Record>(
+        &self,
+        record: &R,
+        use_revcomp: bool,
+    ) -> Option<&Cluster> {
+        self.find_cluster(&self.single_key(record.seq(), use_revcomp, false))
+    }
+
+    /// Whether a read represents its cluster, the record written out for it
+    pub fn is_single_representative<R: fastx::Record>(
+        &self,
+        record: &R,
+        use_revcomp: bool,
+    ) -> bool {
+        self.single_cluster(record, use_revcomp)
+            .is_some_and(|cluster| cluster.id == self.record_id(record.id()))
+    }
+
+    /// Whether a sequence, inserted as a single read, would join an existing cluster
+    pub fn contains_sequence(&self, seq: &[u8], use_revcomp: bool) -> bool {
+        self.find_cluster(&self.single_key(seq, use_revcomp, false))
+            .is_some()
+    }
+
+    /// The cluster stored under a hash from `single_hash`, `pair_hash`, or
+    /// the cluster output's `cluster hash` column
+    pub fn find(&self, hash: u128) -> Option<&Cluster> {
+        self.cluster_map.get(&hash)
+    }
+
+    /// Each cluster's (representative read id, size) in the order the
+    /// clusters were created. Empty in window and sorted modes, which forget
+    /// clusters as they go.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> + '_ {
+        self.cluster_order
+            .iter()
+            .filter_map(move |cluster_hash| self.cluster_map.get(cluster_hash))
+            .map(|cluster| (cluster.id(), cluster.size()))
+    }
+
+    pub fn insert_pair<R: fastx::Record>(
+        &mut self,
+        record: &PairedRecord<R>,
+        use_revcomp: bool,
+    ) -> Result<bool, csv::Error> {
+        if use_revcomp {
+            self.insert_pair_oriented(record, Revcomp)
+        } else {
+            self.insert_pair_oriented(record, Forward)
+        }
+    }
+
+    /// `insert_pair` with the orientation fixed for the run, for callers
+    /// that insert every pair the same way
+    pub fn insert_pair_oriented<O: Orientation, R: fastx::Record>(
+        &mut self,
+        record: &PairedRecord<R>,
+        _orientation: O,
+    ) -> Result<bool, csv::Error> {
+        if self.is_filtered(&[record.r1(), record.r2()]) {
+            return Ok(false);
+        }
+        let id = self.record_id(record.id());
+        self.check_duplicate_id(&id)?;
+        let seqs = [record.r1().seq(), record.r2().seq()];
+        self.check_composition(&seqs)?;
+        if self.skip_empty(&id, &seqs)? || self.is_n_filtered(&seqs) {
+            return Ok(false);
+        }
+        self.check_seqs(&id, &seqs);
+        if self.r1_keys_opt.is_some() {
+            let r1_hash = self
+                .oriented_single_key::<O>(record.r1().seq(), false)
+                .seq_hash;
+            if let Some(r1_keys) = self.r1_keys_opt.as_mut() {
+                r1_keys.insert(r1_hash);
+            }
+        }
+        if let Some(prefix_length) = self.options.prefix_length_opt {
+            if record.r1().seq().len() < prefix_length || record.r2().seq().len() < prefix_length {
+                self.short_mate_pairs += 1;
+            }
+        }
+        let mut key = self.oriented_pair_key::<O, R>(record);
+        if self.options.quality_aggregation_opt.is_some() && !self.is_merged(record) {
+            key.quals_opt = self.canonical_quals(&[record.r1(), record.r2()], key.is_revcomp);
+        }
+        let score = self.representative_score(&[record.r1(), record.r2()]);
+        self.insert_record(key, id, &seqs, score)
+    }
+
+    fn is_merged<R: fastx::Record>(&self, record: &PairedRecord<R>) -> bool {
+        self.options.merge_overlaps
+            && overlap::merge_overlap(record.r1().seq(), record.r2().seq()).is_some()
+    }
+
+    /// The qualities of a read pair's cluster aggregated into the pair's
+    /// orientation, if qualities are aggregated and it was inserted unmerged
+    pub fn pair_quals<R: fastx::Record>(
+        &self,
+        record: &PairedRecord<R>,
+        use_revcomp: bool,
+    ) -> Option<(Vec<u8>, Vec<u8>)> {
+        if self.is_merged(record) {
+            return None;
+        }
+        let mut quals = self.aggregated_quals(&self.pair_key(record, use_revcomp))?;
+        let r2 = quals.pop()?;
+        Some((quals.pop()?, r2))
+    }
+
+    /// The hash identifying a read pair's cluster, as used by `insert_pair`
+    pub fn pair_hash<R: fastx::Record>(&self, record: &PairedRecord<R>, use_revcomp: bool) -> u128 {
+        self.pair_key(record, use_revcomp).seq_hash
+    }
+
+    /// The cluster a read pair belongs to, if it has been inserted
+    pub fn pair_cluster<R: fastx::Record>(
+        &self,
+        record: &PairedRecord<R>,
+        use_revcomp: bool,
+    ) -> Option<&Cluster> {
+        self.find_cluster(&self.pair_key(record, use_revcomp))
+    }
+
+    /// Whether a pair represents its cluster, the pair written out for it
+    pub fn is_pair_representative<R: fastx::Record>(
+        &self,
+        record: &PairedRecord<R>,
+        use_revcomp: bool,
+    ) -> bool {
+        self.pair_cluster(record, use_revcomp)
+            .is_some_and(|cluster| cluster.id == self.record_id(record.id()))
+    }
+
+    fn pair_key<R: fastx::Record>(&self, record: &PairedRecord<R>, use_revcomp: bool) -> RecordKey {
+        if use_revcomp {
+            self.oriented_pair_key::<Revcomp, R>(record)
+        } else {
+            self.oriented_pair_key::<Forward, R>(record)
+        }
+    }
+
+    fn oriented_pair_key<O: Orientation, R: fastx::Record>(
+        &self,
+        record: &PairedRecord<R>,
+    ) -> RecordKey {
+        self.ambiguity_key(&[record.r1().seq(), record.r2().seq()], |seqs| {
+            self.canonical_pair_key::<O>(seqs[0], seqs[1])
+        })
+    }
+
+    fn canonical_pair_key<O: Orientation>(&self, r1_seq: &[u8], r2_seq: &[u8]) -> RecordKey {
+        if self.options.merge_overlaps {
+            if let Some(fragment) = overlap::merge_overlap(r1_seq, r2_seq) {
+                return if O::USE_REVCOMP && self.options.pair_canonical != PairCanonical::Off {
+                    self.canonical_single_key::<Revcomp>(&fragment, true)
+                } else {
+                    self.canonical_single_key::<Forward>(&fragment, true)
+                };
+            }
+        }
+
+        // Reverse complement sequences only if use_revcomp is set
+        if !O::USE_REVCOMP {
+            return self.record_key(&[r1_seq, r2_seq], false, false); // Use original sequences
+        }
+
+        let r1_revcomp;
+        let r2_revcomp;
+
+        let (r1_canon, r2_canon, is_revcomp) = match self.options.pair_canonical {
+            PairCanonical::Off => (r1_seq, r2_seq, false),
+            PairCanonical::R2Only => {
+                r2_revcomp = revcomp(r2_seq);
+                if r2_revcomp.as_slice() < r2_seq {
+                    (r1_seq, r2_revcomp.as_slice(), true) // Reverse complement r2 is canonical
+                } else {
+                    (r1_seq, r2_seq, false)
+                }
+            }
+            pair_canonical => {
+                r1_revcomp = revcomp(r1_seq);
+                r2_revcomp = revcomp(r2_seq);
+                let flipped = if pair_canonical == PairCanonical::Fragment {
+                    (r2_revcomp.as_slice(), r1_revcomp.as_slice())
+                } else {
+                    (r1_revcomp.as_slice(), r2_revcomp.as_slice())
+                };
+
+                // Choose the lexicographically smaller pair (canonical), or
+                // the larger for Lexmax
+                let flip = if pair_canonical == PairCanonical::Lexmax {
+                    (r1_seq, r2_seq) < flipped
+                } else {
+                    flipped < (r1_seq, r2_seq)
+                };
+                if flip {
+                    (flipped.0, flipped.1, true) // Flipped pair is canonical
+                } else {
+                    (r1_seq, r2_seq, false) // Original sequences are canonical
+                }
+            }
+        };
+
+        self.record_key(&[r1_canon, r2_canon], false, is_revcomp)
+    }
+
+    /// Inserts a set of any number of mates, keyed on every mate's sequence.
+    /// With `use_revcomp` the set's mates are reverse complemented in place
+    /// like `PairCanonical::Lexmin`, unless pair canonicalization is off.
+    pub fn insert_mates<R: fastx::Record>(
+        &mut self,
+        record: &MateSet<R>,
+        use_revcomp: bool,
+    ) -> Result<bool, csv::Error> {
+        let mates = record
+            .mates()
+            .iter()
+            .map(|mate| mate as &dyn fastx::Record)
+            .collect::<Vec<_>>();
+        if self.is_filtered(&mates) {
+            return Ok(false);
+        }
+        let id = self.record_id(record.id());
+        self.check_duplicate_id(&id)?;
+        let seqs = record
+            .mates()
+            .iter()
+            .map(|mate| mate.seq())
+            .collect::<Vec<_>>();
+        self.check_composition(&seqs)?;
+        if self.skip_empty(&id, &seqs)? || self.is_n_filtered(&seqs) {
+            return Ok(false);
+        }
+        self.check_seqs(&id, &seqs);
+        let score = self.representative_score(&mates);
+        let key = self.mates_key(record, use_revcomp);
+        self.insert_record(key, id, &seqs, score)
+    }
+
+    /// The hash identifying a mate set's cluster, as used by `insert_mates`
+    pub fn mates_hash<R: fastx::Record>(&self, record: &MateSet<R>, use_revcomp: bool) -> u128 {
+        self.mates_key(record, use_revcomp).seq_hash
+    }
+
+    fn mates_key<R: fastx::Record>(&self, record: &MateSet<R>, use_revcomp: bool) -> RecordKey {
+        let seqs = record
+            .mates()
+            .iter()
+            .map(|mate| mate.seq())
+            .collect::<Vec<_>>();
+        self.ambiguity_key(&seqs, |seqs| self.canonical_mates_key(seqs, use_revcomp))
+    }
+
+    fn canonical_mates_key(&self, seqs: &[&[u8]], use_revcomp: bool) -> RecordKey {
+        if !use_revcomp || self.options.pair_canonical == PairCanonical::Off {
+            return self.record_key(seqs, false, false);
+        }
+        let flipped = seqs.iter().map(|seq| revcomp(*seq)).collect::<Vec<_>>();
+        let flipped = flipped.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        if flipped.as_slice() < seqs {
+            self.record_key(&flipped, false, true)
+        } else {
+            self.record_key(seqs, false, false)
+        }
+    }
+
+    pub fn unique_records(&self) -> u64 {
+        self.unique_records
+    }
+
+    pub fn duplicate_records(&self) -> u64 {
+        self.total_records - self.unique_records()
+    }
+
+    pub fn total_records(&self) -> u64 {
+        self.total_records
+    }
+
+    pub fn cluster_size_histogram(&self) -> BTreeMap<u64, u64> {
+        let mut histogram = self.evicted_size_histogram.clone();
+        for cluster in self.cluster_map.values() {
+            *histogram.entry(cluster.size).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    pub fn summary(&self, use_revcomp: bool) -> DedupSummary {
+        let duplication_rate =
+            summary::duplication_rate(self.duplicate_records(), self.total_records);
+        DedupSummary {
+            total_records: self.total_records,
+            unique_records: self.unique_records(),
+            duplicate_records: self.duplicate_records(),
+            duplication_rate,
+            cluster_size_histogram: self.cluster_size_histogram(),
+            short_mate_pairs: self.short_mate_pairs,
+            prefix_divergent_clusters: self.prefix_divergent_clusters,
+            warning_counts: self
+                .warnings
+                .counts()
+                .iter()
+                .map(|(kind, count)| (kind.as_str().to_owned(), *count))
+                .collect(),
+            input_files: Vec::new(),
+            output_files: Vec::new(),
+            orphan_records: None,
+            unique_orphan_records: None,
+            single_records: None,
+            unique_single_records: None,
+            paired_duplicate_single_records: None,
+            pair_counts: None,
+            filtered_records: self.filtered_records,
+            empty_records: self.empty_records,
+            n_filtered_records: self.n_filtered_records,
+            ambiguous_records: self.ambiguous_records,
+            length_bins: self.length_bins(),
+            gzip_members: Vec::new(),
+            unique_estimate: self.unique_estimator_opt.as_ref().map(|unique_estimator| {
+                let estimate = unique_estimator.estimate();
+                let margin = 1.96 * unique_estimator.standard_error() * estimate;
+                UniqueEstimate {
+                    estimate: estimate.round() as u64,
+                    lower_bound: (estimate - margin).floor() as u64,
+                    upper_bound: (estimate + margin).ceil() as u64,
+                }
+            }),
+            exact_duplicate_records: self
+                .exact_ids_opt
+                .as_ref()
+                .map(|_| self.exact_duplicate_records),
+            prefix_duplicate_records: self
+                .exact_ids_opt
+                .as_ref()
+                .map(|_| self.duplicate_records() - self.exact_duplicate_records),
+            parameters: DedupParameters {
+                prefix_length: self.options.prefix_length_opt,
+                auto_prefix: false,
+                use_revcomp,
+                merge_overlaps: self.options.merge_overlaps,
+                two_tier: self.options.two_tier,
+                seed: self.options.seed,
+                strip_read_suffix: self.options.strip_read_suffix,
+                window: self.options.window_opt,
+                assume_sorted: self.options.assume_sorted,
+                pair_canonical: self.options.pair_canonical.to_string(),
+                short_mate: self.options.short_mate.to_string(),
+                hash_region: self.options.hash_region.to_string(),
+                key: self.options.key.to_string(),
+                max_mismatches: self.options.max_mismatches_opt,
+                allow_orphans: false,
+                filter_expr: None,
+                id_filter: None,
+                id_exclude: None,
+                trim_to: None,
+                quality_trim: None,
+                strict: false,
+                lenient: false,
+                empty_reads: self.options.empty_reads.to_string(),
+                on_duplicate_ids: self.options.duplicate_ids.to_string(),
+                representative: self.options.representative.to_string(),
+                max_n_fraction: self.options.max_n_fraction_opt,
+                ambiguity: self.options.ambiguity.to_string(),
+                length_bins: self.options.length_bins.clone(),
+                quality_aggregation: self
+                    .options
+                    .quality_aggregation_opt
+                    .map(|aggregation| aggregation.to_string()),
+                kmer_size: match self.options.key {
+                    KeyMode::Sequence => None,
+                    KeyMode::KmerSet => Some(self.options.kmer_size),
+                },
+                compare_region: self
+                    .options
+                    .compare_region_opt
+                    .map(|region| region.to_string()),
+                key_salted: self.options.key_salt_opt.is_some(),
+                gz_members: None,
+                fix_legacy_quality: false,
+                double_hash: self.options.double_hash,
+            },
+            status: if self.cancelled {
+                RunStatus::Cancelled
+            } else if self.deadline_reached {
+                RunStatus::TimeLimited
+            } else {
+                RunStatus::Ok
+            },
+            error: None,
+            failed_at_record: None,
+        }
+    }
+
+    pub fn write_sizes<R: std::io::Write>(
+        &self,
+        csv_writer: &mut csv::Writer<R>,
+    ) -> Result<(), csv::Error> {
+        if !self.options.omit_cluster_header {
+            csv_writer.write_record(size_columns(&self.options))?;
+        }
+        for cluster_hash in self.cluster_order.iter() {
+            // guaranteed to be present
+            let cluster = self.cluster_map.get(cluster_hash).unwrap();
+            let mut row = vec![cluster.id.clone(), cluster.size.to_string()];
+            if self.options.size_revcomp_counts {
+                row.push(cluster.revcomp_members.to_string());
+            }
+            if self.options.cluster_hash {
+                row.push(format_hash(cluster.hash));
+            }
+            csv_writer.write_record(row)?;
+        }
+        Ok(())
+    }
+
+    /// Metadata describing the cluster output, with the run's parameters
+    pub fn cluster_output_meta(&self, parameters: &DedupParameters) -> ClusterOutputMeta {
+        self.output_meta("clusters", cluster_columns(&self.options), parameters)
+    }
+
+    /// Metadata describing the cluster size output written by `write_sizes`
+    pub fn size_output_meta(&self, parameters: &DedupParameters) -> ClusterOutputMeta {
+        self.output_meta("cluster sizes", size_columns(&self.options), parameters)
+    }
+
+    fn output_meta(
+        &self,
+        output: &str,
+        columns: Vec<&str>,
+        parameters: &DedupParameters,
+    ) -> ClusterOutputMeta {
+        let delimiter = self.options.cluster_delimiter_opt.unwrap_or(b',');
+        ClusterOutputMeta {
+            schema_version: summary::CLUSTER_SCHEMA_VERSION,
+            output: output.to_owned(),
+            columns: columns.into_iter().map(str::to_owned).collect(),
+            delimiter: (delimiter as char).to_string(),
+            header: !self.options.omit_cluster_header,
+            hash_algorithm: HASH_ALGORITHM.to_owned(),
+            parameters: parameters.clone(),
+        }
+    }
+
+    /// Drops records the filter rejects before they're clustered, a pair
+    /// is kept only if both mates are
+    pub fn set_filter<F: RecordFilter + 'static>(&mut self, filter: F) {
+        self.filter_opt = Some(Box::new(filter));
+    }
+
+    /// Sets when reading should stop, records read before it are kept
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline_opt = Some(deadline);
+    }
+
+    /// Whether the deadline has passed, so no more records should be read
+    ///
+    /// Once it has, the run's summary is marked as time limited.
+    pub fn past_deadline(&mut self) -> bool {
+        if !self.deadline_reached {
+            self.deadline_reached = self
+                .deadline_opt
+                .is_some_and(|deadline| Instant::now() >= deadline);
+        }
+        self.deadline_reached
+    }
+
+    /// Keeps the hash of every inserted pair's r1 keyed as a single read, so
+    /// single reads can be deduplicated against the pairs
+    pub fn track_r1_keys(&mut self) {
+        self.r1_keys_opt = Some(HashSet::new());
+    }
+
+    /// The r1 hashes kept since `track_r1_keys`
+    pub fn take_r1_keys(&mut self) -> HashSet<u128> {
+        self.r1_keys_opt.take().unwrap_or_default()
+    }
+
+    /// Makes single reads keyed like one of a pair's r1s duplicates of that
+    /// pair, they're counted but not clustered
+    pub fn set_paired_keys(&mut self, paired_keys: HashSet<u128>) {
+        self.paired_keys = paired_keys;
+    }
+
+    /// Single reads dropped as duplicates of a pair's r1
+    pub fn paired_duplicate_records(&self) -> u64 {
+        self.paired_duplicate_records
+    }
+
+    /// Counts a read whose other mate was missing or invalid, before it's
+    /// inserted as an orphan
+    pub fn count_orphan(&mut self, mate: Mate) {
+        self.mate_orphan_records[mate as usize] += 1;
+    }
+
+    /// Orphans that came from `mate`'s input, whether or not they were kept
+    pub fn mate_orphan_records(&self, mate: Mate) -> u64 {
+        self.mate_orphan_records[mate as usize]
+    }
+
+    /// Lets a run be cancelled through a clone of `token`
+    pub fn set_cancellation(&mut self, token: cancel::CancellationToken) {
+        self.cancellation_opt = Some(token);
+    }
+
+    /// Whether the run was cancelled or its deadline has passed, so no more
+    /// records should be read
+    ///
+    /// Once it has been, the run's summary is marked as cancelled.
+    pub fn should_stop(&mut self) -> bool {
+        if !self.cancelled {
+            self.cancelled = self
+                .cancellation_opt
+                .as_ref()
+                .is_some_and(cancel::CancellationToken::is_cancelled);
+        }
+        self.cancelled || self.past_deadline()
+    }
+
+    fn is_filtered(&mut self, records: &[&dyn fastx::Record]) -> bool {
+        let filtered = match self.filter_opt.as_ref() {
+            Some(filter) => !records.iter().all(|record| filter.keep(*record)),
+            None => false,
+        };
+        if filtered {
+            self.filtered_records += 1;
+        }
+        filtered
+    }
+
+    pub fn filtered_records(&self) -> u64 {
+        self.filtered_records
+    }
+
+    /// Whether a record is dropped for a sequence with more than the maximum
+    /// fraction of `N` bases
+    fn is_n_filtered(&mut self, seqs: &[&[u8]]) -> bool {
+        let n_filtered = match self.options.max_n_fraction_opt {
+            Some(max_n_fraction) => seqs
+                .iter()
+                .any(|seq| warnings::n_fraction(seq) > max_n_fraction),
+            None => false,
+        };
+        if n_filtered {
+            self.n_filtered_records += 1;
+        }
+        n_filtered
+    }
+
+    /// Checks a record's id wasn't seen before, failing instead of warning
+    /// if duplicate ids are errors
+    fn check_duplicate_id(&mut self, read_id: &str) -> Result<(), csv::Error> {
+        if self.options.duplicate_ids == DuplicateIds::Ignore
+            || self.seen_ids.insert(read_id.to_owned())
+        {
+            return Ok(());
+        }
+        match self.options.duplicate_ids {
+            DuplicateIds::Error => {
+                let message = format!("{} is the id of more than one record", read_id);
+                Err(io::Error::new(io::ErrorKind::InvalidData, message).into())
+            }
+            _ => {
+                self.warn(WarningKind::DuplicateId, read_id, || {
+                    "an earlier record has the same id".to_owned()
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// A record's score under the representative strategy, the bases in all
+    /// of its mates or their mean Phred score, 0 for FASTA records
+    fn representative_score<R: fastx::Record + ?Sized>(&self, records: &[&R]) -> f64 {
+        match self.options.representative {
+            Representative::First => 0.0,
+            Representative::Longest => records
+                .iter()
+                .map(|record| record.seq().len())
+                .sum::<usize>() as f64,
+            Representative::BestQuality => {
+                let (total, bases) = records
+                    .iter()
+                    .filter_map(|record| record.qual())
+                    .flatten()
+                    .fold((0u64, 0u64), |(total, bases), q| {
+                        (total + q.saturating_sub(33) as u64, bases + 1)
+                    });
+                if bases == 0 {
+                    0.0
+                } else {
+                    total as f64 / bases as f64
+                }
+            }
+        }
+    }
+
+    /// Whether a record with no bases in any of its sequences is skipped,
+    /// failing instead if empty reads are errors
+    fn skip_empty(&mut self, read_id: &str, seqs: &[&[u8]]) -> Result<bool, csv::Error> {
+        if !seqs.iter().all(|seq| seq.is_empty()) {
+            return Ok(false);
+        }
+        self.empty_records += 1;
+        match self.options.empty_reads {
+            EmptyReads::Error => {
+                let message = format!("{} has an empty sequence", read_id);
+                Err(io::Error::new(io::ErrorKind::InvalidData, message).into())
+            }
+            EmptyReads::Drop => {
+                self.warn(WarningKind::EmptyRead, read_id, || {
+                    "it has no bases".to_owned()
+                });
+                Ok(true)
+            }
+            EmptyReads::Keep => Ok(false),
+        }
+    }
+
+    /// Keeps the record offsets of each representative, from r1's and, for
+    /// pairs, r2's input
+    pub fn track_offsets(&mut self, record_offsets: Vec<fastx::RecordOffsets>) {
+        self.record_offsets = record_offsets;
+    }
+
+    fn current_offsets(&self) -> Option<[u64; 2]> {
+        let mut offsets = [0; 2];
+        for (offset, record_offsets) in offsets.iter_mut().zip(self.record_offsets.iter()) {
+            *offset = record_offsets.current();
+        }
+        if self.record_offsets.is_empty() {
+            None
+        } else {
+            Some(offsets)
+        }
+    }
+
+    /// Every representative's record offsets, in input order, `None` unless
+    /// offsets were tracked for all of them
+    pub fn representative_offsets(&self) -> Option<Vec<[u64; 2]>> {
+        let mut offsets = self
+            .cluster_order
+            .iter()
+            .filter_map(|cluster_hash| self.cluster_map.get(cluster_hash))
+            .map(|cluster| cluster.offsets_opt)
+            .collect::<Option<Vec<_>>>()?;
+        offsets.sort_unstable();
+        Some(offsets)
+    }
+
+    /// Sets the lane, and the read group written for it, of the records
+    /// inserted from now on
+    pub fn set_lane(&mut self, lane: usize, read_group_opt: Option<&str>) {
+        self.lane = lane;
+        self.read_group_opt = read_group_opt.map(str::to_owned);
+    }
+
+    /// Records, or pairs, read so far, whether they were clustered, filtered,
+    /// or skipped, like the summary's `processed_records` less orphans
+    pub fn records_read(&self) -> u64 {
+        let counts = self.warnings.counts();
+        self.total_records
+            + self.filtered_records
+            + self.n_filtered_records
+            + [WarningKind::InvalidRecord, WarningKind::EmptyRead]
+                .iter()
+                .filter_map(|kind| counts.get(kind))
+                .sum::<u64>()
+    }
+
+    /// (representative's lane, duplicate's lane) -> number of duplicates
+    pub fn lane_duplicates(&self) -> &BTreeMap<(usize, usize), u64> {
+        &self.lane_duplicates
+    }
+
+    /// The options records' keys depend on, which a loaded state must share,
+    /// by name with the values they're given on the command line
+    fn key_options(&self, use_revcomp: bool) -> Vec<(&'static str, String)> {
+        let options = &self.options;
+        let or_none = |value_opt: Option<String>| value_opt.unwrap_or_else(|| "none".to_owned());
+        vec![
+            (
+                "prefix-length",
+                or_none(options.prefix_length_opt.map(|n| n.to_string())),
+            ),
+            ("merge-overlaps", options.merge_overlaps.to_string()),
+            ("pair-canonical", options.pair_canonical.to_string()),
+            ("short-mate", options.short_mate.to_string()),
+            ("hash-region", options.hash_region.to_string()),
+            (
+                "compare-region",
+                or_none(options.compare_region_opt.as_ref().map(ToString::to_string)),
+            ),
+            ("key", options.key.to_string()),
+            ("kmer-size", options.kmer_size.to_string()),
+            (
+                "max-mismatches",
+                or_none(options.max_mismatches_opt.map(|n| n.to_string())),
+            ),
+            ("ambiguity", options.ambiguity.to_string()),
+            // the salt's hash, so state files don't reveal the salt
+            (
+                "key-salt",
+                or_none(options.key_salt_opt.as_ref().map(|key_salt| {
+                    let mut hasher = StableHasher::default();
+                    Hash::hash_slice(key_salt, &mut hasher);
+                    format!("{:016x}", hasher.finish())
+                })),
+            ),
+            ("double-hash", options.double_hash.to_string()),
+            ("reverse-complement", use_revcomp.to_string()),
+        ]
+    }
+
+    /// Writes every cluster's hash, size, and representative in a compact
+    /// binary format, read back with `load_state`
+    pub fn save_state<W: io::Write>(&self, writer: W, use_revcomp: bool) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        writer.write_all(STATE_MAGIC)?;
+        let key_options = self
+            .key_options(use_revcomp)
+            .iter()
+            .map(|(name, value)| format!("{}={}\n", name, value))
+            .collect::<String>();
+        write_bytes(&mut writer, key_options.as_bytes())?;
+        let clusters = self
+            .cluster_order
+            .iter()
+            .filter_map(|cluster_hash| self.cluster_map.get(cluster_hash))
+            .collect::<Vec<_>>();
+        write_u64(&mut writer, clusters.len() as u64)?;
+        for cluster in clusters {
+            write_u128(&mut writer, cluster.hash)?;
+            write_u64(&mut writer, cluster.full_hash)?;
+            write_u64(&mut writer, cluster.size)?;
+            write_u64(&mut writer, cluster.score.to_bits())?;
+            write_bytes(&mut writer, cluster.id.as_bytes())?;
+            // bit 0 flags compare bytes, bit 1 a reverse complemented representative
+            let revcomp_flag = if cluster.is_revcomp { 2 } else { 0 };
+            match cluster.compare_bytes_opt.as_ref() {
+                Some(compare_bytes) => {
+                    writer.write_all(&[1 | revcomp_flag])?;
+                    write_bytes(&mut writer, compare_bytes)?;
+                }
+                None => writer.write_all(&[revcomp_flag])?,
+            }
+            // only saved with double_hash, which the key options record
+            if let Some(check_hash) = cluster.check_hash_opt {
+                write_u64(&mut writer, check_hash)?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// Restores the clusters written by `save_state`, before any records are
+    /// inserted, so records matching them are duplicates, returning how many
+    /// were loaded
+    ///
+    /// The state must have been saved with the same key options. Loaded
+    /// clusters keep their sizes but not their qualities, members, or
+    /// revcomp member counts.
+    pub fn load_state<R: io::Read>(&mut self, reader: R, use_revcomp: bool) -> io::Result<u64> {
+        let mut reader = io::BufReader::new(reader);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != STATE_MAGIC {
+            return Err(invalid_state("not a cluster state file"));
+        }
+        let saved_options = String::from_utf8(read_bytes(&mut reader)?)
+            .map_err(|_| invalid_state("cluster state is corrupt"))?;
+        let saved_options = saved_options
+            .lines()
+            .map(|line| line.split_once('=').unwrap_or((line, "")))
+            .collect::<Vec<_>>();
+        let key_options = self.key_options(use_revcomp);
+        let same_names = saved_options.len() == key_options.len()
+            && saved_options
+                .iter()
+                .zip(key_options.iter())
+                .all(|((saved_name, _), (name, _))| saved_name == name);
+        if !same_names {
+            return Err(invalid_state(
+                "cluster state was saved with different key options",
+            ));
+        }
+        for ((_, saved_value), (name, value)) in saved_options.iter().zip(key_options.iter()) {
+            if saved_value != value {
+                let message = format!(
+                    "cluster state was saved with {} {}, but this run uses {}",
+                    name, saved_value, value
+                );
+                return Err(invalid_state(&message));
+            }
+        }
+        let count = read_u64(&mut reader)?;
+        for _ in 0..count {
+            let hash = read_u128(&mut reader)?;
+            let full_hash = read_u64(&mut reader)?;
+            let size = read_u64(&mut reader)?;
+            let score = f64::from_bits(read_u64(&mut reader)?);
+            let id = String::from_utf8(read_bytes(&mut reader)?)
+                .map_err(|_| invalid_state("cluster state has a read id that isn't UTF-8"))?;
+            let mut flag = [0u8; 1];
+            reader.read_exact(&mut flag)?;
+            let compare_bytes_opt = match flag[0] & !2 {
+                0 => None,
+                1 => Some(read_bytes(&mut reader)?),
+                _ => return Err(invalid_state("cluster state is corrupt")),
+            };
+            let is_revcomp = flag[0] & 2 != 0;
+            let check_hash_opt = if self.options.double_hash {
+                Some(read_u64(&mut reader)?)
+            } else {
+                None
+            };
+            if let (Some(band_index), Some(compare_bytes)) =
+                (self.band_index_opt.as_mut(), compare_bytes_opt.as_ref())
+            {
+                band_index.insert(compare_bytes, hash);
+            }
+            if let Some(exact_ids) = self.exact_ids_opt.as_mut() {
+                exact_ids.entry(full_hash).or_insert_with(|| id.clone());
+            }
+            let members_opt = if self.options.keep_members {
+                Some(Vec::new())
+            } else {
+                None
+            };
+            let cluster = Cluster {
+                id,
+                size,
+                revcomp_members: 0,
+                hash,
+                full_hash,
+                is_revcomp,
+                score,
+                last_seen: 0,
+                compare_bytes_opt,
+                check_hash_opt,
+                lane: 0,
+                quals_opt: None,
+                has_prefix_duplicate: false,
+                members_opt,
+                offsets_opt: None,
+            };
+            if self.cluster_map.insert(hash, cluster).is_none() {
+                self.cluster_order.push(hash);
+            }
+        }
+        Ok(count)
+    }
+
+    /// Writes a matrix of how many duplicates from each lane, by column,
+    /// joined clusters whose representative came from each lane, by row.
+    /// The diagonal counts duplication within a lane.
+    pub fn write_lane_matrix<R: std::io::Write>(
+        &self,
+        csv_writer: &mut csv::Writer<R>,
+        lanes: &[&str],
+    ) -> Result<(), csv::Error> {
+        let mut header = vec!["representative lane"];
+        header.extend_from_slice(lanes);
+        csv_writer.write_record(header)?;
+        for (representative_lane, name) in lanes.iter().enumerate() {
+            let mut row = vec![name.to_string()];
+            for duplicate_lane in 0..lanes.len() {
+                let key = (representative_lane, duplicate_lane);
+                let count = self.lane_duplicates.get(&key).copied().unwrap_or(0);
+                row.push(count.to_string());
+            }
+            csv_writer.write_record(row)?;
+        }
+        Ok(())
+    }
+
+    pub fn on_duplicate<F: FnMut(&str, &str, bool) + 'static>(&mut self, callback: F) {
+        self.on_duplicate = Some(Box::new(callback));
+    }
+
+    /// Also writes a (representative, member, weight) edge for every duplicate,
+    /// ready to load into graph tools
+    pub fn write_edges(&mut self, edges_output: T) -> Result<(), csv::Error> {
+        let mut edges_csv_writer = csv::Writer::from_writer(edges_output);
+        edges_csv_writer.write_record(["source", "target", "weight"])?;
+        self.edges_csv_writer = Some(edges_csv_writer);
+        Ok(())
+    }
+
+    /// Also writes the full sequences of a sample of duplicates and their
+    /// representatives side by side, to check the keys aren't collapsing
+    /// reads that differ
+    ///
+    /// Clusters are sampled with probability `fraction`, seeded by the
+    /// options' seed, and every duplicate in a sampled cluster is written.
+    /// Mates are joined with `+`.
+    pub fn write_audit_sample(&mut self, audit_output: T, fraction: f64) -> Result<(), csv::Error> {
+        let mut audit_csv_writer = csv::Writer::from_writer(audit_output);
+        audit_csv_writer.write_record([
+            "representative read id",
+            "read id",
+            "duplicate type",
+            "representative sequence",
+            "sequence",
+        ])?;
+        self.audit_csv_writer = Some(audit_csv_writer);
+        self.audit_fraction = fraction;
+        Ok(())
+    }
+
+    /// Whether the cluster in a slot is in the audit sample
+    fn is_audited(&self, slot: u128) -> bool {
+        if self.audit_csv_writer.is_none() {
+            return false;
+        }
+        // hashed stably so the sample is the same on every build and machine
+        let mut hasher = StableHasher::default();
+        (self.options.seed, slot).hash(&mut hasher);
+        (hasher.finish() as f64) < self.audit_fraction * u64::MAX as f64
+    }
+
+    /// Also writes every record's canonical key, the hash its cluster is found
+    /// by, so runs can be compared record by record across versions and machines
+    pub fn write_key_dump(&mut self, key_dump_output: T) -> Result<(), csv::Error> {
+        let mut key_dump_csv_writer = csv::Writer::from_writer(key_dump_output);
+        key_dump_csv_writer.write_record(["read id", "canonical key"])?;
+        self.key_dump_csv_writer = Some(key_dump_csv_writer);
+        Ok(())
+    }
+
+    /// Also writes a (read id, representative read id) row for every read,
+    /// representatives mapping to themselves, to reassign counts by read
+    pub fn write_id_map(&mut self, id_map_output: T) -> Result<(), csv::Error> {
+        let mut id_map_csv_writer = csv::Writer::from_writer(id_map_output);
+        id_map_csv_writer.write_record(["read id", "representative read id"])?;
+        self.id_map_csv_writer = Some(id_map_csv_writer);
+        Ok(())
+    }
+
+    pub fn from_writer(
+        cluster_output_opt: Option<T>,
+        prefix_length_opt: Option<usize>,
+        capacity: usize,
+    ) -> Result<Self, csv::Error> {
+        let options = ClusterOptions {
+            prefix_length_opt,
+            ..ClusterOptions::default()
+        };
+        Clusters::from_writer_with_options(cluster_output_opt, options, capacity)
+    }
+
+    pub fn from_writer_with_options(
+        cluster_output_opt: Option<T>,
+        options: ClusterOptions,
+        capacity: usize,
+    ) -> Result<Self, csv::Error> {
+        let write_header = !options.omit_cluster_header;
+        Clusters::with_cluster_output(cluster_output_opt, options, capacity, write_header)
+    }
+
+    fn with_cluster_output(
+        cluster_output_opt: Option<T>,
+        options: ClusterOptions,
+        capacity: usize,
+        write_header: bool,
+    ) -> Result<Self, csv::Error> {
+        let delimiter = options.cluster_delimiter_opt.unwrap_or(b',');
+        let mut builder = csv::WriterBuilder::new();
+        builder.delimiter(delimiter);
+        if options.cluster_flush == ClusterFlush::OnExit {
+            builder.buffer_capacity(ON_EXIT_BUFFER_CAPACITY);
+        }
+        let cluster_csv_writer_opt = cluster_output_opt.map(|output| builder.from_writer(output));
+        let cluster_map = HashMap::with_capacity(capacity);
+        let cluster_order = Vec::with_capacity(capacity);
+        let cluster_csv_writer = match cluster_csv_writer_opt {
+            Some(mut cluster_csv_writer) if write_header => {
+                cluster_csv_writer.write_record(cluster_columns(&options))?;
+                Some(cluster_csv_writer)
+            }
+            cluster_csv_writer_opt => cluster_csv_writer_opt,
+        };
+        let band_index_opt = options.max_mismatches_opt.map(lsh::BandIndex::new);
+        let unique_estimator_opt = if options.estimate_unique {
+            Some(HyperLogLog::default())
+        } else {
+            None
+        };
+        let length_bin_counts = if options.length_bins.is_empty() {
+            Vec::new()
+        } else {
+            vec![(0, 0); options.length_bins.len() + 1]
+        };
+        let exact_ids_opt = if options.two_tier {
+            Some(HashMap::with_capacity(capacity))
+        } else {
+            None
+        };
+        Ok(Clusters {
+            cluster_map,
+            cluster_order,
+            cluster_csv_writer,
+            total_records: 0,
+            unique_records: 0,
+            options,
+            window: VecDeque::new(),
+            evicted_size_histogram: BTreeMap::new(),
+            exact_ids_opt,
+            exact_duplicate_records: 0,
+            short_mate_pairs: 0,
+            prefix_divergent_clusters: 0,
+            on_duplicate: None,
+            edges_csv_writer: None,
+            audit_csv_writer: None,
+            audit_fraction: 0.0,
+            audit_seqs: HashMap::new(),
+            key_dump_csv_writer: None,
+            id_map_csv_writer: None,
+            warnings: Warnings::default(),
+            on_warning: None,
+            band_index_opt,
+            filter_opt: None,
+            filtered_records: 0,
+            empty_records: 0,
+            n_filtered_records: 0,
+            seen_ids: HashSet::new(),
+            ambiguous_records: 0,
+            length_bin_counts,
+            unique_estimator_opt,
+            record_offsets: Vec::new(),
+            lane: 0,
+            read_group_opt: None,
+            lane_duplicates: BTreeMap::new(),
+            deadline_opt: None,
+            deadline_reached: false,
+            cancellation_opt: None,
+            cancelled: false,
+            r1_keys_opt: None,
+            paired_keys: HashSet::new(),
+            paired_duplicate_records: 0,
+            mate_orphan_records: [0, 0],
+            chunk_bytes: 0,
+            chunk_non_acgtn: 0,
+        })
+    }
+}
+
+impl Clusters<File> {
+    pub fn from_file<P: AsRef<std::path::Path>>(
+        cluster_output_path_opt: Option<P>,
+        prefix_length_opt: Option<usize>,
+        capacity: usize,
+    ) -> Result<Self, csv::Error> {
+        let options = ClusterOptions {
+            prefix_length_opt,
+            ..ClusterOptions::default()
+        };
+        Clusters::from_file_with_options(cluster_output_path_opt, options, capacity)
+    }
+
+    pub fn from_file_with_options<P: AsRef<std::path::Path>>(
+        cluster_output_path_opt: Option<P>,
+        options: ClusterOptions,
+        capacity: usize,
+    ) -> Result<Self, csv::Error> {
+        let cluster_output_opt = match cluster_output_path_opt {
+            Some(path) if options.append_cluster_output => {
+                let file = OpenOptions::new().append(true).create(true).open(&path)?;
+                // an existing file already has its header, which must be this run's
+                let is_empty = file.metadata()?.len() == 0;
+                if !is_empty && !options.omit_cluster_header {
+                    check_appended_header(path.as_ref(), &options)?;
+                }
+                Some((file, is_empty))
+            }
+            Some(path) => Some((File::create(path)?, true)),
+            None => None,
+        };
+        let write_header = !options.omit_cluster_header
+            && cluster_output_opt
+                .as_ref()
+                .is_none_or(|(_, is_empty)| *is_empty);
+        let cluster_output_opt = cluster_output_opt.map(|(file, _)| file);
+        Clusters::with_cluster_output(cluster_output_opt, options, capacity, write_header)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_support::random_seq;
+    use bio::io::{fasta, fastq};
+    use std::cell::RefCell;
+    use std::convert::TryFrom;
+    use std::io::Cursor;
+    use std::rc::Rc;
+    use std::str;
+
+    #[test]
+    fn test_insert_single() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        {
+            let mut clusters =
+                Clusters::from_writer(Some(&mut cluster_output), Some(10), 200).expect("asdasd");
+            let seq = random_seq(20);
+            let record_1 = fasta::Record::with_attrs("id_a", None, &seq);
+            clusters
+                .insert_single(&record_1, false)
+                .expect("don't break");
+            let record_2 = fasta::Record::with_attrs("id_b", None, &seq);
+            clusters
+                .insert_single(&record_2, false)
+                .expect("don't break");
+            assert_eq!(clusters.duplicate_records(), 1);
+            assert_eq!(clusters.unique_records(), 1);
+            assert_eq!(clusters.total_records(), 2);
+        }
+        assert_eq!(
+            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+            "representative read id,read id,duplicate type,revcomp\nid_a,id_a,representative,false\nid_a,id_b,exact,false\n"
+        );
+    }
+
+    #[test]
+    fn test_insert_pair() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        {
+            let mut clusters =
+                Clusters::from_writer(Some(&mut cluster_output), Some(10), 200).expect("asdasd");
+            let seq_r1 = random_seq(20);
+            let seq_r2 = random_seq(20);
+            let record_1_r1 = fasta::Record::with_attrs("id_a", None, &seq_r1);
+            let record_1_r2 = fasta::Record::with_attrs("id_a", None, &seq_r2);
+            clusters
+                .insert_pair(
+                    &PairedRecord::try_from((record_1_r1, record_1_r2)).unwrap(),
+                    false,
+                )
+                .expect("don't break");
+            let record_2_r1 = fasta::Record::with_attrs("id_b", None, &seq_r1);
+            let record_2_r2 = fasta::Record::with_attrs("id_b", None, &seq_r2);
+            clusters
+                .insert_pair(
+                    &PairedRecord::try_from((record_2_r1, record_2_r2)).unwrap(),
+                    false,
+                )
+                .expect("don't break");
+            assert_eq!(clusters.duplicate_records(), 1);
+            assert_eq!(clusters.unique_records(), 1);
+            assert_eq!(clusters.total_records(), 2);
+        }
+        assert_eq!(
+            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+            "representative read id,read id,duplicate type,revcomp\nid_a,id_a,representative,false\nid_a,id_b,exact,false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_cluster_sizes() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        let mut cluster_sizes_writer = Cursor::new(Vec::new());
+        {
+            let mut cluster_sizes_output = csv::Writer::from_writer(&mut cluster_sizes_writer);
+            let mut clusters =
+                Clusters::from_writer(Some(&mut cluster_output), Some(10), 200).expect("asdasd");
+            let seq1 = random_seq(20);
+            let record_1 = fasta::Record::with_attrs("id_a", None, &seq1);
+            clusters
+                .insert_single(&record_1, false)
+                .expect("don't break");
+            let record_2 = fasta::Record::with_attrs("id_b", None, &seq1);
+            clusters
+                .insert_single(&record_2, false)
+                .expect("don't break");
+            let seq2 = random_seq(20);
+            let record_3 = fasta::Record::with_attrs("id_c", None, &seq2);
+            clusters
+                .insert_single(&record_3, false)
+                .expect("don't break");
+            clusters
+                .write_sizes(&mut cluster_sizes_output)
+                .expect("don't break");
+        }
+        let cluster_sizes_output_inner = cluster_sizes_writer.into_inner();
+        let cluster_sizes = str::from_utf8(cluster_sizes_output_inner.as_slice()).unwrap();
+        assert_eq!(
+            cluster_sizes,
+            "representative read id,cluster size\nid_a,2\nid_c,1\n"
+        );
+    }
+
+    #[test]
+    fn test_write_cluster_sizes_hash() {
+        let mut cluster_sizes_writer = Cursor::new(Vec::new());
+        let options = ClusterOptions {
+            cluster_hash: true,
+            ..ClusterOptions::default()
+        };
+        let hash = {
+            let mut cluster_sizes_output = csv::Writer::from_writer(&mut cluster_sizes_writer);
+            let mut clusters = Clusters::from_writer_with_options(None::<File>, options, 200)
+                .expect("don't break");
+            let record = fasta::Record::with_attrs("id_a", None, &random_seq(20));
+            clusters.insert_single(&record, false).expect("don't break");
+            clusters
+                .write_sizes(&mut cluster_sizes_output)
+                .expect("don't break");
+            clusters.single_cluster(&record, false).unwrap().hash()
+        };
+        assert_eq!(
+            str::from_utf8(cluster_sizes_writer.get_ref()).expect("don't break"),
+            format!(
+                "representative read id,cluster size,cluster hash\nid_a,1,{}\n",
+                format_hash(hash)
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_cluster_sizes_revcomp_counts() {
+        let mut cluster_sizes_writer = Cursor::new(Vec::new());
+        let options = ClusterOptions {
+            size_revcomp_counts: true,
+            ..ClusterOptions::default()
+        };
+        {
+            let mut cluster_sizes_output = csv::Writer::from_writer(&mut cluster_sizes_writer);
+            let mut clusters = Clusters::from_writer_with_options(None::<File>, options, 200)
+                .expect("don't break");
+            let records = [
+                ("id_a", b"AACCGGTTAC".to_vec()),
+                ("id_b", b"AACCGGTTAC".to_vec()),
+                ("id_c", revcomp(b"AACCGGTTAC")),
+                ("id_d", revcomp(b"AACCGGTTAC")),
+                ("id_e", b"AAGGTTACCA".to_vec()),
+                // larger than its reverse complement, so stored reverse complemented
+                ("id_f", b"TTGGCCAAGT".to_vec()),
+                ("id_g", revcomp(b"TTGGCCAAGT")),
+            ];
+            for (id, seq) in records.iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, true).expect("don't break");
+            }
+            let record = fasta::Record::with_attrs("id_a", None, b"AACCGGTTAC");
+            let cluster = clusters.single_cluster(&record, true).unwrap();
+            assert_eq!(cluster.revcomp_members(), 2);
+            clusters
+                .write_sizes(&mut cluster_sizes_output)
+                .expect("don't break");
+        }
+        assert_eq!(
+            str::from_utf8(cluster_sizes_writer.get_ref()).expect("don't break"),
+            "representative read id,cluster size,revcomp members\nid_a,4,2\nid_e,1,0\nid_f,2,1\n"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut clusters =
+            Clusters::from_writer(None::<Cursor<Vec<u8>>>, Some(10), 200).expect("don't break");
+        let seq1 = random_seq(20);
+        let seq2 = random_seq(20);
+        let records = [
+            ("id_a", &seq1),
+            ("id_b", &seq1),
+            ("id_c", &seq2),
+            ("id_d", &seq1),
+        ];
+        for (id, seq) in records.iter() {
+            let record = fasta::Record::with_attrs(id, None, seq);
+            clusters.insert_single(&record, false).expect("don't break");
+        }
+        let summary = clusters.summary(false);
+        assert_eq!(summary.total_records, 4);
+        assert_eq!(summary.unique_records, 2);
+        assert_eq!(summary.duplicate_records, 2);
+        assert_eq!(summary.duplication_rate, 0.5);
+        assert_eq!(
+            summary
+                .cluster_size_histogram
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(1, 1), (3, 1)]
+        );
+        assert_eq!(summary.parameters.prefix_length, Some(10));
+        assert!(!summary.parameters.use_revcomp);
+    }
+
+    #[test]
+    fn test_on_duplicate() {
+        let duplicates = Rc::new(RefCell::new(Vec::new()));
+        let mut clusters =
+            Clusters::from_writer(None::<Cursor<Vec<u8>>>, None, 200).expect("don't break");
+        {
+            let duplicates = duplicates.clone();
+            clusters.on_duplicate(move |rep_id, dup_id, is_revcomp| {
+                duplicates
+                    .borrow_mut()
+                    .push((rep_id.to_owned(), dup_id.to_owned(), is_revcomp));
+            });
+        }
+        let seq = b"AACCGGTTAC".to_vec();
+        let rc_seq = revcomp(&seq);
+        let records = [("id_a", &seq), ("id_b", &seq), ("id_c", &rc_seq)];
+        for (id, seq) in records.iter() {
+            let record = fasta::Record::with_attrs(id, None, seq);
+            clusters.insert_single(&record, true).expect("don't break");
+        }
+        assert_eq!(
+            *duplicates.borrow(),
+            vec![
+                ("id_a".to_owned(), "id_b".to_owned(), false),
+                ("id_a".to_owned(), "id_c".to_owned(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_pair_merge_overlaps() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        {
+            let options = ClusterOptions {
+                merge_overlaps: true,
+                ..ClusterOptions::default()
+            };
+            let mut clusters =
+                Clusters::from_writer_with_options(Some(&mut cluster_output), options, 200)
+                    .expect("don't break");
+            let fragment = b"ACGGTCATTGCAAGTCCTAGGATCCATGACGTTAGCAGTCATGCATAGGCTTACGATGCA";
+            let seq_r1 = &fragment[..40];
+            // the second pair's r2 was trimmed by a few more bases
+            let pairs = [
+                ("id_a", revcomp(&fragment[20..])),
+                ("id_b", revcomp(&fragment[25..])),
+            ];
+            for (id, seq_r2) in pairs.iter() {
+                let record_r1 = fasta::Record::with_attrs(id, None, seq_r1);
+                let record_r2 = fasta::Record::with_attrs(id, None, seq_r2);
+                clusters
+                    .insert_pair(
+                        &PairedRecord::try_from((record_r1, record_r2)).unwrap(),
+                        false,
+                    )
+                    .expect("don't break");
+            }
+            assert_eq!(clusters.duplicate_records(), 1);
+        }
+        assert_eq!(
+            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+            "representative read id,read id,duplicate type,revcomp\nid_a,id_a,representative,false\nid_a,id_b,exact,false\n"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_types() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        {
+            let mut clusters = Clusters::from_writer(Some(&mut cluster_output), Some(8), 200)
+                .expect("don't break");
+            let records = [
+                ("id_a", b"AACCGGTTAC".to_vec()),
+                ("id_b", b"AACCGGTTAC".to_vec()),
+                ("id_c", b"AACCGGTTGG".to_vec()),
+                ("id_d", revcomp(b"AACCGGTTAC")),
+            ];
+            for (id, seq) in records.iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, true).expect("don't break");
+            }
+        }
+        assert_eq!(
+            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+            "representative read id,read id,duplicate type,revcomp\n\
+             id_a,id_a,representative,false\n\
+             id_a,id_b,exact,false\n\
+             id_a,id_c,prefix,false\n\
+             id_a,id_d,revcomp,true\n"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_types_revcomp_representative() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        let duplicates = Rc::new(RefCell::new(Vec::new()));
+        {
+            let options = ClusterOptions {
+                legacy_rc_suffix: true,
+                ..ClusterOptions::default()
+            };
+            let mut clusters =
+                Clusters::from_writer_with_options(Some(&mut cluster_output), options, 200)
+                    .expect("don't break");
+            {
+                let duplicates = duplicates.clone();
+                clusters.on_duplicate(move |_, dup_id, is_revcomp| {
+                    duplicates
+                        .borrow_mut()
+                        .push((dup_id.to_owned(), is_revcomp));
+                });
+            }
+            // larger than its reverse complement, so stored reverse complemented
+            let seq = b"TTGGCCAAGT".to_vec();
+            let records = [
+                ("id_a", seq.clone()),
+                ("id_b", seq.clone()),
+                ("id_c", revcomp(&seq)),
+            ];
+            for (id, seq) in records.iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, true).expect("don't break");
+            }
+        }
+        assert_eq!(
+            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+            "representative read id,read id,duplicate type\n\
+             id_a,id_a,representative\n\
+             id_a,id_b,exact\n\
+             id_a,id_c (rc),revcomp\n"
+        );
+        assert_eq!(
+            *duplicates.borrow(),
+            vec![("id_b".to_owned(), false), ("id_c".to_owned(), true)]
+        );
+    }
+
+    #[test]
+    fn test_revcomp_column_revcomp_representative() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        {
+            let mut clusters =
+                Clusters::from_writer(Some(&mut cluster_output), None, 200).expect("don't break");
+            // larger than its reverse complement, so stored reverse complemented
+            let seq = b"TTGGCCAAGT".to_vec();
+            let records = [
+                ("id_a", seq.clone()),
+                ("id_b", revcomp(&seq)),
+                ("id_c", seq),
+            ];
+            for (id, seq) in records.iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, true).expect("don't break");
+            }
+        }
+        assert_eq!(
+            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+            "representative read id,read id,duplicate type,revcomp\n\
+             id_a,id_a,representative,false\n\
+             id_a,id_b,revcomp,true\n\
+             id_a,id_c,exact,false\n"
+        );
+    }
+
+    #[test]
+    fn test_legacy_rc_suffix() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        {
+            let options = ClusterOptions {
+                legacy_rc_suffix: true,
+                ..ClusterOptions::default()
+            };
+            let mut clusters =
+                Clusters::from_writer_with_options(Some(&mut cluster_output), options, 200)
+                    .expect("don't break");
+            let records = [
+                ("id_a", b"AACCGGTTAC".to_vec()),
+                ("id_b", revcomp(b"AACCGGTTAC")),
+            ];
+            for (id, seq) in records.iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, true).expect("don't break");
+            }
+        }
+        assert_eq!(
+            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+            "representative read id,read id,duplicate type\n\
+             id_a,id_a,representative\n\
+             id_a,id_b (rc),revcomp\n"
+        );
+    }
+
+    #[test]
+    fn test_two_tier() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        let summary = {
+            let options = ClusterOptions {
+                prefix_length_opt: Some(8),
+                two_tier: true,
+                ..ClusterOptions::default()
+            };
+            let mut clusters =
+                Clusters::from_writer_with_options(Some(&mut cluster_output), options, 200)
+                    .expect("don't break");
+            let records = [
+                ("id_a", b"AACCGGTTAC"),
+                ("id_b", b"AACCGGTTGG"),
+                ("id_c", b"AACCGGTTGG"),
+                ("id_d", b"AACCGGTTAC"),
+            ];
+            for (id, seq) in records.iter() {
+                let record = fasta::Record::with_attrs(id, None, *seq);
+                clusters.insert_single(&record, false).expect("don't break");
+            }
+            clusters.summary(false)
+        };
+        assert_eq!(summary.exact_duplicate_records, Some(2));
+        assert_eq!(summary.prefix_duplicate_records, Some(1));
+        assert_eq!(
+            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+            "representative read id,read id,duplicate type,revcomp,exact representative read id\n\
+             id_a,id_a,representative,false,id_a\n\
+             id_a,id_b,prefix,false,id_b\n\
+             id_a,id_c,exact,false,id_b\n\
+             id_a,id_d,exact,false,id_a\n"
+        );
+    }
+
+    #[test]
+    fn test_cluster_output_meta() {
+        let option_sets = [
+            ClusterOptions::default(),
+            ClusterOptions {
+                two_tier: true,
+                cluster_hash: true,
+                read_groups: true,
+                cluster_delimiter_opt: Some(b'\t'),
+                ..ClusterOptions::default()
+            },
+            ClusterOptions {
+                legacy_cluster_output: true,
+                ..ClusterOptions::default()
+            },
+        ];
+        for options in option_sets.iter() {
+            let mut cluster_output = Cursor::new(Vec::new());
+            let meta = {
+                let clusters = Clusters::from_writer_with_options(
+                    Some(&mut cluster_output),
+                    options.clone(),
+                    200,
+                )
+                .expect("don't break");
+                clusters.cluster_output_meta(&clusters.summary(false).parameters)
+            };
+            // the columns described are the ones in the header
+            let header = format!("{}\n", meta.columns.join(&meta.delimiter));
+            assert_eq!(
+                str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+                header
+            );
+            assert_eq!(meta.schema_version, summary::CLUSTER_SCHEMA_VERSION);
+            assert!(meta.header);
+        }
+    }
+
+    fn pair_clusters(pair_canonical: PairCanonical, pairs: &[(&[u8], &[u8])]) -> u64 {
+        let options = ClusterOptions {
+            pair_canonical,
+            ..ClusterOptions::default()
+        };
+        let mut clusters =
+            Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                .expect("don't break");
+        for (i, (seq_r1, seq_r2)) in pairs.iter().enumerate() {
+            let id = format!("id_{}", i);
+            let record_r1 = fasta::Record::with_attrs(&id, None, seq_r1);
+            let record_r2 = fasta::Record::with_attrs(&id, None, seq_r2);
+            clusters
+                .insert_pair(
+                    &PairedRecord::try_from((record_r1, record_r2)).unwrap(),
+                    true,
+                )
+                .expect("don't break");
+        }
+        clusters.unique_records()
+    }
+
+    #[test]
+    fn test_pair_canonical() {
+        let a = random_seq(20);
+        let b = random_seq(20);
+        let (rc_a, rc_b) = (revcomp(&a), revcomp(&b));
+        let mate_flipped: [(&[u8], &[u8]); 2] = [(&a, &b), (&rc_a, &rc_b)];
+        let strand_flipped: [(&[u8], &[u8]); 2] = [(&a, &b), (&rc_b, &rc_a)];
+
+        assert_eq!(pair_clusters(PairCanonical::Lexmin, &mate_flipped), 1);
+        assert_eq!(pair_clusters(PairCanonical::Lexmin, &strand_flipped), 2);
+        assert_eq!(pair_clusters(PairCanonical::Lexmax, &mate_flipped), 1);
+        assert_eq!(pair_clusters(PairCanonical::Lexmax, &strand_flipped), 2);
+        assert_eq!(pair_clusters(PairCanonical::Fragment, &strand_flipped), 1);
+        assert_eq!(pair_clusters(PairCanonical::Fragment, &mate_flipped), 2);
+        assert_eq!(pair_clusters(PairCanonical::R2Only, &mate_flipped), 2);
+        assert_eq!(pair_clusters(PairCanonical::Off, &mate_flipped), 2);
+        assert_eq!(pair_clusters(PairCanonical::Off, &strand_flipped), 2);
+    }
+
+    #[test]
+    fn test_insert_oriented() {
+        let seq = random_seq(20);
+        let rc_seq = revcomp(&seq);
+        let pair = |id: &str, r1: &[u8], r2: &[u8]| {
+            PairedRecord::try_from((
+                fasta::Record::with_attrs(id, None, r1),
+                fasta::Record::with_attrs(id, None, r2),
+            ))
+            .unwrap()
+        };
+        let mut forward = Clusters::from_writer(None::<Cursor<Vec<u8>>>, None, 200).unwrap();
+        let mut reverse = Clusters::from_writer(None::<Cursor<Vec<u8>>>, None, 200).unwrap();
+        for (id, r1, r2) in [("id_a", &seq, &seq), ("id_b", &rc_seq, &rc_seq)].iter() {
+            let record = fasta::Record::with_attrs(id, None, r1);
+            forward
+                .insert_single_oriented(&record, Forward)
+                .expect("don't break");
+            reverse
+                .insert_single_oriented(&record, Revcomp)
+                .expect("don't break");
+            forward
+                .insert_pair_oriented(&pair(id, r1, r2), Forward)
+                .expect("don't break");
+            reverse
+                .insert_pair_oriented(&pair(id, r1, r2), Revcomp)
+                .expect("don't break");
+        }
+        assert_eq!(forward.unique_records(), 4);
+        assert_eq!(reverse.unique_records(), 2);
+        // the same keys as choosing the orientation per record
+        let record = fasta::Record::with_attrs("id_b", None, &rc_seq);
+        assert!(forward.single_cluster(&record, false).is_some());
+        assert_eq!(
+            reverse.single_cluster(&record, true).map(Cluster::id),
+            Some("id_a")
+        );
+        let record = pair("id_b", &rc_seq, &rc_seq);
+        assert!(forward.pair_cluster(&record, false).is_some());
+        assert_eq!(
+            reverse.pair_cluster(&record, true).map(Cluster::id),
+            Some("id_a")
+        );
+    }
+
+    #[test]
+    fn test_pair_canonical_r2_only() {
+        let a = random_seq(20);
+        let b = random_seq(20);
+        let rc_b = revcomp(&b);
+        let r2_flipped: [(&[u8], &[u8]); 2] = [(&a, &b), (&a, &rc_b)];
+        assert_eq!(pair_clusters(PairCanonical::R2Only, &r2_flipped), 1);
+        assert_eq!(pair_clusters(PairCanonical::Lexmin, &r2_flipped), 2);
+    }
+
+    #[test]
+    fn test_strip_read_suffix() {
+        let mut cluster_output = Cursor::new(Vec::new());
+        {
+            let options = ClusterOptions {
+                strip_read_suffix: true,
+                ..ClusterOptions::default()
+            };
+            let mut clusters =
+                Clusters::from_writer_with_options(Some(&mut cluster_output), options, 200)
+                    .expect("don't break");
+            let seq = random_seq(20);
+            for id in ["id_a/1", "id_b/1"].iter() {
+                let record = fasta::Record::with_attrs(id, None, &seq);
+                clusters.insert_single(&record, false).expect("don't break");
+            }
+        }
+        assert_eq!(
+            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
+            "representative read id,read id,duplicate type,revcomp\n\
+             id_a,id_a,representative,false\n\
+             id_a,id_b,exact,false\n"
+        );
+    }
+
+    #[test]
+    fn test_window() {
+        let options = ClusterOptions {
+            window_opt: Some(2),
+            ..ClusterOptions::default()
+        };
+        let mut clusters =
+            Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                .expect("don't break");
+        let seq1 = random_seq(20);
+        let seq2 = random_seq(20);
+        let seq3 = random_seq(20);
+        // seq1 is a duplicate within the window the first time but not after it falls out
+        let records = [
+            ("id_a", &seq1),
+            ("id_b", &seq1),
+            ("id_c", &seq2),
+            ("id_d", &seq3),
+            ("id_e", &seq1),
+        ];
+        let kept = records
+            .iter()
+            .map(|(id, seq)| {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, false).expect("don't break")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec![true, false, true, true, true]);
+        assert_eq!(clusters.unique_records(), 4);
+        assert_eq!(clusters.duplicate_records(), 1);
+        assert_eq!(
+            clusters
+                .cluster_size_histogram()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(1, 3), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn test_estimate_unique() {
+        let options = ClusterOptions {
+            window_opt: Some(2),
+            estimate_unique: true,
+            ..ClusterOptions::default()
+        };
+        let mut clusters =
+            Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                .expect("don't break");
+        let seqs = (0..50).map(|_| random_seq(20)).collect::<Vec<_>>();
+        // every sequence comes back after it has left the window
+        for (i, seq) in seqs.iter().chain(seqs.iter()).enumerate() {
+            let record = fasta::Record::with_attrs(&format!("id_{}", i), None, seq);
+            clusters.insert_single(&record, false).expect("don't break");
+        }
+        assert_eq!(clusters.unique_records(), 100);
+        let unique_estimate = clusters
+            .summary(false)
+            .unique_estimate
+            .expect("don't break");
+        // far from the 100 clusters made, not exact when two reads share a register
+        assert!((48..=52).contains(&unique_estimate.estimate));
+        assert!(unique_estimate.lower_bound < 50 && unique_estimate.upper_bound > 50);
+    }
+
+    #[test]
+    fn test_assume_sorted() {
+        let options = ClusterOptions {
+            assume_sorted: true,
+            ..ClusterOptions::default()
+        };
+        let mut clusters =
+            Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                .expect("don't break");
+        let seq1 = b"AAAACCCCGGGGTTTT".to_vec();
+        let seq2 = b"CCCCGGGGTTTTAAAA".to_vec();
+        let records = [
+            ("id_a", &seq1),
+            ("id_b", &seq1),
+            ("id_c", &seq1),
+            ("id_d", &seq2),
+            ("id_e", &seq2),
+        ];
+        let kept = records
+            .iter()
+            .map(|(id, seq)| {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, false).expect("don't break")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec![true, false, false, true, false]);
+        assert_eq!(clusters.cluster_map.len(), 1);
+        assert_eq!(
+            clusters
+                .cluster_size_histogram()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(2, 1), (3, 1)]
+        );
+    }
+
+    #[test]
+    fn test_write_edges() {
+        let mut edges_output = Cursor::new(Vec::new());
+        {
+            let mut clusters = Clusters::from_writer(None, None, 200).expect("don't break");
+            clusters
+                .write_edges(&mut edges_output)
+                .expect("don't break");
+            let seq1 = random_seq(20);
+            let seq2 = random_seq(20);
+            let records = [
+                ("id_a", &seq1),
+                ("id_b", &seq2),
+                ("id_c", &seq1),
+                ("id_d", &seq1),
+            ];
+            for (id, seq) in records.iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, false).expect("don't break");
+            }
+        }
+        assert_eq!(
+            str::from_utf8(edges_output.get_ref()).expect("don't break"),
+            "source,target,weight\nid_a,id_c,1\nid_a,id_d,1\n"
+        );
+    }
+
+    #[test]
+    fn test_write_audit_sample() {
+        let seq1 = random_seq(20);
+        let seq2 = [&seq1[..10], &random_seq(10)[..]].concat();
+        let audit = |fraction: f64| {
+            let mut audit_output = Cursor::new(Vec::new());
+            {
+                let options = ClusterOptions {
+                    prefix_length_opt: Some(10),
+                    ..ClusterOptions::default()
+                };
+                let mut clusters =
+                    Clusters::from_writer_with_options(None, options, 200).expect("don't break");
+                clusters
+                    .write_audit_sample(&mut audit_output, fraction)
+                    .expect("don't break");
+                for (id, seq) in [("id_a", &seq1), ("id_b", &seq2)].iter() {
+                    let record = fasta::Record::with_attrs(id, None, seq);
+                    clusters.insert_single(&record, false).expect("don't break");
+                }
+            }
+            String::from_utf8(audit_output.into_inner()).expect("don't break")
+        };
+        let header =
+            "representative read id,read id,duplicate type,representative sequence,sequence\n";
+        assert_eq!(
+            audit(1.0),
+            format!(
+                "{}id_a,id_b,prefix,{},{}\n",
+                header,
+                str::from_utf8(&seq1).unwrap(),
+                str::from_utf8(&seq2).unwrap()
+            )
+        );
+        assert_eq!(audit(1e-12), header);
+    }
+
+    #[test]
+    fn test_write_id_map() {
+        let mut id_map_output = Cursor::new(Vec::new());
+        {
+            let mut clusters = Clusters::from_writer(None, None, 200).expect("don't break");
+            clusters
+                .write_id_map(&mut id_map_output)
+                .expect("don't break");
+            let seq1 = random_seq(20);
+            let seq2 = random_seq(20);
+            let records = [("id_a", &seq1), ("id_b", &seq2), ("id_c", &seq1)];
+            for (id, seq) in records.iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, false).expect("don't break");
             }
         }
+        assert_eq!(
+            str::from_utf8(id_map_output.get_ref()).expect("don't break"),
+            "read id,representative read id\nid_a,id_a\nid_b,id_b\nid_c,id_a\n"
+        );
     }
 
+    #[test]
+    fn test_quality_aggregation() {
+        for (aggregation, expected) in [
+            (QualityAggregation::Max, b"IIII+".to_vec()),
+            (QualityAggregation::Sum, b"qqqq+".to_vec()),
+        ]
+        .iter()
+        {
+            let options = ClusterOptions {
+                quality_aggregation_opt: Some(*aggregation),
+                ..ClusterOptions::default()
+            };
+            let mut clusters =
+                Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                    .expect("don't break");
+            let representative = fastq::Record::with_attrs("id_a", None, b"AACGT", b"IIII!");
+            // the reverse complement's qualities line up from its other end
+            let revcomp_duplicate = fastq::Record::with_attrs("id_b", None, b"ACGTT", b"+IIII");
+            clusters
+                .insert_single(&representative, true)
+                .expect("don't break");
+            clusters
+                .insert_single(&revcomp_duplicate, true)
+                .expect("don't break");
+            assert_eq!(
+                clusters.single_quals(&representative, true).as_ref(),
+                Some(expected)
+            );
+            let reversed = expected.iter().rev().copied().collect::<Vec<_>>();
+            assert_eq!(
+                clusters.single_quals(&revcomp_duplicate, true),
+                Some(reversed)
+            );
+        }
+    }
 
-    fn get_prefix<'a, 'b>(&'a self, seq: &'b [u8]) -> &'b [u8] {
-        let seq_length = seq.len();
-        let prefix_length = self
-            .prefix_length_opt
-            .map(|prefix_length| cmp::min(prefix_length, seq_length))
-            .unwrap_or(seq_length);
-        &seq[..prefix_length]
+    #[test]
+    fn test_pair_quality_aggregation() {
+        let options = ClusterOptions {
+            quality_aggregation_opt: Some(QualityAggregation::Max),
+            ..ClusterOptions::default()
+        };
+        let mut clusters =
+            Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                .expect("don't break");
+        let pair = |id: &str, qual_r1: &[u8], qual_r2: &[u8]| {
+            PairedRecord::try_from((
+                fastq::Record::with_attrs(id, None, b"ACGT", qual_r1),
+                fastq::Record::with_attrs(id, None, b"TTGA", qual_r2),
+            ))
+            .expect("don't break")
+        };
+        let representative = pair("id_a", b"I!I!", b"!!!!");
+        clusters
+            .insert_pair(&representative, false)
+            .expect("don't break");
+        clusters
+            .insert_pair(&pair("id_b", b"!I!!", b"!!5!"), false)
+            .expect("don't break");
+        assert_eq!(
+            clusters.pair_quals(&representative, false),
+            Some((b"III!".to_vec(), b"!!5!".to_vec()))
+        );
     }
 
-    pub fn insert_single<R: fastx::Record>(&mut self, record: &R, use_revcomp: bool) -> Result<bool, csv::Error> {
-        let seq = record.seq();
-        let rev_seq;
-    
-        // determine the canonical sequence (either original or reverse complement)
-        let (canonical_seq, is_revcomp) = if use_revcomp {
-            rev_seq = revcomp(seq);
-            if seq <= rev_seq.as_slice() { 
-                (seq, false) // Original sequence is canonical
-            } else {
-                (rev_seq.as_slice(), true) // Reverse complement is canonical
+    #[test]
+    fn test_iter_and_lookup() {
+        let mut clusters =
+            Clusters::from_writer(None::<Cursor<Vec<u8>>>, None, 200).expect("don't break");
+        let seq_a = b"AACCGGTTAC".to_vec();
+        let seq_b = b"ACGTTTGCAG".to_vec();
+        for (id, seq) in [("id_a", &seq_a), ("id_b", &seq_b), ("id_c", &seq_a)].iter() {
+            let record = fasta::Record::with_attrs(id, None, seq);
+            clusters.insert_single(&record, false).expect("don't break");
+        }
+        assert_eq!(
+            clusters.iter().collect::<Vec<_>>(),
+            vec![("id_a", 2), ("id_b", 1)]
+        );
+        assert!(clusters.contains_sequence(&seq_b, false));
+        assert!(!clusters.contains_sequence(b"GTAACCGGTT", false));
+        // seq_a is its own canonical sequence so its reverse complement matches it
+        assert!(clusters.contains_sequence(b"GTAACCGGTT", true));
+        let record = fasta::Record::with_attrs("id_x", None, &seq_a);
+        let hash = clusters.single_hash(&record, false);
+        assert_eq!(clusters.find(hash).map(Cluster::id), Some("id_a"));
+        assert!(clusters.find(hash.wrapping_add(1)).is_none());
+    }
+
+    #[test]
+    fn test_key_salt() {
+        let seq_a = random_seq(20);
+        let seq_b = random_seq(20);
+        let hashes = |key_salt_opt: Option<Vec<u8>>| {
+            let options = ClusterOptions {
+                prefix_length_opt: Some(10),
+                key_salt_opt,
+                ..ClusterOptions::default()
+            };
+            let config = KeyConfig::from_options(&options, false);
+            let mut clusters =
+                Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                    .expect("don't break");
+            for (id, seq) in [("id_a", &seq_a), ("id_b", &seq_b), ("id_c", &seq_a)].iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, false).expect("don't break");
             }
-        } else {
-            (seq, false) // Use original sequence
+            assert_eq!(clusters.unique_records(), 2);
+            let record = fasta::Record::with_attrs("id_a", None, &seq_a);
+            let hash = clusters.single_hash(&record, false);
+            assert_eq!(key_for(&seq_a, &config), hash);
+            hash
         };
-    
-        // Compute hash for the canonical sequence
-        let mut seq_hasher = DefaultHasher::new();
-        Hash::hash_slice(self.get_prefix(canonical_seq), &mut seq_hasher);
-        let seq_hash = seq_hasher.finish();
-    
-        // Ensure `insert_record()` supports `is_revcomp`
-        self.insert_record(seq_hash, record.id().to_owned(), is_revcomp)
+        let unsalted = hashes(None);
+        let salted = hashes(Some(b"sample 1".to_vec()));
+        assert_ne!(unsalted, salted);
+        assert_ne!(salted, hashes(Some(b"sample 2".to_vec())));
+        assert_eq!(salted, hashes(Some(b"sample 1".to_vec())));
     }
 
-    pub fn insert_pair<R: fastx::Record>(
-        &mut self,
-        record: &PairedRecord<R>,
-        use_revcomp: bool,
-    ) -> Result<bool, csv::Error> {
-        let r1_seq = record.r1().seq();
-        let r2_seq = record.r2().seq();
-        
-        let r1_revcomp;
-        let r2_revcomp;
-        
-        // Reverse complement sequences only if use_revcomp is set
-        let (r1_canon, r2_canon, is_revcomp) = if use_revcomp {
-            r1_revcomp = revcomp(r1_seq);
-            r2_revcomp = revcomp(r2_seq);
-    
-            // Choose the lexicographically smaller pair (canonical)
-            if (r1_seq, r2_seq) < (r1_revcomp.as_slice(), r2_revcomp.as_slice()) {
-                (r1_revcomp.as_slice(), r2_revcomp.as_slice(), true) // Reverse complement pair is canonical
-            } else {
-                (r1_seq, r2_seq, false) // Original sequences are canonical
+    #[test]
+    fn test_double_hash() {
+        let seq_a = random_seq(20);
+        let seq_b = random_seq(20);
+        let records = [
+            fasta::Record::with_attrs("id_a", None, &seq_a),
+            fasta::Record::with_attrs("id_b", None, &seq_b),
+            fasta::Record::with_attrs("id_c", None, &seq_a),
+        ];
+        let options = ClusterOptions {
+            double_hash: true,
+            ..ClusterOptions::default()
+        };
+        let mut single = Clusters::from_writer_with_options(
+            None::<Cursor<Vec<u8>>>,
+            ClusterOptions::default(),
+            200,
+        )
+        .expect("don't break");
+        let mut clusters =
+            Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options.clone(), 200)
+                .expect("don't break");
+        for record in records.iter() {
+            single.insert_single(record, false).expect("don't break");
+            clusters.insert_single(record, false).expect("don't break");
+        }
+        assert_eq!(clusters.unique_records(), 2);
+        // the first hash is unchanged, so cluster hashes are too
+        assert_eq!(
+            clusters.single_hash(&records[0], false),
+            single.single_hash(&records[0], false)
+        );
+
+        // a record whose first hash collides isn't a duplicate unless its
+        // second does too
+        let key = clusters.record_key(&[&seq_a], false, false);
+        assert_eq!(clusters.probe(&key), (key.seq_hash, true));
+        let check_hash = key.check_hash_opt.expect("don't break");
+        let collision = RecordKey {
+            check_hash_opt: Some(check_hash ^ 1),
+            ..key
+        };
+        assert!(!clusters.probe(&collision).1);
+
+        let mut state = Vec::new();
+        clusters.save_state(&mut state, false).expect("don't break");
+        let mut loaded = Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+            .expect("don't break");
+        assert_eq!(
+            loaded.load_state(&state[..], false).expect("don't break"),
+            2
+        );
+        assert!(!loaded
+            .insert_single(&records[2], false)
+            .expect("don't break"));
+        let mut mismatched = Clusters::from_writer_with_options(
+            None::<Cursor<Vec<u8>>>,
+            ClusterOptions::default(),
+            200,
+        )
+        .expect("don't break");
+        assert!(mismatched.load_state(&state[..], false).is_err());
+    }
+
+    #[test]
+    fn test_key_for() {
+        let option_sets = [
+            ClusterOptions::default(),
+            ClusterOptions {
+                prefix_length_opt: Some(6),
+                ..ClusterOptions::default()
+            },
+            ClusterOptions {
+                key: KeyMode::KmerSet,
+                kmer_size: 3,
+                ..ClusterOptions::default()
+            },
+            ClusterOptions {
+                ambiguity: Ambiguity::Match,
+                ..ClusterOptions::default()
+            },
+        ];
+        // the ambiguous read comes first, with match it'd join a compatible cluster
+        let seqs: [&[u8]; 3] = [b"AACCRGTTAC", b"AACCGGTTAC", b"GTAACCGGTT"];
+        for options in option_sets.iter() {
+            for use_revcomp in [false, true].iter() {
+                let config = KeyConfig::from_options(options, *use_revcomp);
+                let mut clusters = Clusters::from_writer_with_options(
+                    None::<Cursor<Vec<u8>>>,
+                    options.clone(),
+                    200,
+                )
+                .expect("don't break");
+                for seq in seqs.iter() {
+                    let record = fasta::Record::with_attrs("id", None, seq);
+                    assert_eq!(
+                        key_for(seq, &config),
+                        clusters.single_hash(&record, *use_revcomp)
+                    );
+                    clusters
+                        .insert_single(&record, *use_revcomp)
+                        .expect("don't break");
+                }
             }
-        } else {
-            (r1_seq, r2_seq, false) // Use original sequences
+        }
+        let config = KeyConfig {
+            use_revcomp: true,
+            ..KeyConfig::default()
+        };
+        assert_eq!(
+            key_for(b"AACCGGTTAC", &config),
+            key_for(b"GTAACCGGTT", &config)
+        );
+        // SipHash-1-3-128 with zero keys of the salt then the bases, on any build
+        assert_eq!(
+            key_for(b"AACCGGTTAC", &config),
+            0x1e97_13d9_1e14_2cc6_48ac_d710_d50a_b8dd
+        );
+        let config = KeyConfig {
+            key_salt_opt: Some(vec![0x5f, 0x3a]),
+            ..KeyConfig::default()
         };
-    
-        let mut seq_hasher = DefaultHasher::new();
-        Hash::hash_slice(self.get_prefix(r1_canon), &mut seq_hasher);
-        Hash::hash(&0, &mut seq_hasher);
-        Hash::hash_slice(self.get_prefix(r2_canon), &mut seq_hasher);
-        let seq_hash = seq_hasher.finish();
-        
-        self.insert_record(seq_hash, record.id().to_owned(), is_revcomp)
+        assert_eq!(
+            key_for(b"AACCGGTTAC", &config),
+            0x4267_1393_e3df_8fcf_86d2_d3aa_80eb_5fb0
+        );
     }
 
-    pub fn unique_records(&self) -> u64 {
-        self.cluster_map.len() as u64
+    #[test]
+    fn test_keep_members() {
+        for keep_members in [true, false].iter() {
+            let options = ClusterOptions {
+                keep_members: *keep_members,
+                ..ClusterOptions::default()
+            };
+            let mut clusters =
+                Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                    .expect("don't break");
+            let seq_a = random_seq(20);
+            let seq_b = random_seq(20);
+            for (id, seq) in [("id_a", &seq_a), ("id_b", &seq_b), ("id_c", &seq_a)].iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, false).expect("don't break");
+            }
+            let record = fasta::Record::with_attrs("id_x", None, &seq_a);
+            let members = clusters
+                .single_cluster(&record, false)
+                .expect("don't break")
+                .members()
+                .map(<[String]>::to_vec);
+            let expected = vec!["id_a".to_owned(), "id_c".to_owned()];
+            assert_eq!(members, if *keep_members { Some(expected) } else { None });
+        }
     }
 
-    pub fn duplicate_records(&self) -> u64 {
-        self.total_records - self.unique_records()
+    #[test]
+    fn test_cluster_flush() {
+        let dir = tempfile::tempdir().expect("don't break");
+        let cluster_path = dir.path().join("cluster.csv");
+        let seq = random_seq(20);
+        for cluster_flush in [ClusterFlush::PerRecord, ClusterFlush::OnExit].iter() {
+            let options = ClusterOptions {
+                cluster_flush: *cluster_flush,
+                ..ClusterOptions::default()
+            };
+            let mut clusters = Clusters::from_file_with_options(Some(&cluster_path), options, 200)
+                .expect("don't break");
+            let record = fasta::Record::with_attrs("id_a", None, &seq);
+            clusters.insert_single(&record, false).expect("don't break");
+            let read = || std::fs::read_to_string(&cluster_path).expect("don't break");
+            let expected =
+                "representative read id,read id,duplicate type,revcomp\nid_a,id_a,representative,false\n";
+            if *cluster_flush == ClusterFlush::PerRecord {
+                assert_eq!(read(), expected);
+            } else {
+                assert_eq!(read(), "");
+                clusters.flush().expect("don't break");
+                assert_eq!(read(), expected);
+            }
+        }
+        dir.close().expect("don't break");
     }
 
-    pub fn total_records(&self) -> u64 {
-        self.total_records
+    #[test]
+    fn test_write_lane_matrix() {
+        let mut clusters =
+            Clusters::from_writer(None::<Cursor<Vec<u8>>>, None, 200).expect("don't break");
+        let seq1 = random_seq(20);
+        let seq2 = random_seq(20);
+        let lanes = [
+            vec![("id_a", &seq1), ("id_b", &seq1), ("id_c", &seq2)],
+            vec![("id_d", &seq1), ("id_e", &seq2), ("id_f", &seq2)],
+        ];
+        for (lane, records) in lanes.iter().enumerate() {
+            clusters.set_lane(lane, None);
+            for (id, seq) in records.iter() {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, false).expect("don't break");
+            }
+        }
+        let mut csv_writer = csv::Writer::from_writer(Vec::new());
+        clusters
+            .write_lane_matrix(&mut csv_writer, &["L1", "L2"])
+            .expect("don't break");
+        assert_eq!(
+            String::from_utf8(csv_writer.into_inner().expect("don't break")).expect("don't break"),
+            "representative lane,L1,L2\nL1,1,3\nL2,0,0\n"
+        );
     }
 
-    pub fn write_sizes<R: std::io::Write>(
-        &self,
-        csv_writer: &mut csv::Writer<R>,
-    ) -> Result<(), csv::Error> {
-        csv_writer.write_record(vec!["representative read id", "cluster size"])?;
-        for cluster_hash in self.cluster_order.iter() {
-            // guaranteed to be present
-            let cluster = self.cluster_map.get(cluster_hash).unwrap();
-            csv_writer.write_record(vec![&cluster.id, &cluster.size.to_string()])?;
+    #[test]
+    fn test_write_key_dump() {
+        let mut key_dump_output = Cursor::new(Vec::new());
+        {
+            let mut clusters = Clusters::from_writer(None, None, 200).expect("don't break");
+            clusters
+                .write_key_dump(&mut key_dump_output)
+                .expect("don't break");
+            let seq = b"AACCGGTTAC";
+            let record = fasta::Record::with_attrs("id_a", None, seq);
+            clusters.insert_single(&record, true).expect("don't break");
+            let record = fasta::Record::with_attrs("id_b", None, &revcomp(&seq[..]));
+            clusters.insert_single(&record, true).expect("don't break");
         }
-        Ok(())
+        // the same on every build and machine
+        assert_eq!(
+            str::from_utf8(key_dump_output.get_ref()).expect("don't break"),
+            "read id,canonical key\n\
+             id_a,1e9713d91e142cc648acd710d50ab8dd\n\
+             id_b,1e9713d91e142cc648acd710d50ab8dd\n"
+        );
     }
 
-    pub fn from_writer(
-        cluster_output_opt: Option<T>,
-        prefix_length_opt: Option<usize>,
-        capacity: usize,
-    ) -> Result<Self, csv::Error> {
-        let cluster_csv_writer_opt = cluster_output_opt.map(csv::Writer::from_writer);
-        let cluster_map = HashMap::with_capacity(capacity);
-        let cluster_order = Vec::with_capacity(capacity);
-        let cluster_csv_writer = cluster_csv_writer_opt
-            .map(|mut cluster_csv_writer| {
-                cluster_csv_writer
-                    .write_record(vec!["representative read id", "read id"])
-                    .map(|_| Some(cluster_csv_writer))
-            })
-            .unwrap_or(Ok(None))?;
-        Ok(Clusters {
-            cluster_map,
-            cluster_order,
-            cluster_csv_writer,
-            total_records: 0,
-            prefix_length_opt,
-        })
+    #[test]
+    fn test_warnings() {
+        let raised = Rc::new(RefCell::new(Vec::new()));
+        let mut clusters =
+            Clusters::from_writer(None::<Cursor<Vec<u8>>>, Some(4), 200).expect("don't break");
+        {
+            let raised = raised.clone();
+            clusters.on_warning(move |warning| {
+                raised
+                    .borrow_mut()
+                    .push((warning.kind, warning.read_id.clone()));
+            });
+        }
+        let records = [
+            ("id_a", &b"ACGT"[..]),
+            ("id_b", b"ACGTAC"),
+            ("id_c", b"NNN"),
+        ];
+        for (id, seq) in records.iter() {
+            let record = fasta::Record::with_attrs(id, None, seq);
+            clusters.insert_single(&record, false).expect("don't break");
+        }
+        clusters.skip_invalid("id_d", "bad record");
+        assert_eq!(
+            *raised.borrow(),
+            vec![
+                (WarningKind::PrefixTruncated, "id_b".to_owned()),
+                (WarningKind::NHeavy, "id_c".to_owned()),
+                (WarningKind::InvalidRecord, "id_d".to_owned()),
+            ]
+        );
+        assert_eq!(clusters.warnings().count(WarningKind::NHeavy), 1);
+        assert_eq!(
+            clusters.summary(false).warning_counts.get("N-heavy"),
+            Some(&1)
+        );
     }
+
+    #[test]
+    fn test_corrupt_input() {
+        let mut clusters =
+            Clusters::from_writer(None::<Cursor<Vec<u8>>>, None, 200).expect("don't break");
+        // RNA and ambiguity codes are well under the corrupt fraction
+        let seq = b"ACGUNRY-".repeat(warnings::COMPOSITION_CHUNK_BYTES / 8);
+        let record = fasta::Record::with_attrs("id_a", None, &seq);
+        clusters.insert_single(&record, false).expect("don't break");
+
+        let garbage = (b'!'..=b'@').cycle().take(1000).collect::<Vec<_>>();
+        for i in 0..4 {
+            let record = fasta::Record::with_attrs(&format!("id_{}", i), None, &garbage);
+            assert!(clusters.insert_single(&record, false).is_ok());
+        }
+        let record = fasta::Record::with_attrs("id_x", None, &garbage);
+        let error = clusters.insert_single(&record, false).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("input appears corrupt/binary at record 6"));
     }
 
-impl Clusters<File> {
-    pub fn from_file<P: AsRef<std::path::Path>>(
-        cluster_output_path_opt: Option<P>,
-        prefix_length_opt: Option<usize>,
-        capacity: usize,
-    ) -> Result<Self, csv::Error> {
-        cluster_output_path_opt
-            .map(|cluster_output_path| {
-                File::create(cluster_output_path).map(|cluster_output| Some(cluster_output))
-            })
-            .unwrap_or(Ok(None))
-            .map_err(csv::Error::from)
-            .and_then(|cluster_output| {
-                Clusters::from_writer(cluster_output, prefix_length_opt, capacity)
+    #[test]
+    fn test_cancellation() {
+        let mut clusters =
+            Clusters::from_writer(None::<Cursor<Vec<u8>>>, None, 200).expect("don't break");
+        let token = cancel::CancellationToken::new();
+        clusters.set_cancellation(token.clone());
+        assert!(!clusters.should_stop());
+        assert_eq!(clusters.summary(false).status, RunStatus::Ok);
+        token.cancel();
+        assert!(clusters.should_stop());
+        assert_eq!(clusters.summary(false).status, RunStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_paired_keys() {
+        let mut clusters =
+            Clusters::from_writer(None::<Cursor<Vec<u8>>>, None, 200).expect("don't break");
+        clusters.track_r1_keys();
+        let (seq_r1, seq_r2) = (random_seq(20), random_seq(20));
+        let r1 = fasta::Record::with_attrs("id_a", None, &seq_r1);
+        let r2 = fasta::Record::with_attrs("id_a", None, &seq_r2);
+        let pair = PairedRecord::try_from((r1, r2)).expect("don't break");
+        clusters.insert_pair(&pair, true).expect("don't break");
+
+        let mut single_clusters =
+            Clusters::from_writer(None::<Cursor<Vec<u8>>>, None, 200).expect("don't break");
+        single_clusters.set_paired_keys(clusters.take_r1_keys());
+        let singles = [
+            ("id_b", revcomp(&seq_r1)),
+            ("id_c", seq_r2.clone()),
+            ("id_d", seq_r2),
+        ];
+        let inserted = singles
+            .iter()
+            .map(|(id, seq)| {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                single_clusters
+                    .insert_single(&record, true)
+                    .expect("don't break")
             })
+            .collect::<Vec<_>>();
+        assert_eq!(inserted, vec![false, true, false]);
+        assert_eq!(single_clusters.paired_duplicate_records(), 1);
+        assert_eq!(single_clusters.total_records(), 3);
+        assert_eq!(single_clusters.unique_records(), 1);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[test]
+    fn test_prefix_divergent_clusters() {
+        let options = ClusterOptions {
+            prefix_length_opt: Some(4),
+            ..ClusterOptions::default()
+        };
+        let mut clusters =
+            Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                .expect("don't break");
+        let seqs: [&[u8]; 7] = [
+            b"AAAAC", b"AAAAG", b"AAAAT", b"CCCCA", b"CCCCT", b"GGGGA", b"GGGGA",
+        ];
+        for (i, seq) in seqs.iter().enumerate() {
+            let record = fasta::Record::with_attrs(&format!("id_{}", i), None, seq);
+            clusters.insert_single(&record, false).expect("don't break");
+        }
+        let summary = clusters.summary(false);
+        assert_eq!(summary.prefix_divergent_clusters, 2);
+        assert_eq!(summary.prefix_truncated_records(), 7);
+        assert!(summary.possible_over_collapse());
+    }
 
-    use bio::io::fasta;
-    use rand::Rng;
-    use std::convert::TryFrom;
-    use std::io::Cursor;
-    use std::str;
+    #[test]
+    fn test_short_mate() {
+        let long_r1 = b"ACGTACGTAC".to_vec();
+        let short_r2 = b"GGCC".to_vec();
+        let padded_r2 = b"GGCCNNNNNN".to_vec();
+        let insert_pairs = |short_mate: ShortMate| {
+            let options = ClusterOptions {
+                prefix_length_opt: Some(10),
+                short_mate,
+                ..ClusterOptions::default()
+            };
+            let mut clusters =
+                Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                    .expect("don't break");
+            let kept = [&short_r2, &padded_r2]
+                .iter()
+                .map(|seq_r2| {
+                    let record = PairedRecord::try_from((
+                        fasta::Record::with_attrs("id", None, &long_r1),
+                        fasta::Record::with_attrs("id", None, seq_r2),
+                    ))
+                    .expect("don't break");
+                    clusters.insert_pair(&record, false).expect("don't break")
+                })
+                .collect::<Vec<_>>();
+            (kept, clusters.summary(false).short_mate_pairs)
+        };
+        assert_eq!(insert_pairs(ShortMate::Truncate), (vec![true, true], 1));
+        assert_eq!(insert_pairs(ShortMate::Pad), (vec![true, false], 1));
+        assert_eq!(insert_pairs(ShortMate::Flag), (vec![true, true], 1));
+    }
+
+    #[test]
+    fn test_compare_region() {
+        let seq1 = b"ACGTACGTAAAA".to_vec();
+        let seq2 = b"ACGTACGTCCCC".to_vec();
+        let insert_records = |hash_region: Region, compare_region_opt: Option<Region>| {
+            let options = ClusterOptions {
+                prefix_length_opt: Some(8),
+                hash_region,
+                compare_region_opt,
+                ..ClusterOptions::default()
+            };
+            let mut clusters =
+                Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                    .expect("don't break");
+            [("id_a", &seq1), ("id_b", &seq2), ("id_c", &seq2)]
+                .iter()
+                .map(|(id, seq)| {
+                    let record = fasta::Record::with_attrs(id, None, seq);
+                    clusters.insert_single(&record, false).expect("don't break")
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            insert_records(Region::Prefix, None),
+            vec![true, false, false]
+        );
+        assert_eq!(
+            insert_records(Region::Prefix, Some(Region::Prefix)),
+            vec![true, false, false]
+        );
+        // hashed by prefix but kept apart by their full sequences
+        assert_eq!(
+            insert_records(Region::Prefix, Some(Region::Full)),
+            vec![true, true, false]
+        );
+        assert_eq!(insert_records(Region::Full, None), vec![true, true, false]);
+    }
 
-    fn random_seq(len: usize) -> Vec<u8> {
-        const CHARSET: &[u8] = b"ACTG";
-        let mut rng = rand::thread_rng();
-        (0..len)
-            .map(|_| {
-                let idx = rng.gen_range(0, CHARSET.len());
-                CHARSET[idx]
+    #[test]
+    fn test_kmerset_key() {
+        let options = ClusterOptions {
+            key: KeyMode::KmerSet,
+            kmer_size: 5,
+            ..ClusterOptions::default()
+        };
+        let mut clusters =
+            Clusters::from_writer_with_options(None::<Cursor<Vec<u8>>>, options, 200)
+                .expect("don't break");
+        let records = [
+            ("id_a", &b"ACGGTCATTGCAAGTC"[..]),
+            ("id_b", b"TTGCAAGTCACGGTCA"),
+            ("id_c", b"ACGGTCATTGCAAGTA"),
+        ];
+        let kept = records
+            .iter()
+            .map(|(id, seq)| {
+                let record = fasta::Record::with_attrs(id, None, seq);
+                clusters.insert_single(&record, false).expect("don't break")
             })
-            .collect()
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec![true, false, true]);
     }
 
     #[test]
-    fn test_insert_single() {
-        let mut cluster_output = Cursor::new(Vec::new());
+    fn test_longest_representative() {
+        for (representative, expected) in [
+            (Representative::First, "id_a"),
+            (Representative::Longest, "id_b"),
+        ]
+        .iter()
         {
-            let mut clusters =
-                Clusters::from_writer(Some(&mut cluster_output), Some(10), 200).expect("asdasd");
-            let seq = random_seq(20);
-            let record_1 = fasta::Record::with_attrs("id_a", None, &seq);
-            clusters.insert_single(&record_1).expect("don't break");
-            let record_2 = fasta::Record::with_attrs("id_b", None, &seq);
-            clusters.insert_single(&record_2).expect("don't break");
-            assert_eq!(clusters.duplicate_records(), 1);
-            assert_eq!(clusters.unique_records(), 1);
-            assert_eq!(clusters.total_records(), 2);
+            let options = ClusterOptions {
+                prefix_length_opt: Some(4),
+                representative: *representative,
+                ..ClusterOptions::default()
+            };
+            let mut clusters = Clusters::from_writer_with_options(None::<File>, options, 200)
+                .expect("don't break");
+            let records = [
+                fasta::Record::with_attrs("id_a", None, b"ACGTA"),
+                fasta::Record::with_attrs("id_b", None, b"ACGTAC"),
+                fasta::Record::with_attrs("id_c", None, b"ACGTC"),
+            ];
+            for record in records.iter() {
+                clusters.insert_single(record, false).expect("don't break");
+            }
+            assert_eq!(
+                clusters.single_cluster(&records[2], false).unwrap().id(),
+                *expected
+            );
+            let representatives = records
+                .iter()
+                .filter(|record| clusters.is_single_representative(*record, false))
+                .map(|record| record.id())
+                .collect::<Vec<_>>();
+            assert_eq!(representatives, vec![*expected]);
+        }
+    }
+
+    #[test]
+    fn test_save_load_state() {
+        let options = ClusterOptions {
+            prefix_length_opt: Some(4),
+            ..ClusterOptions::default()
+        };
+        let mut clusters = Clusters::from_writer_with_options(None::<File>, options.clone(), 200)
+            .expect("don't break");
+        let records = [
+            fasta::Record::with_attrs("id_a", None, b"ACGTA"),
+            fasta::Record::with_attrs("id_b", None, b"ACGTC"),
+            fasta::Record::with_attrs("id_c", None, b"TTTTT"),
+        ];
+        for record in records[..2].iter() {
+            clusters.insert_single(record, false).expect("don't break");
         }
+        let mut state = Vec::new();
+        clusters.save_state(&mut state, false).expect("don't break");
+
+        let mut loaded =
+            Clusters::from_writer_with_options(None::<File>, options, 200).expect("don't break");
         assert_eq!(
-            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
-            "representative read id,read id\nid_a,id_a\nid_a,id_b\n"
+            loaded.load_state(&state[..], false).expect("don't break"),
+            1
+        );
+        assert!(!loaded
+            .insert_single(&records[0], false)
+            .expect("don't break"));
+        assert!(loaded
+            .insert_single(&records[2], false)
+            .expect("don't break"));
+        let cluster = loaded.single_cluster(&records[0], false).unwrap();
+        assert_eq!((cluster.id(), cluster.size()), ("id_a", 3));
+
+        let options = ClusterOptions {
+            prefix_length_opt: Some(5),
+            ..ClusterOptions::default()
+        };
+        let mut mismatched =
+            Clusters::from_writer_with_options(None::<File>, options, 200).expect("don't break");
+        let err = mismatched.load_state(&state[..], false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cluster state was saved with prefix-length 4, but this run uses 5"
+        );
+        // every option that changes keys must match, reverse complementing too
+        let options = ClusterOptions {
+            prefix_length_opt: Some(4),
+            ambiguity: Ambiguity::Normalize,
+            ..ClusterOptions::default()
+        };
+        let mut mismatched =
+            Clusters::from_writer_with_options(None::<File>, options, 200).expect("don't break");
+        let err = mismatched.load_state(&state[..], false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cluster state was saved with ambiguity keep, but this run uses normalize"
         );
+        let mut mismatched = Clusters::from_writer_with_options(
+            None::<File>,
+            ClusterOptions {
+                prefix_length_opt: Some(4),
+                short_mate: ShortMate::Pad,
+                ..ClusterOptions::default()
+            },
+            200,
+        )
+        .expect("don't break");
+        assert!(mismatched.load_state(&state[..], false).is_err());
+        let mut loaded = Clusters::from_writer_with_options(
+            None::<File>,
+            ClusterOptions {
+                prefix_length_opt: Some(4),
+                ..ClusterOptions::default()
+            },
+            200,
+        )
+        .expect("don't break");
+        let err = loaded.load_state(&state[..], true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cluster state was saved with reverse-complement false, but this run uses true"
+        );
+        let err = mismatched
+            .load_state(&b"not a state"[..], false)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "not a cluster state file");
     }
 
     #[test]
-    fn test_insert_pair() {
-        let mut cluster_output = Cursor::new(Vec::new());
+    fn test_length_bins() {
+        let options = ClusterOptions {
+            length_bins: vec![5, 10],
+            ..ClusterOptions::default()
+        };
+        let mut clusters =
+            Clusters::from_writer_with_options(None::<File>, options, 200).expect("don't break");
+        for (id, seq) in [
+            ("id_a", &b"ACG"[..]),
+            ("id_b", b"ACG"),
+            ("id_c", b"ACGTACGT"),
+            ("id_d", b"ACGTACGTACGT"),
+        ]
+        .iter()
         {
-            let mut clusters =
-                Clusters::from_writer(Some(&mut cluster_output), Some(10), 200).expect("asdasd");
-            let seq_r1 = random_seq(20);
-            let seq_r2 = random_seq(20);
-            let record_1_r1 = fasta::Record::with_attrs("id_a", None, &seq_r1);
-            let record_1_r2 = fasta::Record::with_attrs("id_a", None, &seq_r2);
-            clusters
-                .insert_pair(&PairedRecord::try_from((record_1_r1, record_1_r2)).unwrap())
-                .expect("don't break");
-            let record_2_r1 = fasta::Record::with_attrs("id_b", None, &seq_r1);
-            let record_2_r2 = fasta::Record::with_attrs("id_b", None, &seq_r2);
-            clusters
-                .insert_pair(&PairedRecord::try_from((record_2_r1, record_2_r2)).unwrap())
-                .expect("don't break");
-            assert_eq!(clusters.duplicate_records(), 1);
-            assert_eq!(clusters.unique_records(), 1);
-            assert_eq!(clusters.total_records(), 2);
+            let record = fasta::Record::with_attrs(id, None, seq);
+            clusters.insert_single(&record, false).expect("don't break");
         }
+        let bins = clusters.summary(false).length_bins;
         assert_eq!(
-            str::from_utf8(cluster_output.into_inner().as_slice()).unwrap(),
-            "representative read id,read id\nid_a,id_a\nid_a,id_b\n"
+            bins.iter()
+                .map(|bin| (
+                    bin.min_length,
+                    bin.max_length,
+                    bin.total_records,
+                    bin.duplicate_records
+                ))
+                .collect::<Vec<_>>(),
+            vec![(0, Some(4), 2, 1), (5, Some(9), 1, 0), (10, None, 1, 0)]
         );
+        assert_eq!(bins[0].duplication_rate, 0.5);
     }
 
     #[test]
-    fn test_write_cluster_sizes() {
+    fn test_near_duplicates() {
         let mut cluster_output = Cursor::new(Vec::new());
-        let mut cluster_sizes_writer = Cursor::new(Vec::new());
         {
-            let mut cluster_sizes_output = csv::Writer::from_writer(&mut cluster_sizes_writer);
+            let options = ClusterOptions {
+                max_mismatches_opt: Some(1),
+                ..ClusterOptions::default()
+            };
             let mut clusters =
-                Clusters::from_writer(Some(&mut cluster_output), Some(10), 200).expect("asdasd");
-            let seq1 = random_seq(20);
-            let record_1 = fasta::Record::with_attrs("id_a", None, &seq1);
-            clusters.insert_single(&record_1).expect("don't break");
-            let record_2 = fasta::Record::with_attrs("id_b", None, &seq1);
-            clusters.insert_single(&record_2).expect("don't break");
-            let seq2 = random_seq(20);
-            let record_3 = fasta::Record::with_attrs("id_c", None, &seq2);
-            clusters.insert_single(&record_3).expect("don't break");
-            clusters
-                .write_sizes(&mut cluster_sizes_output)
-                .expect("don't break");
+                Clusters::from_writer_with_options(Some(&mut cluster_output), options, 200)
+                    .expect("don't break");
+            let records = [
+                ("id_a", &b"ACGTACGTACGT"[..]),
+                ("id_b", b"ACGTACGAACGT"),
+                ("id_c", b"ACGTACGAACGA"),
+                ("id_d", b"ACGTACGTACGT"),
+            ];
+            let kept = records
+                .iter()
+                .map(|(id, seq)| {
+                    let record = fasta::Record::with_attrs(id, None, seq);
+                    clusters.insert_single(&record, false).expect("don't break")
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(kept, vec![true, false, true, false]);
         }
-        let cluster_sizes_output_inner = cluster_sizes_writer.into_inner();
-        let cluster_sizes = str::from_utf8(cluster_sizes_output_inner.as_slice()).unwrap();
+        let rows = str::from_utf8(cluster_output.get_ref())
+            .expect("don't break")
+            .lines()
+            .skip(1)
+            .collect::<Vec<_>>();
         assert_eq!(
-            cluster_sizes,
-            "representative read id,cluster size\nid_a,2\nid_c,1\n"
+            rows,
+            vec![
+                "id_a,id_a,representative",
+                "id_a,id_b,near",
+                "id_c,id_c,representative",
+                "id_a,id_d,exact",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ambiguity() {
+        let inserted = |ambiguity: Ambiguity| {
+            let options = ClusterOptions {
+                ambiguity,
+                ..ClusterOptions::default()
+            };
+            let mut clusters = Clusters::from_writer_with_options(None::<File>, options, 200)
+                .expect("don't break");
+            let records = [
+                ("id_a", &b"ACGTACGTAC"[..]),
+                ("id_b", b"ACRTACGTAC"),
+                ("id_c", b"ACYTACGTAC"),
+                ("id_d", b"ACSTACGTAC"),
+            ];
+            let kept = records
+                .iter()
+                .map(|(id, seq)| {
+                    let record = fasta::Record::with_attrs(id, None, seq);
+                    clusters.insert_single(&record, true).expect("don't break")
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(clusters.summary(true).ambiguous_records, 3);
+            kept
+        };
+        assert_eq!(inserted(Ambiguity::Keep), vec![true, true, true, true]);
+        assert_eq!(
+            inserted(Ambiguity::Normalize),
+            vec![true, true, false, false]
         );
+        // R and S are compatible with id_a's G, Y is not
+        assert_eq!(inserted(Ambiguity::Match), vec![true, false, true, false]);
     }
 }
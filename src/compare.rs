@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+
+/// A cluster found in both runs, matched by its cluster hash
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedCluster {
+    pub hash: String,
+    pub representative_a: String,
+    pub representative_b: String,
+    pub reads_a: u64,
+    pub reads_b: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterComparison {
+    pub clusters_a: u64,
+    pub clusters_b: u64,
+    pub reads_a: u64,
+    pub reads_b: u64,
+    /// shared clusters in the order they first appear in run a
+    pub shared: Vec<SharedCluster>,
+}
+
+impl ClusterComparison {
+    pub fn shared_clusters(&self) -> u64 {
+        self.shared.len() as u64
+    }
+
+    /// Reads of run a in clusters that also appear in run b
+    pub fn shared_reads_a(&self) -> u64 {
+        self.shared.iter().map(|cluster| cluster.reads_a).sum()
+    }
+
+    /// Reads of run b in clusters that also appear in run a
+    pub fn shared_reads_b(&self) -> u64 {
+        self.shared.iter().map(|cluster| cluster.reads_b).sum()
+    }
+
+    pub fn write_shared<W: std::io::Write>(
+        &self,
+        csv_writer: &mut csv::Writer<W>,
+    ) -> Result<(), csv::Error> {
+        csv_writer.write_record([
+            "cluster hash",
+            "representative read id a",
+            "representative read id b",
+            "reads a",
+            "reads b",
+        ])?;
+        for cluster in self.shared.iter() {
+            csv_writer.write_record([
+                &cluster.hash,
+                &cluster.representative_a,
+                &cluster.representative_b,
+                &cluster.reads_a.to_string(),
+                &cluster.reads_b.to_string(),
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+/// Cluster hash -> (representative read id, reads), and the hashes in order of appearance
+type ClusterReads = (HashMap<String, (String, u64)>, Vec<String>);
+
+fn column(headers: &csv::StringRecord, name: &str) -> Result<usize, Box<dyn Error>> {
+    headers
+        .iter()
+        .position(|header| header == name)
+        .ok_or_else(|| {
+            let message = format!("cluster file has no {} column", name);
+            From::from(message)
+        })
+}
+
+fn cluster_reads<R: Read>(cluster_file: R) -> Result<ClusterReads, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_reader(cluster_file);
+    let headers = reader.headers()?.clone();
+    let representative_column = column(&headers, "representative read id")?;
+    let hash_column = column(&headers, "cluster hash")
+        .map_err(|err| format!("{}, rerun czid-dedup with --cluster-hash", err))?;
+    let mut clusters = HashMap::new();
+    let mut order = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+        let hash = &record[hash_column];
+        let representative = &record[representative_column];
+        let (_, reads) = clusters.entry(hash.to_owned()).or_insert_with(|| {
+            order.push(hash.to_owned());
+            (representative.to_owned(), 0)
+        });
+        *reads += 1;
+    }
+    Ok((clusters, order))
+}
+
+/// Reports the clusters and reads two runs' cluster files have in common
+///
+/// Both runs must have been made with `--cluster-hash` and the same
+/// clustering options for their hashes to be comparable.
+pub fn compare_clusters<R: Read, S: Read>(
+    cluster_file_a: R,
+    cluster_file_b: S,
+) -> Result<ClusterComparison, Box<dyn Error>> {
+    let (clusters_a, order_a) = cluster_reads(cluster_file_a)?;
+    let (clusters_b, _) = cluster_reads(cluster_file_b)?;
+    let shared = order_a
+        .into_iter()
+        .filter_map(|hash| {
+            let (representative_b, reads_b) = clusters_b.get(&hash)?;
+            // every hash in the order has an entry
+            let (representative_a, reads_a) = clusters_a.get(&hash).unwrap();
+            Some(SharedCluster {
+                representative_a: representative_a.clone(),
+                representative_b: representative_b.clone(),
+                reads_a: *reads_a,
+                reads_b: *reads_b,
+                hash,
+            })
+        })
+        .collect();
+    Ok(ClusterComparison {
+        clusters_a: clusters_a.len() as u64,
+        clusters_b: clusters_b.len() as u64,
+        reads_a: clusters_a.values().map(|(_, reads)| reads).sum(),
+        reads_b: clusters_b.values().map(|(_, reads)| reads).sum(),
+        shared,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_compare_clusters() {
+        let cluster_file_a = "representative read id,read id,duplicate type,cluster hash
+a1,a1,representative,00000000000000aa
+a1,a2,exact,00000000000000aa
+a3,a3,representative,00000000000000bb
+a4,a4,representative,00000000000000cc
+";
+        let cluster_file_b = "representative read id,read id,duplicate type,cluster hash
+b1,b1,representative,00000000000000cc
+b2,b2,representative,00000000000000aa
+b1,b3,exact,00000000000000cc
+b1,b4,exact,00000000000000cc
+";
+        let comparison = compare_clusters(Cursor::new(cluster_file_a), Cursor::new(cluster_file_b))
+            .expect("don't break");
+        assert_eq!(comparison.clusters_a, 3);
+        assert_eq!(comparison.clusters_b, 2);
+        assert_eq!(comparison.reads_a, 4);
+        assert_eq!(comparison.reads_b, 4);
+        assert_eq!(comparison.shared_clusters(), 2);
+        assert_eq!(comparison.shared_reads_a(), 3);
+        assert_eq!(comparison.shared_reads_b(), 4);
+        assert_eq!(
+            comparison.shared[0],
+            SharedCluster {
+                hash: "00000000000000aa".to_owned(),
+                representative_a: "a1".to_owned(),
+                representative_b: "b2".to_owned(),
+                reads_a: 2,
+                reads_b: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_compare_clusters_without_hashes() {
+        let cluster_file = "representative read id,read id,duplicate type\na1,a1,representative\n";
+        assert!(compare_clusters(Cursor::new(cluster_file), Cursor::new(cluster_file)).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+/// Number of (key, index) entries sorted in memory before spilling a run to disk
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Bytes an entry takes in memory and in a spilled run
+pub const ENTRY_BYTES: usize = 16;
+
+/// Fewest entries sorted in memory, however little memory there is
+const MIN_CHUNK_SIZE: usize = 1 << 10;
+
+type Entry = (u64, u64);
+
+/// Number of entries sorted in memory within `memory_limit` bytes, leaving
+/// three quarters of it to read and write records and merge runs
+pub fn chunk_size_for(memory_limit: u64) -> usize {
+    let entries = memory_limit / 4 / ENTRY_BYTES as u64;
+    usize::try_from(entries)
+        .unwrap_or(usize::MAX)
+        .max(MIN_CHUNK_SIZE)
+}
+
+fn write_entry<W: Write>(writer: &mut W, (key, index): Entry) -> Result<(), Error> {
+    writer.write_all(&key.to_le_bytes())?;
+    writer.write_all(&index.to_le_bytes())
+}
+
+fn read_entry<R: Read>(reader: &mut R) -> Result<Option<Entry>, Error> {
+    let mut buf = [0u8; 16];
+    match reader.read_exact(&mut buf) {
+        Ok(()) => {
+            let mut key = [0u8; 8];
+            let mut index = [0u8; 8];
+            key.copy_from_slice(&buf[..8]);
+            index.copy_from_slice(&buf[8..]);
+            Ok(Some((u64::from_le_bytes(key), u64::from_le_bytes(index))))
+        }
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Sorts (key, index) entries with a bounded amount of memory by spilling
+/// sorted runs to anonymous temporary files and merging them
+pub struct ExternalSorter {
+    chunk: Vec<Entry>,
+    chunk_size: usize,
+    runs: Vec<File>,
+}
+
+impl ExternalSorter {
+    pub fn new(chunk_size: usize) -> Self {
+        ExternalSorter {
+            chunk: Vec::with_capacity(chunk_size),
+            chunk_size,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, entry: Entry) -> Result<(), Error> {
+        self.chunk.push(entry);
+        if self.chunk.len() >= self.chunk_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> Result<(), Error> {
+        self.chunk.sort_unstable();
+        let mut writer = BufWriter::new(tempfile::tempfile()?);
+        for entry in self.chunk.drain(..) {
+            write_entry(&mut writer, entry)?;
+        }
+        let mut run = writer.into_inner().map_err(|err| err.into_error())?;
+        run.seek(SeekFrom::Start(0))?;
+        self.runs.push(run);
+        Ok(())
+    }
+
+    /// Returns every pushed entry in ascending order
+    pub fn finish(mut self) -> Result<SortedEntries, Error> {
+        if !self.chunk.is_empty() {
+            self.spill()?;
+        }
+        let mut readers = self
+            .runs
+            .into_iter()
+            .map(BufReader::new)
+            .collect::<Vec<_>>();
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some(entry) = read_entry(reader)? {
+                heap.push(Reverse((entry, run)));
+            }
+        }
+        Ok(SortedEntries { heap, readers })
+    }
+}
+
+pub struct SortedEntries {
+    heap: BinaryHeap<Reverse<(Entry, usize)>>,
+    readers: Vec<BufReader<File>>,
+}
+
+impl Iterator for SortedEntries {
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((entry, run)) = self.heap.pop()?;
+        match read_entry(&mut self.readers[run]) {
+            Ok(Some(next_entry)) => self.heap.push(Reverse((next_entry, run))),
+            Ok(None) => (),
+            Err(err) => return Some(Err(err)),
+        }
+        Some(Ok(entry))
+    }
+}
+
+/// The outcome of grouping sorted (key, index) entries by key
+pub struct Representatives {
+    /// the index of the first record of each key, in ascending order
+    pub indices: SortedEntries,
+    pub unique_records: u64,
+    /// cluster size -> number of clusters of that size
+    pub cluster_size_histogram: BTreeMap<u64, u64>,
+}
+
+/// Picks the first record of every key as its representative
+///
+/// Entries are sorted by (key, index) so the first entry of each key is its
+/// representative. Representatives come out in key order, so they are sorted
+/// again by index for a streaming pass over the input.
+pub fn representatives(
+    entries: SortedEntries,
+    chunk_size: usize,
+) -> Result<Representatives, Error> {
+    let mut sorter = ExternalSorter::new(chunk_size);
+    let mut unique_records = 0;
+    let mut cluster_size_histogram = BTreeMap::new();
+    let mut current: Option<(u64, u64)> = None;
+    for entry in entries {
+        let (key, index) = entry?;
+        match current.as_mut() {
+            Some((current_key, size)) if *current_key == key => *size += 1,
+            _ => {
+                if let Some((_, size)) = current {
+                    *cluster_size_histogram.entry(size).or_insert(0) += 1;
+                }
+                current = Some((key, 1));
+                unique_records += 1;
+                sorter.push((index, 0))?;
+            }
+        }
+    }
+    if let Some((_, size)) = current {
+        *cluster_size_histogram.entry(size).or_insert(0) += 1;
+    }
+    Ok(Representatives {
+        indices: sorter.finish()?,
+        unique_records,
+        cluster_size_histogram,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_external_sort() {
+        let mut sorter = ExternalSorter::new(3);
+        let entries = vec![(5, 0), (1, 1), (3, 2), (1, 3), (4, 4), (5, 5), (2, 6)];
+        for entry in entries.iter() {
+            sorter.push(*entry).expect("don't break");
+        }
+        let sorted = sorter
+            .finish()
+            .expect("don't break")
+            .collect::<Result<Vec<_>, _>>()
+            .expect("don't break");
+        let mut expected = entries.clone();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn test_chunk_size_for() {
+        assert_eq!(chunk_size_for(64 << 20), 1 << 20);
+        assert_eq!(chunk_size_for(0), MIN_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn test_representatives() {
+        let mut sorter = ExternalSorter::new(2);
+        for entry in [(7, 0), (9, 1), (7, 2), (8, 3), (7, 4)].iter() {
+            sorter.push(*entry).expect("don't break");
+        }
+        let representatives =
+            representatives(sorter.finish().expect("don't break"), 2).expect("don't break");
+        assert_eq!(representatives.unique_records, 3);
+        assert_eq!(
+            representatives
+                .cluster_size_histogram
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(1, 2), (3, 1)]
+        );
+        let indices = representatives
+            .indices
+            .map(|entry| entry.map(|(index, _)| index))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("don't break");
+        assert_eq!(indices, vec![0, 1, 3]);
+    }
+}
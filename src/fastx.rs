@@ -1,27 +1,183 @@
 use bio::io::{fasta, fastq};
-use std::fs::File;
-use std::io::{BufReader, Read, Write};
 use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use super::archive;
+use super::bgzf::{self, BgzfReader, BgzfWriter};
 
 pub trait Record {
     fn id(&self) -> &str;
     fn seq(&self) -> &[u8];
+    /// Phred+33 base qualities, `None` for FASTA
+    fn qual(&self) -> Option<&[u8]>;
     fn check(&self) -> Result<(), &str>;
+    /// A copy of this record with a different id
+    fn with_id(&self, id: &str) -> Self
+    where
+        Self: Sized;
+    /// A copy of this record keeping only its first `length` bases
+    fn truncated(&self, length: usize) -> Self
+    where
+        Self: Sized;
+    /// A copy of this record with different qualities, FASTA records have
+    /// none to replace
+    fn with_qual(&self, qual: &[u8]) -> Self
+    where
+        Self: Sized;
+    /// A record of this record's bases, then `spacer`, then `other`'s, named
+    /// without the mate suffix; the spacer's qualities are all `!`
+    fn concatenated(&self, other: &Self, spacer: &[u8]) -> Self
+    where
+        Self: Sized;
 }
 
+/// Removes a trailing mate suffix (`/1`, `/2`) or Casava comment (` 1:N:0:...`)
+/// from a read id so both mates report the same fragment name
+pub fn strip_read_suffix(id: &str) -> &str {
+    let id = id.split_whitespace().next().unwrap_or(id);
+    id.strip_suffix("/1")
+        .or_else(|| id.strip_suffix("/2"))
+        .unwrap_or(id)
+}
+
+/// Infers an R2 file's path from its R1 file's by the usual naming
+/// conventions, `_R1_` to `_R2_`, `_R1.` to `_R2.`, or `_1.` to `_2.`,
+/// changing only the last match in the file name
+pub fn r2_path(r1_path: &str) -> Option<String> {
+    let name_start = r1_path.rfind('/').map_or(0, |i| i + 1);
+    let name = &r1_path[name_start..];
+    ["_R1_", "_R1.", "_1."].iter().find_map(|marker| {
+        name.rfind(marker).map(|i| {
+            let i = name_start + i;
+            let replacement = marker.replace('1', "2");
+            format!(
+                "{}{}{}",
+                &r1_path[..i],
+                replacement,
+                &r1_path[i + marker.len()..]
+            )
+        })
+    })
+}
+
+/// Fills an output path template from an input's path
+///
+/// `{dir}` is the input's directory, `{stem}` its file name up to the first
+/// `.`, and `{ext}` the rest, so `sample.fastq.gz` has stem `sample` and
+/// extension `fastq.gz`. Archive members are named by the member's file and
+/// the archive's directory. For mates, `{pair}` is `R1`, `R2`, and so on,
+/// and the mate marker `r2_path` recognizes is dropped from `{stem}`.
+pub fn output_from_template(
+    template: &str,
+    input: &str,
+    mate_opt: Option<usize>,
+) -> Result<String, String> {
+    let (dir_path, file_path) = match archive::split_member(input) {
+        Some((archive_path, member)) => (archive_path, member),
+        None => (input, input),
+    };
+    let dir = dir_path.rfind('/').map_or(".", |i| &dir_path[..i.max(1)]);
+    let name = file_path.rsplit('/').next().unwrap_or(file_path);
+    let (stem, ext) = name.split_once('.').unwrap_or((name, ""));
+    let stem = match mate_opt {
+        Some(mate) => {
+            let markers = [format!("_R{}", mate), format!("_{}", mate)];
+            let infix = format!("_R{}_", mate);
+            if let Some(stripped) = markers.iter().find_map(|m| stem.strip_suffix(m.as_str())) {
+                stripped.to_owned()
+            } else if let Some(i) = stem.rfind(&infix) {
+                format!("{}_{}", &stem[..i], &stem[i + infix.len()..])
+            } else {
+                stem.to_owned()
+            }
+        }
+        None => stem.to_owned(),
+    };
+    let mut path = template
+        .replace("{dir}", dir)
+        .replace("{stem}", &stem)
+        .replace("{ext}", ext);
+    if path.contains("{pair}") {
+        let mate = mate_opt.ok_or_else(|| {
+            format!(
+                "--output-template {} uses {{pair}} without paired inputs",
+                template
+            )
+        })?;
+        path = path.replace("{pair}", &format!("R{}", mate));
+    }
+    Ok(path)
+}
 
 pub fn read_gz<P: AsRef<std::path::Path>>(path: P) -> Box<dyn Read> {
     let file = File::open(&path).expect("failed to open input file");
     let buf = BufReader::new(file);
-    let path_str = path.as_ref().to_string_lossy();
+    decode_gz(&path.as_ref().to_string_lossy(), buf)
+}
+
+/// Wraps a reader in a gzip decoder if its name ends with `.gz`
+pub fn decode_gz<R: Read + 'static>(name: &str, reader: R) -> Box<dyn Read> {
+    if name.ends_with(".gz") {
+        Box::new(MultiGzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    }
+}
 
-    if path_str.ends_with(".gz") {
-        Box::new(MultiGzDecoder::new(buf))
+/// Creates an output file, gzip compressing it if its name ends with `.gz`
+pub fn write_gz<P: AsRef<std::path::Path>>(path: P) -> Result<Box<dyn Write>, std::io::Error> {
+    let file = File::create(&path)?;
+    if path.as_ref().to_string_lossy().ends_with(".gz") {
+        Ok(Box::new(GzEncoder::new(file, Compression::default())))
     } else {
-        Box::new(buf)
+        Ok(Box::new(file))
+    }
+}
+
+/// Creates a deduped output file, BGZF compressed on `threads` threads if
+/// `bgzf_threads_opt` is set
+pub fn create_output<P: AsRef<std::path::Path>>(
+    path: P,
+    bgzf_threads_opt: Option<usize>,
+) -> Result<Box<dyn Write>, std::io::Error> {
+    let file = File::create(path)?;
+    match bgzf_threads_opt {
+        Some(threads) => Ok(Box::new(BgzfWriter::new(file, threads))),
+        None => Ok(Box::new(file)),
     }
 }
 
+/// Opens a plain or gzipped file, or a member of an archive given as
+/// `archive::member`, and determines its type
+pub fn open_input(input: &str) -> Result<(FastxType, Box<dyn Read>), std::io::Error> {
+    let reader = match archive::split_member(input) {
+        Some((path, member)) if archive::is_archive(path) => archive::open_member(path, member)?,
+        Some((path, _)) => {
+            let message = format!("{} is not a supported archive", path);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                message,
+            ));
+        }
+        None => {
+            let mut reader = BufReader::new(File::open(input)?);
+            // gzip magic bytes, so BGZF outputs are read back whatever their names
+            if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+                Box::new(MultiGzDecoder::new(reader))
+            } else {
+                decode_gz(input, reader)
+            }
+        }
+    };
+    peek_fastx_type(reader)
+}
+
 impl Record for fasta::Record {
     fn id(&self) -> &str {
         self.id()
@@ -31,9 +187,31 @@ impl Record for fasta::Record {
         self.seq()
     }
 
+    fn qual(&self) -> Option<&[u8]> {
+        None
+    }
+
     fn check(&self) -> Result<(), &str> {
         self.check()
     }
+
+    fn with_id(&self, id: &str) -> Self {
+        fasta::Record::with_attrs(id, self.desc(), self.seq())
+    }
+
+    fn truncated(&self, length: usize) -> Self {
+        let length = length.min(self.seq().len());
+        fasta::Record::with_attrs(self.id(), self.desc(), &self.seq()[..length])
+    }
+
+    fn with_qual(&self, _qual: &[u8]) -> Self {
+        self.clone()
+    }
+
+    fn concatenated(&self, other: &Self, spacer: &[u8]) -> Self {
+        let seq = [self.seq(), spacer, other.seq()].concat();
+        fasta::Record::with_attrs(strip_read_suffix(self.id()), None, &seq)
+    }
 }
 
 impl Record for fastq::Record {
@@ -45,27 +223,678 @@ impl Record for fastq::Record {
         self.seq()
     }
 
+    fn qual(&self) -> Option<&[u8]> {
+        Some(self.qual())
+    }
+
     fn check(&self) -> Result<(), &str> {
         self.check()
     }
+
+    fn with_id(&self, id: &str) -> Self {
+        fastq::Record::with_attrs(id, self.desc(), self.seq(), self.qual())
+    }
+
+    fn truncated(&self, length: usize) -> Self {
+        let length = length.min(self.seq().len());
+        fastq::Record::with_attrs(
+            self.id(),
+            self.desc(),
+            &self.seq()[..length],
+            &self.qual()[..length],
+        )
+    }
+
+    fn with_qual(&self, qual: &[u8]) -> Self {
+        fastq::Record::with_attrs(self.id(), self.desc(), self.seq(), qual)
+    }
+
+    fn concatenated(&self, other: &Self, spacer: &[u8]) -> Self {
+        let seq = [self.seq(), spacer, other.seq()].concat();
+        let qual = [self.qual(), &vec![b'!'; spacer.len()], other.qual()].concat();
+        fastq::Record::with_attrs(strip_read_suffix(self.id()), None, &seq, &qual)
+    }
 }
 
 pub trait Writer<T: Record> {
     fn write_record(&mut self, record: &T) -> Result<(), std::io::Error>;
 }
 
+impl<T: Record, W: Writer<T>> Writer<T> for &mut W {
+    fn write_record(&mut self, record: &T) -> Result<(), std::io::Error> {
+        (**self).write_record(record)
+    }
+}
+
+/// Writes each pair as one record, r1's bases then a spacer then r2's, for
+/// --concat-pairs-output
+///
+/// Mates are written to it in turn, r1 is held until its r2 is written.
+pub struct ConcatPairsWriter<W, T> {
+    inner: W,
+    spacer: Vec<u8>,
+    r1_opt: Option<T>,
+}
+
+impl<W, T> ConcatPairsWriter<W, T> {
+    pub fn new(inner: W, spacer: &[u8]) -> Self {
+        ConcatPairsWriter {
+            inner,
+            spacer: spacer.to_vec(),
+            r1_opt: None,
+        }
+    }
+}
+
+impl<T: Record + Clone, W: Writer<T>> Writer<T> for ConcatPairsWriter<W, T> {
+    fn write_record(&mut self, record: &T) -> Result<(), std::io::Error> {
+        match self.r1_opt.take() {
+            Some(r1) => self
+                .inner
+                .write_record(&r1.concatenated(record, &self.spacer)),
+            None => {
+                self.r1_opt = Some(record.clone());
+                Ok(())
+            }
+        }
+    }
+}
+
 impl<T: Write> Writer<fasta::Record> for fasta::Writer<T> {
     fn write_record(&mut self, record: &fasta::Record) -> Result<(), std::io::Error> {
-        self.write_record(&record)
+        self.write_record(record)
     }
 }
 
 impl<T: Write> Writer<fastq::Record> for fastq::Writer<T> {
     fn write_record(&mut self, record: &fastq::Record) -> Result<(), std::io::Error> {
-        self.write_record(&record)
+        self.write_record(record)
+    }
+}
+
+/// Fails a FASTA input as soon as a record has more than `max_bases` bases,
+/// before the record is read into memory
+///
+/// Sequences may be wrapped over any number of lines, only their bases count.
+pub struct RecordLengthLimit<R: Read> {
+    inner: R,
+    max_bases: usize,
+    bases: usize,
+    at_line_start: bool,
+    in_header: bool,
+    /// the current record's id, for the error
+    id: Vec<u8>,
+}
+
+impl<R: Read> RecordLengthLimit<R> {
+    pub fn new(inner: R, max_bases: usize) -> Self {
+        RecordLengthLimit {
+            inner,
+            max_bases,
+            bases: 0,
+            at_line_start: true,
+            in_header: false,
+            id: Vec::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for RecordLengthLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for byte in buf[..n].iter() {
+            match byte {
+                b'\n' => {
+                    self.at_line_start = true;
+                    self.in_header = false;
+                    continue;
+                }
+                b'>' if self.at_line_start => {
+                    self.in_header = true;
+                    self.bases = 0;
+                    self.id.clear();
+                }
+                b'\r' => (),
+                _ if self.in_header => {
+                    // ids end at the first whitespace, like bio reads them
+                    if !self.id.contains(&b' ') && !self.id.contains(&b'\t') {
+                        self.id.push(*byte);
+                    }
+                }
+                _ => {
+                    self.bases += 1;
+                    if self.bases > self.max_bases {
+                        let id = String::from_utf8_lossy(&self.id);
+                        let id = id.split_whitespace().next().unwrap_or_default();
+                        let message = format!(
+                            "record {} is longer than the maximum record length of {} bases",
+                            id, self.max_bases
+                        );
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            message,
+                        ));
+                    }
+                }
+            }
+            self.at_line_start = false;
+        }
+        Ok(n)
+    }
+}
+
+/// The kind of a FASTQ line
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum FastqLine {
+    #[default]
+    Header,
+    Sequence,
+    Separator,
+    Quality,
+}
+
+/// Follows a FASTQ input's layout line by line the way bio parses it:
+/// sequence lines up to one starting with `+`, then as many quality lines
+#[derive(Debug, Default)]
+struct FastqLayout {
+    expected: FastqLine,
+    sequence_lines: usize,
+    quality_lines: usize,
+}
+
+impl FastqLayout {
+    /// The kind of the next line, given its first byte
+    fn next_line(&mut self, first: u8) -> FastqLine {
+        match self.expected {
+            FastqLine::Sequence if first == b'+' => {
+                self.quality_lines = self.sequence_lines;
+                self.expected = if self.sequence_lines == 0 {
+                    FastqLine::Header
+                } else {
+                    FastqLine::Quality
+                };
+                FastqLine::Separator
+            }
+            FastqLine::Sequence => {
+                self.sequence_lines += 1;
+                FastqLine::Sequence
+            }
+            FastqLine::Quality => {
+                self.quality_lines -= 1;
+                if self.quality_lines == 0 {
+                    self.expected = FastqLine::Header;
+                }
+                FastqLine::Quality
+            }
+            FastqLine::Header | FastqLine::Separator => {
+                self.sequence_lines = 0;
+                self.expected = FastqLine::Sequence;
+                FastqLine::Header
+            }
+        }
+    }
+}
+
+/// Fails a FASTQ input at the first record bio would misread, naming it
+///
+/// Sequences and qualities may be wrapped over several lines, as long as
+/// both are wrapped over the same number of lines. A record wrapped
+/// differently, or a missing header, would otherwise be misparsed or fail
+/// with a generic error far from its cause. Each record is held back until
+/// it's complete, so the records before a bad one are all read first.
+pub struct FastqLayoutCheck<R: Read> {
+    inner: R,
+    layout: FastqLayout,
+    line: FastqLine,
+    at_line_start: bool,
+    /// the current record's header, for the errors
+    id: Vec<u8>,
+    bases: usize,
+    qualities: usize,
+    /// bytes read but not returned yet, complete records up to `released`
+    pending: Vec<u8>,
+    released: usize,
+    error_opt: Option<std::io::Error>,
+    eof: bool,
+}
+
+impl<R: Read> FastqLayoutCheck<R> {
+    pub fn new(inner: R) -> Self {
+        FastqLayoutCheck {
+            inner,
+            layout: FastqLayout::default(),
+            line: FastqLine::Header,
+            at_line_start: true,
+            id: Vec::new(),
+            bases: 0,
+            qualities: 0,
+            pending: Vec::new(),
+            released: 0,
+            error_opt: None,
+            eof: false,
+        }
+    }
+
+    fn id(&self) -> String {
+        let id = String::from_utf8_lossy(&self.id);
+        id.split_whitespace().next().unwrap_or_default().to_owned()
+    }
+
+    /// Checks the record read so far wrapped its qualities like its sequence
+    fn check_record(&self) -> std::io::Result<()> {
+        if self.layout.sequence_lines > 1 && self.bases != self.qualities {
+            let message = format!(
+                "record {} is wrapped over {} sequence lines but its {} bases have {} qualities, multi-line FASTQ records must wrap their qualities like their sequence",
+                self.id(),
+                self.layout.sequence_lines,
+                self.bases,
+                self.qualities
+            );
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ));
+        }
+        Ok(())
+    }
+
+    fn check(&mut self, data: &[u8]) -> std::io::Result<()> {
+        for byte in data.iter() {
+            if self.at_line_start {
+                if self.line == FastqLine::Quality && self.layout.expected == FastqLine::Header {
+                    self.check_record()?;
+                }
+                self.line = self.layout.next_line(*byte);
+                if self.line == FastqLine::Header {
+                    self.released = self.pending.len();
+                    if *byte != b'@' {
+                        let message = if self.id.is_empty() {
+                            "expected a FASTQ record header starting with @".to_owned()
+                        } else {
+                            format!(
+                                "expected a FASTQ record header starting with @ after record {}",
+                                self.id()
+                            )
+                        };
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            message,
+                        ));
+                    }
+                    self.id.clear();
+                    self.bases = 0;
+                    self.qualities = 0;
+                }
+            }
+            self.at_line_start = *byte == b'\n';
+            self.pending.push(*byte);
+            match (self.line, byte) {
+                (_, b'\n') | (_, b'\r') => (),
+                (FastqLine::Header, b'@') if self.id.is_empty() => (),
+                (FastqLine::Header, _) => self.id.push(*byte),
+                (FastqLine::Sequence, _) => self.bases += 1,
+                (FastqLine::Quality, _) => self.qualities += 1,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for FastqLayoutCheck<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.released == 0 && !self.eof {
+            if let Some(err) = self.error_opt.take() {
+                return Err(err);
+            }
+            let mut data = [0u8; 8192];
+            let n = self.inner.read(&mut data)?;
+            let result = if n == 0 {
+                self.eof = true;
+                self.check_record()
+                    .map(|_| self.released = self.pending.len())
+            } else {
+                self.check(&data[..n])
+            };
+            // the records before the bad one are returned first
+            if let Err(err) = result {
+                self.error_opt = Some(err);
+                self.eof = false;
+            }
+        }
+        let n = std::cmp::min(buf.len(), self.released);
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        self.released -= n;
+        Ok(n)
+    }
+}
+
+/// Limits the record length of a FASTA input, other inputs are returned as is
+pub fn limit_record_length(
+    fastx_type: &FastxType,
+    reader: Box<dyn Read>,
+    max_bases_opt: Option<usize>,
+) -> Box<dyn Read> {
+    match (fastx_type, max_bases_opt) {
+        (FastxType::Fasta, Some(max_bases)) => Box::new(RecordLengthLimit::new(reader, max_bases)),
+        _ => reader,
+    }
+}
+
+/// Opens the records of an uncompressed file whose headers start in the
+/// byte range `start..end`, so workers can each deduplicate part of a file
+///
+/// Both ends are moved forward to the next record's header, so adjacent
+/// ranges split the file's records between them.
+pub fn open_byte_range(
+    input: &str,
+    start: u64,
+    end: u64,
+) -> Result<(FastxType, Box<dyn Read>), std::io::Error> {
+    let file = File::open(input)?;
+    let mut magic = Vec::new();
+    (&file).take(2).read_to_end(&mut magic)?;
+    if archive::split_member(input).is_some() || magic.starts_with(&[0x1f, 0x8b]) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--byte-range needs an uncompressed input",
+        ));
+    }
+    let fastx_type = match magic.first() {
+        Some(b'>') => FastxType::Fasta,
+        Some(b'@') => FastxType::Fastq,
+        _ => FastxType::Invalid,
+    };
+    let start = record_boundary(&file, start, &fastx_type)?;
+    let end = record_boundary(&file, end, &fastx_type)?;
+    (&file).seek(SeekFrom::Start(start))?;
+    let reader = BufReader::new(file).take(end.saturating_sub(start));
+    if fastx_type == FastxType::Fastq {
+        return Ok((fastx_type, Box::new(FastqLayoutCheck::new(reader))));
+    }
+    Ok((fastx_type, Box::new(reader)))
+}
+
+/// The offset of the first record header at or after an offset, the end of
+/// the file if there's none
+///
+/// A FASTQ header is a line starting with `@` two lines before one starting
+/// with `+`, which a quality line starting with `@` never is.
+fn record_boundary(file: &File, offset: u64, fastx_type: &FastxType) -> std::io::Result<u64> {
+    let len = file.metadata()?.len();
+    if offset == 0 || offset >= len {
+        return Ok(std::cmp::min(offset, len));
+    }
+    // from the byte before, so a line starting at the offset isn't skipped
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(offset - 1))?;
+    let mut line = Vec::new();
+    let mut position = offset - 1 + reader.read_until(b'\n', &mut line)? as u64;
+    let mut lines = VecDeque::new();
+    loop {
+        line.clear();
+        let n = reader.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            return Ok(len);
+        }
+        lines.push_back((position, line.first().copied()));
+        position += n as u64;
+        let header_opt = match fastx_type {
+            FastxType::Fastq if lines.len() < 3 => continue,
+            FastxType::Fastq => {
+                (lines[0].1 == Some(b'@') && lines[2].1 == Some(b'+')).then_some(lines[0].0)
+            }
+            _ => (lines[0].1 == Some(b'>')).then_some(lines[0].0),
+        };
+        if let Some(header) = header_opt {
+            return Ok(header);
+        }
+        lines.pop_front();
+    }
+}
+
+/// The offsets of an input's records, shared with the `OffsetReader`
+/// recording them
+///
+/// Offsets are byte offsets in plain files and virtual offsets in BGZF files.
+/// `advance` is called as each record is parsed, after the reader has passed
+/// its header, so `current` is the offset of the record parsed last.
+#[derive(Clone, Debug, Default)]
+pub struct RecordOffsets(Rc<RefCell<OffsetState>>);
+
+#[derive(Debug, Default)]
+struct OffsetState {
+    /// header offsets read past but not yet reached by the parser
+    starts: VecDeque<u64>,
+    current: u64,
+}
+
+impl RecordOffsets {
+    /// Moves on to the next record's offset
+    pub fn advance(&self) {
+        let mut state = self.0.borrow_mut();
+        if let Some(start) = state.starts.pop_front() {
+            state.current = start;
+        }
+    }
+
+    /// The offset of the record parsed last
+    pub fn current(&self) -> u64 {
+        self.0.borrow().current
+    }
+}
+
+/// Advances record offsets as each record is read, if they're tracked
+pub fn advance_offsets<I: Iterator>(
+    records: I,
+    offsets_opt: Option<RecordOffsets>,
+) -> impl Iterator<Item = I::Item> {
+    records.inspect(move |_| {
+        if let Some(offsets) = offsets_opt.as_ref() {
+            offsets.advance();
+        }
+    })
+}
+
+enum OffsetInput {
+    Plain(BufReader<File>, u64),
+    Bgzf(BgzfReader<BufReader<File>>),
+}
+
+/// Reads a plain or BGZF input, recording the offset of every record's header
+///
+/// FASTQ records are followed line by line the way bio reads them.
+pub struct OffsetReader {
+    input: OffsetInput,
+    offsets: RecordOffsets,
+    fastq_opt: Option<bool>,
+    at_line_start: bool,
+    layout: FastqLayout,
+}
+
+impl Read for OffsetReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // the data is all from one block, so offsets in it are consecutive
+        let (offset, data) = match &mut self.input {
+            OffsetInput::Plain(reader, position) => (*position, reader.fill_buf()?),
+            OffsetInput::Bgzf(reader) => (reader.virtual_offset()?, reader.fill_buf()?),
+        };
+        let n = std::cmp::min(buf.len(), data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        let mut state = self.offsets.0.borrow_mut();
+        for (i, byte) in buf[..n].iter().enumerate() {
+            if self.at_line_start {
+                let fastq = *self.fastq_opt.get_or_insert(*byte == b'@');
+                let is_header = if fastq {
+                    self.layout.next_line(*byte) == FastqLine::Header
+                } else {
+                    *byte == b'>'
+                };
+                if is_header {
+                    state.starts.push_back(offset + i as u64);
+                }
+            }
+            self.at_line_start = *byte == b'\n';
+        }
+        match &mut self.input {
+            OffsetInput::Plain(reader, position) => {
+                reader.consume(n);
+                *position += n as u64;
+            }
+            OffsetInput::Bgzf(reader) => reader.consume(n),
+        }
+        Ok(n)
     }
 }
 
+type OffsetInputOpened = (FastxType, Box<dyn Read>, RecordOffsets);
+
+/// Opens a plain or BGZF file recording its records' offsets, `None` for
+/// other inputs, which can't be seeked in by record
+pub fn open_with_offsets(input: &str) -> Result<Option<OffsetInputOpened>, std::io::Error> {
+    if archive::split_member(input).is_some() {
+        return Ok(None);
+    }
+    let mut reader = BufReader::new(File::open(input)?);
+    let data = reader.fill_buf()?;
+    let input = if bgzf::is_bgzf(data) {
+        OffsetInput::Bgzf(BgzfReader::new(reader))
+    } else if data.starts_with(&[0x1f, 0x8b]) || input.ends_with(".gz") {
+        return Ok(None);
+    } else {
+        OffsetInput::Plain(reader, 0)
+    };
+    let offsets = RecordOffsets::default();
+    let reader = OffsetReader {
+        input,
+        offsets: offsets.clone(),
+        fastq_opt: None,
+        at_line_start: true,
+        layout: FastqLayout::default(),
+    };
+    let (fastx_type, reader) = peek_fastx_type(Box::new(reader))?;
+    Ok(Some((fastx_type, reader, offsets)))
+}
+
+/// A plain or BGZF input read from the record offsets `RecordOffsets` recorded
+pub enum SeekableInput {
+    Plain(File),
+    Bgzf(BgzfReader<File>),
+}
+
+impl SeekableInput {
+    pub fn open(input: &str) -> Result<Self, std::io::Error> {
+        let mut file = File::open(input)?;
+        let mut header = Vec::new();
+        (&mut file).take(18).read_to_end(&mut header)?;
+        file.seek(SeekFrom::Start(0))?;
+        if bgzf::is_bgzf(&header) {
+            Ok(SeekableInput::Bgzf(BgzfReader::new(file)))
+        } else {
+            Ok(SeekableInput::Plain(file))
+        }
+    }
+
+    /// The input from a record's offset on
+    pub fn seek(&mut self, offset: u64) -> Result<&mut dyn Read, std::io::Error> {
+        match self {
+            SeekableInput::Plain(file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(file)
+            }
+            SeekableInput::Bgzf(reader) => {
+                reader.seek_virtual(offset)?;
+                Ok(reader)
+            }
+        }
+    }
+}
+
+/// What `--gz-members` does with the members of gzip files made by
+/// concatenating others, like `cat a.gz b.gz`
+///
+/// - `Report` reports each member's reads in the summary
+/// - `ReadGroups` also gives each member its own read group
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GzMembers {
+    Report,
+    ReadGroups,
+}
+
+impl std::str::FromStr for GzMembers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "report" => Ok(GzMembers::Report),
+            "read-groups" => Ok(GzMembers::ReadGroups),
+            _ => Err(format!(
+                "invalid gz members: {}, expected report or read-groups",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for GzMembers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            GzMembers::Report => "report",
+            GzMembers::ReadGroups => "read-groups",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A member of a gzip file, by its compressed bytes
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GzipMember {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Finds the members of a gzip file by decompressing them one at a time
+///
+/// BGZF files are made of many small members that don't mark samples, so
+/// they're rejected.
+pub fn gzip_members(path: &str) -> Result<Vec<GzipMember>, std::io::Error> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+    let mut reader = BufReader::new(File::open(path)?);
+    let data = reader.fill_buf()?;
+    if bgzf::is_bgzf(data) {
+        return Err(invalid(format!("{} is BGZF, not concatenated gzip", path)));
+    }
+    if !data.starts_with(&[0x1f, 0x8b]) {
+        return Err(invalid(format!("{} isn't gzipped", path)));
+    }
+    let mut members = Vec::new();
+    let mut offset = 0;
+    // trailing bytes that don't start another member are ignored, as
+    // MultiGzDecoder ignores them
+    while reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        // the bufread decoder stops at the end of the member
+        let mut decoder = flate2::bufread::GzDecoder::new(reader);
+        std::io::copy(&mut decoder, &mut std::io::sink())?;
+        reader = decoder.into_inner();
+        let end = reader.get_mut().stream_position()? - reader.buffer().len() as u64;
+        members.push(GzipMember {
+            offset,
+            length: end - offset,
+        });
+        offset = end;
+    }
+    Ok(members)
+}
+
+/// Opens one member of a gzip file
+pub fn open_gzip_member(path: &str, member: GzipMember) -> Result<Box<dyn Read>, std::io::Error> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(member.offset))?;
+    let reader = BufReader::new(file.take(member.length));
+    Ok(Box::new(flate2::bufread::GzDecoder::new(reader)))
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum FastxType {
     Fastq,
@@ -86,6 +915,21 @@ pub fn fastx_type<P: AsRef<std::path::Path>>(path: P) -> Result<FastxType, std::
     }
 }
 
+/// Determines the type of an already opened input without consuming any of it
+pub fn peek_fastx_type(
+    reader: Box<dyn Read>,
+) -> Result<(FastxType, Box<dyn Read>), std::io::Error> {
+    let mut buf_reader = BufReader::new(reader);
+    let fastx_type = match buf_reader.fill_buf()?.first() {
+        Some(b'>') => FastxType::Fasta,
+        Some(b'@') => FastxType::Fastq,
+        _ => FastxType::Invalid,
+    };
+    if fastx_type == FastxType::Fastq {
+        return Ok((fastx_type, Box::new(FastqLayoutCheck::new(buf_reader))));
+    }
+    Ok((fastx_type, Box::new(buf_reader)))
+}
 
 impl std::fmt::Display for FastxType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -97,3 +941,236 @@ impl std::fmt::Display for FastxType {
         write!(f, "{}", s)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_concat_pairs_writer() {
+        struct Records(Vec<fasta::Record>);
+        impl Writer<fasta::Record> for Records {
+            fn write_record(&mut self, record: &fasta::Record) -> Result<(), std::io::Error> {
+                self.0.push(record.clone());
+                Ok(())
+            }
+        }
+        let mut writer = ConcatPairsWriter::new(Records(Vec::new()), b"NN");
+        writer
+            .write_record(&fasta::Record::with_attrs("id_a/1", Some("r1"), b"ACG"))
+            .expect("don't break");
+        assert!(writer.inner.0.is_empty());
+        writer
+            .write_record(&fasta::Record::with_attrs("id_a/2", Some("r2"), b"TTT"))
+            .expect("don't break");
+        assert_eq!(writer.inner.0.len(), 1);
+        let record = &writer.inner.0[0];
+        assert_eq!(
+            (record.id(), record.desc(), record.seq()),
+            ("id_a", None, &b"ACGNNTTT"[..])
+        );
+
+        let r1 = fastq::Record::with_attrs("id_b", None, b"AC", b"II");
+        let r2 = fastq::Record::with_attrs("id_b", None, b"G", b"5");
+        let record = r1.concatenated(&r2, b"N");
+        assert_eq!((record.seq(), record.qual()), (&b"ACNG"[..], &b"II!5"[..]));
+    }
+
+    #[test]
+    fn test_gzip_members() {
+        let dir = tempfile::tempdir().expect("don't break");
+        let path = dir.path().join("input.fasta.gz");
+        let path = path.to_str().unwrap();
+        let mut data = Vec::new();
+        for record in [">id_a\nACGT\n", ">id_b\nTTTT\n>id_c\nGGGG\n"].iter() {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(record.as_bytes()).expect("don't break");
+            data.extend(encoder.finish().expect("don't break"));
+        }
+        std::fs::write(path, &data).expect("don't break");
+
+        let members = gzip_members(path).expect("don't break");
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].offset, 0);
+        assert_eq!(members[1].offset, members[0].length);
+        assert_eq!(members[1].offset + members[1].length, data.len() as u64);
+        let mut text = String::new();
+        open_gzip_member(path, members[1])
+            .expect("don't break")
+            .read_to_string(&mut text)
+            .expect("don't break");
+        assert_eq!(text, ">id_b\nTTTT\n>id_c\nGGGG\n");
+
+        std::fs::write(path, b">id_a\nACGT\n").expect("don't break");
+        assert!(gzip_members(path).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_strip_read_suffix() {
+        assert_eq!(strip_read_suffix("read_1/1"), "read_1");
+        assert_eq!(strip_read_suffix("read_1/2"), "read_1");
+        assert_eq!(strip_read_suffix("read_1 1:N:0:ACGT"), "read_1");
+        assert_eq!(strip_read_suffix("read_1/3"), "read_1/3");
+        assert_eq!(strip_read_suffix("read_1"), "read_1");
+    }
+
+    #[test]
+    fn test_r2_path() {
+        assert_eq!(
+            r2_path("run/sample_S1_L001_R1_001.fastq.gz").as_deref(),
+            Some("run/sample_S1_L001_R2_001.fastq.gz")
+        );
+        assert_eq!(r2_path("sample_R1.fq").as_deref(), Some("sample_R2.fq"));
+        assert_eq!(
+            r2_path("run_1/SRR123_1.fastq").as_deref(),
+            Some("run_1/SRR123_2.fastq")
+        );
+        assert_eq!(r2_path("run_R1_/sample.fastq"), None);
+    }
+
+    #[test]
+    fn test_output_from_template() {
+        let template = "{dir}/{stem}.dedup.{ext}";
+        assert_eq!(
+            output_from_template(template, "run/sample.fastq.gz", None),
+            Ok("run/sample.dedup.fastq.gz".to_owned())
+        );
+        assert_eq!(
+            output_from_template(template, "sample.fa", None),
+            Ok("./sample.dedup.fa".to_owned())
+        );
+        let template = "out/{stem}_{pair}.dedup.{ext}";
+        assert_eq!(
+            output_from_template(template, "run/sample_R2.fq", Some(2)),
+            Ok("out/sample_R2.dedup.fq".to_owned())
+        );
+        assert_eq!(
+            output_from_template(template, "sample_S1_L001_R1_001.fastq.gz", Some(1)),
+            Ok("out/sample_S1_L001_001_R1.dedup.fastq.gz".to_owned())
+        );
+        assert_eq!(
+            output_from_template(template, "run/reads.tar.gz::lane/SRR1_1.fastq", Some(1)),
+            Ok("out/SRR1_R1.dedup.fastq".to_owned())
+        );
+        assert!(output_from_template(template, "sample.fastq", None).is_err());
+    }
+
+    #[test]
+    fn test_record_length_limit() {
+        let input = b">id_a desc\nACGT\r\nAC\n>id_b\nACGTA\nC\n";
+        let read = |max_bases: usize| {
+            let mut output = Vec::new();
+            RecordLengthLimit::new(&input[..], max_bases)
+                .read_to_end(&mut output)
+                .map(|_| output)
+        };
+        assert_eq!(read(6).expect("don't break"), input.to_vec());
+        let err = read(5).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "record id_a is longer than the maximum record length of 5 bases"
+        );
+    }
+
+    #[test]
+    fn test_fastq_layout_check() {
+        let read = |input: &[u8]| {
+            let mut output = Vec::new();
+            FastqLayoutCheck::new(input)
+                .read_to_end(&mut output)
+                .map(|_| output)
+        };
+        let input = b"@id_a\nACGT\n+\n@III\n@id_b desc\nAC\nGT\n+\nII\n@I\n";
+        assert_eq!(read(input).expect("don't break"), input.to_vec());
+        let records = fastq::Reader::new(&input[..])
+            .records()
+            .map(|record| record.expect("don't break").seq().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(records, vec![b"ACGT".to_vec(), b"ACGT".to_vec()]);
+
+        let err = read(b"@id_a\nAC\nGT\n+\nIIII\n@id_b\nACGT\n+\nIIII\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "record id_a is wrapped over 2 sequence lines but its 4 bases have 9 qualities, multi-line FASTQ records must wrap their qualities like their sequence"
+        );
+        // the records before a bad one are read first
+        let input = b"@id_a\nACGT\n+\nIIII\n@id_b\nAC\nGT\n+\nIIII\n@id_c\nA\n+\nI\n";
+        let mut records = fastq::Reader::new(FastqLayoutCheck::new(&input[..])).records();
+        assert_eq!(records.next().unwrap().expect("don't break").id(), "id_a");
+        let err = records.next().unwrap().unwrap_err();
+        assert!(err.to_string().starts_with("record id_b is wrapped"));
+        let err = read(b"@id_a\nACGT\n+\nII\nII\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected a FASTQ record header starting with @ after record id_a"
+        );
+    }
+
+    #[test]
+    fn test_open_byte_range() {
+        let dir = tempfile::tempdir().expect("don't break");
+        let path = dir.path().join("input.fastq");
+        let mut input = Vec::new();
+        for i in 0..100 {
+            // quality lines starting with '@' aren't headers
+            writeln!(input, "@id_{}\nACGT\n+\n@III", i).expect("don't break");
+        }
+        std::fs::write(&path, &input).expect("don't break");
+        let path = path.to_str().unwrap();
+        let read_ids = |start: u64, end: u64| {
+            let (fastx_type, reader) = open_byte_range(path, start, end).expect("don't break");
+            assert_eq!(fastx_type, FastxType::Fastq);
+            fastq::Reader::new(reader)
+                .records()
+                .map(|record| record.expect("don't break").id().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let len = input.len() as u64;
+        let mut ids = Vec::new();
+        for (start, end) in [(0, 100), (100, 1017), (1017, 1018), (1018, len + 10)] {
+            ids.extend(read_ids(start, end));
+        }
+        let expected = (0..100).map(|i| format!("id_{}", i)).collect::<Vec<_>>();
+        assert_eq!(ids, expected);
+        assert_eq!(read_ids(1, 2), Vec::<String>::new());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_record_offsets() {
+        let dir = tempfile::tempdir().expect("don't break");
+        let mut input = Vec::new();
+        for i in 0..5000 {
+            // quality lines starting with '@' aren't headers
+            writeln!(input, "@id_{}\nACGT\n+\n@III", i).expect("don't break");
+        }
+        let plain_path = dir.path().join("input.fastq");
+        std::fs::write(&plain_path, &input).expect("don't break");
+        let bgzf_path = dir.path().join("input.fastq.gz");
+        let mut writer = BgzfWriter::new(File::create(&bgzf_path).expect("don't break"), 1);
+        writer.write_all(&input).expect("don't break");
+        writer.finish().expect("don't break");
+
+        for path in [plain_path, bgzf_path] {
+            let path = path.to_str().unwrap();
+            let (fastx_type, reader, offsets) = open_with_offsets(path)
+                .expect("don't break")
+                .expect("seekable");
+            assert_eq!(fastx_type, FastxType::Fastq);
+            let mut seekable = SeekableInput::open(path).expect("don't break");
+            let records = fastq::Reader::new(reader).records();
+            for result in advance_offsets(records, Some(offsets.clone())) {
+                let record = result.expect("don't break");
+                let reader = seekable.seek(offsets.current()).expect("don't break");
+                let seeked = fastq::Reader::new(reader)
+                    .records()
+                    .next()
+                    .expect("a record")
+                    .expect("don't break");
+                assert_eq!(seeked.id(), record.id());
+            }
+        }
+        dir.close().expect("don't break");
+    }
+}
@@ -0,0 +1,367 @@
+use regex::Regex;
+
+use super::fastx;
+
+/// Decides which records are kept before they're clustered
+pub trait RecordFilter {
+    fn keep(&self, record: &dyn fastx::Record) -> bool;
+}
+
+/// No filter keeps every record
+impl<F: RecordFilter> RecordFilter for Option<F> {
+    fn keep(&self, record: &dyn fastx::Record) -> bool {
+        self.as_ref().is_none_or(|filter| filter.keep(record))
+    }
+}
+
+/// Both filters must keep a record
+impl<A: RecordFilter, B: RecordFilter> RecordFilter for (A, B) {
+    fn keep(&self, record: &dyn fastx::Record) -> bool {
+        self.0.keep(record) && self.1.keep(record)
+    }
+}
+
+/// Keeps records whose ids match `include_opt` and don't match
+/// `exclude_opt`, like the tiles or lanes in Illumina read names
+///
+/// Patterns match anywhere in the id unless anchored.
+#[derive(Clone, Debug, Default)]
+pub struct IdFilter {
+    pub include_opt: Option<Regex>,
+    pub exclude_opt: Option<Regex>,
+}
+
+impl RecordFilter for IdFilter {
+    fn keep(&self, record: &dyn fastx::Record) -> bool {
+        let id = record.id();
+        self.include_opt
+            .as_ref()
+            .is_none_or(|include| include.is_match(id))
+            && !self
+                .exclude_opt
+                .as_ref()
+                .is_some_and(|exclude| exclude.is_match(id))
+    }
+}
+
+/// A per-record value a filter expression compares against
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    /// number of bases
+    Length,
+    /// mean Phred quality, FASTQ only
+    MeanQ,
+    /// lowest Phred quality, FASTQ only
+    MinQ,
+    /// percent of bases that are `G` or `C`
+    Gc,
+    /// number of `N` bases
+    NBases,
+}
+
+impl Field {
+    fn value(&self, record: &dyn fastx::Record) -> Option<f64> {
+        let seq = record.seq();
+        let count = |bases: &[u8]| seq.iter().filter(|base| bases.contains(base)).count() as f64;
+        let scores = || {
+            record
+                .qual()
+                .map(|qual| qual.iter().map(|quality| quality.saturating_sub(33) as f64))
+        };
+        match self {
+            Field::Length => Some(seq.len() as f64),
+            Field::MeanQ if seq.is_empty() => None,
+            Field::MeanQ => scores().map(|scores| scores.sum::<f64>() / seq.len() as f64),
+            Field::MinQ => scores().and_then(|scores| scores.reduce(f64::min)),
+            Field::Gc if seq.is_empty() => Some(0.0),
+            Field::Gc => Some(count(b"GCgc") * 100.0 / seq.len() as f64),
+            Field::NBases => Some(count(b"Nn")),
+        }
+    }
+
+    pub fn uses_quality(&self) -> bool {
+        matches!(self, Field::MeanQ | Field::MinQ)
+    }
+}
+
+impl std::str::FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "length" => Ok(Field::Length),
+            "meanq" => Ok(Field::MeanQ),
+            "minq" => Ok(Field::MinQ),
+            "gc" => Ok(Field::Gc),
+            "nbases" => Ok(Field::NBases),
+            _ => Err(format!("invalid filter field: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    fn compare(&self, a: f64, b: f64) -> bool {
+        match self {
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+        }
+    }
+}
+
+/// A filter expression such as `length>=50 && meanq>=20`
+///
+/// Conditions compare a field to a number and combine with `&&`, `||`, and
+/// parentheses, `&&` binding tighter. A quality condition is false for reads
+/// without qualities.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FilterExpr {
+    Condition(Field, Op, f64),
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+}
+
+impl FilterExpr {
+    pub fn eval(&self, record: &dyn fastx::Record) -> bool {
+        match self {
+            FilterExpr::Condition(field, op, threshold) => field
+                .value(record)
+                .map(|value| op.compare(value, *threshold))
+                .unwrap_or(false),
+            FilterExpr::And(a, b) => a.eval(record) && b.eval(record),
+            FilterExpr::Or(a, b) => a.eval(record) || b.eval(record),
+        }
+    }
+
+    /// Whether any condition needs base qualities
+    pub fn uses_quality(&self) -> bool {
+        match self {
+            FilterExpr::Condition(field, _, _) => field.uses_quality(),
+            FilterExpr::And(a, b) | FilterExpr::Or(a, b) => a.uses_quality() || b.uses_quality(),
+        }
+    }
+}
+
+impl RecordFilter for FilterExpr {
+    fn keep(&self, record: &dyn fastx::Record) -> bool {
+        self.eval(record)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            (c, _) if c.is_ascii_alphanumeric() || c == '.' || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '.' || **c == '_')
+                    .count();
+                (Token::Word(chars[i..i + len].iter().collect()), len)
+            }
+            (c, _) => return Err(format!("unexpected character in filter expression: {}", c)),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Result<FilterExpr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<FilterExpr, String> {
+        let mut expr = self.atom()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.atom()?));
+        }
+        Ok(expr)
+    }
+
+    fn atom(&mut self) -> Result<FilterExpr, String> {
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("unclosed ( in filter expression".to_owned()),
+                }
+            }
+            Some(Token::Word(field)) => {
+                let field = field.parse::<Field>()?;
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    _ => return Err("expected a comparison in filter expression".to_owned()),
+                };
+                let threshold = match self.next() {
+                    Some(Token::Word(number)) => number
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid number in filter expression: {}", number))?,
+                    _ => return Err("expected a number in filter expression".to_owned()),
+                };
+                Ok(FilterExpr::Condition(field, op, threshold))
+            }
+            _ => Err("expected a condition in filter expression".to_owned()),
+        }
+    }
+}
+
+impl std::str::FromStr for FilterExpr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err("unexpected trailing input in filter expression".to_owned());
+        }
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bio::io::{fasta, fastq};
+
+    #[test]
+    fn test_parse() {
+        let expr = "length>=50 && meanq>=20".parse::<FilterExpr>();
+        assert_eq!(
+            expr,
+            Ok(FilterExpr::And(
+                Box::new(FilterExpr::Condition(Field::Length, Op::Ge, 50.0)),
+                Box::new(FilterExpr::Condition(Field::MeanQ, Op::Ge, 20.0)),
+            ))
+        );
+        assert!("length >= 50 || (gc < 60.5 && nbases == 0)"
+            .parse::<FilterExpr>()
+            .is_ok());
+        assert!("length".parse::<FilterExpr>().is_err());
+        assert!("size>=50".parse::<FilterExpr>().is_err());
+        assert!("length>=50 &&".parse::<FilterExpr>().is_err());
+        assert!("(length>=50".parse::<FilterExpr>().is_err());
+        assert!("length>=fifty".parse::<FilterExpr>().is_err());
+    }
+
+    #[test]
+    fn test_id_filter() {
+        let filter = IdFilter {
+            include_opt: Some(Regex::new(r":1:110[12]:").expect("don't break")),
+            exclude_opt: Some(Regex::new(r"^skip").expect("don't break")),
+        };
+        let ids = [
+            "A00123:8:H5:1:1101:1000:1000",
+            "A00123:8:H5:1:1103:1000:1000",
+            "skip:8:H5:1:1102:1000:1000",
+        ];
+        let kept = ids
+            .iter()
+            .map(|id| filter.keep(&fasta::Record::with_attrs(id, None, b"ACGT")))
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec![true, false, false]);
+
+        let record = fasta::Record::with_attrs(ids[0], None, b"ACGT");
+        let length = "length>=5".parse::<FilterExpr>().expect("don't break");
+        assert!(!(Some(length.clone()), filter.clone()).keep(&record));
+        assert!((None::<FilterExpr>, filter).keep(&record));
+        assert!(IdFilter::default().keep(&record));
+    }
+
+    #[test]
+    fn test_eval() {
+        let expr = "length>=4 && meanq>=20"
+            .parse::<FilterExpr>()
+            .expect("don't break");
+        assert!(expr.uses_quality());
+        let record = fastq::Record::with_attrs("id_a", None, b"ACGT", b"IIII");
+        assert!(expr.keep(&record));
+        let record = fastq::Record::with_attrs("id_a", None, b"ACGT", b"I###");
+        assert!(!expr.keep(&record));
+        let record = fastq::Record::with_attrs("id_a", None, b"ACG", b"III");
+        assert!(!expr.keep(&record));
+        // FASTA has no qualities to compare
+        let record = fasta::Record::with_attrs("id_a", None, b"ACGT");
+        assert!(!expr.keep(&record));
+    }
+
+    #[test]
+    fn test_eval_or() {
+        let expr = "gc>50 || nbases==0 && length<3"
+            .parse::<FilterExpr>()
+            .expect("don't break");
+        assert!(!expr.uses_quality());
+        assert!(expr.keep(&fasta::Record::with_attrs("id_a", None, b"GGCA")));
+        assert!(expr.keep(&fasta::Record::with_attrs("id_a", None, b"AT")));
+        assert!(!expr.keep(&fasta::Record::with_attrs("id_a", None, b"ATN")));
+        assert!(!expr.keep(&fasta::Record::with_attrs("id_a", None, b"ATAT")));
+    }
+}
@@ -0,0 +1,233 @@
+//! Golden-file regression fixtures, for `czid-dedup fixtures`
+//!
+//! `generate` writes a small, fixed set of inputs that exercise the tricky
+//! parts of deduplication: reverse complemented pairs, runs of `N`, reads
+//! tied on their prefix, and a truncated gzip file. Each `Case` runs the
+//! command line tool on some of them, and its digest is a CRC-32 of the
+//! outputs this version writes, or of its error for inputs that must fail.
+//! A build whose outputs differ fails `fixtures verify`.
+use bio::alphabets::dna::revcomp;
+use bio::io::{fasta, fastq};
+use flate2::write::GzEncoder;
+use flate2::{Compression, Crc};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::simulate::Rng;
+
+/// Seed of the fixtures' random bases, changing it changes every digest
+const SEED: u64 = 20_201_015;
+
+pub struct Case {
+    pub name: &'static str,
+    /// Input files in the fixtures directory, r1 then r2 for pairs
+    pub inputs: &'static [&'static str],
+    /// Arguments after the inputs, outputs, and cluster output
+    pub args: &'static [&'static str],
+    /// Whether the run must fail, its digest is then of the error
+    pub fails: bool,
+    pub digest: u32,
+}
+
+pub const CASES: &[Case] = &[
+    Case {
+        name: "revcomp-pairs",
+        inputs: &["revcomp_r1.fasta", "revcomp_r2.fasta"],
+        args: &["--reverse-complement"],
+        fails: false,
+        digest: 0x72ac_bda1,
+    },
+    Case {
+        name: "revcomp-pairs-r2-only",
+        inputs: &["revcomp_r1.fasta", "revcomp_r2.fasta"],
+        args: &["--revcomp-r2-only"],
+        fails: false,
+        digest: 0x0287_1c4a,
+    },
+    Case {
+        name: "n-runs",
+        inputs: &["n_runs.fasta"],
+        args: &[],
+        fails: false,
+        digest: 0x6347_c4c3,
+    },
+    Case {
+        name: "n-runs-max-n-fraction",
+        inputs: &["n_runs.fasta"],
+        args: &["--max-n-fraction", "0.2"],
+        fails: false,
+        digest: 0x0ecf_0e05,
+    },
+    Case {
+        name: "prefix-ties",
+        inputs: &["prefix_ties.fastq"],
+        args: &["-l", "30"],
+        fails: false,
+        digest: 0x5137_2782,
+    },
+    Case {
+        name: "prefix-ties-two-tier",
+        inputs: &["prefix_ties.fastq"],
+        args: &["-l", "30", "--two-tier"],
+        fails: false,
+        digest: 0xf32d_6870,
+    },
+    Case {
+        name: "truncated-gz",
+        inputs: &["truncated.fasta.gz"],
+        args: &[],
+        fails: true,
+        digest: 0x7c7d_a6f0,
+    },
+];
+
+impl Case {
+    /// The deduplicated outputs' file names, one per input
+    pub fn outputs(&self) -> Vec<String> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                let extension = input.trim_end_matches(".gz").rsplit('.').next().unwrap();
+                format!("{}.out{}.{}", self.name, i + 1, extension)
+            })
+            .collect()
+    }
+
+    /// The cluster output's file name
+    pub fn cluster_output(&self) -> String {
+        format!("{}.clusters.csv", self.name)
+    }
+
+    /// Digests the outputs written to `dir`, by name and contents
+    pub fn digest_outputs<P: AsRef<Path>>(&self, dir: P) -> io::Result<u32> {
+        let mut crc = Crc::new();
+        for output in self.outputs().iter().chain(Some(&self.cluster_output())) {
+            crc.update(output.as_bytes());
+            crc.update(&fs::read(dir.as_ref().join(output))?);
+        }
+        Ok(crc.sum())
+    }
+}
+
+/// Digests a failed run's error message
+pub fn digest_error(message: &str) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(b"error: ");
+    crc.update(message.as_bytes());
+    crc.sum()
+}
+
+fn random_seq(rng: &mut Rng, len: usize) -> Vec<u8> {
+    (0..len).map(|_| b"ACGT"[rng.below(4)]).collect()
+}
+
+/// Writes every case's inputs to `dir`, the same bytes on every call
+pub fn generate<P: AsRef<Path>>(dir: P) -> io::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    let mut rng = Rng::new(SEED);
+
+    // pairs repeated as is, with their mates swapped, and with both mates
+    // reverse complemented
+    let pairs = (0..3)
+        .map(|_| (random_seq(&mut rng, 40), random_seq(&mut rng, 40)))
+        .collect::<Vec<_>>();
+    let mut reads = pairs.clone();
+    reads.push(pairs[0].clone());
+    reads.push((pairs[1].1.clone(), pairs[1].0.clone()));
+    reads.push((revcomp(&pairs[2].0), revcomp(&pairs[2].1)));
+    reads.push((pairs[2].0.clone(), revcomp(&pairs[2].1)));
+    let mut writer_r1 = fasta::Writer::to_file(dir.join("revcomp_r1.fasta"))?;
+    let mut writer_r2 = fasta::Writer::to_file(dir.join("revcomp_r2.fasta"))?;
+    for (i, (r1, r2)) in reads.iter().enumerate() {
+        let id = format!("pair_{}", i + 1);
+        writer_r1.write(&id, Some("1"), r1)?;
+        writer_r2.write(&id, Some("2"), r2)?;
+    }
+    writer_r1.flush()?;
+    writer_r2.flush()?;
+
+    // runs of N in the same and different places, and a read of only N
+    let seq = random_seq(&mut rng, 50);
+    let with_run = |start: usize, len: usize| {
+        let mut seq = seq.clone();
+        seq[start..start + len]
+            .iter_mut()
+            .for_each(|base| *base = b'N');
+        seq
+    };
+    let reads = [
+        seq.clone(),
+        with_run(10, 5),
+        with_run(10, 5),
+        with_run(30, 5),
+        with_run(0, 20),
+        vec![b'N'; 50],
+        vec![b'N'; 50],
+    ];
+    let mut writer = fasta::Writer::to_file(dir.join("n_runs.fasta"))?;
+    for (i, seq) in reads.iter().enumerate() {
+        writer.write(&format!("read_{}", i + 1), None, seq)?;
+    }
+    writer.flush()?;
+
+    // reads sharing a 30 base prefix, of different lengths and qualities,
+    // and exact copies of them
+    let prefix = random_seq(&mut rng, 30);
+    let mut writer = fastq::Writer::to_file(dir.join("prefix_ties.fastq"))?;
+    for (i, (suffix_len, qual)) in [(20, b'5'), (10, b'I'), (20, b'I'), (0, b'#'), (20, b'5')]
+        .iter()
+        .enumerate()
+    {
+        let mut seq = prefix.clone();
+        // the first and last reads are identical
+        let mut suffix_rng = Rng::new(SEED + (i % 4) as u64);
+        seq.extend(random_seq(&mut suffix_rng, *suffix_len));
+        let quals = vec![*qual; seq.len()];
+        writer.write(&format!("read_{}", i + 1), None, &seq, &quals)?;
+    }
+    writer.flush()?;
+
+    // a gzip file cut off part way through its deflate stream
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for i in 0..50 {
+        writeln!(encoder, ">read_{}", i + 1)?;
+        encoder.write_all(&random_seq(&mut rng, 60))?;
+        writeln!(encoder)?;
+    }
+    let gzipped = encoder.finish()?;
+    fs::write(
+        dir.join("truncated.fasta.gz"),
+        &gzipped[..gzipped.len() * 2 / 3],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_generate() {
+        let dir_a = tempdir().expect("don't break");
+        let dir_b = tempdir().expect("don't break");
+        generate(dir_a.path()).expect("don't break");
+        generate(dir_b.path()).expect("don't break");
+        for input in CASES.iter().flat_map(|case| case.inputs.iter()) {
+            let a = fs::read(dir_a.path().join(input)).expect("don't break");
+            let b = fs::read(dir_b.path().join(input)).expect("don't break");
+            assert_eq!(a, b);
+        }
+        assert_eq!(
+            CASES[0].outputs(),
+            vec!["revcomp-pairs.out1.fasta", "revcomp-pairs.out2.fasta"]
+        );
+        assert_eq!(CASES[6].outputs(), vec!["truncated-gz.out1.fasta"]);
+        dir_a.close().expect("don't break");
+        dir_b.close().expect("don't break");
+    }
+}
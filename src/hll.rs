@@ -0,0 +1,101 @@
+/// Bits of a hash choosing its register, 2^14 registers for a standard
+/// error of about 0.8%
+pub const PRECISION: u32 = 14;
+
+/// Approximately counts distinct hashes in a fixed 16 KiB, however many
+/// there are
+///
+/// Each hash picks a register with its top bits and the register keeps the
+/// longest run of leading zeros seen in the remaining bits, so memory stays
+/// the same while the cluster map may forget clusters in window or sorted mode.
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog {
+            registers: vec![0; 1 << PRECISION],
+        }
+    }
+}
+
+impl HyperLogLog {
+    /// Adds a well mixed 64 bit hash, like a cluster hash
+    pub fn insert(&mut self, hash: u64) {
+        let index = (hash >> (64 - PRECISION)) as usize;
+        // the guard bit caps the run of zeros when the remaining bits are all 0
+        let rest = (hash << PRECISION) | (1 << (PRECISION - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// The estimated number of distinct hashes inserted
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|register| 2f64.powi(-(*register as i32)))
+            .sum::<f64>();
+        let raw = alpha * m * m / sum;
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|register| **register == 0)
+            .count();
+        if raw <= 2.5 * m && zeros > 0 {
+            // linear counting is more accurate while many registers are empty
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+
+    /// The estimate's relative standard error
+    pub fn standard_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::hash::{Hash, Hasher};
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(n: u64) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        Hash::hash(&n, &mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_estimate() {
+        let mut hll = HyperLogLog::default();
+        assert_eq!(hll.estimate(), 0.0);
+        for n in 0..100_000 {
+            hll.insert(hash(n));
+        }
+        let estimate = hll.estimate();
+        // repeats don't change the estimate
+        for n in 0..1_000 {
+            hll.insert(hash(n));
+        }
+        assert_eq!(hll.estimate(), estimate);
+        let error = (estimate - 100_000.0).abs() / 100_000.0;
+        assert!(error < 3.0 * hll.standard_error(), "{}", estimate);
+    }
+
+    #[test]
+    fn test_estimate_small() {
+        let mut hll = HyperLogLog::default();
+        for n in 0..100 {
+            hll.insert(hash(n));
+        }
+        assert!((hll.estimate() - 100.0).abs() < 2.0);
+    }
+}
@@ -0,0 +1,98 @@
+/// The most unambiguous sequences a read's ambiguity codes are expanded into
+/// when matching them against compatible bases, reads with more are only
+/// keyed with their codes normalized to `N`
+pub const MAX_EXPANSIONS: usize = 64;
+
+/// The unambiguous bases an IUPAC ambiguity code stands for, empty for
+/// unambiguous bases and `N`, which is keyed as is
+pub fn compatible_bases(base: u8) -> &'static [u8] {
+    match base.to_ascii_uppercase() {
+        b'R' => b"AG",
+        b'Y' => b"CT",
+        b'S' => b"CG",
+        b'W' => b"AT",
+        b'K' => b"GT",
+        b'M' => b"AC",
+        b'B' => b"CGT",
+        b'D' => b"AGT",
+        b'H' => b"ACT",
+        b'V' => b"ACG",
+        _ => b"",
+    }
+}
+
+/// Whether a base is an ambiguity code other than `N`
+pub fn is_ambiguous(base: u8) -> bool {
+    !compatible_bases(base).is_empty()
+}
+
+/// Whether a sequence has any ambiguity codes
+pub fn has_ambiguous(seq: &[u8]) -> bool {
+    seq.iter().any(|base| is_ambiguous(*base))
+}
+
+/// A sequence with its ambiguity codes replaced by `N`
+pub fn normalize(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .map(|base| if is_ambiguous(*base) { b'N' } else { *base })
+        .collect()
+}
+
+/// Every way of replacing the ambiguity codes of a record's sequences with
+/// compatible bases, `None` if there are more than `limit`
+pub fn expansions(seqs: &[&[u8]], limit: usize) -> Option<Vec<Vec<Vec<u8>>>> {
+    let mut expanded = vec![seqs.iter().map(|seq| seq.to_vec()).collect::<Vec<_>>()];
+    for (i, seq) in seqs.iter().enumerate() {
+        for (j, base) in seq.iter().enumerate() {
+            let bases = compatible_bases(*base);
+            if bases.is_empty() {
+                continue;
+            }
+            if expanded.len() * bases.len() > limit {
+                return None;
+            }
+            expanded = expanded
+                .into_iter()
+                .flat_map(|record| {
+                    bases.iter().map(move |compatible| {
+                        let mut record = record.clone();
+                        record[i][j] = *compatible;
+                        record
+                    })
+                })
+                .collect();
+        }
+    }
+    Some(expanded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(b"ACRTNyg"), b"ACNTNNg".to_vec());
+        assert!(!has_ambiguous(b"ACGTN"));
+        assert!(has_ambiguous(b"ACGTW"));
+    }
+
+    #[test]
+    fn test_expansions() {
+        let seqs: &[&[u8]] = &[b"AR", b"YT"];
+        assert_eq!(
+            expansions(seqs, 4),
+            Some(vec![
+                vec![b"AA".to_vec(), b"CT".to_vec()],
+                vec![b"AA".to_vec(), b"TT".to_vec()],
+                vec![b"AG".to_vec(), b"CT".to_vec()],
+                vec![b"AG".to_vec(), b"TT".to_vec()],
+            ])
+        );
+        assert_eq!(expansions(seqs, 3), None);
+        assert_eq!(
+            expansions(&[b"ACGN"], 1),
+            Some(vec![vec![b"ACGN".to_vec()]])
+        );
+    }
+}
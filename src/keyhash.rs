@@ -0,0 +1,109 @@
+use core::hash::Hasher;
+use siphasher::sip128::{Hasher128, SipHasher13};
+
+/// The SipHash keys cluster keys are hashed with, fixed so every build and
+/// machine computes the same keys
+pub const SIP_KEYS: (u64, u64) = (0, 0);
+
+/// SipHash-1-3 with 128 bit output and fixed keys, fed integers as little
+/// endian bytes and lengths as 64 bit integers
+///
+/// Unlike the standard library's `DefaultHasher`, whose algorithm may change
+/// between Rust releases and which hashes integers in native byte order, the
+/// hashes are the same on every toolchain and machine, so they can be
+/// written to outputs and recomputed by other services.
+#[derive(Clone, Debug)]
+pub struct StableHasher(SipHasher13);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(SipHasher13::new_with_keys(SIP_KEYS.0, SIP_KEYS.1))
+    }
+}
+
+impl StableHasher {
+    pub fn finish128(&self) -> u128 {
+        self.0.finish128().as_u128()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+    }
+
+    /// The low 64 bits of the 128 bit hash
+    fn finish(&self) -> u64 {
+        self.finish128() as u64
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.write(&[i]);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i8(&mut self, i: i8) {
+        self.write_u8(i as u8);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::hash::Hash;
+
+    #[test]
+    fn test_stable_hasher() {
+        // integers hash as their little endian bytes, lengths as u64s
+        let mut hasher = StableHasher::default();
+        Hash::hash(&(1u32, [7u8].as_ref()), &mut hasher);
+        let mut bytes_hasher = StableHasher::default();
+        bytes_hasher.write(&[1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 7]);
+        assert_eq!(hasher.finish128(), bytes_hasher.finish128());
+        assert_eq!(hasher.finish(), hasher.finish128() as u64);
+        // SipHash-1-3-128 of no bytes with zero keys
+        assert_eq!(
+            StableHasher::default().finish128(),
+            0xb91c_5ed0_31fb_3303_2134_935d_61d9_b40d
+        );
+    }
+}
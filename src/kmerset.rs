@@ -0,0 +1,61 @@
+use bio::alphabets::dna::revcomp;
+use core::hash::{Hash, Hasher};
+
+/// Default k-mer length for `--key kmerset`
+pub const DEFAULT_KMER_SIZE: usize = 15;
+
+/// Hashes a sequence's multiset of canonical k-mers
+///
+/// The sequence is read as circular, wrapping k-mers around its end, so cyclic
+/// rotations of a read, like rolling-circle amplification artifacts, have the
+/// same multiset. Each k-mer is canonicalized against its reverse complement,
+/// so a read and its reverse complement do too. Sequences shorter than `k` hash
+/// as their canonical sequence.
+pub fn hash_kmer_multiset<H: Hasher>(seq: &[u8], k: usize, hasher: &mut H) {
+    if seq.len() < k || k == 0 {
+        let rev_seq = revcomp(seq);
+        Hash::hash_slice(std::cmp::min(seq, rev_seq.as_slice()), hasher);
+        return;
+    }
+    let mut circular = seq.to_vec();
+    circular.extend_from_slice(&seq[..k - 1]);
+    let mut kmers = circular
+        .windows(k)
+        .map(|kmer| {
+            let rev_kmer = revcomp(kmer);
+            std::cmp::min(kmer.to_vec(), rev_kmer)
+        })
+        .collect::<Vec<_>>();
+    kmers.sort_unstable();
+    for kmer in kmers.iter() {
+        Hash::hash_slice(kmer, hasher);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::hash_map::DefaultHasher;
+
+    fn kmer_hash(seq: &[u8], k: usize) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hash_kmer_multiset(seq, k, &mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_hash_kmer_multiset() {
+        let seq = b"ACGGTCATTGCAAGTC";
+        let rotated = b"TTGCAAGTCACGGTCA";
+        assert_eq!(kmer_hash(seq, 5), kmer_hash(rotated, 5));
+        assert_eq!(kmer_hash(seq, 5), kmer_hash(&revcomp(&seq[..]), 5));
+        assert_ne!(kmer_hash(seq, 5), kmer_hash(b"ACGGTCATTGCAAGTA", 5));
+    }
+
+    #[test]
+    fn test_hash_kmer_multiset_short() {
+        assert_eq!(kmer_hash(b"ACG", 5), kmer_hash(b"CGT", 5));
+        assert_ne!(kmer_hash(b"ACG", 5), kmer_hash(b"ACC", 5));
+    }
+}
@@ -0,0 +1,34 @@
+pub mod archive;
+pub mod autoprefix;
+pub mod bam;
+pub mod bench;
+pub mod bgzf;
+pub mod cancel;
+pub mod clusters;
+pub mod compare;
+pub mod external;
+pub mod fastx;
+pub mod filter;
+pub mod fixtures;
+pub mod hll;
+pub mod iupac;
+pub mod keyhash;
+pub mod kmerset;
+pub mod lsh;
+pub mod memory;
+pub mod overlap;
+pub mod paired;
+pub mod position;
+pub mod preflight;
+pub mod quality;
+pub mod resources;
+pub mod simulate;
+#[cfg(feature = "sra")]
+pub mod sra;
+pub mod summary;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod trim;
+pub mod tune;
+pub mod warnings;
+pub mod webhook;
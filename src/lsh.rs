@@ -0,0 +1,86 @@
+use core::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+
+/// Buckets sequences by exact bands so near-duplicate candidates are only
+/// compared with sequences sharing a band
+///
+/// A sequence is split into `max_mismatches + 1` bands. Two equal length
+/// sequences with at most `max_mismatches` mismatches have at least one band
+/// with no mismatches, so every near-duplicate shares a bucket and candidate
+/// lookups don't scale with the number of sequences indexed.
+pub struct BandIndex {
+    bands: usize,
+    buckets: HashMap<u64, Vec<u128>>,
+}
+
+impl BandIndex {
+    pub fn new(max_mismatches: usize) -> Self {
+        BandIndex {
+            bands: max_mismatches + 1,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// The bucket of each band of a sequence, including its length so only
+    /// equal length sequences share buckets
+    fn band_hashes<'a>(&'a self, seq: &'a [u8]) -> impl Iterator<Item = u64> + 'a {
+        (0..self.bands).map(move |band| {
+            let start = seq.len() * band / self.bands;
+            let end = seq.len() * (band + 1) / self.bands;
+            let mut hasher = DefaultHasher::new();
+            Hash::hash(&(seq.len(), band), &mut hasher);
+            Hash::hash_slice(&seq[start..end], &mut hasher);
+            hasher.finish()
+        })
+    }
+
+    pub fn insert(&mut self, seq: &[u8], id: u128) {
+        let band_hashes = self.band_hashes(seq).collect::<Vec<_>>();
+        for band_hash in band_hashes {
+            self.buckets.entry(band_hash).or_default().push(id);
+        }
+    }
+
+    /// Ids of indexed sequences sharing a band with `seq`, in insertion order
+    /// within each band
+    pub fn candidates(&self, seq: &[u8]) -> Vec<u128> {
+        let mut candidates = Vec::new();
+        for band_hash in self.band_hashes(seq) {
+            for id in self.buckets.get(&band_hash).into_iter().flatten() {
+                if !candidates.contains(id) {
+                    candidates.push(*id);
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// Whether two sequences have equal lengths and at most `max_mismatches` mismatches
+pub fn within_mismatches(a: &[u8], b: &[u8], max_mismatches: usize) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).filter(|(x, y)| x != y).count() <= max_mismatches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_band_index() {
+        let mut index = BandIndex::new(2);
+        index.insert(b"AAAAAACCCCCCGGGGGG", 1);
+        index.insert(b"TTTTTTTTTTTTTTTTTT", 2);
+        // two mismatches in different bands still share the third band
+        assert_eq!(index.candidates(b"AATAAACCCCCCGGGTGG"), vec![1]);
+        // different lengths never share a band
+        assert!(index.candidates(b"AAAAAACCCCCCGGGGG").is_empty());
+    }
+
+    #[test]
+    fn test_within_mismatches() {
+        assert!(within_mismatches(b"ACGT", b"ACGA", 1));
+        assert!(!within_mismatches(b"ACGT", b"TCGA", 1));
+        assert!(!within_mismatches(b"ACGT", b"ACG", 1));
+    }
+}
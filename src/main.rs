@@ -1,12 +1,31 @@
 use bio::io::{fasta, fastq};
-use clap::{App, Arg};
-use simple_error;
+use clap::{App, Arg, ArgMatches};
+#[cfg(feature = "sra")]
+use czid_dedup::sra;
+use czid_dedup::summary::{self, DedupSummary};
+use czid_dedup::warnings::WarningKind;
+use czid_dedup::{
+    archive, autoprefix, bam, bench, bgzf, cancel, clusters, compare, external, fastx, filter,
+    fixtures, paired, position, preflight, quality, resources, simulate, trim, tune, warnings,
+    webhook,
+};
+use regex::Regex;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fs::File;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::time::{Duration, Instant};
 
-mod clusters;
-mod fastx;
-mod paired;
+/// The exit status of a run stopped by --time-limit, `EX_TEMPFAIL` so batch
+/// schedulers can tell it from a failure and retry
+const TIME_LIMITED_EXIT_STATUS: i32 = 75;
+
+/// Exit status of a run cancelled with --cancel-file, as for an interrupt
+const CANCELLED_EXIT_STATUS: i32 = 130;
 
 macro_rules! box_result_error {
     ($result:expr) => {
@@ -29,53 +48,262 @@ macro_rules! box_bail {
     };
 }
 
+/// Evaluates `$body` with `$orientation` bound to `clusters::Revcomp` or
+/// `clusters::Forward`, so the read loop in it is compiled for each and
+/// doesn't check `$use_revcomp` per read
+macro_rules! with_orientation {
+    ($use_revcomp:expr, $orientation:ident => $body:expr) => {
+        if $use_revcomp {
+            let $orientation = clusters::Revcomp;
+            $body
+        } else {
+            let $orientation = clusters::Forward;
+            $body
+        }
+    };
+}
+
 macro_rules! dedup {
-    ($fastx:tt, $fastx_type_r1:expr, $input_r1:expr, $output_r1:expr, $inputs:expr, $outputs:expr, $clusters:expr, $use_revcomp:expr) => {{
-        let reader_r1 = fastx::read_gz($input_r1); // handle input gzipped files
-        let records_r1 = $fastx::Reader::new(reader_r1).records();
-        let writer_r1 = $fastx::Writer::to_file($output_r1).unwrap();
-        //let writer_r1 = $fastx::Writer::new(fastx::write_gz($output_r1));
-        match ($inputs.next(), $outputs.next()) {
-            (Some(input_r2), Some(output_r2)) => {
-                let fastx_type_r2 = fastx::fastx_type(input_r2).unwrap();
-                if fastx_type_r2 != $fastx_type_r1 {
-                    let message = format!(
-                        "paired inputs have different file types r1: {}, r2: {}",
-                        $fastx_type_r1, fastx_type_r2
+    ($fastx:tt, $fastx_type_r1:expr, $run:expr, $clusters:expr) => {{
+        let DedupRun {
+            lanes,
+            output_r1,
+            mut outputs,
+            interleave_output_opt,
+            concat_pairs_opt,
+            use_revcomp,
+            leniency,
+            bgzf_threads_opt,
+            orphans_opt,
+            trimmer,
+            max_record_length_opt,
+            two_pass,
+            lane_records,
+            fix_legacy_quality,
+        } = $run;
+        let mut writer_r1 = trim::TrimWriter::new(
+            $fastx::Writer::new(create_deduped_output(
+                output_r1,
+                bgzf_threads_opt,
+                two_pass,
+            )?),
+            trimmer,
+        );
+        let mut writer_r2_opt = match outputs.next() {
+            Some(output_r2) => Some(trim::TrimWriter::new(
+                $fastx::Writer::new(create_deduped_output(
+                    output_r2,
+                    bgzf_threads_opt,
+                    two_pass,
+                )?),
+                trimmer,
+            )),
+            None if interleave_output_opt.is_some() => {
+                return Err(Box::new(simple_error::simple_error!(
+                    "interleaved output requires paired inputs"
+                )));
+            }
+            None if concat_pairs_opt.is_some() => {
+                return Err(Box::new(simple_error::simple_error!(
+                    "concatenated pair output requires paired inputs"
+                )));
+            }
+            None => None,
+        };
+        let mut interleaved_writer_opt = match interleave_output_opt {
+            Some(interleave_output) => Some(trim::TrimWriter::new(
+                $fastx::Writer::new(match bgzf_threads_opt {
+                    Some(_) => fastx::create_output(interleave_output, bgzf_threads_opt)?,
+                    None => fastx::write_gz(interleave_output)?,
+                }),
+                trimmer,
+            )),
+            None => None,
+        };
+        let mut concat_writer_opt = match concat_pairs_opt {
+            Some((concat_output, spacer)) => Some(trim::TrimWriter::new(
+                fastx::ConcatPairsWriter::new(
+                    $fastx::Writer::new(match bgzf_threads_opt {
+                        Some(_) => fastx::create_output(concat_output, bgzf_threads_opt)?,
+                        None => fastx::write_gz(concat_output)?,
+                    }),
+                    spacer,
+                ),
+                trimmer,
+            )),
+            None => None,
+        };
+        let mut orphans_opt = match orphans_opt {
+            Some((orphan_output, orphan_clusters)) => Some((
+                trim::TrimWriter::new(
+                    $fastx::Writer::new(fastx::create_output(orphan_output, bgzf_threads_opt)?),
+                    trimmer,
+                ),
+                orphan_clusters,
+            )),
+            None => None,
+        };
+        let mut result = Ok(());
+        for (lane_index, lane) in lanes.into_iter().enumerate() {
+            $clusters.set_lane(lane_index, lane.read_group_opt);
+            let reader_r1 =
+                fastx::limit_record_length(&$fastx_type_r1, lane.reader_r1, max_record_length_opt);
+            let records_r1 = quality::LegacyQuality::new(
+                fastx::advance_offsets(
+                    $fastx::Reader::new(reader_r1).records(),
+                    lane.offsets.first().cloned(),
+                ),
+                fix_legacy_quality,
+            );
+            // every lane's r2 is opened, and its file type checked, before any
+            // output is created
+            result = match (lane.reader_r2_opt, writer_r2_opt.as_mut()) {
+                (Some(reader_r2), Some(writer_r2)) => {
+                    let reader_r2 = fastx::limit_record_length(
+                        &$fastx_type_r1,
+                        reader_r2,
+                        max_record_length_opt,
                     );
-                    return Err(Box::new(simple_error::simple_error!(message)));
+                    let records_r2 = quality::LegacyQuality::new(
+                        fastx::advance_offsets(
+                            $fastx::Reader::new(reader_r2).records(),
+                            lane.offsets.get(1).cloned(),
+                        ),
+                        fix_legacy_quality,
+                    );
+                    let records = paired::PairedRecords::new(records_r1, records_r2);
+                    with_orientation!(use_revcomp, orientation => pair(
+                        records,
+                        (&mut writer_r1, writer_r2),
+                        (interleaved_writer_opt.as_mut(), concat_writer_opt.as_mut()),
+                        orphans_opt
+                            .as_mut()
+                            .map(|(orphan_writer, orphan_clusters)| {
+                                (orphan_writer, &mut **orphan_clusters)
+                            }),
+                        &mut $clusters,
+                        orientation,
+                        leniency,
+                    ))
                 }
-                let reader_r2 = fastx::read_gz(input_r2); // handle input gzipped files
-                let records_r2 = $fastx::Reader::new(reader_r2).records();
-                let writer_r2 = $fastx::Writer::to_file(output_r2).unwrap();
-                //let writer_r2 = $fastx::Writer::new(fastx::write_gz(output_r2));
-                let records = paired::PairedRecords::new(records_r1, records_r2);
-                pair(records, writer_r1, writer_r2, &mut $clusters, $use_revcomp)
-            }
-            (None, None) => single(records_r1, writer_r1, &mut $clusters, $use_revcomp),
-            _ => panic!("must have the same number of inputs and outputs"),
+                (None, None) => with_orientation!(use_revcomp, orientation => single(
+                    records_r1,
+                    &mut writer_r1,
+                    &mut $clusters,
+                    orientation,
+                    leniency,
+                )),
+                _ => panic!("must have the same number of inputs and outputs"),
+            };
+            lane_records.push($clusters.records_read());
+            if result.is_err() {
+                break;
+            }
         }
+        result
     }};
 }
 
+/// A member of a concatenated gzip input, read as a lane of its own with
+/// --gz-members
+struct MemberLane<'a> {
+    /// the lane of the input it's a member of
+    lane: usize,
+    input_r1: &'a str,
+    /// 1-based, in order of the members in the input
+    member: usize,
+    member_r1: fastx::GzipMember,
+    member_r2_opt: Option<fastx::GzipMember>,
+    /// the input and the member's 1-based position in it, as `input#2`
+    name: String,
+}
+
+/// The inputs of one lane, deduplicated jointly with any other lanes
+struct Lane<'a> {
+    read_group_opt: Option<&'a str>,
+    reader_r1: Box<dyn Read>,
+    input_r2_opt: Option<&'a str>,
+    /// r2, opened and checked against r1 before any output is created
+    reader_r2_opt: Option<Box<dyn Read>>,
+    /// r1's and r2's record offsets, empty unless they're tracked
+    offsets: Vec<fastx::RecordOffsets>,
+}
+
+/// What dedup! reads and writes, and how, apart from the record format and
+/// the clusters it fills
+struct DedupRun<'a, O> {
+    lanes: Vec<Lane<'a>>,
+    output_r1: &'a str,
+    /// r2's output, if the input is paired
+    outputs: O,
+    interleave_output_opt: Option<&'a str>,
+    /// the concatenated pairs' output, and the spacer between mates
+    concat_pairs_opt: Option<(&'a str, &'a [u8])>,
+    use_revcomp: bool,
+    leniency: warnings::Leniency,
+    bgzf_threads_opt: Option<usize>,
+    /// the orphans' output, and the clusters they're deduplicated against
+    orphans_opt: Option<(&'a str, &'a mut clusters::Clusters<File>)>,
+    trimmer: trim::Trimmer,
+    max_record_length_opt: Option<usize>,
+    two_pass: bool,
+    /// records read before each lane, and after the last
+    lane_records: &'a mut Vec<u64>,
+    fix_legacy_quality: bool,
+}
+
+/// A deduped output, or a sink for the first pass of a two pass run, whose
+/// representatives are only written in the second pass
+fn create_deduped_output(
+    path: &str,
+    bgzf_threads_opt: Option<usize>,
+    two_pass: bool,
+) -> std::io::Result<Box<dyn std::io::Write>> {
+    if two_pass {
+        Ok(Box::new(std::io::sink()))
+    } else {
+        fastx::create_output(path, bgzf_threads_opt)
+    }
+}
+
+/// Whether a read error is a compressed input ending part way through a stream
+fn is_truncated(err: &std::io::Error) -> bool {
+    err.kind() == std::io::ErrorKind::UnexpectedEof
+}
+
 fn single<
     T: fastx::Record,
     R: Iterator<Item = Result<T, std::io::Error>>,
     S: fastx::Writer<T>,
     U: std::io::Write,
+    O: clusters::Orientation,
 >(
     records: R,
     mut writer: S,
     clusters: &mut clusters::Clusters<U>,
-    use_revcomp: bool, // add boolean revcomp param
+    orientation: O,
+    leniency: warnings::Leniency,
 ) -> Result<(), Box<dyn Error>> {
     for result in records {
-        let record = box_bail!(result);
-        box_bail!(record
-            .check()
-            .map_err(|err| simple_error::simple_error!(err)));
+        if clusters.should_stop() {
+            break;
+        }
+        let record = match result {
+            Err(err) if leniency.allow_truncated && is_truncated(&err) => {
+                clusters.skip_anomaly(WarningKind::TruncatedInput, "", &err.to_string());
+                break;
+            }
+            result => box_bail!(result),
+        };
+        if let Err(err) = record.check() {
+            if leniency.skip_invalid {
+                clusters.skip_invalid(record.id(), err);
+                continue;
+            }
+            return Err(Box::new(simple_error::simple_error!(err)));
+        }
 
-        let result = clusters.insert_single(&record, use_revcomp);
+        let result = clusters.insert_single_oriented(&record, orientation);
         if box_bail!(result) {
             box_bail!(writer.write_record(&record));
         }
@@ -83,324 +311,6934 @@ fn single<
     Ok(())
 }
 
-fn pair<
+/// Deduplicates --single-input against the pairs' r1s and among itself,
+/// writing the reads that aren't duplicates to --single-output
+fn dedup_single_input<U: std::io::Write>(
+    (single_input, single_output): (&str, &str),
+    single_clusters: &mut clusters::Clusters<U>,
+    use_revcomp: bool,
+    leniency: warnings::Leniency,
+    bgzf_threads_opt: Option<usize>,
+    trimmer: trim::Trimmer,
+) -> Result<(), Box<dyn Error>> {
+    let (fastx_type, reader) = fastx::open_input(single_input)?;
+    let output = fastx::create_output(single_output, bgzf_threads_opt)?;
+    with_orientation!(use_revcomp, orientation => match fastx_type {
+        fastx::FastxType::Fasta => single(
+            fasta::Reader::new(reader).records(),
+            trim::TrimWriter::new(fasta::Writer::new(output), trimmer),
+            single_clusters,
+            orientation,
+            leniency,
+        ),
+        fastx::FastxType::Fastq => single(
+            fastq::Reader::new(reader).records(),
+            trim::TrimWriter::new(fastq::Writer::new(output), trimmer),
+            single_clusters,
+            orientation,
+            leniency,
+        ),
+        fastx::FastxType::Invalid => Err(Box::new(simple_error::simple_error!(
+            "single input file is not a valid FASTA or FASTQ file"
+        ))),
+    })
+}
+
+/// Deduplicates sets of three or more synchronized mates, writing each mate
+/// of the unique sets to its own output
+fn mates<
     T: fastx::Record,
     R: Iterator<Item = Result<T, std::io::Error>>,
     S: fastx::Writer<T>,
     U: std::io::Write,
 >(
-    records: paired::PairedRecords<T, R>,
-    mut writer_r1: S,
-    mut writer_r2: S,
+    records: paired::MateSets<T, R>,
+    writers: &mut [S],
     clusters: &mut clusters::Clusters<U>,
-    use_revcomp: bool, // add boolean revcomp param
+    use_revcomp: bool,
+    leniency: warnings::Leniency,
 ) -> Result<(), Box<dyn Error>> {
     for result in records {
+        if clusters.should_stop() {
+            break;
+        }
         let record = box_bail!(result);
+        if let Err(err) = record.check() {
+            if leniency.skip_invalid {
+                clusters.skip_invalid(record.id(), &err);
+                continue;
+            }
+            return Err(Box::new(simple_error::simple_error!(err)));
+        }
+        if box_bail!(clusters.insert_mates(&record, use_revcomp)) {
+            for (writer, mate) in writers.iter_mut().zip(record.mates()) {
+                box_bail!(writer.write_record(mate));
+            }
+        }
+    }
+    Ok(())
+}
 
-        box_bail!(record
-            .check()
-            .map_err(|err| simple_error::simple_error!(&err)));
+fn pair<
+    T: fastx::Record,
+    R: Iterator<Item = Result<T, std::io::Error>>,
+    S: fastx::Writer<T>,
+    I: fastx::Writer<T>,
+    C: fastx::Writer<T>,
+    U: std::io::Write,
+    O: clusters::Orientation,
+>(
+    records: paired::PairedRecords<T, R>,
+    (mut writer_r1, mut writer_r2): (S, S),
+    (mut interleaved_writer_opt, mut concat_writer_opt): (Option<I>, Option<C>),
+    mut orphans_opt: Option<(S, &mut clusters::Clusters<U>)>,
+    clusters: &mut clusters::Clusters<U>,
+    orientation: O,
+    leniency: warnings::Leniency,
+) -> Result<(), Box<dyn Error>> {
+    let mut records = records;
+    // unpaired and mismatched mates are only returned to be skipped
+    let next_mates = orphans_opt.is_some()
+        || leniency.skip_id_mismatch
+        || leniency.drop_unpaired
+        || leniency.allow_truncated;
+    loop {
+        if clusters.should_stop() {
+            break;
+        }
+        let mates = if next_mates {
+            records.next_mates()
+        } else {
+            records.next().map(|result| result.map(paired::Mates::Pair))
+        };
+        let record = match mates {
+            None => break,
+            Some(Err(err)) if leniency.allow_truncated && is_truncated(&err) => {
+                clusters.skip_anomaly(WarningKind::TruncatedInput, "", &err.to_string());
+                break;
+            }
+            Some(result) => match box_bail!(result) {
+                paired::Mates::Pair(record) => record,
+                paired::Mates::Mismatched(r1, r2) => {
+                    if leniency.skip_id_mismatch {
+                        let reason = format!("r2 had read id {}", r2.id());
+                        clusters.skip_anomaly(WarningKind::IdMismatch, r1.id(), &reason);
+                        continue;
+                    }
+                    // the ids differ so this returns the mismatch error
+                    box_bail!(paired::PairedRecord::try_from((r1, r2)))
+                }
+                paired::Mates::Orphan(mate, record) => {
+                    if orphans_opt.is_none() {
+                        if leniency.drop_unpaired {
+                            let reason = "its mate's input ended first";
+                            clusters.skip_anomaly(WarningKind::UnpairedRead, record.id(), reason);
+                            continue;
+                        }
+                        return Err(Box::new(simple_error::simple_error!(
+                            "one paired input ended before the other"
+                        )));
+                    }
+                    if let Err(err) = record.check() {
+                        if leniency.skip_invalid {
+                            clusters.skip_invalid(record.id(), err);
+                            continue;
+                        }
+                        return Err(Box::new(simple_error::simple_error!(err)));
+                    }
+                    // checked above
+                    let (orphan_writer, orphan_clusters) = orphans_opt.as_mut().unwrap();
+                    orphan_clusters.count_orphan(mate);
+                    if box_bail!(orphan_clusters.insert_single_oriented(&record, orientation)) {
+                        box_bail!(orphan_writer.write_record(&record));
+                    }
+                    continue;
+                }
+            },
+        };
+
+        if let Err(err) = record.check() {
+            let record = match orphans_opt.as_mut() {
+                Some((orphan_writer, orphan_clusters)) => match record.into_orphan() {
+                    Ok((mate, orphan)) => {
+                        orphan_clusters.count_orphan(mate);
+                        if box_bail!(orphan_clusters.insert_single_oriented(&orphan, orientation)) {
+                            box_bail!(orphan_writer.write_record(&orphan));
+                        }
+                        continue;
+                    }
+                    Err(record) => record,
+                },
+                None => record,
+            };
+            if leniency.skip_invalid {
+                clusters.skip_invalid(record.id(), &err);
+                continue;
+            }
+            return Err(Box::new(simple_error::simple_error!(&err)));
+        }
 
-        let result = clusters.insert_pair(&record, use_revcomp);
+        let result = clusters.insert_pair_oriented(&record, orientation);
         if box_bail!(result) {
             box_bail!(writer_r1.write_record(record.r1()));
             box_bail!(writer_r2.write_record(record.r2()));
+            if let Some(interleaved_writer) = interleaved_writer_opt.as_mut() {
+                box_bail!(interleaved_writer.write_record(record.r1()));
+                box_bail!(interleaved_writer.write_record(record.r2()));
+            }
+            if let Some(concat_writer) = concat_writer_opt.as_mut() {
+                box_bail!(concat_writer.write_record(record.r1()));
+                box_bail!(concat_writer.write_record(record.r2()));
+            }
         }
     }
     Ok(())
 }
 
-fn run_dedup<T: Into<std::ffi::OsString> + Clone, R: IntoIterator<Item = T>>(
-    args: R,
-) -> Result<clusters::Clusters<File>, Box<dyn Error>> {
-    let matches = App::new(clap::crate_name!())
+macro_rules! dedup_mates {
+    ($fastx:tt, $fastx_type:expr, $inputs:expr, $outputs:expr, $clusters:expr, $use_revcomp:expr, $leniency:expr, $bgzf_threads_opt:expr, $trimmer:expr, $max_record_length_opt:expr, $fix_legacy_quality:expr) => {{
+        let mut records = Vec::with_capacity($inputs.len());
+        for input in $inputs.iter() {
+            let (fastx_type, reader) = fastx::open_input(input)?;
+            if fastx_type != $fastx_type {
+                let message = format!(
+                    "mate inputs have different file types: {}, {}",
+                    $fastx_type, fastx_type
+                );
+                return Err(Box::new(simple_error::simple_error!(message)));
+            }
+            let reader = fastx::limit_record_length(&fastx_type, reader, $max_record_length_opt);
+            records.push(quality::LegacyQuality::new(
+                $fastx::Reader::new(reader).records(),
+                $fix_legacy_quality,
+            ));
+        }
+        let mut writers = Vec::with_capacity($outputs.len());
+        for output in $outputs.iter() {
+            writers.push(trim::TrimWriter::new(
+                $fastx::Writer::new(fastx::create_output(output, $bgzf_threads_opt)?),
+                $trimmer,
+            ));
+        }
+        mates(
+            paired::MateSets::new(records),
+            &mut writers,
+            &mut $clusters,
+            $use_revcomp,
+            $leniency,
+        )
+    }};
+}
+
+macro_rules! sort_dedup {
+    ($fastx:tt, $inputs:expr, $outputs:expr, $clusters:expr, $use_revcomp:expr, $bgzf_threads_opt:expr, $trimmer:expr, $chunk_size:expr) => {{
+        let open = |input: &str| -> Result<_, Box<dyn Error>> {
+            let (_, reader) = fastx::open_input(input)?;
+            Ok($fastx::Reader::new(reader).records())
+        };
+        match ($inputs.as_slice(), $outputs.as_slice()) {
+            ([input_r1], [output_r1]) => {
+                let mut writer = trim::TrimWriter::new(
+                    $fastx::Writer::new(fastx::create_output(output_r1, $bgzf_threads_opt)?),
+                    $trimmer,
+                );
+                sorted(
+                    $chunk_size,
+                    || open(input_r1),
+                    |record: &$fastx::Record| {
+                        record
+                            .check()
+                            .map_err(|err| simple_error::simple_error!(err))?;
+                        // the external sort keys on the low 64 bits
+                        Ok($clusters.single_hash(record, $use_revcomp) as u64)
+                    },
+                    |record| Ok(fastx::Writer::write_record(&mut writer, record)?),
+                )
+            }
+            ([input_r1, input_r2], [output_r1, output_r2]) => {
+                let fastx_type_r1 = fastx::open_input(input_r1)?.0;
+                let fastx_type_r2 = fastx::open_input(input_r2)?.0;
+                if fastx_type_r2 != fastx_type_r1 {
+                    let message = format!(
+                        "paired inputs have different file types r1: {}, r2: {}",
+                        fastx_type_r1, fastx_type_r2
+                    );
+                    return Err(Box::new(simple_error::simple_error!(message)));
+                }
+                let mut writer_r1 = trim::TrimWriter::new(
+                    $fastx::Writer::new(fastx::create_output(output_r1, $bgzf_threads_opt)?),
+                    $trimmer,
+                );
+                let mut writer_r2 = trim::TrimWriter::new(
+                    $fastx::Writer::new(fastx::create_output(output_r2, $bgzf_threads_opt)?),
+                    $trimmer,
+                );
+                sorted(
+                    $chunk_size,
+                    || Ok(paired::PairedRecords::new(open(input_r1)?, open(input_r2)?)),
+                    |record: &paired::PairedRecord<$fastx::Record>| {
+                        record
+                            .check()
+                            .map_err(|err| simple_error::simple_error!(&err))?;
+                        Ok($clusters.pair_hash(record, $use_revcomp) as u64)
+                    },
+                    |record| {
+                        fastx::Writer::write_record(&mut writer_r1, record.r1())?;
+                        Ok(fastx::Writer::write_record(&mut writer_r2, record.r2())?)
+                    },
+                )
+            }
+            _ => panic!("must have the same number of inputs and outputs"),
+        }
+    }};
+}
+
+macro_rules! write_representatives {
+    ($fastx:tt, $inputs:expr, $outputs:expr, $clusters:expr, $use_revcomp:expr, $bgzf_threads_opt:expr, $trimmer:expr) => {{
+        let open = |input: &str| -> Result<_, Box<dyn Error>> {
+            let (_, reader) = fastx::open_input(input)?;
+            Ok($fastx::Reader::new(reader).records())
+        };
+        let read_at = |input: &mut fastx::SeekableInput,
+                       offset: u64|
+         -> Result<$fastx::Record, Box<dyn Error>> {
+            match $fastx::Reader::new(input.seek(offset)?).records().next() {
+                Some(result) => Ok(result?),
+                None => Err(Box::new(simple_error::simple_error!(
+                    "no record at offset {}",
+                    offset
+                ))),
+            }
+        };
+        // with record offsets the inputs are only read at the representatives
+        let offsets_opt = $clusters.representative_offsets();
+        // the first pass didn't write the outputs
+        let create = |output: &str| -> Result<_, Box<dyn Error>> {
+            Ok(trim::TrimWriter::new(
+                $fastx::Writer::new(fastx::create_output(output, $bgzf_threads_opt)?),
+                $trimmer,
+            ))
+        };
+        match ($inputs, $outputs) {
+            ([input_r1], [output_r1]) => {
+                let mut writer = create(output_r1)?;
+                if let Some(offsets) = offsets_opt {
+                    let mut input = fastx::SeekableInput::open(input_r1)?;
+                    for [offset, _] in offsets {
+                        let record = read_at(&mut input, offset)?;
+                        fastx::Writer::write_record(&mut writer, &record)?;
+                    }
+                } else {
+                    for result in open(input_r1)? {
+                        let record = result?;
+                        if $clusters.is_single_representative(&record, $use_revcomp) {
+                            fastx::Writer::write_record(&mut writer, &record)?;
+                        }
+                    }
+                }
+            }
+            ([input_r1, input_r2], [output_r1, output_r2]) => {
+                let mut writer_r1 = create(output_r1)?;
+                let mut writer_r2 = create(output_r2)?;
+                if let Some(offsets) = offsets_opt {
+                    let mut input_r1 = fastx::SeekableInput::open(input_r1)?;
+                    let mut input_r2 = fastx::SeekableInput::open(input_r2)?;
+                    for [offset_r1, offset_r2] in offsets {
+                        let r1 = read_at(&mut input_r1, offset_r1)?;
+                        let r2 = read_at(&mut input_r2, offset_r2)?;
+                        fastx::Writer::write_record(&mut writer_r1, &r1)?;
+                        fastx::Writer::write_record(&mut writer_r2, &r2)?;
+                    }
+                } else {
+                    for result in paired::PairedRecords::new(open(input_r1)?, open(input_r2)?) {
+                        let record = result?;
+                        if $clusters.is_pair_representative(&record, $use_revcomp) {
+                            fastx::Writer::write_record(&mut writer_r1, record.r1())?;
+                            fastx::Writer::write_record(&mut writer_r2, record.r2())?;
+                        }
+                    }
+                }
+            }
+            _ => panic!("must have the same number of inputs and outputs"),
+        }
+        Ok(()) as Result<(), Box<dyn Error>>
+    }};
+}
+
+macro_rules! split_by_cluster {
+    ($fastx:tt, $extension:expr, $inputs:expr, $dir:expr, $min_cluster_size:expr, $clusters:expr, $use_revcomp:expr) => {{
+        let open = |input: &str| -> Result<_, Box<dyn Error>> {
+            let (_, reader) = fastx::open_input(input)?;
+            Ok($fastx::Reader::new(reader).records())
+        };
+        std::fs::create_dir_all($dir)?;
+        let mut writers: HashMap<String, $fastx::Writer<File>> = HashMap::new();
+        match $inputs {
+            [input_r1] => {
+                for result in open(input_r1)? {
+                    let record = result?;
+                    if let Some(cluster) = $clusters
+                        .single_cluster(&record, $use_revcomp)
+                        .filter(|cluster| cluster.size() >= $min_cluster_size)
+                    {
+                        cluster_writer(
+                            &mut writers,
+                            cluster,
+                            ($dir, $extension),
+                            $fastx::Writer::to_file,
+                        )?
+                        .write_record(&record)?;
+                    }
+                }
+            }
+            [input_r1, input_r2] => {
+                for result in paired::PairedRecords::new(open(input_r1)?, open(input_r2)?) {
+                    let record = result?;
+                    if let Some(cluster) = $clusters
+                        .pair_cluster(&record, $use_revcomp)
+                        .filter(|cluster| cluster.size() >= $min_cluster_size)
+                    {
+                        let writer = cluster_writer(
+                            &mut writers,
+                            cluster,
+                            ($dir, $extension),
+                            $fastx::Writer::to_file,
+                        )?;
+                        writer.write_record(record.r1())?;
+                        writer.write_record(record.r2())?;
+                    }
+                }
+            }
+            _ => panic!("must have one or two inputs"),
+        }
+        Ok(()) as Result<(), Box<dyn Error>>
+    }};
+}
+
+macro_rules! write_abundance {
+    ($fastx:tt, $outputs:expr, $csv_writer:expr, $clusters:expr, $use_revcomp:expr) => {{
+        let open = |output: &str| -> Result<_, Box<dyn Error>> {
+            let (_, reader) = fastx::open_input(output)?;
+            Ok($fastx::Reader::new(reader).records())
+        };
+        let total_records = $clusters.total_records() as f64;
+        // the deduped outputs hold exactly the representatives, in input order
+        match $outputs {
+            [output_r1] => {
+                $csv_writer.write_record(&[
+                    "representative read id",
+                    "sequence",
+                    "count",
+                    "frequency",
+                ])?;
+                for result in open(output_r1)? {
+                    let record = result?;
+                    let cluster = $clusters
+                        .single_cluster(&record, $use_revcomp)
+                        .ok_or_else(|| missing_cluster(record.id()))?;
+                    $csv_writer.write_record(&[
+                        cluster.id(),
+                        str::from_utf8(record.seq())?,
+                        &cluster.size().to_string(),
+                        &(cluster.size() as f64 / total_records).to_string(),
+                    ])?;
+                }
+            }
+            [output_r1, output_r2] => {
+                $csv_writer.write_record(&[
+                    "representative read id",
+                    "sequence",
+                    "r2 sequence",
+                    "count",
+                    "frequency",
+                ])?;
+                for result in paired::PairedRecords::new(open(output_r1)?, open(output_r2)?) {
+                    let record = result?;
+                    let cluster = $clusters
+                        .pair_cluster(&record, $use_revcomp)
+                        .ok_or_else(|| missing_cluster(record.r1().id()))?;
+                    $csv_writer.write_record(&[
+                        cluster.id(),
+                        str::from_utf8(record.r1().seq())?,
+                        str::from_utf8(record.r2().seq())?,
+                        &cluster.size().to_string(),
+                        &(cluster.size() as f64 / total_records).to_string(),
+                    ])?;
+                }
+            }
+            _ => panic!("must have one or two outputs"),
+        }
+        Ok(()) as Result<(), Box<dyn Error>>
+    }};
+}
+
+macro_rules! write_weights {
+    ($fastx:tt, $outputs:expr, $weights_writer:expr, $clusters:expr, $use_revcomp:expr) => {{
+        let open = |output: &str| -> Result<_, Box<dyn Error>> {
+            let (_, reader) = fastx::open_input(output)?;
+            Ok($fastx::Reader::new(reader).records())
+        };
+        // the deduped outputs hold exactly the representatives under the ids
+        // downstream tools will see
+        match $outputs {
+            [output_r1] => {
+                for result in open(output_r1)? {
+                    let record = result?;
+                    let cluster = $clusters
+                        .single_cluster(&record, $use_revcomp)
+                        .ok_or_else(|| missing_cluster(record.id()))?;
+                    $weights_writer.write_record(&[record.id(), &cluster.size().to_string()])?;
+                }
+            }
+            [output_r1, output_r2] => {
+                for result in paired::PairedRecords::new(open(output_r1)?, open(output_r2)?) {
+                    let record = result?;
+                    let cluster = $clusters
+                        .pair_cluster(&record, $use_revcomp)
+                        .ok_or_else(|| missing_cluster(record.r1().id()))?;
+                    $weights_writer.write_record(&[record.id(), &cluster.size().to_string()])?;
+                }
+            }
+            _ => panic!("must have one or two outputs"),
+        }
+        Ok(()) as Result<(), Box<dyn Error>>
+    }};
+}
+
+macro_rules! relabel_outputs {
+    ($fastx:tt, $outputs:expr, $clusters:expr, $use_revcomp:expr, $relabel_opt:expr, $sizeout:expr, $annotate_headers:expr, $bgzf_threads_opt:expr) => {{
+        let open = |output: &str| -> Result<_, Box<dyn Error>> {
+            let (_, reader) = fastx::open_input(output)?;
+            Ok($fastx::Reader::new(reader).records())
+        };
+        // outputs are rewritten next to themselves then moved over the originals
+        let temp_path = |output: &str| format!("{}.relabel", output);
+        match $outputs {
+            [output_r1] => {
+                let mut writer = $fastx::Writer::new(fastx::create_output(
+                    temp_path(output_r1),
+                    $bgzf_threads_opt,
+                )?);
+                for (index, result) in open(output_r1)?.enumerate() {
+                    let record = result?;
+                    // windowed and sorted runs no longer hold the clusters, so
+                    // they're only looked up for what --relabel alone doesn't need
+                    let (size_opt, hash_opt) = if $sizeout || $annotate_headers {
+                        let cluster = $clusters
+                            .single_cluster(&record, $use_revcomp)
+                            .ok_or_else(|| missing_cluster(record.id()))?;
+                        cluster_annotations(cluster, $sizeout, $annotate_headers)
+                    } else {
+                        (None, None)
+                    };
+                    let id = relabeled_id(record.id(), index, $relabel_opt, size_opt, hash_opt);
+                    writer.write_record(&fastx::Record::with_id(&record, &id))?;
+                }
+            }
+            [output_r1, output_r2] => {
+                let mut writer_r1 = $fastx::Writer::new(fastx::create_output(
+                    temp_path(output_r1),
+                    $bgzf_threads_opt,
+                )?);
+                let mut writer_r2 = $fastx::Writer::new(fastx::create_output(
+                    temp_path(output_r2),
+                    $bgzf_threads_opt,
+                )?);
+                let records = paired::PairedRecords::new(open(output_r1)?, open(output_r2)?);
+                for (index, result) in records.enumerate() {
+                    let record = result?;
+                    let (size_opt, hash_opt) = if $sizeout || $annotate_headers {
+                        let cluster = $clusters
+                            .pair_cluster(&record, $use_revcomp)
+                            .ok_or_else(|| missing_cluster(record.r1().id()))?;
+                        cluster_annotations(cluster, $sizeout, $annotate_headers)
+                    } else {
+                        (None, None)
+                    };
+                    let id_r1 =
+                        relabeled_id(record.r1().id(), index, $relabel_opt, size_opt, hash_opt);
+                    let id_r2 =
+                        relabeled_id(record.r2().id(), index, $relabel_opt, size_opt, hash_opt);
+                    writer_r1.write_record(&fastx::Record::with_id(record.r1(), &id_r1))?;
+                    writer_r2.write_record(&fastx::Record::with_id(record.r2(), &id_r2))?;
+                }
+            }
+            _ => panic!("must have one or two outputs"),
+        }
+        for output in $outputs.iter() {
+            std::fs::rename(temp_path(output), output)?;
+        }
+        Ok(()) as Result<(), Box<dyn Error>>
+    }};
+}
+
+/// The error for a deduped read whose cluster can't be found again from
+/// its output record
+fn missing_cluster(id: &str) -> Box<dyn Error> {
+    let message = format!("no cluster found for deduped read {}", id);
+    Box::new(simple_error::SimpleError::new(message))
+}
+
+/// The cluster size and hash a representative's header is annotated with,
+/// with --sizeout and --annotate-headers
+fn cluster_annotations(
+    cluster: &clusters::Cluster,
+    sizeout: bool,
+    annotate_headers: bool,
+) -> (Option<u64>, Option<u128>) {
+    let size_opt = if sizeout { Some(cluster.size()) } else { None };
+    let hash_opt = if annotate_headers {
+        Some(cluster.hash())
+    } else {
+        None
+    };
+    (size_opt, hash_opt)
+}
+
+/// A representative's id in vsearch style, `<prefix><n>` with `--relabel`,
+/// suffixed with `;size=<cluster size>` with `--sizeout` and
+/// `;key=<cluster hash>` with `--annotate-headers`
+fn relabeled_id(
+    id: &str,
+    index: usize,
+    relabel_opt: Option<&str>,
+    size_opt: Option<u64>,
+    hash_opt: Option<u128>,
+) -> String {
+    let mut relabeled = match relabel_opt {
+        Some(prefix) => format!("{}{}", prefix, index + 1),
+        None => id.to_owned(),
+    };
+    if let Some(size) = size_opt {
+        relabeled.push_str(&format!(";size={}", size));
+    }
+    if let Some(hash) = hash_opt {
+        relabeled.push_str(&format!(";key={}", clusters::format_hash(hash)));
+    }
+    relabeled
+}
+
+/// The writer for a cluster's members, opening it on the cluster's first member
+fn cluster_writer<'a, W, O: FnOnce(PathBuf) -> std::io::Result<W>>(
+    writers: &'a mut HashMap<String, W>,
+    cluster: &clusters::Cluster,
+    (dir, extension): (&str, &str),
+    open: O,
+) -> std::io::Result<&'a mut W> {
+    Ok(match writers.entry(cluster.id().to_owned()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(open(cluster_path(dir, cluster.id(), extension))?),
+    })
+}
+
+/// The file in `dir` holding the members of the cluster represented by `id`,
+/// with characters that aren't safe in file names replaced by `_`
+fn cluster_path(dir: &str, id: &str, extension: &str) -> PathBuf {
+    let name = id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    Path::new(dir).join(format!("{}.{}", name, extension))
+}
+
+/// Rewrites FASTQ outputs with each representative's qualities aggregated
+/// over its cluster, keeping those of representatives no longer tracked
+fn aggregate_output_qualities<T: std::io::Write>(
+    outputs: &[&str],
+    clusters: &clusters::Clusters<T>,
+    use_revcomp: bool,
+    bgzf_threads_opt: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let open = |output: &str| -> Result<_, Box<dyn Error>> {
+        let (_, reader) = fastx::open_input(output)?;
+        Ok(fastq::Reader::new(reader).records())
+    };
+    // outputs are rewritten next to themselves then moved over the originals
+    let temp_path = |output: &str| format!("{}.quals", output);
+    let create = |output: &str| -> Result<_, Box<dyn Error>> {
+        let writer = fastx::create_output(temp_path(output), bgzf_threads_opt)?;
+        Ok(fastq::Writer::new(writer))
+    };
+    let with_qual = |record: &fastq::Record, qual: &[u8]| {
+        fastq::Record::with_attrs(record.id(), record.desc(), record.seq(), qual)
+    };
+    match outputs {
+        [output_r1] => {
+            let mut writer = create(output_r1)?;
+            for result in open(output_r1)? {
+                let record = result?;
+                match clusters.single_quals(&record, use_revcomp) {
+                    Some(qual) => writer.write_record(&with_qual(&record, &qual))?,
+                    None => writer.write_record(&record)?,
+                }
+            }
+        }
+        [output_r1, output_r2] => {
+            let mut writer_r1 = create(output_r1)?;
+            let mut writer_r2 = create(output_r2)?;
+            for result in paired::PairedRecords::new(open(output_r1)?, open(output_r2)?) {
+                let record = result?;
+                match clusters.pair_quals(&record, use_revcomp) {
+                    Some((qual_r1, qual_r2)) => {
+                        writer_r1.write_record(&with_qual(record.r1(), &qual_r1))?;
+                        writer_r2.write_record(&with_qual(record.r2(), &qual_r2))?;
+                    }
+                    None => {
+                        writer_r1.write_record(record.r1())?;
+                        writer_r2.write_record(record.r2())?;
+                    }
+                }
+            }
+        }
+        _ => panic!("must have one or two outputs"),
+    }
+    for output in outputs.iter() {
+        std::fs::rename(temp_path(output), output)?;
+    }
+    Ok(())
+}
+
+/// Writes the virtual offset of every read in each BGZF output, one column per output
+fn write_bgzf_index(index_output: &str, outputs: &[&str]) -> Result<(), Box<dyn Error>> {
+    let indexes = outputs
+        .iter()
+        .map(|output| bgzf::index_reads(&mut bgzf::BgzfReader::new(File::open(output)?)))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let mut csv_writer = csv::Writer::from_path(index_output)?;
+    if indexes.len() == 1 {
+        csv_writer.write_record(["representative read id", "virtual offset"])?;
+    } else {
+        csv_writer.write_record([
+            "representative read id",
+            "r1 virtual offset",
+            "r2 virtual offset",
+        ])?;
+    }
+    for (i, (id, offset)) in indexes[0].iter().enumerate() {
+        let mut record = vec![id.clone(), offset.to_string()];
+        for index in indexes[1..].iter() {
+            let (_, offset) = index
+                .get(i)
+                .ok_or_else(|| simple_error::simple_error!("outputs have different read counts"))?;
+            record.push(offset.to_string());
+        }
+        csv_writer.write_record(record)?;
+    }
+    Ok(())
+}
+
+/// Total records, unique records, and the cluster size histogram of a sorted run
+type SortedCounts = (u64, u64, BTreeMap<u64, u64>);
+
+/// Deduplicates with an external sort: the first pass spills each record's
+/// cluster hash to disk, the second re-reads the input writing representatives
+fn sorted<X, R, O, H, W>(
+    chunk_size: usize,
+    open_records: O,
+    hash: H,
+    mut write: W,
+) -> Result<SortedCounts, Box<dyn Error>>
+where
+    R: Iterator<Item = Result<X, std::io::Error>>,
+    O: Fn() -> Result<R, Box<dyn Error>>,
+    H: Fn(&X) -> Result<u64, Box<dyn Error>>,
+    W: FnMut(&X) -> Result<(), Box<dyn Error>>,
+{
+    let mut sorter = external::ExternalSorter::new(chunk_size);
+    let mut total_records = 0;
+    for result in open_records()? {
+        let record = result?;
+        sorter.push((hash(&record)?, total_records))?;
+        total_records += 1;
+    }
+
+    let external::Representatives {
+        mut indices,
+        unique_records,
+        cluster_size_histogram,
+    } = external::representatives(sorter.finish()?, chunk_size)?;
+    let mut next_index_opt = indices.next().transpose()?.map(|(index, _)| index);
+    for (index, result) in open_records()?.enumerate() {
+        match next_index_opt {
+            Some(next_index) if next_index == index as u64 => {
+                write(&result?)?;
+                next_index_opt = indices.next().transpose()?.map(|(index, _)| index);
+            }
+            Some(_) => (),
+            None => break,
+        }
+    }
+    Ok((total_records, unique_records, cluster_size_histogram))
+}
+
+/// Chooses a prefix length from the first `sample_size` reads of each input
+fn auto_prefix_length(
+    inputs: &[String],
+    sample_size: usize,
+) -> Result<Option<usize>, Box<dyn Error>> {
+    let mut sampler = autoprefix::PrefixSampler::default();
+    for input in inputs.iter() {
+        let (fastx_type, reader) = fastx::open_input(input)?;
+        match fastx_type {
+            fastx::FastxType::Fasta => {
+                for result in fasta::Reader::new(reader).records().take(sample_size) {
+                    sampler.add(result?.seq(), None);
+                }
+            }
+            fastx::FastxType::Fastq => {
+                for result in fastq::Reader::new(reader).records().take(sample_size) {
+                    let record = result?;
+                    if record.check().is_ok() {
+                        sampler.add(record.seq(), Some(record.qual()));
+                    }
+                }
+            }
+            fastx::FastxType::Invalid => (),
+        }
+    }
+    Ok(sampler.prefix_length())
+}
+
+/// An opened input's type, reader, and size in bytes if known
+type OpenedInput = (fastx::FastxType, Box<dyn Read>, usize);
+
+/// Opens the r1 input, either the first `--inputs` path or a streamed `--sra` accession
+#[cfg_attr(not(feature = "sra"), allow(unused_variables))]
+fn open_input_r1<'a, I: Iterator<Item = &'a str>>(
+    matches: &ArgMatches,
+    inputs: &mut I,
+) -> Result<OpenedInput, Box<dyn Error>> {
+    #[cfg(feature = "sra")]
+    {
+        if let Some(accession) = matches.value_of("sra") {
+            let reader = Box::new(sra::SraReader::open(accession)?);
+            let (fastx_type, reader) = fastx::peek_fastx_type(reader)?;
+            return Ok((fastx_type, reader, 0));
+        }
+    }
+    // presence guarunteed by clap
+    let input_r1 = inputs.next().unwrap();
+    if let Some(byte_range) = matches.value_of("byte-range") {
+        let (start, end) = parse_byte_range(byte_range)?;
+        let (fastx_type, reader) = fastx::open_byte_range(input_r1, start, end)?;
+        return Ok((fastx_type, reader, (end - start) as usize));
+    }
+    let path = archive::split_member(input_r1)
+        .map(|(path, _)| path)
+        .unwrap_or(input_r1);
+    let bytes = File::open(path)?.metadata()?.len() as usize;
+    let (fastx_type, reader) = fastx::open_input(input_r1)?;
+    Ok((fastx_type, reader, bytes))
+}
+
+/// Parses a field delimiter given as `comma`, `tab`, or a single character
+fn parse_delimiter(delimiter: &str) -> Result<u8, String> {
+    match delimiter {
+        "comma" => Ok(b','),
+        "tab" => Ok(b'\t'),
+        _ if delimiter.len() == 1 => Ok(delimiter.as_bytes()[0]),
+        _ => Err(format!(
+            "invalid delimiter: {}, expected comma, tab, or a single character",
+            delimiter
+        )),
+    }
+}
+
+/// Parses a byte range given as `START:END`, end exclusive
+fn parse_byte_range(range: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("invalid byte range: {}, expected START:END", range);
+    let (start, end) = range.split_once(':').ok_or_else(invalid)?;
+    match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start < end => Ok((start, end)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses a key salt given as an even number of hex digits
+fn parse_key_salt(salt: &str) -> Result<Vec<u8>, String> {
+    // the salt is a secret, so it is left out of the error
+    let invalid = || "invalid key salt, expected an even number of hex digits".to_owned();
+    if salt.is_empty() || !salt.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..salt.len())
+        .step_by(2)
+        .map(|i| {
+            salt.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+/// Parses a duration given as whole numbers of `d`, `h`, `m`, and `s`,
+/// like `2h` or `1h30m`, or as a number of seconds
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "invalid duration: {}, expected e.g. 2h, 90m, or 1h30m",
+            duration
+        )
+    };
+    if duration.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(seconds) = duration.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+    let mut seconds = 0;
+    let mut rest = duration;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let count = rest[..digits].parse::<u64>().map_err(|_| invalid())?;
+        let unit = match rest[digits..].chars().next() {
+            Some('d') => 24 * 60 * 60,
+            Some('h') => 60 * 60,
+            Some('m') => 60,
+            Some('s') => 1,
+            _ => return Err(invalid()),
+        };
+        seconds += count.saturating_mul(unit);
+        rest = &rest[digits + 1..];
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parses a number of bytes with an optional binary `K`, `M`, `G`, or `T`
+/// suffix, like `512M` or `4G`
+fn parse_size(size: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid size: {}, expected e.g. 512M or 4G", size);
+    let (digits, shift) = match size.chars().last() {
+        Some('k') | Some('K') => (&size[..size.len() - 1], 10),
+        Some('m') | Some('M') => (&size[..size.len() - 1], 20),
+        Some('g') | Some('G') => (&size[..size.len() - 1], 30),
+        Some('t') | Some('T') => (&size[..size.len() - 1], 40),
+        _ => (size, 0),
+    };
+    let count = digits.parse::<u64>().map_err(|_| invalid())?;
+    count.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
+/// The `CZID_DEDUP_*` environment variable that can set an argument
+fn env_var_name(name: &str) -> String {
+    format!("CZID_DEDUP_{}", name.to_uppercase().replace('-', "_"))
+}
+
+/// Every flag's name and long form
+///
+/// Options read their environment variables through clap, but clap would
+/// make flags take a value if they did, so `with_env_flags` adds these.
+const ENV_FLAGS: &[(&str, &str)] = &[
+    ("auto-pair", "--auto-pair"),
+    ("fix-legacy-quality", "--fix-legacy-quality"),
+    ("allow-orphans", "--allow-orphans"),
+    ("cluster-append", "--cluster-append"),
+    ("no-cluster-header", "--no-cluster-header"),
+    ("auto-prefix", "--auto-prefix"),
+    ("merge-overlaps", "--merge-overlaps"),
+    ("two-tier", "--two-tier"),
+    ("two-pass", "--two-pass"),
+    ("double-hash", "--double-hash"),
+    ("bgzf", "--bgzf"),
+    ("cluster-hash", "--cluster-hash"),
+    ("legacy-rc-suffix", "--legacy-rc-suffix"),
+    ("size-revcomp-counts", "--size-revcomp-counts"),
+    ("sizeout", "--sizeout"),
+    ("annotate-headers", "--annotate-headers"),
+    ("assume-sorted", "--assume-sorted"),
+    ("skip-invalid", "--skip-invalid"),
+    ("strict", "--strict"),
+    ("lenient", "--lenient"),
+    ("strip-read-suffix", "--strip-read-suffix"),
+    ("revcomp-r2-only", "--revcomp-r2-only"),
+    ("estimate-unique", "--estimate-unique"),
+    ("legacy-semantics", "--legacy-semantics"),
+    ("revcomp", "--reverse-complement"),
+];
+
+/// Whether a flag's environment variable is set to `1`, `true`, or `yes`
+fn is_env_flag_set(name: &str) -> bool {
+    std::env::var(env_var_name(name))
+        .map(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// `args` with the flags turned on by their environment variables added,
+/// so clap checks their conflicts and requirements like those of flags on
+/// the command line, which take precedence
+fn with_env_flags(
+    app: &App,
+    args: Vec<std::ffi::OsString>,
+    flags: &[(&str, &str)],
+) -> Vec<std::ffi::OsString> {
+    let env_flags = flags
+        .iter()
+        .filter(|(name, _)| is_env_flag_set(name))
+        .collect::<Vec<_>>();
+    if env_flags.is_empty() {
+        return args;
+    }
+    // a flag can only be given once
+    let matches = app.clone().get_matches_from(args.clone());
+    let mut args = args;
+    for (name, long) in env_flags {
+        if !matches.is_present(name) {
+            args.push(std::ffi::OsString::from(*long));
+        }
+    }
+    args
+}
+
+/// Arguments naming files a run writes, besides the deduplicated outputs
+const OUTPUT_ARGS: &[&str] = &[
+    "cluster-output",
+    "cluster-size-output",
+    "summary-json",
+    "interleave-output",
+    "concat-pairs-output",
+    "orphan-output",
+    "single-output",
+    "lane-matrix-output",
+    "bgzf-index",
+    "abundance-output",
+    "audit-output",
+    "edges-output",
+    "id-map-output",
+    "emit-key-dump",
+    "save-state",
+];
+
+/// A way of running that some options can't be combined with
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RunMode {
+    /// --trim-to or --quality-trim
+    Trimmed,
+    /// --legacy-semantics
+    Legacy,
+    /// --by position
+    ByPosition,
+    /// three or more synchronized mates
+    ManyMates,
+    /// --mode sort
+    Sort,
+    /// --two-pass, or a --representative that implies it
+    TwoPass,
+    /// --save-state or --load-state
+    ClusterState,
+}
+
+/// The options a way of running can't be combined with
+struct Unsupported {
+    mode: RunMode,
+    /// rejected whenever they're present
+    args: &'static [&'static str],
+    /// rejected only when given, their defaults are fine
+    explicit_args: &'static [&'static str],
+}
+
+/// Every way of running's unsupported options, checked as `run_dedup` picks it
+const UNSUPPORTED: &[Unsupported] = &[
+    Unsupported {
+        mode: RunMode::Trimmed,
+        args: &[
+            "abundance-output",
+            "emit-weights",
+            "sizeout",
+            "annotate-headers",
+            "aggregate-qualities",
+        ],
+        explicit_args: &[],
+    },
+    Unsupported {
+        mode: RunMode::Legacy,
+        args: &[
+            "revcomp-r2-only",
+            "two-tier",
+            "cluster-hash",
+            "read-group",
+            "no-cluster-header",
+            "strip-read-suffix",
+        ],
+        explicit_args: &["pair-canonical", "cluster-delimiter"],
+    },
+    Unsupported {
+        mode: RunMode::ByPosition,
+        args: &[
+            "cluster-output",
+            "cluster-size-output",
+            "interleave-output",
+            "concat-pairs-output",
+            "prefix-length",
+            "auto-prefix",
+            "revcomp",
+            "two-tier",
+            "mode",
+            "window",
+            "assume-sorted",
+            "two-pass",
+            "representative",
+            "filter-expr",
+            "id-filter",
+            "id-exclude",
+            "time-limit",
+            "cancel-file",
+            "trim-to",
+            "quality-trim",
+            "split-by-cluster",
+            "abundance-output",
+            "emit-weights",
+            "relabel",
+            "sizeout",
+            "annotate-headers",
+            "edges-output",
+            "audit-sample",
+            "id-map-output",
+            "emit-key-dump",
+            "key-salt",
+            "double-hash",
+            "save-state",
+            "load-state",
+            "byte-range",
+            "sra",
+            "read-group",
+            "lane-matrix-output",
+            "gz-members",
+            "allow-orphans",
+            "single-input",
+            "max-mismatches",
+            "aggregate-qualities",
+            "merge-overlaps",
+            "length-bins",
+            "estimate-unique",
+            "on-duplicate-ids",
+            "max-n-fraction",
+            "max-record-length",
+            "fix-legacy-quality",
+        ],
+        explicit_args: &[],
+    },
+    Unsupported {
+        mode: RunMode::ManyMates,
+        args: &[
+            "interleave-output",
+            "concat-pairs-output",
+            "allow-orphans",
+            "single-input",
+            "read-group",
+            "lane-matrix-output",
+            "gz-members",
+            "split-by-cluster",
+            "abundance-output",
+            "emit-weights",
+            "relabel",
+            "sizeout",
+            "annotate-headers",
+            "aggregate-qualities",
+            "merge-overlaps",
+            "lenient",
+            "representative",
+            "two-pass",
+            "save-state",
+            "load-state",
+            "byte-range",
+        ],
+        explicit_args: &[],
+    },
+    Unsupported {
+        mode: RunMode::Sort,
+        args: &[
+            "cluster-output",
+            "cluster-size-output",
+            "interleave-output",
+            "concat-pairs-output",
+            "two-tier",
+            "window",
+            "sra",
+            "allow-orphans",
+            "single-input",
+            "filter-expr",
+            "id-filter",
+            "id-exclude",
+            "time-limit",
+            "cancel-file",
+            "emit-key-dump",
+            "id-map-output",
+            "estimate-unique",
+            "max-record-length",
+            "max-n-fraction",
+            "lenient",
+            "aggregate-qualities",
+            "empty-reads",
+            "on-duplicate-ids",
+            "length-bins",
+            "representative",
+            "two-pass",
+            "read-group",
+            "lane-matrix-output",
+            "gz-members",
+            "fix-legacy-quality",
+            "save-state",
+            "load-state",
+            "byte-range",
+            "audit-sample",
+            "double-hash",
+        ],
+        explicit_args: &[],
+    },
+    Unsupported {
+        mode: RunMode::TwoPass,
+        args: &[
+            "window",
+            "assume-sorted",
+            "max-mismatches",
+            "cluster-output",
+            "edges-output",
+            "audit-sample",
+            "id-map-output",
+            "interleave-output",
+            "concat-pairs-output",
+            "allow-orphans",
+            "single-input",
+            "aggregate-qualities",
+            "byte-range",
+            "gz-members",
+            "fix-legacy-quality",
+        ],
+        explicit_args: &[],
+    },
+    Unsupported {
+        mode: RunMode::ClusterState,
+        args: &["window", "assume-sorted"],
+        explicit_args: &[],
+    },
+];
+
+/// Fails on the first option `mode` doesn't support, naming `cause` as what
+/// it can't be combined with
+fn check_supported(matches: &ArgMatches, mode: RunMode, cause: &str) -> Result<(), Box<dyn Error>> {
+    for unsupported in UNSUPPORTED
+        .iter()
+        .filter(|unsupported| unsupported.mode == mode)
+    {
+        let arg_opt = unsupported
+            .args
+            .iter()
+            .find(|arg| matches.is_present(arg))
+            .or_else(|| {
+                unsupported
+                    .explicit_args
+                    .iter()
+                    .find(|arg| matches.occurrences_of(arg) > 0)
+            });
+        if let Some(arg) = arg_opt {
+            let message = format!("--{} is not supported with {}", arg, cause);
+            return Err(Box::new(simple_error::SimpleError::new(message)));
+        }
+    }
+    Ok(())
+}
+
+/// The deduplicating command's arguments
+fn dedup_app<'a, 'b>() -> App<'a, 'b> {
+    let app = App::new(clap::crate_name!())
         .version(clap::crate_version!())
         .author(clap::crate_authors!())
         .about(clap::crate_description!())
+        .after_help("Run `czid-dedup compare-clusters --help` to compare two runs' cluster files.")
         .arg(
             Arg::with_name("inputs")
                 .short("i")
                 .long("inputs")
-                .help("Input FASTQ")
+                .help("Input FASTQ, or a tar/zip archive member as archive.tar.gz::R1.fastq, three or more for synchronized mates")
                 .multiple(true)
                 .min_values(1)
-                .max_values(2)
                 .takes_value(true)
-                .required(true),
+                .required_unless("sra"),
         )
         .arg(
             Arg::with_name("deduped-outputs")
                 .short("o")
                 .long("deduped-outputs")
-                .help("Output deduped FASTQ")
+                .help("Output deduped FASTQ, one for each input")
                 .multiple(true)
                 .min_values(1)
-                .max_values(2)
                 .takes_value(true)
-                .required(true),
+                .required_unless("output-template"),
         )
         .arg(
-            Arg::with_name("cluster-output")
-                .short("c")
-                .long("cluster-output")
-                .help("Output cluster file")
-                .takes_value(true),
+            Arg::with_name("output-template")
+                .long("output-template")
+                .help("Names outputs from their inputs instead of --deduped-outputs, e.g. '{dir}/{stem}.dedup.{ext}', with {pair} for R1/R2")
+                .takes_value(true)
+                .env("CZID_DEDUP_OUTPUT_TEMPLATE"),
         )
         .arg(
-            Arg::with_name("cluster-size-output")
-                .long("cluster-size-output")
-                .help("Output cluster size file")
-                .takes_value(true),
+            Arg::with_name("read-group")
+                .long("read-group")
+                .help("Read group of each lane, comma separated, added to the cluster file. Lanes are given as comma separated input files")
+                .takes_value(true)
+                .env("CZID_DEDUP_READ_GROUP"),
         )
         .arg(
-            Arg::with_name("prefix-length")
-                .short("l")
-                .long("prefix-length")
-                .help("Length of the prefix to consider")
-                .takes_value(true),
+            Arg::with_name("gz-members")
+                .long("gz-members")
+                .help("Reads each member of gzip inputs made by concatenating others, like cat a.gz b.gz, on its own: report lists each member's reads in the summary, read-groups also makes each member a read group")
+                .takes_value(true)
+                .env("CZID_DEDUP_GZ_MEMBERS"),
         )
         .arg(
-            Arg::with_name("revcomp")
-                .short("r")
-                .long("reverse-complement")
-                .help("Clusters using reverse complement also")
+            Arg::with_name("lane-matrix-output")
+                .long("lane-matrix-output")
+                .help("Output CSV matrix of duplicates by their representative's lane and their own lane")
+                .takes_value(true)
+                .env("CZID_DEDUP_LANE_MATRIX_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("auto-pair")
+                .long("auto-pair")
+                .help("Infers the R2 input and output from the R1 paths, e.g. _R1_ to _R2_ or _1.fastq to _2.fastq")
                 .takes_value(false)
+                .requires("inputs"),
         )
-        .get_matches_from(args);
+        .arg(
+            Arg::with_name("interleave-output")
+                .long("interleave-output")
+                .help("Also write deduped pairs interleaved into one file (gzipped if it ends in .gz)")
+                .takes_value(true)
+                .env("CZID_DEDUP_INTERLEAVE_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("concat-pairs-output")
+                .long("concat-pairs-output")
+                .help("Also write each deduped pair as one record of r1, a spacer, then r2 (gzipped if it ends in .gz)")
+                .takes_value(true)
+                .env("CZID_DEDUP_CONCAT_PAIRS_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("concat-spacer")
+                .long("concat-spacer")
+                .help("Bases written between r1 and r2 with --concat-pairs-output [default: NNNNNNNNNN]")
+                .takes_value(true)
+                .requires("concat-pairs-output")
+                .env("CZID_DEDUP_CONCAT_SPACER"),
+        )
+        .arg(
+            Arg::with_name("filter-expr")
+                .long("filter-expr")
+                .help("Keeps only reads matching this expression before clustering, e.g. 'length>=50 && meanq>=20'")
+                .takes_value(true)
+                .env("CZID_DEDUP_FILTER_EXPR"),
+        )
+        .arg(
+            Arg::with_name("id-filter")
+                .long("id-filter")
+                .help("Keeps only reads whose id matches this regex before clustering")
+                .takes_value(true)
+                .env("CZID_DEDUP_ID_FILTER"),
+        )
+        .arg(
+            Arg::with_name("id-exclude")
+                .long("id-exclude")
+                .help("Drops reads whose id matches this regex before clustering")
+                .takes_value(true)
+                .env("CZID_DEDUP_ID_EXCLUDE"),
+        )
+        .arg(
+            Arg::with_name("trim-to")
+                .long("trim-to")
+                .help("Trims written representatives to at most this many bases")
+                .takes_value(true)
+                .env("CZID_DEDUP_TRIM_TO"),
+        )
+        .arg(
+            Arg::with_name("fix-legacy-quality")
+                .long("fix-legacy-quality")
+                .help("Detects Solexa and Illumina 1.3+ qualities and rewrites them as Phred+33 before deduplicating")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("quality-trim")
+                .long("quality-trim")
+                .help("Trims bases below this quality from the 3' end of written representatives")
+                .takes_value(true)
+                .env("CZID_DEDUP_QUALITY_TRIM"),
+        )
+        .arg(
+            Arg::with_name("summary-json")
+                .long("summary-json")
+                .help("Output JSON of the run summary, including the cluster size histogram")
+                .takes_value(true)
+                .env("CZID_DEDUP_SUMMARY_JSON"),
+        )
+        .arg(
+            Arg::with_name("summary-webhook")
+                .long("summary-webhook")
+                .help("URL the JSON run summary is POSTed to when the run ends, retrying failed requests")
+                .takes_value(true)
+                .env("CZID_DEDUP_SUMMARY_WEBHOOK"),
+        )
+        .arg(
+            Arg::with_name("allow-orphans")
+                .long("allow-orphans")
+                .help("Keeps reads whose mate is missing or invalid, writing them to --orphan-output")
+                .takes_value(false)
+                .requires("orphan-output"),
+        )
+        .arg(
+            Arg::with_name("single-input")
+                .long("single-input")
+                .help("Single reads, like orphans from earlier trimming, deduplicated against the pairs' r1s and written to --single-output")
+                .takes_value(true)
+                .requires("single-output")
+                .env("CZID_DEDUP_SINGLE_INPUT"),
+        )
+        .arg(
+            Arg::with_name("single-output")
+                .long("single-output")
+                .help("Output for the single reads of --single-input that aren't duplicates")
+                .takes_value(true)
+                .requires("single-input")
+                .env("CZID_DEDUP_SINGLE_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("orphan-output")
+                .long("orphan-output")
+                .help("Output for orphaned reads, deduplicated among themselves")
+                .takes_value(true)
+                .env("CZID_DEDUP_ORPHAN_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("cluster-output")
+                .short("c")
+                .long("cluster-output")
+                .help("Output cluster file")
+                .takes_value(true)
+                .env("CZID_DEDUP_CLUSTER_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("cluster-size-output")
+                .long("cluster-size-output")
+                .help("Output cluster size file")
+                .takes_value(true)
+                .env("CZID_DEDUP_CLUSTER_SIZE_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("cluster-delimiter")
+                .long("cluster-delimiter")
+                .help("Field delimiter of the cluster and cluster size files, comma, tab, or a single character")
+                .takes_value(true)
+                .env("CZID_DEDUP_CLUSTER_DELIMITER")
+                .default_value("comma"),
+        )
+        .arg(
+            Arg::with_name("cluster-flush")
+                .long("cluster-flush")
+                .help("When cluster file rows are written, per-record to follow it during a run, or on-exit for throughput")
+                .possible_values(&["per-record", "buffered", "on-exit"])
+                .takes_value(true)
+                .env("CZID_DEDUP_CLUSTER_FLUSH")
+                .default_value("buffered"),
+        )
+        .arg(
+            Arg::with_name("cluster-append")
+                .long("cluster-append")
+                .help("Appends to the cluster file if it exists, writing its header only when it is new")
+                .takes_value(false)
+                .requires("cluster-output"),
+        )
+        .arg(
+            Arg::with_name("no-cluster-header")
+                .long("no-cluster-header")
+                .help("Leaves the header row out of the cluster and cluster size files")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("prefix-length")
+                .short("l")
+                .long("prefix-length")
+                .help("Length of the prefix to consider")
+                .takes_value(true)
+                .env("CZID_DEDUP_PREFIX_LENGTH"),
+        )
+        .arg(
+            Arg::with_name("auto-prefix")
+                .long("auto-prefix")
+                .help("Chooses the prefix length from the read lengths and qualities of a sample of reads")
+                .takes_value(false)
+                .conflicts_with_all(&["prefix-length", "sra"]),
+        )
+        .arg(
+            Arg::with_name("auto-prefix-sample")
+                .long("auto-prefix-sample")
+                .help("Number of reads sampled from each input by --auto-prefix")
+                .default_value("10000")
+                .takes_value(true)
+                .env("CZID_DEDUP_AUTO_PREFIX_SAMPLE"),
+        )
+        .arg(
+            Arg::with_name("merge-overlaps")
+                .long("merge-overlaps")
+                .help("Clusters overlapping pairs by their merged fragment sequence")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .long("mode")
+                .help("hash keeps every cluster in memory, sort uses an external sort bounded by disk instead")
+                .possible_values(&["hash", "sort"])
+                .takes_value(true)
+                .env("CZID_DEDUP_MODE"),
+        )
+        .arg(
+            Arg::with_name("two-tier")
+                .long("two-tier")
+                .help("Collapses identical reads before clustering by prefix, reporting both")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .help("Seed for any random sampling, reported in the summary")
+                .takes_value(true)
+                .env("CZID_DEDUP_SEED")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("pair-canonical")
+                .long("pair-canonical")
+                .help("How read pairs are reverse complemented with --reverse-complement")
+                .possible_values(&["lexmin", "fragment", "r2-only", "off"])
+                .takes_value(true)
+                .env("CZID_DEDUP_PAIR_CANONICAL")
+                .default_value("lexmin"),
+        )
+        .arg(
+            Arg::with_name("empty-reads")
+                .long("empty-reads")
+                .help("Whether reads with no bases fail the run, are dropped with a warning, or are kept [default: drop]")
+                .possible_values(&["error", "drop", "keep"])
+                .takes_value(true)
+                .env("CZID_DEDUP_EMPTY_READS"),
+        )
+        .arg(
+            Arg::with_name("length-bins")
+                .long("length-bins")
+                .help("Comma separated upper bounds of the read length bins the summary reports duplication for [default: 50,100,150]")
+                .takes_value(true)
+                .use_delimiter(true)
+                .env("CZID_DEDUP_LENGTH_BINS"),
+        )
+        .arg(
+            Arg::with_name("representative")
+                .long("representative")
+                .help("Which read represents each cluster, the first, the longest, or the highest mean quality; all but first imply --two-pass [default: first]")
+                .possible_values(&["first", "longest", "best-quality"])
+                .takes_value(true)
+                .env("CZID_DEDUP_REPRESENTATIVE"),
+        )
+        .arg(
+            Arg::with_name("two-pass")
+                .long("two-pass")
+                .help("Builds the clusters in a first pass and writes their representatives in a second pass over the inputs")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("save-state")
+                .long("save-state")
+                .help("Saves the clusters to a binary state file that a later run can load")
+                .takes_value(true)
+                .env("CZID_DEDUP_SAVE_STATE"),
+        )
+        .arg(
+            Arg::with_name("by")
+                .long("by")
+                .help("Finds duplicates by sequence, or for BAM input by mapped position and orientation [default: sequence]")
+                .possible_values(&["sequence", "position"])
+                .takes_value(true)
+                .env("CZID_DEDUP_BY"),
+        )
+        .arg(
+            Arg::with_name("byte-range")
+                .long("byte-range")
+                .help("Only deduplicates the records of a single uncompressed input whose headers start in this START:END byte range, so workers can split a large file between them")
+                .takes_value(true)
+                .env("CZID_DEDUP_BYTE_RANGE"),
+        )
+        .arg(
+            Arg::with_name("load-state")
+                .long("load-state")
+                .help("Loads the clusters of a state file saved with the same key options, so reads matching them are duplicates")
+                .takes_value(true)
+                .env("CZID_DEDUP_LOAD_STATE"),
+        )
+        .arg(
+            Arg::with_name("on-duplicate-ids")
+                .long("on-duplicate-ids")
+                .help("Whether records with an already seen id are kept with a warning, fail the run, or aren't checked for [default: ignore]")
+                .possible_values(&["warn", "error", "ignore"])
+                .takes_value(true)
+                .env("CZID_DEDUP_ON_DUPLICATE_IDS"),
+        )
+        .arg(
+            Arg::with_name("max-n-fraction")
+                .long("max-n-fraction")
+                .help("Drops reads, or pairs with a mate, whose fraction of N bases is above this before clustering")
+                .takes_value(true)
+                .env("CZID_DEDUP_MAX_N_FRACTION"),
+        )
+        .arg(
+            Arg::with_name("ambiguity")
+                .long("ambiguity")
+                .help("Whether IUPAC ambiguity codes like R or Y are keyed as is, as N, or match any compatible base")
+                .possible_values(&["keep", "normalize", "match"])
+                .takes_value(true)
+                .env("CZID_DEDUP_AMBIGUITY")
+                .default_value("keep"),
+        )
+        .arg(
+            Arg::with_name("aggregate-qualities")
+                .long("aggregate-qualities")
+                .help("Writes FASTQ representatives with the max, or capped sum, of their cluster's qualities at each base")
+                .possible_values(&["max", "sum"])
+                .takes_value(true)
+                .env("CZID_DEDUP_AGGREGATE_QUALITIES"),
+        )
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .help("Clusters reads by their sequence, or experimentally by their canonical k-mer multiset")
+                .possible_values(&["sequence", "kmerset"])
+                .default_value("sequence")
+                .takes_value(true)
+                .env("CZID_DEDUP_KEY"),
+        )
+        .arg(
+            Arg::with_name("kmer-size")
+                .long("kmer-size")
+                .help("k-mer length for --key kmerset")
+                .default_value("15")
+                .takes_value(true)
+                .env("CZID_DEDUP_KMER_SIZE"),
+        )
+        .arg(
+            Arg::with_name("key-salt")
+                .long("key-salt")
+                .help("Hex salt mixed into every hash, so shared key dumps and cluster hashes can't be matched to guessed sequences without it")
+                .takes_value(true)
+                .env("CZID_DEDUP_KEY_SALT"),
+        )
+        .arg(
+            Arg::with_name("double-hash")
+                .long("double-hash")
+                .help("Also keys records by a second, independent hash, only counting a duplicate when both hashes match")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("max-mismatches")
+                .long("max-mismatches")
+                .help("Near-duplicate mode, reads within this many mismatches of a representative join its cluster")
+                .takes_value(true)
+                .env("CZID_DEDUP_MAX_MISMATCHES"),
+        )
+        .arg(
+            Arg::with_name("bgzf")
+                .long("bgzf")
+                .help("Writes deduplicated outputs as BGZF, block gzip that can be indexed")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("bgzf-index")
+                .long("bgzf-index")
+                .help("Output CSV of each representative's BGZF virtual offset in the deduplicated outputs")
+                .takes_value(true)
+                .env("CZID_DEDUP_BGZF_INDEX")
+                .requires("bgzf"),
+        )
+        .arg(
+            Arg::with_name("max-record-length")
+                .long("max-record-length")
+                .help("Fails the run on a FASTA record with more bases than this, before reading it into memory")
+                .takes_value(true)
+                .env("CZID_DEDUP_MAX_RECORD_LENGTH"),
+        )
+        .arg(
+            Arg::with_name("time-limit")
+                .long("time-limit")
+                .help("Stops reading after this long, e.g. 2h, 90m, or 1h30m, writing the outputs so far and exiting with status 75")
+                .takes_value(true)
+                .env("CZID_DEDUP_TIME_LIMIT"),
+        )
+        .arg(
+            Arg::with_name("cancel-file")
+                .long("cancel-file")
+                .help("Stops reading once this file exists, writing the outputs so far and exiting with status 130")
+                .takes_value(true)
+                .env("CZID_DEDUP_CANCEL_FILE"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .help("Threads used to compress --bgzf outputs, defaulting to the CPUs available, within a container's CPU limit")
+                .takes_value(true)
+                .env("CZID_DEDUP_THREADS"),
+        )
+        .arg(
+            Arg::with_name("memory-limit")
+                .long("memory-limit")
+                .help("Memory --mode sort may use, like 512M or 4G, bounding how many reads are sorted at once. Without it a container's memory limit can only make the default smaller")
+                .takes_value(true)
+                .env("CZID_DEDUP_MEMORY_LIMIT"),
+        )
+        .arg(
+            Arg::with_name("hash-region")
+                .long("hash-region")
+                .help("Bases hashed to find a read's cluster")
+                .possible_values(&["prefix", "full"])
+                .default_value("prefix")
+                .takes_value(true)
+                .env("CZID_DEDUP_HASH_REGION"),
+        )
+        .arg(
+            Arg::with_name("compare-region")
+                .long("compare-region")
+                .help("Bases compared with a cluster's representative before a read joins it")
+                .possible_values(&["none", "prefix", "full"])
+                .default_value("none")
+                .takes_value(true)
+                .env("CZID_DEDUP_COMPARE_REGION"),
+        )
+        .arg(
+            Arg::with_name("short-mate")
+                .long("short-mate")
+                .help("How a mate shorter than the prefix length is keyed in a pair")
+                .possible_values(&["truncate", "pad", "flag"])
+                .default_value("truncate")
+                .takes_value(true)
+                .env("CZID_DEDUP_SHORT_MATE"),
+        )
+        .arg(
+            Arg::with_name("window")
+                .long("window")
+                .help("Only detects duplicates within the most recent N million reads, bounding memory")
+                .takes_value(true)
+                .env("CZID_DEDUP_WINDOW")
+                .conflicts_with_all(&["two-tier", "cluster-size-output"]),
+        )
+        .arg(
+            Arg::with_name("abundance-output")
+                .long("abundance-output")
+                .help("Output CSV of each representative's sequence, cluster size, and relative frequency")
+                .takes_value(true)
+                .env("CZID_DEDUP_ABUNDANCE_OUTPUT")
+                .conflicts_with_all(&["window", "assume-sorted", "mode"]),
+        )
+        .arg(
+            Arg::with_name("emit-weights")
+                .long("emit-weights")
+                .help("Output headerless TSV of each deduped read id and its cluster size, as read weights for abundance-aware tools")
+                .takes_value(true)
+                .env("CZID_DEDUP_EMIT_WEIGHTS")
+                .conflicts_with_all(&["window", "assume-sorted", "mode"]),
+        )
+        .arg(
+            Arg::with_name("cluster-hash")
+                .long("cluster-hash")
+                .help("Adds a cluster hash column to the cluster and cluster size files, for compare-clusters and joins")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("legacy-rc-suffix")
+                .long("legacy-rc-suffix")
+                .help("Marks reverse complemented reads in the cluster file with an \" (rc)\" read id suffix instead of a revcomp column")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("size-revcomp-counts")
+                .long("size-revcomp-counts")
+                .help("Adds a column to the cluster size file counting each cluster's members that joined as a reverse complement")
+                .takes_value(false)
+                .requires("cluster-size-output"),
+        )
+        .arg(
+            Arg::with_name("audit-sample")
+                .long("audit-sample")
+                .help("Writes this fraction of duplicates, e.g. 0.001, next to their representatives with full sequences to --audit-output")
+                .takes_value(true)
+                .requires("audit-output")
+                .env("CZID_DEDUP_AUDIT_SAMPLE"),
+        )
+        .arg(
+            Arg::with_name("audit-output")
+                .long("audit-output")
+                .help("Output CSV of the duplicates sampled by --audit-sample")
+                .takes_value(true)
+                .requires("audit-sample")
+                .env("CZID_DEDUP_AUDIT_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("edges-output")
+                .long("edges-output")
+                .help("Output CSV edge list of (representative, duplicate, weight) for graph tools")
+                .takes_value(true)
+                .env("CZID_DEDUP_EDGES_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("id-map-output")
+                .long("id-map-output")
+                .help("Output CSV mapping every read id to its representative's, for reassigning counts")
+                .takes_value(true)
+                .env("CZID_DEDUP_ID_MAP_OUTPUT"),
+        )
+        .arg(
+            Arg::with_name("emit-key-dump")
+                .long("emit-key-dump")
+                .help("Output CSV of every record's canonical key, for comparing runs record by record across versions and machines")
+                .takes_value(true)
+                .env("CZID_DEDUP_EMIT_KEY_DUMP"),
+        )
+        .arg(
+            Arg::with_name("sizeout")
+                .long("sizeout")
+                .help("Appends ;size=N with the cluster size to representative headers")
+                .takes_value(false)
+                .conflicts_with_all(&["window", "assume-sorted", "mode"]),
+        )
+        .arg(
+            Arg::with_name("annotate-headers")
+                .long("annotate-headers")
+                .help("Appends ;key=<cluster hash> to representative headers, to join them with the cluster files")
+                .takes_value(false)
+                .conflicts_with_all(&["window", "assume-sorted", "mode"]),
+        )
+        .arg(
+            Arg::with_name("relabel")
+                .long("relabel")
+                .help("Renames representatives to this prefix followed by their 1-based position")
+                .takes_value(true)
+                .env("CZID_DEDUP_RELABEL")
+                .conflicts_with("mode"),
+        )
+        .arg(
+            Arg::with_name("split-by-cluster")
+                .long("split-by-cluster")
+                .help("Writes the members of each cluster to its own file in this directory")
+                .takes_value(true)
+                .env("CZID_DEDUP_SPLIT_BY_CLUSTER")
+                .conflicts_with_all(&["window", "assume-sorted", "mode", "sra"]),
+        )
+        .arg(
+            Arg::with_name("min-cluster-size")
+                .long("min-cluster-size")
+                .help("Only splits out clusters with at least this many reads")
+                .takes_value(true)
+                .env("CZID_DEDUP_MIN_CLUSTER_SIZE")
+                .default_value("2"),
+        )
+        .arg(
+            Arg::with_name("assume-sorted")
+                .long("assume-sorted")
+                .help("Input is sorted by sequence, only compares each read with the previous cluster")
+                .takes_value(false)
+                .conflicts_with_all(&["two-tier", "cluster-size-output", "window", "mode"]),
+        )
+        .arg(
+            Arg::with_name("skip-invalid")
+                .long("skip-invalid")
+                .help("Skips invalid records with a warning instead of failing")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("Fails on any anomaly: invalid records, mismatched ids, unequal mate counts, or truncated input")
+                .takes_value(false)
+                .conflicts_with_all(&["lenient", "skip-invalid", "allow-orphans"]),
+        )
+        .arg(
+            Arg::with_name("lenient")
+                .long("lenient")
+                .help("Warns and continues past anomalies where safe: skips invalid records and mismatched pairs, drops unpaired reads, and stops at truncated input")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("strip-read-suffix")
+                .long("strip-read-suffix")
+                .help("Removes /1, /2, and Casava comment suffixes from ids in cluster files")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("revcomp-r2-only")
+                .long("revcomp-r2-only")
+                .help("Clusters pairs using the reverse complement of r2 only, same as --reverse-complement --pair-canonical r2-only")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("estimate-unique")
+                .long("estimate-unique")
+                .help("Also estimates unique reads in fixed memory with a HyperLogLog, a check on --window and --assume-sorted counts")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("legacy-semantics")
+                .long("legacy-semantics")
+                .help("Reproduces the original czid-dedup's cluster file, pair reverse complementing, and defaults to diff against it")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("revcomp")
+                .short("r")
+                .long("reverse-complement")
+                .help("Clusters using reverse complement also")
+                .takes_value(false),
+        );
+    #[cfg(feature = "sra")]
+    let app = app.arg(
+        Arg::with_name("sra")
+            .long("sra")
+            .help("SRA accession to stream reads from with fastq-dump")
+            .takes_value(true)
+            .env("CZID_DEDUP_SRA")
+            .conflicts_with("inputs"),
+    );
+    app
+}
+
+fn run_dedup<T: Into<std::ffi::OsString> + Clone, R: IntoIterator<Item = T>>(
+    args: R,
+) -> Result<DedupSummary, Box<dyn Error>> {
+    let app = dedup_app();
+    let args = args.into_iter().map(Into::into).collect::<Vec<_>>();
+    let args = with_env_flags(&app, args, ENV_FLAGS);
+    let matches = app.get_matches_from(args);
+
+    let auto_pair = matches.is_present("auto-pair");
+    let mut input_paths = archive::expand_inputs(matches.values_of("inputs").unwrap_or_default())?;
+    // either this or --output-template is guarunteed by clap
+    let given_output_paths = matches
+        .values_of("deduped-outputs")
+        .map(|outputs| outputs.collect::<Vec<_>>())
+        .unwrap_or_default();
+    let mut inferred_output_r2_opt = None;
+    if auto_pair {
+        let infer = |r1_path: &str| {
+            fastx::r2_path(r1_path).ok_or_else(|| {
+                let message = format!("--auto-pair can't infer an R2 path from {}", r1_path);
+                simple_error::SimpleError::new(message)
+            })
+        };
+        if let [input_r1] = input_paths.as_slice() {
+            let input_r2 = input_r1
+                .split(',')
+                .map(infer)
+                .collect::<Result<Vec<_>, _>>()?
+                .join(",");
+            input_paths.push(input_r2);
+        }
+        if let [output_r1] = given_output_paths.as_slice() {
+            inferred_output_r2_opt = Some(infer(output_r1)?);
+        }
+    }
+    let templated_output_paths = match matches.value_of("output-template") {
+        Some(template) => {
+            if !given_output_paths.is_empty() {
+                return Err(Box::new(simple_error::simple_error!(
+                    "--output-template and --deduped-outputs can't be used together"
+                )));
+            }
+            if input_paths.is_empty() {
+                return Err(Box::new(simple_error::simple_error!(
+                    "--output-template needs input files to name outputs from"
+                )));
+            }
+            let paired = input_paths.len() > 1;
+            let templated = input_paths
+                .iter()
+                .enumerate()
+                .map(|(i, input)| {
+                    let first_lane = input.split(',').next().unwrap_or(input);
+                    fastx::output_from_template(
+                        template,
+                        first_lane,
+                        Some(i + 1).filter(|_| paired),
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(simple_error::SimpleError::new)?;
+            for (i, output) in templated.iter().enumerate() {
+                if templated[..i].contains(output) || input_paths.contains(output) {
+                    let message = format!(
+                        "--output-template {} gives {} to more than one output or to an input",
+                        template, output
+                    );
+                    return Err(Box::new(simple_error::SimpleError::new(message)));
+                }
+            }
+            templated
+        }
+        None => Vec::new(),
+    };
+    // lanes deduplicated jointly are given as comma separated files for each mate
+    let lanes_r1 = input_paths
+        .first()
+        .map(|input_r1| input_r1.split(',').collect::<Vec<_>>())
+        .unwrap_or_default();
+    let lanes_r2_opt = input_paths
+        .get(1)
+        .map(|input_r2| input_r2.split(',').collect::<Vec<_>>());
+    if lanes_r2_opt
+        .as_ref()
+        .is_some_and(|lanes_r2| lanes_r2.len() != lanes_r1.len())
+    {
+        return Err(Box::new(simple_error::simple_error!(
+            "r1 and r2 inputs have different numbers of lanes"
+        )));
+    }
+    let lane_paths = input_paths
+        .iter()
+        .flat_map(|input| input.split(','))
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    let read_groups_opt = matches
+        .value_of("read-group")
+        .map(|read_groups| read_groups.split(',').collect::<Vec<_>>());
+    if let Some(read_groups) = read_groups_opt.as_ref() {
+        if read_groups.len() != lanes_r1.len().max(1) {
+            return Err(Box::new(simple_error::simple_error!(
+                "--read-group needs one read group for each lane"
+            )));
+        }
+    }
+    let output_paths = given_output_paths
+        .into_iter()
+        .chain(inferred_output_r2_opt.as_deref())
+        .chain(templated_output_paths.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+    // every path is checked before any work, so all their problems are reported together
+    let run_paths = preflight::RunPaths {
+        inputs: lane_paths
+            .iter()
+            .map(String::as_str)
+            .chain(matches.value_of("single-input"))
+            .collect(),
+        replaceable_inputs: matches.value_of("load-state").into_iter().collect(),
+        outputs: output_paths
+            .iter()
+            .copied()
+            .chain(OUTPUT_ARGS.iter().filter_map(|arg| matches.value_of(arg)))
+            .collect(),
+    };
+    let path_problems = run_paths.problems();
+    if !path_problems.is_empty() {
+        let message = format!("invalid paths:\n  {}", path_problems.join("\n  "));
+        return Err(Box::new(simple_error::SimpleError::new(message)));
+    }
+    for warning in run_paths.space_warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    let mut outputs = output_paths.iter().copied();
+    let interleave_output_opt = matches.value_of("interleave-output");
+    let concat_spacer = matches.value_of("concat-spacer").unwrap_or("NNNNNNNNNN");
+    if !concat_spacer.bytes().all(|base| base.is_ascii_alphabetic()) {
+        let message = format!("invalid concat spacer: {}, expected bases", concat_spacer);
+        return Err(Box::new(simple_error::SimpleError::new(message)));
+    }
+    let concat_pairs_opt = matches
+        .value_of("concat-pairs-output")
+        .map(|concat_output| (concat_output, concat_spacer.as_bytes()));
+    let trimmer = trim::Trimmer {
+        trim_to_opt: matches
+            .value_of("trim-to")
+            .map(str::parse::<usize>)
+            .transpose()?,
+        quality_trim_opt: matches
+            .value_of("quality-trim")
+            .map(str::parse::<u8>)
+            .transpose()?,
+    };
+    // these find each representative's cluster again from the deduped
+    // outputs, which trimmed representatives no longer key to
+    if trimmer != trim::Trimmer::default() {
+        check_supported(&matches, RunMode::Trimmed, "--trim-to or --quality-trim")?;
+    }
+    let fix_legacy_quality = matches.is_present("fix-legacy-quality");
+    let filter_expr_opt = matches
+        .value_of("filter-expr")
+        .map(str::parse::<filter::FilterExpr>)
+        .transpose()?;
+    let id_filter = filter::IdFilter {
+        include_opt: matches.value_of("id-filter").map(Regex::new).transpose()?,
+        exclude_opt: matches.value_of("id-exclude").map(Regex::new).transpose()?,
+    };
+    let id_filter_opt = if id_filter.include_opt.is_some() || id_filter.exclude_opt.is_some() {
+        Some(id_filter)
+    } else {
+        None
+    };
+    let is_filtering = filter_expr_opt.is_some() || id_filter_opt.is_some();
+    let deadline_opt = matches
+        .value_of("time-limit")
+        .map(parse_duration)
+        .transpose()?
+        .map(|limit| Instant::now() + limit);
+    let audit_fraction_opt = matches
+        .value_of("audit-sample")
+        .map(str::parse::<f64>)
+        .transpose()?;
+    if let Some(audit_fraction) = audit_fraction_opt {
+        if !(audit_fraction > 0.0 && audit_fraction <= 1.0) {
+            let message = format!(
+                "invalid audit sample: {}, expected a fraction above 0 and at most 1",
+                audit_fraction
+            );
+            return Err(Box::new(simple_error::SimpleError::new(message)));
+        }
+    }
+    let cancellation_opt = matches.value_of("cancel-file").map(|cancel_file| {
+        let token = cancel::CancellationToken::new();
+        cancel::watch_file(token.clone(), PathBuf::from(cancel_file));
+        token
+    });
+    let orphan_output_opt = matches.value_of("orphan-output");
+    if orphan_output_opt.is_some() && !matches.is_present("allow-orphans") {
+        return Err(Box::new(simple_error::simple_error!(
+            "--orphan-output requires --allow-orphans"
+        )));
+    }
+    if orphan_output_opt.is_some() && output_paths.len() != 2 {
+        return Err(Box::new(simple_error::simple_error!(
+            "orphan output requires paired inputs"
+        )));
+    }
+    let single_io_opt = matches
+        .value_of("single-input")
+        .zip(matches.value_of("single-output"));
+    if single_io_opt.is_some() && output_paths.len() != 2 {
+        return Err(Box::new(simple_error::simple_error!(
+            "--single-input requires paired inputs"
+        )));
+    }
+    let cluster_output_opt = matches.value_of("cluster-output");
+    let cluster_size_output_opt = matches.value_of("cluster-size-output");
+    let auto_prefix = matches.is_present("auto-prefix");
+    let prefix_length_opt = if auto_prefix {
+        // presence guarunteed by clap
+        let sample_size = matches
+            .value_of("auto-prefix-sample")
+            .unwrap()
+            .parse::<usize>()?;
+        auto_prefix_length(&lane_paths, sample_size)?
+    } else {
+        matches
+            .value_of("prefix-length")
+            .map(str::parse::<usize>)
+            .transpose()?
+    };
+    let output_r1 = outputs.next().unwrap();
+    // the original czid-dedup only reverse complemented with -r, kept the
+    // larger orientation of pairs, and wrote two comma separated cluster columns
+    let legacy = matches.is_present("legacy-semantics");
+    if legacy {
+        check_supported(&matches, RunMode::Legacy, "--legacy-semantics")?;
+    }
+    let revcomp_r2_only = matches.is_present("revcomp-r2-only");
+    let use_revcomp = if legacy {
+        matches.is_present("revcomp")
+    } else {
+        matches.is_present("revcomp") || revcomp_r2_only
+    };
+    let leniency = if matches.is_present("lenient") {
+        warnings::Leniency::lenient()
+    } else {
+        warnings::Leniency {
+            skip_invalid: matches.is_present("skip-invalid"),
+            ..warnings::Leniency::default()
+        }
+    };
+    let max_n_fraction_opt = match matches.value_of("max-n-fraction") {
+        Some(max_n_fraction) => {
+            let max_n_fraction = max_n_fraction.parse::<f64>()?;
+            if !(0.0..=1.0).contains(&max_n_fraction) {
+                return Err(Box::new(simple_error::simple_error!(
+                    "--max-n-fraction must be between 0 and 1"
+                )));
+            }
+            Some(max_n_fraction)
+        }
+        None => None,
+    };
+    let length_bins = match matches.values_of("length-bins") {
+        Some(bounds) => bounds
+            .map(|bound| bound.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()?,
+        None => summary::DEFAULT_LENGTH_BINS.to_vec(),
+    };
+    if length_bins.contains(&0) || length_bins.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(Box::new(simple_error::simple_error!(
+            "--length-bins must be ascending lengths of at least 1"
+        )));
+    }
+    let max_record_length_opt = match matches.value_of("max-record-length") {
+        Some(max_record_length) => Some(max_record_length.parse::<usize>()?),
+        None => None,
+    };
+    let threads = match matches.value_of("threads") {
+        Some(threads) => threads.parse::<usize>()?,
+        None => resources::default_threads(),
+    };
+    let bgzf_threads_opt = if matches.is_present("bgzf") {
+        Some(threads)
+    } else {
+        None
+    };
+
+    // presence guarunteed by clap
+    let cluster_delimiter = if legacy {
+        b','
+    } else {
+        parse_delimiter(matches.value_of("cluster-delimiter").unwrap())?
+    };
+    let options = clusters::ClusterOptions {
+        prefix_length_opt,
+        merge_overlaps: matches.is_present("merge-overlaps"),
+        two_tier: matches.is_present("two-tier"),
+        // presence guarunteed by clap
+        seed: matches.value_of("seed").unwrap().parse::<u64>()?,
+        pair_canonical: if legacy {
+            clusters::PairCanonical::Lexmax
+        } else if revcomp_r2_only {
+            clusters::PairCanonical::R2Only
+        } else {
+            matches.value_of("pair-canonical").unwrap().parse()?
+        },
+        strip_read_suffix: matches.is_present("strip-read-suffix"),
+        read_groups: read_groups_opt.is_some()
+            || matches.value_of("gz-members") == Some("read-groups"),
+        window_opt: match matches.value_of("window") {
+            Some(window) => Some(window.parse::<u64>()? * 1_000_000),
+            None => None,
+        },
+        assume_sorted: matches.is_present("assume-sorted"),
+        cluster_hash: matches.is_present("cluster-hash"),
+        size_revcomp_counts: matches.is_present("size-revcomp-counts"),
+        // presence guarunteed by clap
+        short_mate: matches.value_of("short-mate").unwrap().parse()?,
+        hash_region: matches.value_of("hash-region").unwrap().parse()?,
+        compare_region_opt: match matches.value_of("compare-region") {
+            Some("none") | None => None,
+            Some(region) => Some(region.parse()?),
+        },
+        key: matches.value_of("key").unwrap().parse()?,
+        kmer_size: matches.value_of("kmer-size").unwrap().parse()?,
+        key_salt_opt: matches
+            .value_of("key-salt")
+            .map(parse_key_salt)
+            .transpose()?,
+        max_mismatches_opt: match matches.value_of("max-mismatches") {
+            Some(max_mismatches) => Some(max_mismatches.parse()?),
+            None => None,
+        },
+        quality_aggregation_opt: match matches.value_of("aggregate-qualities") {
+            Some(aggregation) => Some(aggregation.parse()?),
+            None => None,
+        },
+        // no clap default so sort mode, which keeps empty reads, can tell it was set
+        empty_reads: match matches.value_of("empty-reads") {
+            Some(empty_reads) => empty_reads.parse()?,
+            None if legacy => clusters::EmptyReads::Keep,
+            None => clusters::EmptyReads::Drop,
+        },
+        length_bins,
+        representative: match matches.value_of("representative") {
+            Some(representative) => representative.parse()?,
+            None => clusters::Representative::First,
+        },
+        duplicate_ids: match matches.value_of("on-duplicate-ids") {
+            Some(duplicate_ids) => duplicate_ids.parse()?,
+            None => clusters::DuplicateIds::Ignore,
+        },
+        max_n_fraction_opt,
+        // presence guarunteed by clap
+        ambiguity: matches.value_of("ambiguity").unwrap().parse()?,
+        cluster_delimiter_opt: Some(cluster_delimiter),
+        omit_cluster_header: matches.is_present("no-cluster-header"),
+        append_cluster_output: matches.is_present("cluster-append"),
+        legacy_cluster_output: legacy,
+        legacy_rc_suffix: matches.is_present("legacy-rc-suffix"),
+        estimate_unique: matches.is_present("estimate-unique"),
+        // members are for library callers, the CLI streams them to the cluster file
+        keep_members: false,
+        // presence guarunteed by clap
+        cluster_flush: matches.value_of("cluster-flush").unwrap().parse()?,
+        double_hash: matches.is_present("double-hash"),
+    };
+    let aggregate_qualities = options.quality_aggregation_opt.is_some();
+    if options.key == clusters::KeyMode::KmerSet && options.compare_region_opt.is_some() {
+        return Err(Box::new(simple_error::simple_error!(
+            "--compare-region is not supported with --key kmerset"
+        )));
+    }
+
+    // aligned reads are deduplicated by where they map rather than by sequence
+    if matches.value_of("by") == Some("position") {
+        check_supported(&matches, RunMode::ByPosition, "--by position")?;
+        let (input, output) = match (input_paths.as_slice(), output_paths.as_slice()) {
+            ([input], [output]) if !input.contains(',') && bam::is_bam(input)? => (input, output),
+            _ => {
+                return Err(Box::new(simple_error::simple_error!(
+                    "--by position needs a single BAM input and output"
+                )))
+            }
+        };
+        let open = || bam::BamReader::new(std::io::BufReader::new(File::open(input)?));
+        // the first pass finds the best scoring read at each position
+        let mut position_dedup = position::PositionDedup::new();
+        let mut reader = open()?;
+        while let Some(record) = reader.read_record()? {
+            position_dedup.score(&record);
+        }
+        position_dedup.finish_scoring();
+        let mut reader = open()?;
+        let mut writer = bam::BamWriter::new(
+            std::io::BufWriter::new(File::create(output)?),
+            reader.header(),
+            threads,
+        )?;
+        while let Some(record) = reader.read_record()? {
+            if !position_dedup.is_duplicate(&record) {
+                writer.write_record(&record)?;
+            }
+        }
+        writer.finish()?;
+        let clusters = clusters::Clusters::from_file_with_options(None::<&str>, options, 0)?;
+        let mut summary = clusters.summary(use_revcomp);
+        summary.total_records = position_dedup.total_records();
+        summary.duplicate_records = position_dedup.duplicate_records();
+        summary.unique_records = summary.total_records - summary.duplicate_records;
+        summary.duplication_rate =
+            summary::duplication_rate(summary.duplicate_records, summary.total_records);
+        summary.input_files = vec![input.to_owned()];
+        summary.output_files = vec![output.to_string()];
+        report_summary(&matches, &summary)?;
+        return Ok(summary);
+    }
+
+    // three or more synchronized mates, like r1, r2, and an index read
+    if input_paths.len() > 2 || output_paths.len() > 2 {
+        if input_paths.len() != output_paths.len() {
+            return Err(Box::new(simple_error::simple_error!(
+                "synchronized mates need one output for each input"
+            )));
+        }
+        check_supported(&matches, RunMode::ManyMates, "more than two mates")?;
+        if matches.value_of("mode") == Some("sort") || lanes_r1.len() > 1 {
+            return Err(Box::new(simple_error::simple_error!(
+                "--mode sort and multiple lanes are not supported with more than two mates"
+            )));
+        }
+        let inputs = input_paths.iter().map(String::as_str).collect::<Vec<_>>();
+        let fastx_type = fastx::open_input(inputs[0])?.0;
+        let mut clusters =
+            clusters::Clusters::from_file_with_options(cluster_output_opt, options, 0)?;
+        if let Some(edges_output) = matches.value_of("edges-output") {
+            clusters.write_edges(File::create(edges_output)?)?;
+        }
+        if let Some(audit_output) = matches.value_of("audit-output") {
+            clusters
+                .write_audit_sample(File::create(audit_output)?, audit_fraction_opt.unwrap())?;
+        }
+        if let Some(key_dump) = matches.value_of("emit-key-dump") {
+            clusters.write_key_dump(File::create(key_dump)?)?;
+        }
+        if let Some(id_map_output) = matches.value_of("id-map-output") {
+            clusters.write_id_map(File::create(id_map_output)?)?;
+        }
+        if is_filtering {
+            clusters.set_filter((filter_expr_opt.clone(), id_filter_opt.clone()));
+        }
+        if let Some(deadline) = deadline_opt {
+            clusters.set_deadline(deadline);
+        }
+        if let Some(cancellation) = cancellation_opt.as_ref() {
+            clusters.set_cancellation(cancellation.clone());
+        }
+        match fastx_type {
+            fastx::FastxType::Fasta => dedup_mates!(
+                fasta,
+                fastx_type,
+                inputs,
+                output_paths,
+                clusters,
+                use_revcomp,
+                leniency,
+                bgzf_threads_opt,
+                trimmer,
+                max_record_length_opt,
+                fix_legacy_quality
+            ),
+            fastx::FastxType::Fastq => dedup_mates!(
+                fastq,
+                fastx_type,
+                inputs,
+                output_paths,
+                clusters,
+                use_revcomp,
+                leniency,
+                bgzf_threads_opt,
+                trimmer,
+                max_record_length_opt,
+                fix_legacy_quality
+            ),
+            fastx::FastxType::Invalid => Err(Box::new(simple_error::simple_error!(
+                "input file is not a valid FASTA or FASTQ file"
+            )) as Box<dyn Error>),
+        }?;
+        clusters.flush()?;
+        if let Some(index_output) = matches.value_of("bgzf-index") {
+            write_bgzf_index(index_output, &output_paths)?;
+        }
+        if let Some(cluster_sizes_output) = cluster_size_output_opt {
+            let mut cluster_sizes_writer = csv::WriterBuilder::new()
+                .delimiter(cluster_delimiter)
+                .from_path(cluster_sizes_output)?;
+            clusters.write_sizes(&mut cluster_sizes_writer)?;
+        }
+        let mut summary = clusters.summary(use_revcomp);
+        summary.parameters.auto_prefix = auto_prefix;
+        summary.parameters.trim_to = trimmer.trim_to_opt;
+        summary.parameters.quality_trim = trimmer.quality_trim_opt;
+        summary.parameters.fix_legacy_quality = fix_legacy_quality;
+        summary.parameters.strict = matches.is_present("strict");
+        summary.parameters.filter_expr = matches.value_of("filter-expr").map(str::to_owned);
+        summary.parameters.id_filter = matches.value_of("id-filter").map(str::to_owned);
+        summary.parameters.id_exclude = matches.value_of("id-exclude").map(str::to_owned);
+        summary.input_files = lane_paths.clone();
+        summary.output_files = output_paths
+            .iter()
+            .map(|output| output.to_string())
+            .collect();
+        write_cluster_meta(
+            &clusters,
+            cluster_output_opt,
+            cluster_size_output_opt,
+            &summary.parameters,
+        )?;
+        report_summary(&matches, &summary)?;
+        return Ok(summary);
+    }
+
+    if matches.value_of("mode") == Some("sort") {
+        if options.compare_region_opt.is_some() || options.max_mismatches_opt.is_some() {
+            return Err(Box::new(simple_error::simple_error!(
+                "--compare-region and --max-mismatches are not supported with --mode sort"
+            )));
+        }
+        // matching looks ambiguous reads up among clusters sort mode doesn't keep
+        if options.ambiguity == clusters::Ambiguity::Match {
+            return Err(Box::new(simple_error::simple_error!(
+                "--ambiguity match is not supported with --mode sort"
+            )));
+        }
+        check_supported(&matches, RunMode::Sort, "--mode sort")?;
+        if lanes_r1.len() > 1 {
+            return Err(Box::new(simple_error::simple_error!(
+                "multiple lanes are not supported with --mode sort"
+            )));
+        }
+        let chunk_size = match matches.value_of("memory-limit") {
+            Some(memory_limit) => external::chunk_size_for(parse_size(memory_limit)?),
+            // a container's limit only makes the default smaller, it may be generous
+            None => {
+                resources::memory_limit().map_or(external::DEFAULT_CHUNK_SIZE, |memory_limit| {
+                    external::chunk_size_for(memory_limit).min(external::DEFAULT_CHUNK_SIZE)
+                })
+            }
+        };
+        let clusters = clusters::Clusters::from_file_with_options(None::<&str>, options, 0)?;
+        let inputs = input_paths.iter().map(String::as_str).collect::<Vec<_>>();
+        let outputs = std::iter::once(output_r1)
+            .chain(outputs)
+            .collect::<Vec<_>>();
+        let (total_records, unique_records, cluster_size_histogram) =
+            match fastx::open_input(inputs[0])?.0 {
+                fastx::FastxType::Fasta => {
+                    sort_dedup!(
+                        fasta,
+                        &inputs,
+                        &outputs,
+                        clusters,
+                        use_revcomp,
+                        bgzf_threads_opt,
+                        trimmer,
+                        chunk_size
+                    )
+                }
+                fastx::FastxType::Fastq => {
+                    sort_dedup!(
+                        fastq,
+                        &inputs,
+                        &outputs,
+                        clusters,
+                        use_revcomp,
+                        bgzf_threads_opt,
+                        trimmer,
+                        chunk_size
+                    )
+                }
+                fastx::FastxType::Invalid => Err(Box::new(simple_error::simple_error!(
+                    "input file is not a valid FASTA or FASTQ file"
+                )) as Box<dyn Error>),
+            }?;
+        let mut summary = clusters.summary(use_revcomp);
+        summary.total_records = total_records;
+        summary.unique_records = unique_records;
+        summary.duplicate_records = total_records - unique_records;
+        summary.duplication_rate =
+            summary::duplication_rate(summary.duplicate_records, total_records);
+        summary.cluster_size_histogram = cluster_size_histogram;
+        if let Some(index_output) = matches.value_of("bgzf-index") {
+            write_bgzf_index(index_output, &outputs)?;
+        }
+        summary.parameters.auto_prefix = auto_prefix;
+        summary.parameters.trim_to = trimmer.trim_to_opt;
+        summary.parameters.quality_trim = trimmer.quality_trim_opt;
+        summary.parameters.fix_legacy_quality = fix_legacy_quality;
+        summary.input_files = inputs.iter().map(|input| input.to_string()).collect();
+        summary.output_files = outputs.iter().map(|output| output.to_string()).collect();
+        report_summary(&matches, &summary)?;
+        return Ok(summary);
+    }
+
+    if lanes_r1.len() > 1 && matches.value_of("split-by-cluster").is_some() {
+        return Err(Box::new(simple_error::simple_error!(
+            "--split-by-cluster is not supported with multiple lanes"
+        )));
+    }
+    // the second pass re-reads the inputs, and only knows clusters the first
+    // pass kept and found again from each read
+    let representative = options.representative;
+    let two_pass =
+        matches.is_present("two-pass") || representative != clusters::Representative::First;
+    if two_pass {
+        // names what turned on the second pass, --representative does implicitly
+        let cause = if matches.is_present("two-pass") {
+            "--two-pass".to_owned()
+        } else {
+            format!(
+                "--representative {} (which runs two passes)",
+                representative
+            )
+        };
+        check_supported(&matches, RunMode::TwoPass, &cause)?;
+        if lanes_r1.len() != 1 || matches.value_of("sra").is_some() {
+            let message = format!(
+                "{} is not supported with multiple lanes or SRA input",
+                cause
+            );
+            return Err(Box::new(simple_error::simple_error!(message)));
+        }
+        // a loaded representative could be replaced and written a second time
+        if matches.is_present("load-state") {
+            let message = format!("--load-state is not supported with {}", cause);
+            return Err(Box::new(simple_error::simple_error!(message)));
+        }
+    }
+    if matches.is_present("byte-range") {
+        if input_paths.len() != 1 || lanes_r1.len() != 1 || matches.value_of("sra").is_some() {
+            return Err(Box::new(simple_error::simple_error!(
+                "--byte-range needs a single input"
+            )));
+        }
+        // splitting reads the whole input again
+        if matches.is_present("split-by-cluster") {
+            return Err(Box::new(simple_error::simple_error!(
+                "--split-by-cluster is not supported with --byte-range"
+            )));
+        }
+    }
+    // window and sorted modes forget clusters the state would need
+    if matches.is_present("save-state") || matches.is_present("load-state") {
+        check_supported(&matches, RunMode::ClusterState, "a cluster state")?;
+    }
+    let (fastx_type_r1, reader_r1, bytes) = open_input_r1(&matches, &mut lanes_r1.iter().copied())?;
+    if representative == clusters::Representative::BestQuality
+        && fastx_type_r1 != fastx::FastxType::Fastq
+    {
+        return Err(Box::new(simple_error::simple_error!(
+            "--representative best-quality needs FASTQ input"
+        )));
+    }
+    if aggregate_qualities && fastx_type_r1 != fastx::FastxType::Fastq {
+        return Err(Box::new(simple_error::simple_error!(
+            "--aggregate-qualities needs FASTQ input"
+        )));
+    }
+    let read_group = |lane: usize| {
+        read_groups_opt
+            .as_ref()
+            .map(|read_groups| read_groups[lane])
+    };
+    let input_r2 = |lane: usize| lanes_r2_opt.as_ref().map(|lanes_r2| lanes_r2[lane]);
+    let mut lanes = vec![Lane {
+        read_group_opt: read_group(0),
+        reader_r1,
+        input_r2_opt: input_r2(0),
+        reader_r2_opt: None,
+        offsets: Vec::new(),
+    }];
+    for (lane, input_r1) in lanes_r1.iter().enumerate().skip(1) {
+        let (fastx_type, reader_r1) = fastx::open_input(input_r1)?;
+        if fastx_type != fastx_type_r1 {
+            let message = format!(
+                "lanes have different file types: {}, {}",
+                fastx_type_r1, fastx_type
+            );
+            return Err(Box::new(simple_error::simple_error!(message)));
+        }
+        lanes.push(Lane {
+            read_group_opt: read_group(lane),
+            reader_r1,
+            input_r2_opt: input_r2(lane),
+            reader_r2_opt: None,
+            offsets: Vec::new(),
+        });
+    }
+    // each member of concatenated gzip inputs is read as a lane of its own
+    let gz_members_opt = matches
+        .value_of("gz-members")
+        .map(str::parse::<fastx::GzMembers>)
+        .transpose()?;
+    let mut member_lanes = Vec::new();
+    if let Some(gz_members) = gz_members_opt {
+        if gz_members == fastx::GzMembers::ReadGroups && read_groups_opt.is_some() {
+            return Err(Box::new(simple_error::simple_error!(
+                "--gz-members read-groups names the read groups, so --read-group can't be used with it"
+            )));
+        }
+        if matches.value_of("split-by-cluster").is_some() {
+            return Err(Box::new(simple_error::simple_error!(
+                "--split-by-cluster is not supported with --gz-members"
+            )));
+        }
+        if lanes_r1.is_empty()
+            || lane_paths
+                .iter()
+                .any(|input| archive::split_member(input).is_some())
+        {
+            return Err(Box::new(simple_error::simple_error!(
+                "--gz-members needs gzip input files"
+            )));
+        }
+        for (lane, input_r1) in lanes_r1.iter().enumerate() {
+            let members_r1 = fastx::gzip_members(input_r1)?;
+            let members_r2_opt = input_r2(lane).map(fastx::gzip_members).transpose()?;
+            if let (Some(input_r2), Some(members_r2)) = (input_r2(lane), members_r2_opt.as_ref()) {
+                if members_r2.len() != members_r1.len() {
+                    let message = format!(
+                        "{} has {} gzip members but {} has {}",
+                        input_r1,
+                        members_r1.len(),
+                        input_r2,
+                        members_r2.len()
+                    );
+                    return Err(Box::new(simple_error::SimpleError::new(message)));
+                }
+            }
+            for (i, member_r1) in members_r1.into_iter().enumerate() {
+                member_lanes.push(MemberLane {
+                    lane,
+                    input_r1,
+                    member: i + 1,
+                    member_r1,
+                    member_r2_opt: members_r2_opt.as_ref().map(|members_r2| members_r2[i]),
+                    name: format!("{}#{}", input_r1, i + 1),
+                });
+            }
+        }
+        lanes = Vec::with_capacity(member_lanes.len());
+        for member_lane in member_lanes.iter() {
+            let reader_r1 = fastx::open_gzip_member(member_lane.input_r1, member_lane.member_r1)?;
+            let (fastx_type, reader_r1) = fastx::peek_fastx_type(reader_r1)?;
+            if fastx_type != fastx_type_r1 {
+                let message = format!(
+                    "gzip members have different file types: {}, {}",
+                    fastx_type_r1, fastx_type
+                );
+                return Err(Box::new(simple_error::simple_error!(message)));
+            }
+            let input_r2_opt = input_r2(member_lane.lane);
+            let reader_r2_opt = match (input_r2_opt, member_lane.member_r2_opt) {
+                (Some(input_r2), Some(member_r2)) => {
+                    Some(fastx::open_gzip_member(input_r2, member_r2)?)
+                }
+                _ => None,
+            };
+            lanes.push(Lane {
+                read_group_opt: match gz_members {
+                    fastx::GzMembers::Report => read_group(member_lane.lane),
+                    fastx::GzMembers::ReadGroups => Some(member_lane.name.as_str()),
+                },
+                reader_r1,
+                input_r2_opt,
+                reader_r2_opt,
+                offsets: Vec::new(),
+            });
+        }
+    }
+    // r2s are opened and checked against r1 before any output is created, so
+    // a mismatch doesn't leave empty outputs behind
+    if fastx_type_r1 == fastx::FastxType::Invalid {
+        return Err(Box::new(simple_error::simple_error!(
+            "input file is not a valid FASTA or FASTQ file"
+        )));
+    }
+    for lane in lanes.iter_mut() {
+        let input_r2 = match lane.input_r2_opt {
+            Some(input_r2) => input_r2,
+            None => continue,
+        };
+        let (fastx_type_r2, reader_r2) = match lane.reader_r2_opt.take() {
+            Some(reader_r2) => fastx::peek_fastx_type(reader_r2)?,
+            None => fastx::open_input(input_r2)?,
+        };
+        if fastx_type_r2 != fastx_type_r1 {
+            let message = format!(
+                "paired inputs have different file types r1: {}, r2: {}",
+                fastx_type_r1, fastx_type_r2
+            );
+            return Err(Box::new(simple_error::simple_error!(message)));
+        }
+        lane.reader_r2_opt = Some(reader_r2);
+    }
+    if trimmer.quality_trim_opt.is_some() && fastx_type_r1 == fastx::FastxType::Fasta {
+        return Err(Box::new(simple_error::simple_error!(
+            "--quality-trim needs FASTQ input"
+        )));
+    }
+    if let Some(filter_expr) = filter_expr_opt.as_ref() {
+        if filter_expr.uses_quality() && fastx_type_r1 == fastx::FastxType::Fasta {
+            return Err(Box::new(simple_error::simple_error!(
+                "--filter-expr quality fields need FASTQ input"
+            )));
+        }
+    }
+    // outputs created from here on are removed if the run fails before reading
+    let partial_outputs = preflight::PartialOutputs::new(&run_paths.outputs);
+    // 400 is based on the bytes per record of an example file, should be reasonable
+    // orphans are deduplicated against each other, apart from the pairs
+    let mut orphan_clusters_opt = match orphan_output_opt {
+        Some(_) => Some(clusters::Clusters::from_file_with_options(
+            None::<&str>,
+            options.clone(),
+            0,
+        )?),
+        None => None,
+    };
+    // single reads are deduplicated against the pairs' r1s, then each other
+    let mut single_clusters_opt = match single_io_opt {
+        Some(_) => Some(clusters::Clusters::from_file_with_options(
+            None::<&str>,
+            options.clone(),
+            0,
+        )?),
+        None => None,
+    };
+    let mut clusters =
+        clusters::Clusters::from_file_with_options(cluster_output_opt, options, bytes / 400)?;
+    if single_io_opt.is_some() {
+        clusters.track_r1_keys();
+    }
+    if let Some(load_state) = matches.value_of("load-state") {
+        clusters.load_state(File::open(load_state)?, use_revcomp)?;
+    }
+    // the second pass seeks straight to each representative when every input
+    // is plain or BGZF
+    if two_pass {
+        let opened = input_paths
+            .iter()
+            .map(|input| fastx::open_with_offsets(input))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .collect::<Option<Vec<_>>>();
+        if let Some(opened) = opened {
+            let lane = &mut lanes[0];
+            for (i, (_, reader, offsets)) in opened.into_iter().enumerate() {
+                if i == 0 {
+                    lane.reader_r1 = reader;
+                } else {
+                    lane.reader_r2_opt = Some(reader);
+                }
+                lane.offsets.push(offsets);
+            }
+            clusters.track_offsets(lane.offsets.clone());
+        }
+    }
+    if let Some(edges_output) = matches.value_of("edges-output") {
+        clusters.write_edges(File::create(edges_output)?)?;
+    }
+    if let Some(audit_output) = matches.value_of("audit-output") {
+        clusters.write_audit_sample(File::create(audit_output)?, audit_fraction_opt.unwrap())?;
+    }
+    if let Some(key_dump) = matches.value_of("emit-key-dump") {
+        clusters.write_key_dump(File::create(key_dump)?)?;
+    }
+    if let Some(id_map_output) = matches.value_of("id-map-output") {
+        clusters.write_id_map(File::create(id_map_output)?)?;
+    }
+    if is_filtering {
+        clusters.set_filter((filter_expr_opt.clone(), id_filter_opt.clone()));
+        if let Some(orphan_clusters) = orphan_clusters_opt.as_mut() {
+            orphan_clusters.set_filter((filter_expr_opt.clone(), id_filter_opt.clone()));
+        }
+        if let Some(single_clusters) = single_clusters_opt.as_mut() {
+            single_clusters.set_filter((filter_expr_opt.clone(), id_filter_opt.clone()));
+        }
+    }
+    for clusters in std::iter::once(&mut clusters).chain(single_clusters_opt.as_mut()) {
+        if let Some(deadline) = deadline_opt {
+            clusters.set_deadline(deadline);
+        }
+        if let Some(cancellation) = cancellation_opt.as_ref() {
+            clusters.set_cancellation(cancellation.clone());
+        }
+    }
+
+    let summarize = |clusters: &clusters::Clusters<File>,
+                     orphan_clusters_opt: Option<&clusters::Clusters<File>>,
+                     single_clusters_opt: Option<&clusters::Clusters<File>>| {
+        let mut summary = clusters.summary(use_revcomp);
+        summary.parameters.auto_prefix = auto_prefix;
+        summary.parameters.trim_to = trimmer.trim_to_opt;
+        summary.parameters.quality_trim = trimmer.quality_trim_opt;
+        summary.parameters.fix_legacy_quality = fix_legacy_quality;
+        summary.parameters.filter_expr = matches.value_of("filter-expr").map(str::to_owned);
+        summary.parameters.id_filter = matches.value_of("id-filter").map(str::to_owned);
+        summary.parameters.id_exclude = matches.value_of("id-exclude").map(str::to_owned);
+        summary.parameters.strict = matches.is_present("strict");
+        summary.parameters.lenient = matches.is_present("lenient");
+        if let Some(orphan_clusters) = orphan_clusters_opt {
+            summary.parameters.allow_orphans = true;
+            summary.orphan_records = Some(orphan_clusters.total_records());
+            summary.unique_orphan_records = Some(orphan_clusters.unique_records());
+            summary.filtered_records += orphan_clusters.filtered_records();
+        }
+        if lanes_r2_opt.is_some() {
+            let mate_orphans = |mate: paired::Mate| {
+                orphan_clusters_opt.map(|orphan_clusters| orphan_clusters.mate_orphan_records(mate))
+            };
+            summary.pair_counts = Some(summary::PairCounts {
+                pairs_read: clusters.records_read(),
+                pairs_kept: clusters.unique_records(),
+                r1_only_records: mate_orphans(paired::Mate::R1),
+                r2_only_records: mate_orphans(paired::Mate::R2),
+            });
+        }
+        if let Some(single_clusters) = single_clusters_opt {
+            summary.single_records = Some(single_clusters.total_records());
+            summary.unique_single_records = Some(single_clusters.unique_records());
+            summary.paired_duplicate_single_records =
+                Some(single_clusters.paired_duplicate_records());
+            summary.filtered_records += single_clusters.filtered_records();
+        }
+        summary.input_files = match matches.value_of("sra") {
+            Some(accession) => vec![accession.to_owned()],
+            None => lane_paths.clone(),
+        };
+        summary.output_files = output_paths
+            .iter()
+            .map(|output| output.to_string())
+            .collect();
+        if let Some((single_input, single_output)) = single_io_opt {
+            summary.input_files.push(single_input.to_owned());
+            summary.output_files.push(single_output.to_owned());
+        }
+        summary
+    };
+
+    // records read before each lane, and after the last
+    let mut lane_records = vec![clusters.records_read()];
+    let result = {
+        let run = DedupRun {
+            lanes,
+            output_r1,
+            outputs,
+            interleave_output_opt,
+            concat_pairs_opt,
+            use_revcomp,
+            leniency,
+            bgzf_threads_opt,
+            orphans_opt: orphan_output_opt.zip(orphan_clusters_opt.as_mut()),
+            trimmer,
+            max_record_length_opt,
+            two_pass,
+            lane_records: &mut lane_records,
+            fix_legacy_quality,
+        };
+        match fastx_type_r1 {
+            fastx::FastxType::Fasta => dedup!(fasta, fastx::FastxType::Fasta, run, clusters),
+            fastx::FastxType::Fastq => dedup!(fastq, fastx::FastxType::Fastq, run, clusters),
+            fastx::FastxType::Invalid => Err(Box::new(simple_error::simple_error!(
+                "input file is not a valid FASTA or FASTQ file"
+            )) as Box<dyn Error>),
+        }
+    };
+    // records were read, so a failure from here on is reported with its outputs
+    partial_outputs.keep();
+    let result = match (result, single_io_opt, single_clusters_opt.as_mut()) {
+        (Ok(()), Some(single_io), Some(single_clusters)) => {
+            single_clusters.set_paired_keys(clusters.take_r1_keys());
+            dedup_single_input(
+                single_io,
+                single_clusters,
+                use_revcomp,
+                leniency,
+                bgzf_threads_opt,
+                trimmer,
+            )
+        }
+        (result, _, _) => result,
+    };
+    if let Err(error) = result {
+        // keeps the rows written before the failure, which is reported either way
+        let _ = clusters.flush();
+        let mut summary = summarize(
+            &clusters,
+            orphan_clusters_opt.as_ref(),
+            single_clusters_opt.as_ref(),
+        );
+        summary.status = summary::RunStatus::Failed;
+        summary.failed_at_record = Some(summary.processed_records() + 1);
+        summary.error = Some(error.to_string());
+        report_summary(&matches, &summary)?;
+        return Err(Box::new(FailedRun { summary, error }));
+    }
+
+    if two_pass {
+        let input_paths = input_paths.iter().map(String::as_str).collect::<Vec<_>>();
+        match fastx_type_r1 {
+            fastx::FastxType::Fasta => write_representatives!(
+                fasta,
+                input_paths.as_slice(),
+                output_paths.as_slice(),
+                clusters,
+                use_revcomp,
+                bgzf_threads_opt,
+                trimmer
+            ),
+            fastx::FastxType::Fastq => write_representatives!(
+                fastq,
+                input_paths.as_slice(),
+                output_paths.as_slice(),
+                clusters,
+                use_revcomp,
+                bgzf_threads_opt,
+                trimmer
+            ),
+            fastx::FastxType::Invalid => Ok(()),
+        }?;
+    }
+
+    if let Some(split_dir) = matches.value_of("split-by-cluster") {
+        // presence guarunteed by clap
+        let min_cluster_size = matches
+            .value_of("min-cluster-size")
+            .unwrap()
+            .parse::<u64>()?;
+        let input_paths = input_paths.iter().map(String::as_str).collect::<Vec<_>>();
+        match fastx_type_r1 {
+            fastx::FastxType::Fasta => split_by_cluster!(
+                fasta,
+                "fasta",
+                input_paths.as_slice(),
+                split_dir,
+                min_cluster_size,
+                clusters,
+                use_revcomp
+            ),
+            fastx::FastxType::Fastq => split_by_cluster!(
+                fastq,
+                "fastq",
+                input_paths.as_slice(),
+                split_dir,
+                min_cluster_size,
+                clusters,
+                use_revcomp
+            ),
+            fastx::FastxType::Invalid => Ok(()),
+        }?;
+    }
+
+    if let Some(lane_matrix_output) = matches.value_of("lane-matrix-output") {
+        let lane_names = match read_groups_opt.as_ref() {
+            _ if !member_lanes.is_empty() => member_lanes
+                .iter()
+                .map(|member_lane| member_lane.name.as_str())
+                .collect(),
+            Some(read_groups) => read_groups.clone(),
+            // a single lane streamed from SRA
+            None if lanes_r1.is_empty() => vec![matches.value_of("sra").unwrap_or("input")],
+            None => lanes_r1.clone(),
+        };
+        let mut lane_matrix_writer = csv::Writer::from_path(lane_matrix_output)?;
+        clusters.write_lane_matrix(&mut lane_matrix_writer, &lane_names)?;
+    }
+
+    if let Some(save_state) = matches.value_of("save-state") {
+        clusters.save_state(File::create(save_state)?, use_revcomp)?;
+    }
+
+    if let Some(abundance_output) = matches.value_of("abundance-output") {
+        let mut abundance_writer = csv::Writer::from_path(abundance_output)?;
+        match fastx_type_r1 {
+            fastx::FastxType::Fasta => write_abundance!(
+                fasta,
+                output_paths.as_slice(),
+                abundance_writer,
+                clusters,
+                use_revcomp
+            ),
+            fastx::FastxType::Fastq => write_abundance!(
+                fastq,
+                output_paths.as_slice(),
+                abundance_writer,
+                clusters,
+                use_revcomp
+            ),
+            fastx::FastxType::Invalid => Ok(()),
+        }?;
+    }
+
+    if aggregate_qualities {
+        aggregate_output_qualities(
+            output_paths.as_slice(),
+            &clusters,
+            use_revcomp,
+            bgzf_threads_opt,
+        )?;
+    }
+
+    let relabel_opt = matches.value_of("relabel");
+    let sizeout = matches.is_present("sizeout");
+    let annotate_headers = matches.is_present("annotate-headers");
+    if relabel_opt.is_some() || sizeout || annotate_headers {
+        match fastx_type_r1 {
+            fastx::FastxType::Fasta => relabel_outputs!(
+                fasta,
+                output_paths.as_slice(),
+                clusters,
+                use_revcomp,
+                relabel_opt,
+                sizeout,
+                annotate_headers,
+                bgzf_threads_opt
+            ),
+            fastx::FastxType::Fastq => relabel_outputs!(
+                fastq,
+                output_paths.as_slice(),
+                clusters,
+                use_revcomp,
+                relabel_opt,
+                sizeout,
+                annotate_headers,
+                bgzf_threads_opt
+            ),
+            fastx::FastxType::Invalid => Ok(()),
+        }?;
+    }
+
+    if let Some(weights_output) = matches.value_of("emit-weights") {
+        let mut weights_writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(false)
+            .from_path(weights_output)?;
+        match fastx_type_r1 {
+            fastx::FastxType::Fasta => write_weights!(
+                fasta,
+                output_paths.as_slice(),
+                weights_writer,
+                clusters,
+                use_revcomp
+            ),
+            fastx::FastxType::Fastq => write_weights!(
+                fastq,
+                output_paths.as_slice(),
+                weights_writer,
+                clusters,
+                use_revcomp
+            ),
+            fastx::FastxType::Invalid => Ok(()),
+        }?;
+    }
+
+    if let Some(index_output) = matches.value_of("bgzf-index") {
+        write_bgzf_index(index_output, output_paths.as_slice())?;
+    }
+
+    if let Some(cluster_sizes_output) = cluster_size_output_opt {
+        let mut cluster_sizes_writer = csv::WriterBuilder::new()
+            .delimiter(cluster_delimiter)
+            .from_path(cluster_sizes_output)?;
+        clusters.write_sizes(&mut cluster_sizes_writer)?;
+    }
+    clusters.flush()?;
+    let mut summary = summarize(
+        &clusters,
+        orphan_clusters_opt.as_ref(),
+        single_clusters_opt.as_ref(),
+    );
+    summary.parameters.gz_members = gz_members_opt.map(|gz_members| gz_members.to_string());
+    summary.gzip_members = member_lanes
+        .iter()
+        .zip(lane_records.windows(2))
+        .map(|(member_lane, records)| summary::GzipMemberReads {
+            input: member_lane.input_r1.to_owned(),
+            member: member_lane.member,
+            compressed_offset: member_lane.member_r1.offset,
+            first_record: records[0] - lane_records[0] + 1,
+            records: records[1] - records[0],
+        })
+        .collect();
+    write_cluster_meta(
+        &clusters,
+        cluster_output_opt,
+        cluster_size_output_opt,
+        &summary.parameters,
+    )?;
+    report_summary(&matches, &summary)?;
+    Ok(summary)
+}
+
+/// A run that failed part way through, with a summary of the records read before it failed
+#[derive(Debug)]
+struct FailedRun {
+    summary: DedupSummary,
+    error: Box<dyn Error>,
+}
+
+impl std::fmt::Display for FailedRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl Error for FailedRun {}
+
+/// Writes the cluster and cluster size outputs' metadata beside them, as
+/// `<output>.meta.json`
+fn write_cluster_meta<T: std::io::Write>(
+    clusters: &clusters::Clusters<T>,
+    cluster_output_opt: Option<&str>,
+    cluster_size_output_opt: Option<&str>,
+    parameters: &summary::DedupParameters,
+) -> Result<(), Box<dyn Error>> {
+    let metas = [
+        (cluster_output_opt, clusters.cluster_output_meta(parameters)),
+        (
+            cluster_size_output_opt,
+            clusters.size_output_meta(parameters),
+        ),
+    ];
+    for (output_opt, meta) in metas.iter() {
+        if let Some(output) = output_opt {
+            write_json(&format!("{}.meta.json", output), meta)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the summary to --summary-json and posts it to --summary-webhook
+///
+/// A webhook that can't be reached after its retries is only warned about,
+/// since the run's outputs are already written.
+fn report_summary(matches: &ArgMatches, summary: &DedupSummary) -> Result<(), Box<dyn Error>> {
+    if let Some(summary_json) = matches.value_of("summary-json") {
+        write_summary_json(summary_json, summary)?;
+    }
+    if let Some(url) = matches.value_of("summary-webhook") {
+        let json = serde_json::to_string(summary)?;
+        if let Err(err) = webhook::post_json(url, &json, webhook::ATTEMPTS, webhook::RETRY_DELAY) {
+            eprintln!("warning: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the summary, including the full cluster size histogram, as JSON
+fn write_summary_json(path: &str, summary: &DedupSummary) -> Result<(), Box<dyn Error>> {
+    write_json(path, summary)
+}
+
+/// Writes a value as compact JSON on one line
+fn write_json<T: serde::Serialize>(path: &str, value: &T) -> Result<(), Box<dyn Error>> {
+    let mut writer = std::io::BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, value)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn run_compare_clusters<T: Into<std::ffi::OsString> + Clone, R: IntoIterator<Item = T>>(
+    args: R,
+) -> Result<compare::ClusterComparison, Box<dyn Error>> {
+    let app = App::new(format!("{} compare-clusters", clap::crate_name!()))
+        .version(clap::crate_version!())
+        .about(
+            "Reports clusters shared between two runs' cluster files, written with --cluster-hash",
+        )
+        .arg(
+            Arg::with_name("cluster-file-a")
+                .help("Cluster file of the first run")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("cluster-file-b")
+                .help("Cluster file of the second run")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("shared-output")
+                .short("o")
+                .long("shared-output")
+                .help("Output CSV of the shared clusters")
+                .takes_value(true),
+        );
+    let matches = app.get_matches_from(args);
+
+    // presence guarunteed by clap
+    let cluster_file_a = File::open(matches.value_of("cluster-file-a").unwrap())?;
+    let cluster_file_b = File::open(matches.value_of("cluster-file-b").unwrap())?;
+    let comparison = compare::compare_clusters(cluster_file_a, cluster_file_b)?;
+    if let Some(shared_output) = matches.value_of("shared-output") {
+        let mut shared_writer = csv::Writer::from_path(shared_output)?;
+        comparison.write_shared(&mut shared_writer)?;
+    }
+    Ok(comparison)
+}
+
+fn run_tune_prefix<T: Into<std::ffi::OsString> + Clone, R: IntoIterator<Item = T>>(
+    args: R,
+) -> Result<tune::PrefixReport, Box<dyn Error>> {
+    let app = App::new(format!("{} tune-prefix", clap::crate_name!()))
+        .version(clap::crate_version!())
+        .about("Reports how reads would cluster at each candidate prefix length, to choose --prefix-length")
+        .arg(
+            Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .help("Input FASTA or FASTQ file")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("lengths")
+                .long("lengths")
+                .help("Comma separated candidate prefix lengths")
+                .required(true)
+                .takes_value(true)
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Output CSV of each prefix length's counts")
+                .takes_value(true),
+        );
+    let matches = app.get_matches_from(args);
+
+    // presence guarunteed by clap
+    let prefix_lengths = matches
+        .values_of("lengths")
+        .unwrap()
+        .map(|length| length.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if prefix_lengths.contains(&0) {
+        return Err(Box::new(simple_error::simple_error!(
+            "prefix lengths must be at least 1"
+        )));
+    }
+    let mut tuner = tune::PrefixTuner::new(&prefix_lengths);
+    let (fastx_type, reader) = fastx::open_input(matches.value_of("input").unwrap())?;
+    match fastx_type {
+        fastx::FastxType::Fasta => {
+            for result in fasta::Reader::new(reader).records() {
+                tuner.add(result?.seq());
+            }
+        }
+        fastx::FastxType::Fastq => {
+            for result in fastq::Reader::new(reader).records() {
+                tuner.add(result?.seq());
+            }
+        }
+        fastx::FastxType::Invalid => {
+            return Err(Box::new(simple_error::simple_error!(
+                "input file is not a valid FASTA or FASTQ file"
+            )))
+        }
+    }
+    let report = tuner.report();
+    if let Some(output) = matches.value_of("output") {
+        let mut stats_writer = csv::Writer::from_path(output)?;
+        report.write_stats(&mut stats_writer)?;
+    }
+    Ok(report)
+}
+
+fn run_bench<T: Into<std::ffi::OsString> + Clone, R: IntoIterator<Item = T>>(
+    args: R,
+) -> Result<Vec<bench::BenchResult>, Box<dyn Error>> {
+    let app = App::new(format!("{} bench", clap::crate_name!()))
+        .version(clap::crate_version!())
+        .about(
+            "Reports clustering throughput of each mode over synthetic reads generated in memory",
+        )
+        .arg(
+            Arg::with_name("reads")
+                .long("reads")
+                .help("Number of reads, with an optional K, M, or G suffix")
+                .default_value("1M")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("length")
+                .long("length")
+                .help("Read length")
+                .default_value("150")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("duplication")
+                .long("duplication")
+                .help("Fraction of reads that duplicate an earlier read")
+                .default_value("0.3")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .help("Seed for generating reads")
+                .default_value("1")
+                .takes_value(true),
+        );
+    let matches = app.get_matches_from(args);
+
+    // presence guarunteed by clap
+    let reads = bench::parse_count(matches.value_of("reads").unwrap())?;
+    let length = matches.value_of("length").unwrap().parse::<usize>()?;
+    let duplication = matches.value_of("duplication").unwrap().parse::<f64>()?;
+    let seed = matches.value_of("seed").unwrap().parse::<u64>()?;
+    if !(0.0..=1.0).contains(&duplication) {
+        return Err(Box::new(simple_error::simple_error!(
+            "--duplication must be between 0 and 1"
+        )));
+    }
+    Ok(bench::run(reads, length, duplication, seed))
+}
+
+/// A fixture case's digest, checked in and from this build
+struct FixtureResult {
+    name: &'static str,
+    expected: u32,
+    actual: u32,
+}
+
+/// Writes the fixture inputs, or runs every case on them, returning each
+/// case's digests when verifying
+fn run_fixtures<T: Into<std::ffi::OsString> + Clone, R: IntoIterator<Item = T>>(
+    args: R,
+) -> Result<Option<Vec<FixtureResult>>, Box<dyn Error>> {
+    let app = App::new(format!("{} fixtures", clap::crate_name!()))
+        .version(clap::crate_version!())
+        .about("Generates tricky inputs and verifies this build's outputs on them against checked-in digests")
+        .arg(
+            Arg::with_name("action")
+                .help("generate writes the inputs, verify runs every case on them")
+                .possible_values(&["generate", "verify"])
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("dir")
+                .help("Directory of the inputs and outputs, verify generates them in a temporary directory without it")
+                .required_if("action", "generate")
+                .index(2),
+        );
+    let matches = app.get_matches_from(args);
+
+    let temp_dir_opt = match matches.value_of("dir") {
+        Some(_) => None,
+        None => Some(tempfile::tempdir()?),
+    };
+    // presence guarunteed by clap for generate
+    let dir = match temp_dir_opt.as_ref() {
+        Some(temp_dir) => temp_dir.path().to_owned(),
+        None => PathBuf::from(matches.value_of("dir").unwrap()),
+    };
+    if matches.value_of("action") == Some("generate") {
+        fixtures::generate(&dir)?;
+        return Ok(None);
+    }
+    if temp_dir_opt.is_some() {
+        fixtures::generate(&dir)?;
+    }
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    let mut results = Vec::new();
+    for case in fixtures::CASES.iter() {
+        let mut args = vec!["executable".to_owned(), "-i".to_owned()];
+        args.extend(case.inputs.iter().map(|input| path(input)));
+        args.push("-o".to_owned());
+        args.extend(case.outputs().iter().map(|output| path(output)));
+        args.extend(["-c".to_owned(), path(&case.cluster_output())]);
+        args.extend(case.args.iter().map(|arg| arg.to_string()));
+        let actual = match (run_dedup(args), case.fails) {
+            (Ok(_), false) => case.digest_outputs(&dir)?,
+            (Err(err), true) => fixtures::digest_error(&err.to_string()),
+            // a digest that can't match, the run should have failed or succeeded
+            (Ok(_), true) | (Err(_), false) => !case.digest,
+        };
+        results.push(FixtureResult {
+            name: case.name,
+            expected: case.digest,
+            actual,
+        });
+    }
+    Ok(Some(results))
+}
+
+fn print_fixtures(results: &[FixtureResult]) {
+    for result in results.iter() {
+        if result.actual == result.expected {
+            println!("{:<28}ok", result.name);
+        } else {
+            println!(
+                "{:<28}FAILED, expected {:08x} but got {:08x}",
+                result.name, result.expected, result.actual
+            );
+        }
+    }
+}
+
+fn print_bench(results: &[bench::BenchResult]) {
+    println!(
+        "{:<10}{:>16}{:>16}{:>12}",
+        "mode", "reads", "reads/s", "seconds"
+    );
+    for result in results.iter() {
+        println!(
+            "{:<10}{:>16}{:>16}{:>12.3}",
+            result.mode,
+            with_thousands_separators(result.reads),
+            with_thousands_separators(result.reads_per_second() as u64),
+            result.elapsed.as_secs_f64()
+        );
+    }
+}
+
+fn write_simulated<T: fastx::Record, W: fastx::Writer<T>>(
+    reads: &[simulate::SimulatedRead],
+    (length, paired): (usize, bool),
+    (writer_r1, writer_r2_opt): (&mut W, Option<&mut W>),
+    new_record: impl Fn(&str, &[u8]) -> T,
+) -> Result<(), std::io::Error> {
+    let mut writer_r2_opt = writer_r2_opt;
+    for (i, read) in reads.iter().enumerate() {
+        let id = format!("read_{}", i);
+        let (r1, r2_opt) = read.mates(length, paired);
+        writer_r1.write_record(&new_record(&id, &r1))?;
+        if let (Some(writer_r2), Some(r2)) = (writer_r2_opt.as_mut(), r2_opt) {
+            writer_r2.write_record(&new_record(&id, &r2))?;
+        }
+    }
+    Ok(())
+}
+
+fn run_simulate<T: Into<std::ffi::OsString> + Clone, R: IntoIterator<Item = T>>(
+    args: R,
+) -> Result<Vec<simulate::SimulatedRead>, Box<dyn Error>> {
+    let app = App::new(format!("{} simulate", clap::crate_name!()))
+        .version(clap::crate_version!())
+        .about("Writes synthetic reads with a known duplication structure")
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Output file, or two for paired reads")
+                .required(true)
+                .min_values(1)
+                .max_values(2)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .help("Output format")
+                .possible_values(&["fasta", "fastq"])
+                .default_value("fastq")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("truth-output")
+                .long("truth-output")
+                .help("Output CSV of each read's cluster")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reads")
+                .long("reads")
+                .help("Number of reads, or pairs, with an optional K, M, or G suffix")
+                .default_value("1000")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("length")
+                .long("length")
+                .help("Read length")
+                .default_value("150")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cluster-sizes")
+                .long("cluster-sizes")
+                .help("Cluster sizes and their relative weights, like 1:0.7,2:0.2,5:0.1")
+                .default_value("1:0.7,2:0.2,5:0.1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("revcomp-fraction")
+                .long("revcomp-fraction")
+                .help("Fraction of duplicates that are reverse complemented")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("error-rate")
+                .long("error-rate")
+                .help("Per-base substitution rate of duplicates")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .help("Seed for generating reads")
+                .default_value("1")
+                .takes_value(true),
+        );
+    let matches = app.get_matches_from(args);
+
+    // presence guarunteed by clap
+    let mut outputs = matches.values_of("output").unwrap();
+    let output_r1 = outputs.next().unwrap();
+    let output_r2_opt = outputs.next();
+    let options = simulate::SimulateOptions {
+        reads: bench::parse_count(matches.value_of("reads").unwrap())?,
+        length: matches.value_of("length").unwrap().parse::<usize>()?,
+        cluster_sizes: simulate::parse_cluster_sizes(matches.value_of("cluster-sizes").unwrap())?,
+        revcomp_fraction: matches
+            .value_of("revcomp-fraction")
+            .unwrap()
+            .parse::<f64>()?,
+        error_rate: matches.value_of("error-rate").unwrap().parse::<f64>()?,
+        paired: output_r2_opt.is_some(),
+        seed: matches.value_of("seed").unwrap().parse::<u64>()?,
+    };
+    for (name, value) in [
+        ("--revcomp-fraction", options.revcomp_fraction),
+        ("--error-rate", options.error_rate),
+    ]
+    .iter()
+    {
+        if !(0.0..=1.0).contains(value) {
+            return Err(Box::new(simple_error::simple_error!(
+                "{} must be between 0 and 1",
+                name
+            )));
+        }
+    }
+
+    let reads = simulate::simulate(&options);
+    let settings = (options.length, options.paired);
+    if matches.value_of("format") == Some("fasta") {
+        let mut writer_r1 = fasta::Writer::new(fastx::create_output(output_r1, None)?);
+        let mut writer_r2_opt = output_r2_opt
+            .map(|output_r2| fastx::create_output(output_r2, None).map(fasta::Writer::new))
+            .transpose()?;
+        write_simulated(
+            &reads,
+            settings,
+            (&mut writer_r1, writer_r2_opt.as_mut()),
+            |id, seq| fasta::Record::with_attrs(id, None, seq),
+        )?;
+    } else {
+        let qual = vec![b'I'; options.length];
+        let mut writer_r1 = fastq::Writer::new(fastx::create_output(output_r1, None)?);
+        let mut writer_r2_opt = output_r2_opt
+            .map(|output_r2| fastx::create_output(output_r2, None).map(fastq::Writer::new))
+            .transpose()?;
+        write_simulated(
+            &reads,
+            settings,
+            (&mut writer_r1, writer_r2_opt.as_mut()),
+            |id, seq| fastq::Record::with_attrs(id, None, seq, &qual[..seq.len()]),
+        )?;
+    }
+
+    if let Some(truth_output) = matches.value_of("truth-output") {
+        let mut truth_writer = csv::Writer::from_path(truth_output)?;
+        truth_writer.write_record(["read_id", "cluster", "is_revcomp", "errors"])?;
+        for (i, read) in reads.iter().enumerate() {
+            truth_writer.write_record(&[
+                format!("read_{}", i),
+                read.cluster.to_string(),
+                read.is_revcomp.to_string(),
+                read.errors.to_string(),
+            ])?;
+        }
+        truth_writer.flush()?;
+    }
+    Ok(reads)
+}
+
+fn print_comparison(comparison: &compare::ClusterComparison) {
+    println!(
+        "clusters a:      {:width$}",
+        comparison.clusters_a,
+        width = 16
+    );
+    println!(
+        "clusters b:      {:width$}",
+        comparison.clusters_b,
+        width = 16
+    );
+    println!(
+        "shared clusters: {:width$}",
+        comparison.shared_clusters(),
+        width = 16
+    );
+    println!("reads a:         {:width$}", comparison.reads_a, width = 16);
+    println!("reads b:         {:width$}", comparison.reads_b, width = 16);
+    println!(
+        "shared reads a:  {:width$}",
+        comparison.shared_reads_a(),
+        width = 16
+    );
+    println!(
+        "shared reads b:  {:width$}",
+        comparison.shared_reads_b(),
+        width = 16
+    );
+}
+
+fn print_prefix_report(report: &tune::PrefixReport) {
+    println!(
+        "total reads:      {:width$}",
+        report.total_records,
+        width = 16
+    );
+    println!(
+        "unique sequences: {:width$}",
+        report.unique_sequences,
+        width = 16
+    );
+    println!(
+        "{:>13}{:>16}{:>16}{:>16}{:>16}",
+        "prefix length", "unique keys", "truncated", "over-collapsed", "divergent"
+    );
+    for stats in report.stats.iter() {
+        println!(
+            "{:>13}{:>16}{:>16}{:>16}{:>16}",
+            stats.prefix_length,
+            stats.unique_keys,
+            stats.truncated_reads,
+            stats.over_collapsed_reads,
+            stats.divergent_clusters
+        );
+    }
+}
+
+fn main() {
+    if std::env::args().nth(1).as_deref() == Some("compare-clusters") {
+        match run_compare_clusters(std::env::args().skip(1)) {
+            Err(err) => println!("{}", err),
+            Ok(comparison) => print_comparison(&comparison),
+        }
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("simulate") {
+        match run_simulate(std::env::args().skip(1)) {
+            Err(err) => println!("{}", err),
+            Ok(reads) => println!("reads written: {}", reads.len()),
+        }
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("tune-prefix") {
+        match run_tune_prefix(std::env::args().skip(1)) {
+            Err(err) => println!("{}", err),
+            Ok(report) => print_prefix_report(&report),
+        }
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("fixtures") {
+        match run_fixtures(std::env::args().skip(1)) {
+            Err(err) => println!("{}", err),
+            Ok(None) => println!("fixtures written"),
+            Ok(Some(results)) => {
+                print_fixtures(&results);
+                if results
+                    .iter()
+                    .any(|result| result.actual != result.expected)
+                {
+                    std::process::exit(1);
+                }
+            }
+        }
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        match run_bench(std::env::args().skip(1)) {
+            Err(err) => println!("{}", err),
+            Ok(results) => print_bench(&results),
+        }
+        return;
+    }
+    match run_dedup(std::env::args()) {
+        Err(err) => {
+            println!("{}", err);
+            if let Some(failed_run) = err.downcast_ref::<FailedRun>() {
+                let summary = &failed_run.summary;
+                eprintln!(
+                    "partial summary, failed at record {}:",
+                    summary.failed_at_record.unwrap_or(0)
+                );
+                print_summary(&mut std::io::stderr(), summary).expect("failed to write summary");
+            }
+        }
+        Ok(summary) => {
+            for (kind, count) in summary.warning_counts.iter() {
+                eprintln!("warning: {} {} reads", count, kind);
+            }
+            if summary.possible_over_collapse() {
+                eprintln!(
+                    "warning: possible over-collapse, {} of {} reads were longer than the prefix length and {} clusters hold reads that differ past it",
+                    summary.prefix_truncated_records(),
+                    summary.total_records,
+                    summary.prefix_divergent_clusters
+                );
+            }
+            if std::io::stdout().is_terminal() {
+                print_summary_pretty(
+                    &mut std::io::stdout(),
+                    &summary,
+                    std::env::var_os("NO_COLOR").is_none(),
+                )
+                .expect("failed to write summary");
+            } else {
+                print_summary(&mut std::io::stdout(), &summary).expect("failed to write summary");
+            }
+            if summary.status == summary::RunStatus::TimeLimited {
+                eprintln!(
+                    "warning: stopped reading at the time limit after {} reads, the outputs are partial",
+                    summary.processed_records()
+                );
+                std::process::exit(TIME_LIMITED_EXIT_STATUS);
+            }
+            if summary.status == summary::RunStatus::Cancelled {
+                eprintln!(
+                    "warning: cancelled after {} reads, the outputs are partial",
+                    summary.processed_records()
+                );
+                std::process::exit(CANCELLED_EXIT_STATUS);
+            }
+        }
+    }
+}
+
+fn print_summary<W: std::io::Write>(out: &mut W, summary: &DedupSummary) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "duplicates:   {:width$}",
+        summary.duplicate_records,
+        width = 16
+    )?;
+    writeln!(
+        out,
+        "unique reads: {:width$}",
+        summary.unique_records,
+        width = 16
+    )?;
+    writeln!(
+        out,
+        "total reads:  {:width$}",
+        summary.total_records,
+        width = 16
+    )?;
+    if let (Some(exact), Some(prefix)) = (
+        summary.exact_duplicate_records,
+        summary.prefix_duplicate_records,
+    ) {
+        writeln!(out, "exact dupes:  {:width$}", exact, width = 16)?;
+        writeln!(out, "prefix dupes: {:width$}", prefix, width = 16)?;
+    }
+    if let Some(pair_counts) = summary.pair_counts.as_ref() {
+        writeln!(
+            out,
+            "pairs read:   {:width$}",
+            pair_counts.pairs_read,
+            width = 16
+        )?;
+        writeln!(
+            out,
+            "pairs kept:   {:width$}",
+            pair_counts.pairs_kept,
+            width = 16
+        )?;
+        writeln!(
+            out,
+            "r1 only:      {:width$}",
+            pair_counts.r1_only_records.unwrap_or(0),
+            width = 16
+        )?;
+        writeln!(
+            out,
+            "r2 only:      {:width$}",
+            pair_counts.r2_only_records.unwrap_or(0),
+            width = 16
+        )?;
+    }
+    if summary.short_mate_pairs > 0 {
+        writeln!(
+            out,
+            "short mates:  {:width$}",
+            summary.short_mate_pairs,
+            width = 16
+        )?;
+    }
+    if let (Some(orphans), Some(unique_orphans)) =
+        (summary.orphan_records, summary.unique_orphan_records)
+    {
+        writeln!(out, "orphans:      {:width$}", orphans, width = 16)?;
+        writeln!(out, "uniq orphans: {:width$}", unique_orphans, width = 16)?;
+    }
+    if let (Some(singles), Some(unique_singles)) =
+        (summary.single_records, summary.unique_single_records)
+    {
+        writeln!(out, "singles:      {:width$}", singles, width = 16)?;
+        writeln!(out, "uniq singles: {:width$}", unique_singles, width = 16)?;
+    }
+    if summary.filtered_records > 0 {
+        writeln!(
+            out,
+            "filtered:     {:width$}",
+            summary.filtered_records,
+            width = 16
+        )?;
+    }
+    if summary.empty_records > 0 {
+        writeln!(
+            out,
+            "empty reads:  {:width$}",
+            summary.empty_records,
+            width = 16
+        )?;
+    }
+    if summary.n_filtered_records > 0 {
+        writeln!(
+            out,
+            "N filtered:   {:width$}",
+            summary.n_filtered_records,
+            width = 16
+        )?;
+    }
+    if summary.ambiguous_records > 0 {
+        writeln!(
+            out,
+            "ambiguous:    {:width$}",
+            summary.ambiguous_records,
+            width = 16
+        )?;
+    }
+    if let Some(unique_estimate) = summary.unique_estimate.as_ref() {
+        writeln!(
+            out,
+            "est. unique:  {:width$} ({}-{})",
+            unique_estimate.estimate,
+            unique_estimate.lower_bound,
+            unique_estimate.upper_bound,
+            width = 16
+        )?;
+    }
+    Ok(())
+}
+
+/// Formats a count with `,` between every three digits
+fn with_thousands_separators(n: u64) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Wraps text in an ANSI escape code if color is on
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_owned()
+    }
+}
+
+/// The summary for people at a terminal, with percentages and the files
+/// read and written
+fn print_summary_pretty<W: std::io::Write>(
+    out: &mut W,
+    summary: &DedupSummary,
+    color: bool,
+) -> std::io::Result<()> {
+    let percent = |records: u64| summary::duplication_rate(records, summary.total_records) * 100.0;
+    let row = |out: &mut W, label: &str, records: u64, code: &str| {
+        writeln!(
+            out,
+            "  {:<14}{}  {}",
+            label,
+            paint(
+                &format!("{:>16}", with_thousands_separators(records)),
+                code,
+                color
+            ),
+            paint(&format!("{:5.1}%", percent(records)), code, color)
+        )
+    };
+    writeln!(out, "{}", paint("Deduplication summary", "1", color))?;
+    row(out, "total reads", summary.total_records, "0")?;
+    row(out, "unique reads", summary.unique_records, "32")?;
+    row(out, "duplicates", summary.duplicate_records, "33")?;
+    if let (Some(exact), Some(prefix)) = (
+        summary.exact_duplicate_records,
+        summary.prefix_duplicate_records,
+    ) {
+        row(out, "exact dupes", exact, "33")?;
+        row(out, "prefix dupes", prefix, "33")?;
+    }
+    if let Some(pair_counts) = summary.pair_counts.as_ref() {
+        row(out, "pairs read", pair_counts.pairs_read, "0")?;
+        row(out, "pairs kept", pair_counts.pairs_kept, "32")?;
+        row(
+            out,
+            "r1 only",
+            pair_counts.r1_only_records.unwrap_or(0),
+            "0",
+        )?;
+        row(
+            out,
+            "r2 only",
+            pair_counts.r2_only_records.unwrap_or(0),
+            "0",
+        )?;
+    }
+    if summary.short_mate_pairs > 0 {
+        row(out, "short mates", summary.short_mate_pairs, "0")?;
+    }
+    if let (Some(orphans), Some(unique_orphans)) =
+        (summary.orphan_records, summary.unique_orphan_records)
+    {
+        row(out, "orphans", orphans, "0")?;
+        row(out, "unique orphans", unique_orphans, "32")?;
+    }
+    if let (Some(singles), Some(unique_singles)) =
+        (summary.single_records, summary.unique_single_records)
+    {
+        row(out, "singles", singles, "0")?;
+        row(out, "unique singles", unique_singles, "32")?;
+    }
+    if summary.filtered_records > 0 {
+        row(out, "filtered", summary.filtered_records, "0")?;
+    }
+    if summary.empty_records > 0 {
+        row(out, "empty reads", summary.empty_records, "0")?;
+    }
+    if summary.n_filtered_records > 0 {
+        row(out, "N filtered", summary.n_filtered_records, "0")?;
+    }
+    if summary.ambiguous_records > 0 {
+        row(out, "ambiguous", summary.ambiguous_records, "0")?;
+    }
+    if let Some(unique_estimate) = summary.unique_estimate.as_ref() {
+        row(out, "est. unique", unique_estimate.estimate, "32")?;
+    }
+    writeln!(out, "{}", paint("Files", "1", color))?;
+    for input in summary.input_files.iter() {
+        writeln!(out, "  {:<14}{}", "input", input)?;
+    }
+    for output in summary.output_files.iter() {
+        writeln!(out, "  {:<14}{}", "output", output)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bio::alphabets::dna::revcomp;
+    use bio::io::fastq;
+    use rand::Rng;
+    use std::io::Write;
+    use std::str;
+    use tempfile::tempdir;
+
+    fn random_seq(len: usize) -> Vec<u8> {
+        const CHARSET: &[u8] = b"ACTG";
+        let mut rng = rand::thread_rng();
+        (0..len)
+            .map(|_| {
+                let idx = rng.gen_range(0, CHARSET.len());
+                CHARSET[idx]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_run_dedup_single() {
+        let dir = tempdir().unwrap();
+        let input_path = dir.path().join("input.fastq").to_str().unwrap().to_string();
+        let output_path = dir
+            .path()
+            .join("output.fastq")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let cluster_path = dir.path().join("cluster.csv").to_str().unwrap().to_string();
+
+        {
+            let mut writer = fastq::Writer::to_file(&input_path).expect("don't break");
+            let seq = random_seq(20);
+            writer.write("id_a", None, &seq, &seq).expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "-c",
+            &cluster_path,
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.total_records, 1);
+        assert_eq!(result.parameters.seed, 0);
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--seed",
+            "42",
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.parameters.seed, 42);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_paired() {
+        let dir = tempdir().unwrap();
+        let input_path_r1 = dir
+            .path()
+            .join("input-r1.fasta")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let input_path_r2 = dir
+            .path()
+            .join("input-r2.fasta")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let output_path_r1 = dir
+            .path()
+            .join("output-r1.fasta")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let output_path_r2 = dir
+            .path()
+            .join("output-r2.fasta")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let cluster_path = dir.path().join("cluster.csv").to_str().unwrap().to_string();
+
+        {
+            let mut writer_r1 = fasta::Writer::to_file(&input_path_r1).expect("don't break");
+            let mut writer_r2 = fasta::Writer::to_file(&input_path_r2).expect("don't break");
+            let seq = random_seq(20);
+            writer_r1.write("id_a", None, &seq).expect("don't break");
+            writer_r2.write("id_a", None, &seq).expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path_r1,
+            "-i",
+            &input_path_r2,
+            "-o",
+            &output_path_r1,
+            "-o",
+            &output_path_r2,
+            "-c",
+            &cluster_path,
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.total_records, 1);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_paired_mismatched_files() {
+        let dir = tempdir().unwrap();
+        let input_path_r1 = dir
+            .path()
+            .join("input-r1.fasta")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let input_path_r2 = dir
+            .path()
+            .join("input-r2.fastq")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let output_path_r1 = dir
+            .path()
+            .join("output-r1.fasta")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let output_path_r2 = dir
+            .path()
+            .join("output-r2.fasta")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let cluster_path = dir.path().join("cluster.csv").to_str().unwrap().to_string();
+
+        {
+            let mut writer_r1 = fasta::Writer::to_file(&input_path_r1).expect("don't break");
+            let mut writer_r2 = fastq::Writer::to_file(&input_path_r2).expect("don't break");
+            let seq = random_seq(20);
+            writer_r1.write("id_a", None, &seq).expect("don't break");
+            writer_r2
+                .write("id_a", None, &seq, &seq)
+                .expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path_r1,
+            "-i",
+            &input_path_r2,
+            "-o",
+            &output_path_r1,
+            "-o",
+            &output_path_r2,
+            "-c",
+            &cluster_path,
+        ];
+        let result = run_dedup(args);
+        let message = result
+            .expect_err("should error on mismatched inputs")
+            .to_string();
+        assert_eq!(
+            message,
+            "paired inputs have different file types r1: fasta, r2: fastq"
+        );
+        // the mismatch is found before any output is created
+        for output in [&output_path_r1, &output_path_r2, &cluster_path].iter() {
+            assert!(!Path::new(output).exists());
+        }
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_tar_auto_pair() {
+        let dir = tempdir().unwrap();
+        let input_path = dir.path().join("input.tar").to_str().unwrap().to_string();
+        let output_path_r1 = dir
+            .path()
+            .join("output-r1.fastq")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let output_path_r2 = dir
+            .path()
+            .join("output-r2.fastq")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        {
+            let seq = random_seq(20);
+            let mut builder = tar::Builder::new(File::create(&input_path).expect("don't break"));
+            for name in ["R1.fastq", "R2.fastq"].iter() {
+                let seq = str::from_utf8(&seq).unwrap();
+                let contents = format!("@id_a\n{0}\n+\n{0}\n@id_b\n{0}\n+\n{0}\n", seq);
+                let contents = contents.as_bytes();
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, name, contents)
+                    .expect("don't break");
+            }
+            builder.finish().expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path_r1,
+            "-o",
+            &output_path_r2,
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.total_records, 2);
+        assert_eq!(result.unique_records, 1);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_interleave_output() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path_r1 = path("input-r1.fasta");
+        let input_path_r2 = path("input-r2.fasta");
+        let output_path_r1 = path("output-r1.fasta");
+        let output_path_r2 = path("output-r2.fasta");
+        let interleave_path = path("interleaved.fasta.gz");
+
+        {
+            let mut writer_r1 = fasta::Writer::to_file(&input_path_r1).expect("don't break");
+            let mut writer_r2 = fasta::Writer::to_file(&input_path_r2).expect("don't break");
+            let seq_r1 = random_seq(20);
+            let seq_r2 = random_seq(20);
+            for id in ["id_a", "id_b"].iter() {
+                writer_r1.write(id, None, &seq_r1).expect("don't break");
+                writer_r2.write(id, None, &seq_r2).expect("don't break");
+            }
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path_r1,
+            "-i",
+            &input_path_r2,
+            "-o",
+            &output_path_r1,
+            "-o",
+            &output_path_r2,
+            "--interleave-output",
+            &interleave_path,
+        ];
+        run_dedup(args).expect("don't break");
+        let ids = fasta::Reader::new(fastx::read_gz(&interleave_path))
+            .records()
+            .map(|record| record.expect("don't break").id().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["id_a", "id_a"]);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_concat_pairs_output() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq_r1 = random_seq(20);
+        let seq_r2 = random_seq(20);
+        for (mate, seq) in [("r1", &seq_r1), ("r2", &seq_r2)].iter() {
+            let mut writer = fasta::Writer::to_file(path(&format!("input-{}.fasta", mate)))
+                .expect("don't break");
+            for id in ["id_a", "id_b"].iter() {
+                writer.write(id, None, seq).expect("don't break");
+            }
+        }
+
+        let args = [
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("input-r1.fasta"),
+            path("input-r2.fasta"),
+            "-o".to_owned(),
+            path("output-r1.fasta"),
+            path("output-r2.fasta"),
+            "--concat-pairs-output".to_owned(),
+            path("concat.fasta.gz"),
+            "--concat-spacer".to_owned(),
+            "NNN".to_owned(),
+            "--trim-to".to_owned(),
+            "15".to_owned(),
+        ];
+        run_dedup(args).expect("don't break");
+        let records = fasta::Reader::new(fastx::read_gz(path("concat.fasta.gz")))
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("don't break");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id(), "id_a");
+        assert_eq!(
+            records[0].seq(),
+            [&seq_r1[..15], b"NNN", &seq_r2[..15]].concat().as_slice()
+        );
+
+        let args = [
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("input-r1.fasta"),
+            "-o".to_owned(),
+            path("output.fasta"),
+            "--concat-pairs-output".to_owned(),
+            path("concat.fasta"),
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_sort_mode() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            let seq_a = random_seq(20);
+            let seq_b = random_seq(20);
+            writer.write("id_a", None, &seq_a).expect("don't break");
+            writer.write("id_b", None, &seq_b).expect("don't break");
+            writer.write("id_c", None, &seq_a).expect("don't break");
+            writer.write("id_d", None, &seq_b).expect("don't break");
+            writer.write("id_e", None, &seq_a).expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--mode",
+            "sort",
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.total_records, 5);
+        assert_eq!(result.unique_records, 2);
+        assert_eq!(result.duplicate_records, 3);
+        assert_eq!(
+            result
+                .cluster_size_histogram
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(2, 1), (3, 1)]
+        );
+        let ids = fasta::Reader::from_file(&output_path)
+            .expect("don't break")
+            .records()
+            .map(|record| record.expect("don't break").id().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["id_a", "id_b"]);
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--mode",
+            "sort",
+            "-c",
+            &path("clusters.csv"),
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_invalid_paths() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        std::fs::write(&input_path, ">id_a\nACGT\n").expect("don't break");
+        let err = run_dedup([
+            "executable",
+            "-i",
+            &input_path,
+            &path("missing.fasta"),
+            "-o",
+            &path("missing/output_r1.fasta"),
+            &input_path,
+            "-c",
+            &path("clusters.csv"),
+            "--summary-json",
+            &path("clusters.csv"),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "invalid paths:\n  input {} does not exist\n  output {} is in {}, which does not exist\n  output {} would overwrite input {}\n  output {} is given more than once, also as {}",
+                path("missing.fasta"),
+                path("missing/output_r1.fasta"),
+                path("missing"),
+                input_path,
+                input_path,
+                path("clusters.csv"),
+                path("clusters.csv"),
+            )
+        );
+        // nothing was written
+        assert!(!dir.path().join("clusters.csv").exists());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_memory_limit() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+        let seqs = (0..1000).map(|_| random_seq(20)).collect::<Vec<_>>();
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            for i in 0..3000 {
+                let id = format!("id_{}", i);
+                writer
+                    .write(&id, None, &seqs[i % 1000])
+                    .expect("don't break");
+            }
+        }
+        let run = |memory_limit_opt: Option<&str>| {
+            let mut args = vec![
+                "executable",
+                "-i",
+                &input_path,
+                "-o",
+                &output_path,
+                "--mode",
+                "sort",
+            ];
+            if let Some(memory_limit) = memory_limit_opt {
+                args.extend(["--memory-limit", memory_limit]);
+            }
+            let summary = run_dedup(args).expect("don't break");
+            let output = std::fs::read_to_string(&output_path).expect("don't break");
+            (summary.unique_records, output)
+        };
+        // 64K sorts 1024 reads at a time, spilling runs to disk
+        let (unique_records, output) = run(Some("64K"));
+        assert_eq!(unique_records, 1000);
+        assert_eq!((unique_records, output), run(None));
+
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("4g"), Ok(4 << 30));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("4GB").is_err());
+        assert!(parse_size("99999999T").is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_split_by_cluster() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+        let split_dir = path("clusters");
+
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            let seq_a = random_seq(20);
+            let seq_b = random_seq(20);
+            writer.write("id/a", None, &seq_a).expect("don't break");
+            writer.write("id_b", None, &seq_b).expect("don't break");
+            writer.write("id_c", None, &seq_a).expect("don't break");
+            writer.write("id_d", None, &seq_a).expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--split-by-cluster",
+            &split_dir,
+            "--min-cluster-size",
+            "2",
+        ];
+        run_dedup(args).expect("don't break");
+        let files = std::fs::read_dir(&split_dir)
+            .expect("don't break")
+            .map(|entry| entry.expect("don't break").file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec!["id_a.fasta"]);
+        let ids = fasta::Reader::from_file(cluster_path(&split_dir, "id/a", "fasta"))
+            .expect("don't break")
+            .records()
+            .map(|record| record.expect("don't break").id().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["id/a", "id_c", "id_d"]);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_abundance_output() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+        let abundance_path = path("abundance.csv");
+
+        let seq_a = random_seq(20);
+        let seq_b = random_seq(20);
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            writer.write("id_a", None, &seq_a).expect("don't break");
+            writer.write("id_b", None, &seq_b).expect("don't break");
+            writer.write("id_c", None, &seq_a).expect("don't break");
+            writer.write("id_d", None, &seq_a).expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--abundance-output",
+            &abundance_path,
+        ];
+        run_dedup(args).expect("don't break");
+        let abundance = std::fs::read_to_string(&abundance_path).expect("don't break");
+        let expected = format!(
+            "representative read id,sequence,count,frequency\nid_a,{},3,0.75\nid_b,{},1,0.25\n",
+            str::from_utf8(&seq_a).expect("don't break"),
+            str::from_utf8(&seq_b).expect("don't break"),
+        );
+        assert_eq!(abundance, expected);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_sizeout_relabel() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            let seq_a = random_seq(20);
+            let seq_b = random_seq(20);
+            writer.write("id_a", None, &seq_a).expect("don't break");
+            writer.write("id_b", None, &seq_b).expect("don't break");
+            writer.write("id_c", None, &seq_a).expect("don't break");
+        }
+        let output_ids = || {
+            fasta::Reader::from_file(&output_path)
+                .expect("don't break")
+                .records()
+                .map(|record| record.expect("don't break").id().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--sizeout",
+        ];
+        run_dedup(args).expect("don't break");
+        assert_eq!(output_ids(), vec!["id_a;size=2", "id_b;size=1"]);
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--sizeout",
+            "--relabel",
+            "Uniq",
+        ];
+        run_dedup(args).expect("don't break");
+        assert_eq!(output_ids(), vec!["Uniq1;size=2", "Uniq2;size=1"]);
+
+        let sizes_path = path("sizes.csv");
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--annotate-headers",
+            "--cluster-hash",
+            "--cluster-size-output",
+            &sizes_path,
+        ];
+        run_dedup(args).expect("don't break");
+        let sizes = std::fs::read_to_string(&sizes_path).expect("don't break");
+        let expected = sizes
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let fields = line.split(',').collect::<Vec<_>>();
+                format!("{};key={}", fields[0], fields[2])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(output_ids(), expected);
+
+        let weights_path = path("weights.tsv");
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--relabel",
+            "Uniq",
+            "--emit-weights",
+            &weights_path,
+        ];
+        run_dedup(args).expect("don't break");
+        assert_eq!(
+            std::fs::read_to_string(&weights_path).expect("don't break"),
+            "Uniq1\t2\nUniq2\t1\n"
+        );
+
+        // windowed and sorted runs let clusters go, which --relabel alone
+        // doesn't need
+        // id_c isn't next to its duplicate id_a, so sorted mode keeps it
+        let modes = [
+            (&["--window", "1"][..], vec!["Uniq1", "Uniq2"]),
+            (&["--assume-sorted"][..], vec!["Uniq1", "Uniq2", "Uniq3"]),
+        ];
+        for (mode_args, expected) in modes.iter() {
+            let mut args = vec![
+                "executable",
+                "-i",
+                &input_path,
+                "-o",
+                &output_path,
+                "--relabel",
+                "Uniq",
+            ];
+            args.extend_from_slice(mode_args);
+            run_dedup(args).expect("don't break");
+            assert_eq!(&output_ids(), expected);
+        }
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_compare_clusters() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq_shared = random_seq(20);
+        for (sample, seq) in [("a", random_seq(20)), ("b", random_seq(20))].iter() {
+            let input_path = path(&format!("{}.fasta", sample));
+            {
+                let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+                let id = |n: usize| format!("{}{}", sample, n);
+                writer
+                    .write(&id(1), None, &seq_shared)
+                    .expect("don't break");
+                writer.write(&id(2), None, seq).expect("don't break");
+                writer
+                    .write(&id(3), None, &seq_shared)
+                    .expect("don't break");
+            }
+            let args = [
+                "executable",
+                "-i",
+                &input_path,
+                "-o",
+                &path(&format!("{}-deduped.fasta", sample)),
+                "-c",
+                &path(&format!("{}.csv", sample)),
+                "--cluster-hash",
+            ];
+            run_dedup(args).expect("don't break");
+        }
+
+        let args = ["compare-clusters", &path("a.csv"), &path("b.csv")];
+        let comparison = run_compare_clusters(args).expect("don't break");
+        assert_eq!(comparison.clusters_a, 2);
+        assert_eq!(comparison.shared_clusters(), 1);
+        assert_eq!(comparison.shared_reads_a(), 2);
+        assert_eq!(comparison.shared[0].representative_b, "b1");
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_tune_prefix() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(60);
+        {
+            let mut writer = fasta::Writer::to_file(path("input.fasta")).expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+            let mut diverged = seq.clone();
+            diverged[55] = if diverged[55] == b'A' { b'C' } else { b'A' };
+            writer.write("id_b", None, &diverged).expect("don't break");
+        }
+        let args = [
+            "tune-prefix",
+            "-i",
+            &path("input.fasta"),
+            "--lengths",
+            "50,60",
+            "-o",
+            &path("stats.csv"),
+        ];
+        let report = run_tune_prefix(args).expect("don't break");
+        assert_eq!(report.unique_sequences, 2);
+        assert_eq!(report.stats[0].unique_keys, 1);
+        assert_eq!(report.stats[0].over_collapsed_reads, 1);
+        assert_eq!(report.stats[1].unique_keys, 2);
+        assert_eq!(
+            std::fs::read_to_string(path("stats.csv")).expect("don't break"),
+            "prefix length,unique keys,truncated reads,over-collapsed reads,divergent clusters\n\
+             50,1,2,1,1\n\
+             60,2,0,0,0\n"
+        );
+
+        let args = ["tune-prefix", "-i", &path("input.fasta"), "--lengths", "0"];
+        assert!(run_tune_prefix(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_bench() {
+        let args = [
+            "bench",
+            "--reads",
+            "2K",
+            "--length",
+            "50",
+            "--duplication",
+            "0.5",
+        ];
+        let results = run_bench(args).expect("don't break");
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|result| result.reads == 2000));
+
+        let args = ["bench", "--reads", "10", "--duplication", "2"];
+        assert!(run_bench(args).is_err());
+    }
+
+    #[test]
+    fn test_run_fixtures() {
+        let matches = |results: &[FixtureResult]| {
+            results
+                .iter()
+                .filter(|result| result.actual == result.expected)
+                .map(|result| result.name)
+                .collect::<Vec<_>>()
+        };
+        let results = run_fixtures(["fixtures", "verify"])
+            .expect("don't break")
+            .expect("don't break");
+        assert_eq!(results.len(), fixtures::CASES.len());
+        assert_eq!(matches(&results).len(), fixtures::CASES.len());
+
+        let dir = tempdir().expect("don't break");
+        let fixtures_dir = dir.path().to_str().unwrap();
+        assert!(run_fixtures(["fixtures", "generate", fixtures_dir])
+            .expect("don't break")
+            .is_none());
+        // a changed input changes its cases' outputs
+        let n_runs = dir.path().join("n_runs.fasta");
+        let mut input = std::fs::read(&n_runs).expect("don't break");
+        input.extend_from_slice(b">read_8\nACGTACGT\n");
+        std::fs::write(&n_runs, input).expect("don't break");
+        let results = run_fixtures(["fixtures", "verify", fixtures_dir])
+            .expect("don't break")
+            .expect("don't break");
+        assert_eq!(
+            matches(&results),
+            vec![
+                "revcomp-pairs",
+                "revcomp-pairs-r2-only",
+                "prefix-ties",
+                "prefix-ties-two-tier",
+                "truncated-gz"
+            ]
+        );
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_with_env_flags() {
+        let app = App::new("test")
+            .arg(Arg::with_name("test-flag").long("test-flag"))
+            .arg(
+                Arg::with_name("test-option")
+                    .long("test-option")
+                    .takes_value(true),
+            );
+        let flags = [("test-flag", "--test-flag")];
+        let args = |args: &[&str]| {
+            args.iter()
+                .map(std::ffi::OsString::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            with_env_flags(&app, args(&["test"]), &flags),
+            args(&["test"])
+        );
+        std::env::set_var("CZID_DEDUP_TEST_FLAG", "true");
+        assert_eq!(
+            with_env_flags(&app, args(&["test", "--test-option", "a"]), &flags),
+            args(&["test", "--test-option", "a", "--test-flag"])
+        );
+        // flags on the command line aren't given twice
+        assert_eq!(
+            with_env_flags(&app, args(&["test", "--test-flag"]), &flags),
+            args(&["test", "--test-flag"])
+        );
+        std::env::set_var("CZID_DEDUP_TEST_FLAG", "0");
+        assert_eq!(
+            with_env_flags(&app, args(&["test"]), &flags),
+            args(&["test"])
+        );
+        std::env::remove_var("CZID_DEDUP_TEST_FLAG");
+    }
+
+    #[test]
+    fn test_env_flags() {
+        // every flag in the help is listed, and every listed flag is one
+        let mut help = Vec::new();
+        dedup_app().write_long_help(&mut help).expect("don't break");
+        let help = String::from_utf8(help).expect("don't break");
+        let flags_help = help
+            .split("FLAGS:")
+            .nth(1)
+            .and_then(|rest| rest.split("OPTIONS:").next())
+            .expect("don't break");
+        // flags are indented less than wrapped lines of their help
+        let mut help_longs = flags_help
+            .lines()
+            .filter(|line| line.len() - line.trim_start().len() <= 8)
+            .filter_map(|line| line.split_whitespace().find(|word| word.starts_with("--")))
+            .filter(|long| !["--help", "--version"].contains(long))
+            .collect::<Vec<_>>();
+        help_longs.sort_unstable();
+        let mut longs = ENV_FLAGS.iter().map(|(_, long)| *long).collect::<Vec<_>>();
+        longs.sort_unstable();
+        assert_eq!(help_longs, longs);
+    }
+
+    #[test]
+    fn test_unsupported_args_exist() {
+        // a misspelled name would never be present, so nothing would be rejected
+        let mut help = Vec::new();
+        dedup_app().write_long_help(&mut help).expect("don't break");
+        let help = String::from_utf8(help).expect("don't break");
+        let help_longs = help
+            .lines()
+            .filter(|line| line.len() - line.trim_start().len() <= 8)
+            .filter_map(|line| line.split_whitespace().find(|word| word.starts_with("--")))
+            .collect::<Vec<_>>();
+        for unsupported in UNSUPPORTED.iter() {
+            for arg in unsupported.args.iter().chain(unsupported.explicit_args) {
+                if *arg == "sra" && cfg!(not(feature = "sra")) {
+                    continue;
+                }
+                let long = ENV_FLAGS
+                    .iter()
+                    .find(|(name, _)| name == arg)
+                    .map_or_else(|| format!("--{}", arg), |(_, long)| long.to_string());
+                assert!(help_longs.contains(&long.as_str()), "{}", arg);
+            }
+        }
+    }
+
+    #[test]
+    fn test_with_thousands_separators() {
+        assert_eq!(with_thousands_separators(0), "0");
+        assert_eq!(with_thousands_separators(999), "999");
+        assert_eq!(with_thousands_separators(1000), "1,000");
+        assert_eq!(with_thousands_separators(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_run_dedup_skip_invalid() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fastq");
+        let output_path = path("output.fastq");
+
+        let seq = random_seq(20);
+        std::fs::write(
+            &input_path,
+            format!(
+                "@id_a\n{seq}\n+\n{seq}\n@id_b\n{seq}\n+\nII\n",
+                seq = str::from_utf8(&seq).expect("don't break")
+            ),
+        )
+        .expect("don't break");
+
+        let args = ["executable", "-i", &input_path, "-o", &output_path];
+        assert!(run_dedup(args).is_err());
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--skip-invalid",
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.total_records, 1);
+        assert_eq!(result.warning_counts.get("invalid record"), Some(&1));
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_auto_prefix() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            for i in 0..10 {
+                let seq = random_seq(if i == 0 { 30 } else { 50 });
+                writer
+                    .write(&format!("id_{}", i), None, &seq)
+                    .expect("don't break");
+            }
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--auto-prefix",
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.parameters.prefix_length, Some(50));
+        assert!(result.parameters.auto_prefix);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_bgzf() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta.gz");
+
+        let seq = random_seq(50);
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            for i in 0..1000 {
+                let seq = if i % 2 == 0 {
+                    seq.clone()
+                } else {
+                    random_seq(50)
+                };
+                writer
+                    .write(&format!("id_{}", i), None, &seq)
+                    .expect("don't break");
+            }
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--bgzf",
+            "--threads",
+            "4",
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.unique_records, 501);
+
+        let compressed = std::fs::read(&output_path).expect("don't break");
+        assert_eq!(&compressed[..2], &[0x1f, 0x8b]);
+        assert!(compressed.ends_with(&bgzf::EOF_BLOCK));
+        let (fastx_type, reader) = fastx::open_input(&output_path).expect("don't break");
+        assert_eq!(fastx_type, fastx::FastxType::Fasta);
+        assert_eq!(fasta::Reader::new(reader).records().count(), 501);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_bgzf_index() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta.gz");
+        let index_path = path("index.csv");
+
+        let seq = random_seq(50);
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+            writer.write("id_b", None, &seq).expect("don't break");
+            writer
+                .write("id_c", None, &random_seq(50))
+                .expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--bgzf",
+            "--bgzf-index",
+            &index_path,
+        ];
+        run_dedup(args).expect("don't break");
+
+        let index = std::fs::read_to_string(&index_path).expect("don't break");
+        assert_eq!(
+            index,
+            "representative read id,virtual offset\nid_a,0\nid_c,57\n"
+        );
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_audit_sample() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(20);
+        {
+            let mut writer = fasta::Writer::to_file(path("input.fasta")).expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+            writer.write("id_b", None, &seq).expect("don't break");
+        }
+        let run = |fraction: &str| {
+            let args = [
+                "executable",
+                "-i",
+                &path("input.fasta"),
+                "-o",
+                &path("output.fasta"),
+                "--audit-sample",
+                fraction,
+                "--audit-output",
+                &path("audit.csv"),
+            ];
+            run_dedup(args)
+        };
+
+        run("1").expect("don't break");
+        assert_eq!(
+            std::fs::read_to_string(path("audit.csv")).expect("don't break"),
+            format!(
+                "representative read id,read id,duplicate type,representative sequence,sequence\n\
+                 id_a,id_b,exact,{},{}\n",
+                str::from_utf8(&seq).unwrap(),
+                str::from_utf8(&seq).unwrap()
+            )
+        );
+        assert!(run("0").is_err());
+        assert!(run("1.5").is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_single_input() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let (seq_r1, seq_r2) = (random_seq(20), random_seq(20));
+        for (mate, seq) in [("r1", &seq_r1), ("r2", &seq_r2)].iter() {
+            let mut writer = fasta::Writer::to_file(path(&format!("input-{}.fasta", mate)))
+                .expect("don't break");
+            writer.write("id_a", None, seq).expect("don't break");
+        }
+        {
+            let mut writer = fasta::Writer::to_file(path("singles.fasta")).expect("don't break");
+            let seq = random_seq(20);
+            // a duplicate of the pair's r1, then a single read twice
+            writer.write("id_b", None, &seq_r1).expect("don't break");
+            writer.write("id_c", None, &seq).expect("don't break");
+            writer.write("id_d", None, &seq).expect("don't break");
+        }
+
+        let args = [
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("input-r1.fasta"),
+            path("input-r2.fasta"),
+            "-o".to_owned(),
+            path("output-r1.fasta"),
+            path("output-r2.fasta"),
+            "--single-input".to_owned(),
+            path("singles.fasta"),
+            "--single-output".to_owned(),
+            path("output-singles.fasta"),
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.total_records, 1);
+        assert_eq!(summary.single_records, Some(3));
+        assert_eq!(summary.unique_single_records, Some(1));
+        assert_eq!(summary.paired_duplicate_single_records, Some(1));
+        assert_eq!(summary.processed_records(), 4);
+        let ids = fasta::Reader::from_file(path("output-singles.fasta"))
+            .expect("don't break")
+            .records()
+            .map(|record| record.expect("don't break").id().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["id_c"]);
+
+        let args = [
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("input-r1.fasta"),
+            "-o".to_owned(),
+            path("output.fasta"),
+            "--single-input".to_owned(),
+            path("singles.fasta"),
+            "--single-output".to_owned(),
+            path("output-singles.fasta"),
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_allow_orphans() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_r1_path = path("input_r1.fasta");
+        let input_r2_path = path("input_r2.fasta");
+        let output_r1_path = path("output_r1.fasta");
+        let output_r2_path = path("output_r2.fasta");
+        let orphan_path = path("orphans.fasta");
+
+        let seq = random_seq(50);
+        {
+            let mut writer_r1 = fasta::Writer::to_file(&input_r1_path).expect("don't break");
+            let mut writer_r2 = fasta::Writer::to_file(&input_r2_path).expect("don't break");
+            writer_r1.write("id_a", None, &seq).expect("don't break");
+            writer_r2.write("id_a", None, &seq).expect("don't break");
+            // an invalid r1 orphans its r2
+            writer_r1
+                .write("id_b", None, "AC\u{e9}GT".as_bytes())
+                .expect("don't break");
+            writer_r2.write("id_b", None, &seq).expect("don't break");
+            // r2 ends first, orphaning the rest of r1
+            writer_r1.write("id_c", None, &seq).expect("don't break");
+            writer_r1
+                .write("id_d", None, &random_seq(50))
+                .expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_r1_path,
+            &input_r2_path,
+            "-o",
+            &output_r1_path,
+            &output_r2_path,
+        ];
+        assert!(run_dedup(args).is_err());
+
+        let args = [
+            "executable",
+            "-i",
+            &input_r1_path,
+            &input_r2_path,
+            "-o",
+            &output_r1_path,
+            &output_r2_path,
+            "--allow-orphans",
+            "--orphan-output",
+            &orphan_path,
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.total_records, 1);
+        assert_eq!(result.orphan_records, Some(3));
+        assert_eq!(result.unique_orphan_records, Some(2));
+        assert!(result.parameters.allow_orphans);
+        assert_eq!(
+            result.pair_counts,
+            Some(summary::PairCounts {
+                pairs_read: 1,
+                pairs_kept: 1,
+                r1_only_records: Some(2),
+                r2_only_records: Some(1),
+            })
+        );
+
+        let mut plain = Vec::new();
+        print_summary(&mut plain, &result).expect("don't break");
+        let plain = String::from_utf8(plain).expect("don't break");
+        for line in [
+            format!("pairs read:   {:16}", 1),
+            format!("pairs kept:   {:16}", 1),
+            format!("r1 only:      {:16}", 2),
+            format!("r2 only:      {:16}", 1),
+        ]
+        .iter()
+        {
+            assert!(plain.lines().any(|printed| printed == line), "{}", line);
+        }
+        let mut pretty = Vec::new();
+        print_summary_pretty(&mut pretty, &result, false).expect("don't break");
+        let pretty = String::from_utf8(pretty).expect("don't break");
+        for (label, records) in [
+            ("pairs read", 1),
+            ("pairs kept", 1),
+            ("r1 only", 2),
+            ("r2 only", 1),
+        ]
+        .iter()
+        {
+            let row = format!("  {:<14}{:>16}", label, records);
+            assert!(
+                pretty.lines().any(|printed| printed.starts_with(&row)),
+                "{}",
+                row
+            );
+        }
+        // colors are added after padding, so the columns line up either way
+        let mut colored = Vec::new();
+        print_summary_pretty(&mut colored, &result, true).expect("don't break");
+        let colored = String::from_utf8(colored).expect("don't break");
+        let mut uncolored = String::new();
+        let mut in_escape = false;
+        for c in colored.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                _ if !in_escape => uncolored.push(c),
+                _ => (),
+            }
+        }
+        assert_eq!(uncolored, pretty);
+        assert!(pretty.contains(&format!("  {:<14}{}", "input", input_r1_path)));
+        assert!(pretty.contains(&format!("  {:<14}{}", "output", output_r1_path)));
+
+        let ids = fasta::Reader::from_file(&orphan_path)
+            .expect("don't break")
+            .records()
+            .map(|record| record.expect("don't break").id().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["id_b", "id_d"]);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_filter_expr() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fastq");
+        let output_path = path("output.fastq");
+
+        {
+            let mut writer = fastq::Writer::to_file(&input_path).expect("don't break");
+            let seq = random_seq(60);
+            writer
+                .write("id_a", None, &seq, &[b'I'; 60])
+                .expect("don't break");
+            writer
+                .write("id_b", None, &seq, &[b'I'; 60])
+                .expect("don't break");
+            writer
+                .write("id_c", None, &random_seq(40), &[b'I'; 40])
+                .expect("don't break");
+            writer
+                .write("id_d", None, &random_seq(60), &[b'#'; 60])
+                .expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--filter-expr",
+            "length>=50 && meanq>=20",
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.filtered_records, 2);
+        assert_eq!(result.total_records, 2);
+        assert_eq!(result.unique_records, 1);
+        assert_eq!(
+            result.parameters.filter_expr.as_deref(),
+            Some("length>=50 && meanq>=20")
+        );
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--filter-expr",
+            "length>=",
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_id_filter() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            let seq = random_seq(60);
+            writer.write("sample_a", None, &seq).expect("don't break");
+            writer.write("sample_b", None, &seq).expect("don't break");
+            writer
+                .write("control_c", None, &random_seq(60))
+                .expect("don't break");
+            writer
+                .write("sample_phix", None, &random_seq(60))
+                .expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--id-filter",
+            "^sample_",
+            "--id-exclude",
+            "phix$",
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.filtered_records, 2);
+        assert_eq!(result.total_records, 2);
+        assert_eq!(result.unique_records, 1);
+        assert_eq!(result.parameters.id_filter.as_deref(), Some("^sample_"));
+        assert_eq!(result.parameters.id_exclude.as_deref(), Some("phix$"));
+        let ids = fasta::Reader::from_file(&output_path)
+            .expect("don't break")
+            .records()
+            .map(|record| record.expect("don't break").id().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["sample_a"]);
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--id-filter",
+            "(",
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_trim() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fastq");
+        let output_path = path("output.fastq");
+
+        let seq = random_seq(60);
+        let mut qual = vec![b'I'; 60];
+        for quality in qual[40..].iter_mut() {
+            *quality = b'#';
+        }
+        {
+            let mut writer = fastq::Writer::to_file(&input_path).expect("don't break");
+            writer
+                .write("id_a", None, &seq, &qual)
+                .expect("don't break");
+            writer
+                .write("id_b", None, &seq, &qual)
+                .expect("don't break");
+            writer
+                .write("id_c", None, &random_seq(60), &[b'I'; 60])
+                .expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--quality-trim",
+            "20",
+            "--trim-to",
+            "50",
+        ];
+        let result = run_dedup(args).expect("don't break");
+        assert_eq!(result.unique_records, 2);
+        assert_eq!(result.parameters.trim_to, Some(50));
+        assert_eq!(result.parameters.quality_trim, Some(20));
+
+        let lengths = fastq::Reader::from_file(&output_path)
+            .expect("don't break")
+            .records()
+            .map(|record| record.expect("don't break").seq().len())
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, vec![40, 50]);
+
+        // trimmed outputs can't be keyed back to their clusters
+        let err = run_dedup([
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--trim-to",
+            "50",
+            "--sizeout",
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--sizeout is not supported with --trim-to or --quality-trim"
+        );
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_summary_json() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+        let summary_path = path("summary.json");
+
+        let seq = random_seq(50);
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            for i in 0..3 {
+                writer
+                    .write(&format!("id_{}", i), None, &seq)
+                    .expect("don't break");
+            }
+            writer
+                .write("id_3", None, &random_seq(50))
+                .expect("don't break");
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--summary-json",
+            &summary_path,
+        ];
+        let result = run_dedup(args).expect("don't break");
+        let summary = std::fs::read_to_string(&summary_path).expect("don't break");
+        assert_eq!(
+            summary,
+            format!("{}\n", serde_json::to_string(&result).expect("don't break"))
+        );
+        assert!(summary.contains(r#""cluster_size_histogram":{"1":1,"3":1}"#));
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_failed_summary() {
+        let dir = tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fastq");
+        let output_path = path("output.fastq");
+        let summary_path = path("summary.json");
+
+        let seq = str::from_utf8(&random_seq(20))
+            .expect("don't break")
+            .to_owned();
+        let qual = "I".repeat(20);
+        std::fs::write(
+            &input_path,
+            format!(
+                "@id_a\n{seq}\n+\n{qual}\n@id_b\n{seq}\n+\n{qual}\nid_c\n{seq}\n+\n{qual}\n",
+                seq = seq,
+                qual = qual
+            ),
+        )
+        .expect("don't break");
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &output_path,
+            "--summary-json",
+            &summary_path,
+        ];
+        let err = run_dedup(args).expect_err("should fail on the third record");
+        let summary = &err
+            .downcast_ref::<FailedRun>()
+            .expect("should have a partial summary")
+            .summary;
+        assert_eq!(summary.status, summary::RunStatus::Failed);
+        assert_eq!(summary.total_records, 2);
+        assert_eq!(summary.unique_records, 1);
+        assert_eq!(summary.failed_at_record, Some(3));
+        assert_eq!(summary.error.as_deref(), Some(err.to_string().as_str()));
+
+        let summary_json = std::fs::read_to_string(&summary_path).expect("don't break");
+        assert!(summary_json.contains(r#""status":"failed""#));
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_simulate() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let args = [
+            "simulate".to_owned(),
+            "-o".to_owned(),
+            path("r1.fastq"),
+            path("r2.fastq"),
+            "--reads".to_owned(),
+            "200".to_owned(),
+            "--length".to_owned(),
+            "40".to_owned(),
+            "--cluster-sizes".to_owned(),
+            "2:1".to_owned(),
+            "--truth-output".to_owned(),
+            path("truth.csv"),
+        ];
+        let reads = run_simulate(&args).expect("don't break");
+        assert_eq!(reads.len(), 200);
+        let records = fastq::Reader::from_file(path("r2.fastq"))
+            .expect("don't break")
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("don't break");
+        assert_eq!(records.len(), 200);
+        assert_eq!(records[0].id(), "read_0");
+        assert_eq!(records[0].seq().len(), 40);
+        let truth = std::fs::read_to_string(path("truth.csv")).expect("don't break");
+        assert_eq!(truth.lines().count(), 201);
+
+        let args = [
+            "simulate".to_owned(),
+            "-o".to_owned(),
+            path("r1.fasta"),
+            "--format".to_owned(),
+            "fasta".to_owned(),
+            "--reads".to_owned(),
+            "100".to_owned(),
+            "--cluster-sizes".to_owned(),
+            "2:1".to_owned(),
+        ];
+        run_simulate(&args).expect("don't break");
+        let summary = run_dedup(vec![
+            "czid-dedup".to_owned(),
+            "-i".to_owned(),
+            path("r1.fasta"),
+            "-o".to_owned(),
+            path("dedup.fasta"),
+        ])
+        .expect("don't break");
+        assert_eq!(summary.unique_records, 50);
+
+        let args = ["simulate", "-o", "r1.fastq", "--error-rate", "2"];
+        assert!(run_simulate(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_auto_pair() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        {
+            let mut writer_r1 =
+                fasta::Writer::to_file(path("sample_R1_001.fasta")).expect("don't break");
+            let mut writer_r2 =
+                fasta::Writer::to_file(path("sample_R2_001.fasta")).expect("don't break");
+            let seq = random_seq(20);
+            for id in ["id_a", "id_b"].iter() {
+                writer_r1.write(id, None, &seq).expect("don't break");
+                writer_r2.write(id, None, &seq).expect("don't break");
+            }
+        }
+
+        let args = vec![
+            "executable".to_owned(),
+            "--auto-pair".to_owned(),
+            "-i".to_owned(),
+            path("sample_R1_001.fasta"),
+            "-o".to_owned(),
+            path("deduped_1.fasta"),
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.total_records, 2);
+        assert_eq!(summary.unique_records, 1);
+        assert_eq!(summary.output_files.len(), 2);
+        let output_r2 = std::fs::read_to_string(path("deduped_2.fasta")).expect("don't break");
+        assert!(output_r2.starts_with(">id_a\n"));
+
+        let args = vec![
+            "executable".to_owned(),
+            "--auto-pair".to_owned(),
+            "-i".to_owned(),
+            path("sample_R1_001.fasta"),
+            "-o".to_owned(),
+            path("deduped.fasta"),
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_output_template() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        {
+            let mut writer_r1 =
+                fasta::Writer::to_file(path("sample_R1.fasta")).expect("don't break");
+            let mut writer_r2 =
+                fasta::Writer::to_file(path("sample_R2.fasta")).expect("don't break");
+            let seq = random_seq(20);
+            for id in ["id_a", "id_b"].iter() {
+                writer_r1.write(id, None, &seq).expect("don't break");
+                writer_r2.write(id, None, &seq).expect("don't break");
+            }
+        }
+
+        let args = [
+            "executable",
+            "-i",
+            &path("sample_R1.fasta"),
+            &path("sample_R2.fasta"),
+            "--output-template",
+            "{dir}/{stem}.dedup_{pair}.{ext}",
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.unique_records, 1);
+        assert_eq!(
+            summary.output_files,
+            vec![path("sample.dedup_R1.fasta"), path("sample.dedup_R2.fasta")]
+        );
+        let output_r2 =
+            std::fs::read_to_string(path("sample.dedup_R2.fasta")).expect("don't break");
+        assert!(output_r2.starts_with(">id_a\n"));
+
+        // both mates would be written to the same file
+        let args = [
+            "executable",
+            "-i",
+            &path("sample_R1.fasta"),
+            &path("sample_R2.fasta"),
+            "--output-template",
+            "{dir}/{stem}.dedup.{ext}",
+        ];
+        assert!(run_dedup(args).is_err());
+
+        let args = [
+            "executable",
+            "-i",
+            &path("sample_R1.fasta"),
+            "--output-template",
+            "{dir}/{stem}.dedup.{ext}",
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.output_files, vec![path("sample_R1.dedup.fasta")]);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_lenient_paired() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        {
+            let mut writer_r1 = fasta::Writer::to_file(path("r1.fasta")).expect("don't break");
+            let mut writer_r2 = fasta::Writer::to_file(path("r2.fasta")).expect("don't break");
+            let seq = random_seq(20);
+            for id in ["id_a", "id_b", "id_c", "id_d"].iter() {
+                writer_r1.write(id, None, &seq).expect("don't break");
+            }
+            for id in ["id_a", "id_x", "id_c"].iter() {
+                writer_r2.write(id, None, &seq).expect("don't break");
+            }
+        }
+
+        let args = vec![
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("r1.fasta"),
+            path("r2.fasta"),
+            "-o".to_owned(),
+            path("output-r1.fasta"),
+            path("output-r2.fasta"),
+        ];
+        assert!(run_dedup(args.clone()).is_err());
+        let mut strict_args = args.clone();
+        strict_args.push("--strict".to_owned());
+        assert!(run_dedup(strict_args).is_err());
+
+        let mut lenient_args = args;
+        lenient_args.push("--lenient".to_owned());
+        let summary = run_dedup(lenient_args).expect("don't break");
+        assert_eq!(summary.total_records, 2);
+        assert_eq!(summary.unique_records, 1);
+        assert_eq!(summary.warning_counts.get("id mismatch"), Some(&1));
+        assert_eq!(summary.warning_counts.get("unpaired read"), Some(&1));
+        assert!(summary.parameters.lenient);
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_lenient_truncated() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        {
+            let mut writer = fastq::Writer::new(fastx::write_gz(path("input.fastq.gz")).unwrap());
+            for i in 0..1000 {
+                let seq = random_seq(50);
+                writer
+                    .write(&format!("id_{}", i), None, &seq, &[b'I'; 50])
+                    .expect("don't break");
+            }
+        }
+        let compressed = std::fs::read(path("input.fastq.gz")).expect("don't break");
+        std::fs::write(path("input.fastq.gz"), &compressed[..compressed.len() / 2])
+            .expect("don't break");
+
+        let args = vec![
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("input.fastq.gz"),
+            "-o".to_owned(),
+            path("output.fastq"),
+        ];
+        assert!(run_dedup(args.clone()).is_err());
+
+        let mut lenient_args = args;
+        lenient_args.push("--lenient".to_owned());
+        let summary = run_dedup(lenient_args).expect("don't break");
+        assert!(summary.total_records > 0 && summary.total_records < 1000);
+        assert_eq!(summary.warning_counts.get("truncated input"), Some(&1));
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_read_groups() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(20);
+        for (lane, id) in [("L1", "id_a"), ("L2", "id_b")].iter() {
+            for mate in ["R1", "R2"].iter() {
+                let mut writer = fasta::Writer::to_file(path(&format!("{}_{}.fasta", lane, mate)))
+                    .expect("don't break");
+                writer.write(id, None, &seq).expect("don't break");
+            }
+        }
+
+        let args = vec![
+            "executable".to_owned(),
+            "-i".to_owned(),
+            format!("{},{}", path("L1_R1.fasta"), path("L2_R1.fasta")),
+            format!("{},{}", path("L1_R2.fasta"), path("L2_R2.fasta")),
+            "-o".to_owned(),
+            path("output-r1.fasta"),
+            path("output-r2.fasta"),
+            "--read-group".to_owned(),
+            "L1,L2".to_owned(),
+            "-c".to_owned(),
+            path("cluster.csv"),
+            "--lane-matrix-output".to_owned(),
+            path("lanes.csv"),
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.total_records, 2);
+        assert_eq!(summary.unique_records, 1);
+        assert_eq!(summary.input_files.len(), 4);
+        assert_eq!(
+            std::fs::read_to_string(path("cluster.csv")).expect("don't break"),
+            "representative read id,read id,duplicate type,revcomp,read group\n\
+             id_a,id_a,representative,false,L1\n\
+             id_a,id_b,exact,false,L2\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path("lanes.csv")).expect("don't break"),
+            "representative lane,L1,L2\nL1,0,1\nL2,0,0\n"
+        );
+
+        let args = vec![
+            "executable".to_owned(),
+            "-i".to_owned(),
+            format!("{},{}", path("L1_R1.fasta"), path("L2_R1.fasta")),
+            "-o".to_owned(),
+            path("output.fasta"),
+            "--read-group".to_owned(),
+            "L1".to_owned(),
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_gz_members() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(20);
+        let mut data = Vec::new();
+        for ids in [vec!["id_a", "id_b"], vec!["id_c"]].iter() {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            for id in ids.iter() {
+                write!(encoder, ">{}\n{}\n", id, str::from_utf8(&seq).unwrap())
+                    .expect("don't break");
+            }
+            data.extend(encoder.finish().expect("don't break"));
+        }
+        std::fs::write(path("input.fasta.gz"), &data).expect("don't break");
+
+        let args = [
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("input.fasta.gz"),
+            "-o".to_owned(),
+            path("output.fasta"),
+            "-c".to_owned(),
+            path("cluster.csv"),
+            "--gz-members".to_owned(),
+            "read-groups".to_owned(),
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.total_records, 3);
+        assert_eq!(summary.unique_records, 1);
+        assert_eq!(
+            summary.parameters.gz_members.as_deref(),
+            Some("read-groups")
+        );
+        let members = &summary.gzip_members;
+        assert_eq!(members.len(), 2);
+        assert_eq!(
+            (
+                members[0].member,
+                members[0].first_record,
+                members[0].records
+            ),
+            (1, 1, 2)
+        );
+        assert_eq!(
+            (
+                members[1].member,
+                members[1].first_record,
+                members[1].records
+            ),
+            (2, 3, 1)
+        );
+        assert!(members[1].compressed_offset > 0);
+        assert_eq!(
+            std::fs::read_to_string(path("cluster.csv")).expect("don't break"),
+            format!(
+                "representative read id,read id,duplicate type,revcomp,read group\n\
+                 id_a,id_a,representative,false,{input}#1\n\
+                 id_a,id_b,exact,false,{input}#1\n\
+                 id_a,id_c,exact,false,{input}#2\n",
+                input = path("input.fasta.gz")
+            )
+        );
+
+        let args = [
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("output.fasta"),
+            "-o".to_owned(),
+            path("output-again.fasta"),
+            "--gz-members".to_owned(),
+            "report".to_owned(),
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_fix_legacy_quality() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(4);
+        let mut writer = fastq::Writer::to_file(path("input.fastq")).expect("don't break");
+        // Illumina 1.3+ Q40 and Q0 bases
+        writer
+            .write("id_a", None, &seq, b"hhh@")
+            .expect("don't break");
+        writer
+            .write("id_b", None, &seq, b"hhhh")
+            .expect("don't break");
+        drop(writer);
+
+        let args = [
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("input.fastq"),
+            "-o".to_owned(),
+            path("output.fastq"),
+            "--fix-legacy-quality".to_owned(),
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.unique_records, 1);
+        assert!(summary.parameters.fix_legacy_quality);
+        let output = std::fs::read_to_string(path("output.fastq")).expect("don't break");
+        assert!(output.ends_with("\n+\nIII!\n"), "{}", output);
+
+        let args = [
+            "executable".to_owned(),
+            "-i".to_owned(),
+            path("input.fastq"),
+            "-o".to_owned(),
+            path("output.fastq"),
+            "--fix-legacy-quality".to_owned(),
+            "--two-pass".to_owned(),
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_aggregate_qualities() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fastq");
+        let output_path = path("output.fastq");
+
+        {
+            let mut writer = fastq::Writer::to_file(&input_path).expect("don't break");
+            let seq = random_seq(4);
+            writer
+                .write("id_a", None, &seq, b"I!I!")
+                .expect("don't break");
+            writer
+                .write("id_b", None, &seq, b"5555")
+                .expect("don't break");
+        }
+        let output_quals = || {
+            fastq::Reader::from_file(&output_path)
+                .expect("don't break")
+                .records()
+                .map(|record| record.expect("don't break").qual().to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        for (aggregation, expected) in [("max", b"I5I5"), ("sum", b"]5]5")].iter() {
+            let args = [
+                "executable",
+                "-i",
+                &input_path,
+                "-o",
+                &output_path,
+                "--aggregate-qualities",
+                aggregation,
+            ];
+            let summary = run_dedup(args).expect("don't break");
+            assert_eq!(output_quals(), vec![expected.to_vec()]);
+            assert_eq!(
+                summary.parameters.quality_aggregation.as_deref(),
+                Some(*aggregation)
+            );
+        }
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_empty_reads() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            writer.write("id_a", None, b"").expect("don't break");
+            writer
+                .write("id_b", None, &random_seq(20))
+                .expect("don't break");
+            writer.write("id_c", None, b"").expect("don't break");
+        }
+        let args = |policy: &str| {
+            vec![
+                "executable".to_owned(),
+                "-i".to_owned(),
+                input_path.clone(),
+                "-o".to_owned(),
+                output_path.clone(),
+                "--empty-reads".to_owned(),
+                policy.to_owned(),
+            ]
+        };
+
+        let summary = run_dedup(args("drop")).expect("don't break");
+        assert_eq!(summary.total_records, 1);
+        assert_eq!(summary.empty_records, 2);
+        assert_eq!(summary.warning_counts.get("empty read"), Some(&2));
+        assert_eq!(summary.processed_records(), 3);
+
+        let summary = run_dedup(args("keep")).expect("don't break");
+        assert_eq!(summary.total_records, 3);
+        assert_eq!(summary.unique_records, 2);
+        assert_eq!(summary.empty_records, 2);
+
+        assert!(run_dedup(args("error")).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_longest_representative() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(40);
+        {
+            let mut writer = fasta::Writer::to_file(path("input.fasta")).expect("don't break");
+            writer.write("id_a", None, &seq[..30]).expect("don't break");
+            writer.write("id_b", None, &seq).expect("don't break");
+            writer
+                .write("id_c", None, &random_seq(30))
+                .expect("don't break");
+            writer.write("id_d", None, &seq[..35]).expect("don't break");
+        }
+        let args = [
+            "executable",
+            "-i",
+            &path("input.fasta"),
+            "-o",
+            &path("output.fasta"),
+            "-l",
+            "30",
+            "--representative",
+            "longest",
+            "--cluster-size-output",
+            &path("sizes.csv"),
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.unique_records, 2);
+        assert_eq!(summary.parameters.representative, "longest");
+        let records = fasta::Reader::from_file(path("output.fasta"))
+            .expect("don't break")
+            .records()
+            .map(|record| record.expect("don't break"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records.iter().map(|record| record.id()).collect::<Vec<_>>(),
+            vec!["id_b", "id_c"]
+        );
+        assert_eq!(records[0].seq(), seq.as_slice());
+        assert_eq!(
+            std::fs::read_to_string(path("sizes.csv")).expect("don't break"),
+            "representative read id,cluster size\nid_b,3\nid_c,1\n"
+        );
+
+        let args = [
+            "executable",
+            "-i",
+            &path("input.fasta"),
+            "-o",
+            &path("output.fasta"),
+            "--representative",
+            "longest",
+            "-c",
+            &path("clusters.csv"),
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_two_pass() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(20);
+        {
+            let mut writer = fastq::Writer::to_file(path("input.fastq")).expect("don't break");
+            writer
+                .write("id_a", None, &seq, &[b'+'; 20])
+                .expect("don't break");
+            writer
+                .write("id_b", None, &random_seq(20), &[b'I'; 20])
+                .expect("don't break");
+            writer
+                .write("id_c", None, &seq, &[b'I'; 20])
+                .expect("don't break");
+        }
+        let output_ids = |args: &[&str]| {
+            run_dedup(args).expect("don't break");
+            fastq::Reader::from_file(path("output.fastq"))
+                .expect("don't break")
+                .records()
+                .map(|record| record.expect("don't break").id().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let args = [
+            "executable",
+            "-i",
+            &path("input.fastq"),
+            "-o",
+            &path("output.fastq"),
+            "--two-pass",
+        ];
+        assert_eq!(output_ids(&args), vec!["id_a", "id_b"]);
+        let args = [
+            "executable",
+            "-i",
+            &path("input.fastq"),
+            "-o",
+            &path("output.fastq"),
+            "--representative",
+            "best-quality",
+        ];
+        assert_eq!(output_ids(&args), vec!["id_b", "id_c"]);
+        // BGZF inputs are read back from the representatives' virtual offsets
+        let mut writer = bgzf::BgzfWriter::new(
+            File::create(path("input.fastq.gz")).expect("don't break"),
+            1,
+        );
+        writer
+            .write_all(&std::fs::read(path("input.fastq")).expect("don't break"))
+            .expect("don't break");
+        writer.finish().expect("don't break");
+        let args = [
+            "executable",
+            "-i",
+            &path("input.fastq.gz"),
+            "-o",
+            &path("output.fastq"),
+            "--representative",
+            "best-quality",
+        ];
+        assert_eq!(output_ids(&args), vec!["id_b", "id_c"]);
+        // errors name the option that implied the second pass
+        let err = run_dedup([
+            "executable",
+            "-i",
+            &path("input.fastq"),
+            "-o",
+            &path("output.fastq"),
+            "-c",
+            &path("clusters.csv"),
+            "--representative",
+            "longest",
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--cluster-output is not supported with --representative longest (which runs two passes)"
+        );
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_state() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(20);
+        {
+            let mut writer = fasta::Writer::to_file(path("batch_1.fasta")).expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+            let mut writer = fasta::Writer::to_file(path("batch_2.fasta")).expect("don't break");
+            writer.write("id_b", None, &seq).expect("don't break");
+            writer
+                .write("id_c", None, &random_seq(20))
+                .expect("don't break");
+        }
+        run_dedup([
+            "executable",
+            "-i",
+            &path("batch_1.fasta"),
+            "-o",
+            &path("output_1.fasta"),
+            "--save-state",
+            &path("state.bin"),
+        ])
+        .expect("don't break");
+        run_dedup([
+            "executable",
+            "-i",
+            &path("batch_2.fasta"),
+            "-o",
+            &path("output_2.fasta"),
+            "--load-state",
+            &path("state.bin"),
+            "--cluster-size-output",
+            &path("sizes.csv"),
+        ])
+        .expect("don't break");
+        let ids = fasta::Reader::from_file(path("output_2.fasta"))
+            .expect("don't break")
+            .records()
+            .map(|record| record.expect("don't break").id().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["id_c"]);
+        let sizes = std::fs::read_to_string(path("sizes.csv")).expect("don't break");
+        assert!(sizes.contains("id_a,2"));
+
+        let err = run_dedup([
+            "executable",
+            "-i",
+            &path("batch_2.fasta"),
+            "-o",
+            &path("output_2.fasta"),
+            "--load-state",
+            &path("state.bin"),
+            "-l",
+            "10",
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cluster state was saved with different key options"
+        );
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_duplicate_ids() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let output_path = path("output.fasta");
+
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            let seq = random_seq(20);
+            writer.write("id_a", None, &seq).expect("don't break");
+            writer
+                .write("id_b", None, &random_seq(20))
+                .expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+        }
+        let args = |policy: &str| {
+            vec![
+                "executable".to_owned(),
+                "-i".to_owned(),
+                input_path.clone(),
+                "-o".to_owned(),
+                output_path.clone(),
+                "--on-duplicate-ids".to_owned(),
+                policy.to_owned(),
+            ]
+        };
+
+        let summary = run_dedup(args("warn")).expect("don't break");
+        assert_eq!(summary.total_records, 3);
+        assert_eq!(summary.warning_counts.get("duplicate id"), Some(&1));
+        assert_eq!(summary.parameters.on_duplicate_ids, "warn");
+
+        let summary = run_dedup(args("ignore")).expect("don't break");
+        assert_eq!(summary.warning_counts.get("duplicate id"), None);
+
+        assert!(run_dedup(args("error")).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_max_n_fraction() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        {
+            let mut writer = fasta::Writer::to_file(path("input.fasta")).expect("don't break");
+            writer
+                .write("id_a", None, b"NNNNNNACGT")
+                .expect("don't break");
+            writer
+                .write("id_b", None, b"NNNNNNTGCA")
+                .expect("don't break");
+            writer
+                .write("id_c", None, b"NNNNNACGTA")
+                .expect("don't break");
+        }
+        let args = [
+            "executable",
+            "-i",
+            &path("input.fasta"),
+            "-o",
+            &path("output.fasta"),
+            "--max-n-fraction",
+            "0.5",
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.total_records, 1);
+        assert_eq!(summary.n_filtered_records, 2);
+        assert_eq!(summary.processed_records(), 3);
+        assert_eq!(summary.parameters.max_n_fraction, Some(0.5));
+
+        let args = [
+            "executable",
+            "-i",
+            &path("input.fasta"),
+            "-o",
+            &path("output.fasta"),
+            "--max-n-fraction",
+            "2",
+        ];
+        assert!(run_dedup(args).is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_cluster_delimiter() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let seq = random_seq(20);
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+            writer.write("id_b", None, &seq).expect("don't break");
+        }
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &path("output.fasta"),
+            "-c",
+            &path("cluster.tsv"),
+            "--cluster-size-output",
+            &path("sizes.tsv"),
+            "--cluster-delimiter",
+            "tab",
+            "--no-cluster-header",
+        ];
+        run_dedup(args).expect("don't break");
+        assert_eq!(
+            std::fs::read_to_string(path("cluster.tsv")).expect("don't break"),
+            "id_a\tid_a\trepresentative\tfalse\nid_a\tid_b\texact\tfalse\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path("sizes.tsv")).expect("don't break"),
+            "id_a\t2\n"
+        );
+
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &path("output.fasta"),
+            "-c",
+            &path("cluster.csv"),
+            "--cluster-delimiter",
+            "|",
+        ];
+        run_dedup(args).expect("don't break");
+        assert_eq!(
+            std::fs::read_to_string(path("cluster.csv")).expect("don't break"),
+            "representative read id|read id|duplicate type|revcomp\nid_a|id_a|representative|false\nid_a|id_b|exact|false\n"
+        );
+        assert_eq!(parse_delimiter("comma"), Ok(b','));
+        assert!(parse_delimiter("semicolon").is_err());
+        dir.close().expect("don't break");
+    }
 
-    // presence guarunteed by clap
-    let mut inputs = matches.values_of("inputs").unwrap();
-    let mut outputs = matches.values_of("deduped-outputs").unwrap();
-    let cluster_output_opt = matches.value_of("cluster-output");
-    let cluster_size_output_opt = matches.value_of("cluster-size-output");
-    let prefix_length_opt = matches
-        .value_of("prefix-length")
-        .map(|n| n.parse::<usize>().unwrap());
-    let input_r1 = inputs.next().unwrap();
-    let output_r1 = outputs.next().unwrap();
-    let use_revcomp = matches.is_present("revcomp");
+    #[test]
+    fn test_run_dedup_key_salt() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let seq = random_seq(20);
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+            writer.write("id_b", None, &seq).expect("don't break");
+        }
+        let cluster_hash = |salt_opt: Option<&str>| {
+            let mut args = vec![
+                "executable".to_owned(),
+                "-i".to_owned(),
+                input_path.clone(),
+                "-o".to_owned(),
+                path("output.fasta"),
+                "-c".to_owned(),
+                path("cluster.csv"),
+                "--cluster-hash".to_owned(),
+            ];
+            if let Some(salt) = salt_opt {
+                args.extend(["--key-salt".to_owned(), salt.to_owned()]);
+            }
+            let summary = run_dedup(args).expect("don't break");
+            assert_eq!(summary.unique_records, 1);
+            assert_eq!(summary.parameters.key_salted, salt_opt.is_some());
+            let clusters = std::fs::read_to_string(path("cluster.csv")).expect("don't break");
+            let row = clusters.lines().nth(1).expect("don't break").to_owned();
+            row.rsplit(',').next().expect("don't break").to_owned()
+        };
+        let unsalted = cluster_hash(None);
+        let salted = cluster_hash(Some("00ff10AB"));
+        assert_ne!(unsalted, salted);
+        assert_eq!(salted, cluster_hash(Some("00ff10ab")));
 
-    let bytes = File::open(input_r1).unwrap().metadata().unwrap().len() as usize;
-    // 400 is based on the bytes per record of an example file, should be reasonable
-    let mut clusters =
-        clusters::Clusters::from_file(cluster_output_opt, prefix_length_opt, bytes / 400).unwrap();
+        assert_eq!(parse_key_salt("00ff10AB"), Ok(vec![0x00, 0xff, 0x10, 0xab]));
+        assert!(parse_key_salt("").is_err());
+        assert!(parse_key_salt("abc").is_err());
+        // not hex, and not split mid character
+        assert!(parse_key_salt("zz").is_err());
+        assert!(parse_key_salt("aé").is_err());
+        dir.close().expect("don't break");
+    }
 
-    match fastx::fastx_type(input_r1).unwrap() {
-        fastx::FastxType::Fasta => dedup!(
-            fasta,
-            fastx::FastxType::Fasta,
-            input_r1,
-            output_r1,
-            inputs,
-            outputs,
-            clusters,
-            use_revcomp
-        ),
-        fastx::FastxType::Fastq => dedup!(
-            fastq,
-            fastx::FastxType::Fastq,
-            input_r1,
-            output_r1,
-            inputs,
-            outputs,
-            clusters,
-            use_revcomp
-        ),
-        fastx::FastxType::Invalid => Err(Box::new(simple_error::simple_error!(
-            "input file is not a valid FASTA or FASTQ file"
-        )) as Box<dyn Error>),
-    }?;
+    #[test]
+    fn test_run_dedup_double_hash() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        let (seq_a, seq_b) = (random_seq(20), random_seq(20));
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            writer.write("id_a", None, &seq_a).expect("don't break");
+            writer.write("id_b", None, &seq_b).expect("don't break");
+            writer.write("id_c", None, &seq_a).expect("don't break");
+        }
+        let summary = run_dedup(vec![
+            "executable".to_owned(),
+            "-i".to_owned(),
+            input_path.clone(),
+            "-o".to_owned(),
+            path("output.fasta"),
+            "--double-hash".to_owned(),
+        ])
+        .expect("don't break");
+        assert_eq!(summary.unique_records, 2);
+        assert_eq!(summary.duplicate_records, 1);
+        assert!(summary.parameters.double_hash);
 
-    if let Some(cluster_sizes_output) = cluster_size_output_opt {
-        let mut cluster_sizes_writer = csv::Writer::from_path(cluster_sizes_output)?;
-        clusters.write_sizes(&mut cluster_sizes_writer)?;
+        let err = run_dedup(vec![
+            "executable".to_owned(),
+            "-i".to_owned(),
+            input_path,
+            "-o".to_owned(),
+            path("output.fasta"),
+            "--mode".to_owned(),
+            "sort".to_owned(),
+            "--double-hash".to_owned(),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--double-hash is not supported with --mode sort"
+        );
+        dir.close().expect("don't break");
     }
-    Ok(clusters)
-}
 
-fn main() {
-    match run_dedup(std::env::args()) {
-        Err(err) => println!("{}", err.to_string()),
-        Ok(info) => {
-            println!(
-                "duplicates:   {:width$}",
-                info.duplicate_records(),
-                width = 16
-            );
-            println!("unique reads: {:width$}", info.unique_records(), width = 16);
-            println!("total reads:  {:width$}", info.total_records(), width = 16);
+    #[test]
+    fn test_run_dedup_cluster_meta() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let input_path = path("input.fasta");
+        {
+            let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+            writer
+                .write("id_a", None, &random_seq(20))
+                .expect("don't break");
         }
+        let args = [
+            "executable",
+            "-i",
+            &input_path,
+            "-o",
+            &path("output.fasta"),
+            "-c",
+            &path("cluster.tsv"),
+            "--cluster-size-output",
+            &path("sizes.tsv"),
+            "--cluster-delimiter",
+            "tab",
+            "--no-cluster-header",
+            "--cluster-hash",
+        ];
+        run_dedup(args).expect("don't break");
+        let cluster_meta =
+            std::fs::read_to_string(path("cluster.tsv.meta.json")).expect("don't break");
+        assert!(cluster_meta.starts_with(
+            "{\"schema_version\":2,\"output\":\"clusters\",\"columns\":[\"representative read id\",\"read id\",\"duplicate type\",\"revcomp\",\"cluster hash\"],\"delimiter\":\"\\t\",\"header\":false,"
+        ));
+        assert!(cluster_meta.contains("\"parameters\":{\"prefix_length\":null,"));
+        let size_meta = std::fs::read_to_string(path("sizes.tsv.meta.json")).expect("don't break");
+        assert!(size_meta.contains(
+            "\"output\":\"cluster sizes\",\"columns\":[\"representative read id\",\"cluster size\",\"cluster hash\"]"
+        ));
+        dir.close().expect("don't break");
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[test]
+    fn test_run_dedup_by_position() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let header = bam::encode_header("@HD\tVN:1.6\tSO:coordinate\n", &[("chr1", 1000)]);
+        let mut writer = bam::BamWriter::new(
+            File::create(path("input.bam")).expect("don't break"),
+            &header,
+            1,
+        )
+        .expect("don't break");
+        // the later duplicate has the higher base qualities
+        for (name, flag, qual) in [
+            ("id_a", 0, 20),
+            ("id_b", bam::FLAG_REVERSE, 20),
+            ("id_c", 0, 30),
+        ] {
+            let record = bam::BamRecord::new(name, flag, (0, 100), &[(b'M', 4)], (-1, -1), &[])
+                .with_quals(&[qual; 4]);
+            writer.write_record(&record).expect("don't break");
+        }
+        writer.finish().expect("don't break");
 
-    use bio::io::fastq;
-    use rand::Rng;
-    use tempfile::tempdir;
+        let args = [
+            "executable",
+            "-i",
+            &path("input.bam"),
+            "-o",
+            &path("output.bam"),
+            "--by",
+            "position",
+        ];
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!((summary.total_records, summary.duplicate_records), (3, 1));
+        let mut reader = bam::BamReader::new(File::open(path("output.bam")).expect("don't break"))
+            .expect("don't break");
+        assert_eq!(reader.header(), &header[..]);
+        let mut names = Vec::new();
+        while let Some(record) = reader.read_record().expect("don't break") {
+            names.push(String::from_utf8(record.name().to_vec()).expect("don't break"));
+        }
+        assert_eq!(names, vec!["id_b", "id_c"]);
 
-    fn random_seq(len: usize) -> Vec<u8> {
-        const CHARSET: &[u8] = b"ACTG";
-        let mut rng = rand::thread_rng();
-        (0..len)
-            .map(|_| {
-                let idx = rng.gen_range(0, CHARSET.len());
-                CHARSET[idx]
-            })
-            .collect()
+        let args = [
+            "executable",
+            "-i",
+            &path("input.bam"),
+            "-o",
+            &path("output.bam"),
+            "--by",
+            "position",
+            "-l",
+            "10",
+        ];
+        let err = run_dedup(args).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--prefix-length is not supported with --by position"
+        );
+        dir.close().expect("don't break");
     }
 
     #[test]
-    fn test_run_dedup_single() {
-        let dir = tempdir().unwrap();
-        let input_path = dir.path().join("input.fastq").to_str().unwrap().to_string();
-        let output_path = dir
-            .path()
-            .join("output.fastq")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let cluster_path = dir.path().join("cluster.csv").to_str().unwrap().to_string();
+    fn test_run_dedup_byte_range() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(20);
+        {
+            let mut writer = fasta::Writer::to_file(path("input.fasta")).expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+            writer.write("id_b", None, &seq).expect("don't break");
+            writer
+                .write("id_c", None, &random_seq(20))
+                .expect("don't break");
+        }
+        let output_ids = |byte_range: &str| {
+            let args = [
+                "executable",
+                "-i",
+                &path("input.fasta"),
+                "-o",
+                &path("output.fasta"),
+                "--byte-range",
+                byte_range,
+            ];
+            run_dedup(args).expect("don't break");
+            fasta::Reader::from_file(path("output.fasta"))
+                .expect("don't break")
+                .records()
+                .map(|record| record.expect("don't break").id().to_owned())
+                .collect::<Vec<_>>()
+        };
+        // each record is 26 bytes
+        assert_eq!(output_ids("0:1"), vec!["id_a"]);
+        assert_eq!(output_ids("1:100"), vec!["id_b", "id_c"]);
+        assert_eq!(output_ids("0:100"), vec!["id_a", "id_c"]);
+        assert_eq!(
+            parse_byte_range("10:5"),
+            Err("invalid byte range: 10:5, expected START:END".to_owned())
+        );
+        assert!(parse_byte_range("10").is_err());
+        dir.close().expect("don't break");
+    }
 
+    #[test]
+    fn test_run_dedup_time_limit() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(20);
         {
-            let mut writer = fastq::Writer::to_file(&input_path).expect("don't break");
-            let seq = random_seq(20);
-            writer.write("id_a", None, &seq, &seq).expect("don't break");
+            let mut writer = fasta::Writer::to_file(path("input.fasta")).expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+            writer.write("id_b", None, &seq).expect("don't break");
         }
+        let run = |time_limit: &str| {
+            let args = [
+                "executable",
+                "-i",
+                &path("input.fasta"),
+                "-o",
+                &path("output.fasta"),
+                "-c",
+                &path("cluster.csv"),
+                "--time-limit",
+                time_limit,
+            ];
+            run_dedup(args).expect("don't break")
+        };
 
-        let args = [
+        let summary = run("1h");
+        assert_eq!(summary.status, summary::RunStatus::Ok);
+        assert_eq!(summary.total_records, 2);
+
+        // already past the limit, so the outputs are written without any reads
+        let summary = run("0s");
+        assert_eq!(summary.status, summary::RunStatus::TimeLimited);
+        assert_eq!(summary.total_records, 0);
+        assert_eq!(
+            std::fs::read_to_string(path("output.fasta")).expect("don't break"),
+            ""
+        );
+        assert_eq!(
+            std::fs::read_to_string(path("cluster.csv")).expect("don't break"),
+            "representative read id,read id,duplicate type,revcomp\n"
+        );
+
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(172_800)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("2x").is_err());
+        assert!(parse_duration("30m5").is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_cancel_file() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = random_seq(20);
+        {
+            let mut writer = fasta::Writer::to_file(path("input.fasta")).expect("don't break");
+            writer.write("id_a", None, &seq).expect("don't break");
+        }
+        let run = || {
+            let args = [
+                "executable",
+                "-i",
+                &path("input.fasta"),
+                "-o",
+                &path("output.fasta"),
+                "--cancel-file",
+                &path("cancel"),
+            ];
+            run_dedup(args).expect("don't break")
+        };
+
+        let summary = run();
+        assert_eq!(summary.status, summary::RunStatus::Ok);
+        assert_eq!(summary.total_records, 1);
+
+        // cancelled before the first read, the outputs are still written
+        std::fs::write(path("cancel"), "").expect("don't break");
+        let summary = run();
+        assert_eq!(summary.status, summary::RunStatus::Cancelled);
+        assert_eq!(summary.total_records, 0);
+        assert_eq!(
+            std::fs::read_to_string(path("output.fasta")).expect("don't break"),
+            ""
+        );
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_run_dedup_cluster_append() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let cluster_path = path("cluster.csv");
+        for (chunk, id) in ["chunk_1", "chunk_2"].iter().zip(["id_a", "id_b"].iter()) {
+            let input_path = path(&format!("{}.fasta", chunk));
+            {
+                let mut writer = fasta::Writer::to_file(&input_path).expect("don't break");
+                writer
+                    .write(id, None, &random_seq(20))
+                    .expect("don't break");
+            }
+            let args = [
+                "executable",
+                "-i",
+                &input_path,
+                "-o",
+                &path(&format!("{}-output.fasta", chunk)),
+                "-c",
+                &cluster_path,
+                "--cluster-append",
+            ];
+            run_dedup(args).expect("don't break");
+        }
+        assert_eq!(
+            std::fs::read_to_string(&cluster_path).expect("don't break"),
+            "representative read id,read id,duplicate type,revcomp\n\
+             id_a,id_a,representative,false\n\
+             id_b,id_b,representative,false\n"
+        );
+        // a run that would write a different header can't append
+        let err = run_dedup([
             "executable",
             "-i",
-            &input_path,
+            &path("chunk_1.fasta"),
             "-o",
-            &output_path,
+            &path("chunk_1-output.fasta"),
             "-c",
             &cluster_path,
-        ];
-        let result = run_dedup(&args).expect("don't break");
-        assert_eq!(result.total_records(), 1);
+            "--cluster-append",
+            "--cluster-hash",
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("doesn't match this run's"));
+        assert_eq!(
+            std::fs::read_to_string(&cluster_path)
+                .expect("don't break")
+                .lines()
+                .count(),
+            3
+        );
         dir.close().expect("don't break");
     }
 
     #[test]
-    fn test_run_dedup_paired() {
-        let dir = tempdir().unwrap();
-        let input_path_r1 = dir
-            .path()
-            .join("input-r1.fasta")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let input_path_r2 = dir
-            .path()
-            .join("input-r2.fasta")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let output_path_r1 = dir
-            .path()
-            .join("output-r1.fasta")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let output_path_r2 = dir
-            .path()
-            .join("output-r2.fasta")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let cluster_path = dir.path().join("cluster.csv").to_str().unwrap().to_string();
-
-        {
-            let mut writer_r1 = fasta::Writer::to_file(&input_path_r1).expect("don't break");
-            let mut writer_r2 = fasta::Writer::to_file(&input_path_r2).expect("don't break");
-            let seq = random_seq(20);
-            writer_r1.write("id_a", None, &seq).expect("don't break");
-            writer_r2.write("id_a", None, &seq).expect("don't break");
+    fn test_run_dedup_three_mates() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seqs = [random_seq(20), random_seq(20), random_seq(8)];
+        let other_index = random_seq(8);
+        for (mate, seq) in ["R1", "R2", "R3"].iter().zip(seqs.iter()) {
+            let mut writer =
+                fasta::Writer::to_file(path(&format!("{}.fasta", mate))).expect("don't break");
+            writer.write("id_a", None, seq).expect("don't break");
+            writer.write("id_b", None, seq).expect("don't break");
+            // differs only in its index read
+            let seq_c = if *mate == "R3" { &other_index } else { seq };
+            writer.write("id_c", None, seq_c).expect("don't break");
         }
 
         let args = [
             "executable",
             "-i",
-            &input_path_r1,
-            "-i",
-            &input_path_r2,
+            &path("R1.fasta"),
+            &path("R2.fasta"),
+            &path("R3.fasta"),
             "-o",
-            &output_path_r1,
-            "-o",
-            &output_path_r2,
+            &path("output-R1.fasta"),
+            &path("output-R2.fasta"),
+            &path("output-R3.fasta"),
             "-c",
-            &cluster_path,
+            &path("cluster.csv"),
         ];
-        let result = run_dedup(&args).expect("don't break");
-        assert_eq!(result.total_records(), 1);
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.total_records, 3);
+        assert_eq!(summary.unique_records, 2);
+        for mate in ["R1", "R2", "R3"].iter() {
+            let ids = fasta::Reader::from_file(path(&format!("output-{}.fasta", mate)))
+                .expect("don't break")
+                .records()
+                .map(|record| record.expect("don't break").id().to_owned())
+                .collect::<Vec<_>>();
+            assert_eq!(ids, vec!["id_a", "id_c"]);
+        }
+        assert_eq!(
+            std::fs::read_to_string(path("cluster.csv")).expect("don't break"),
+            "representative read id,read id,duplicate type,revcomp\n\
+             id_a,id_a,representative,false\n\
+             id_a,id_b,exact,false\n\
+             id_c,id_c,representative,false\n"
+        );
+
+        let args = [
+            "executable",
+            "-i",
+            &path("R1.fasta"),
+            &path("R2.fasta"),
+            &path("R3.fasta"),
+            "-o",
+            &path("output-R1.fasta"),
+            &path("output-R2.fasta"),
+        ];
+        assert!(run_dedup(args).is_err());
         dir.close().expect("don't break");
     }
 
     #[test]
-    fn test_run_dedup_paired_mismatched_files() {
-        let dir = tempdir().unwrap();
-        let input_path_r1 = dir
-            .path()
-            .join("input-r1.fasta")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let input_path_r2 = dir
-            .path()
-            .join("input-r2.fastq")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let output_path_r1 = dir
-            .path()
-            .join("output-r1.fasta")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let output_path_r2 = dir
-            .path()
-            .join("output-r2.fasta")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let cluster_path = dir.path().join("cluster.csv").to_str().unwrap().to_string();
+    fn test_run_dedup_wrapped_fasta() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = String::from_utf8(random_seq(30)).unwrap();
+        // the same sequence wrapped at different widths
+        let input = format!(
+            ">id_a\n{}\n{}\n>id_b\n{}\n{}\n{}\n",
+            &seq[..15],
+            &seq[15..],
+            &seq[..10],
+            &seq[10..20],
+            &seq[20..]
+        );
+        std::fs::write(path("input.fasta"), input).expect("don't break");
+        let args = |max_record_length: &str| {
+            vec![
+                "executable".to_owned(),
+                "-i".to_owned(),
+                path("input.fasta"),
+                "-o".to_owned(),
+                path("output.fasta"),
+                "--max-record-length".to_owned(),
+                max_record_length.to_owned(),
+            ]
+        };
+        let summary = run_dedup(args("30")).expect("don't break");
+        assert_eq!(summary.unique_records, 1);
+        assert_eq!(summary.duplicate_records, 1);
+
+        let err = run_dedup(args("29")).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("record id_a is longer than the maximum record length of 29 bases"));
+        dir.close().expect("don't break");
+    }
 
+    #[test]
+    fn test_run_dedup_legacy_semantics() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let seq = b"AAAACCCCGGGGTTTTAC";
+        let rev_seq = revcomp(&seq[..]);
         {
-            let mut writer_r1 = fasta::Writer::to_file(&input_path_r1).expect("don't break");
-            let mut writer_r2 = fastq::Writer::to_file(&input_path_r2).expect("don't break");
-            let seq = random_seq(20);
-            writer_r1.write("id_a", None, &seq).expect("don't break");
+            let mut writer = fasta::Writer::to_file(path("input.fasta")).expect("don't break");
+            writer.write("id_a", None, seq).expect("don't break");
+            writer.write("id_b,x", None, &rev_seq).expect("don't break");
+            writer.write("id_c", None, b"").expect("don't break");
+            writer.write("id_d", None, seq).expect("don't break");
+        }
+        let args = [
+            "executable",
+            "-i",
+            &path("input.fasta"),
+            "-o",
+            &path("output.fasta"),
+            "-c",
+            &path("cluster.csv"),
+            "--cluster-size-output",
+            &path("sizes.csv"),
+            "--legacy-semantics",
+            "-r",
+        ];
+        run_dedup(args).expect("don't break");
+        assert_eq!(
+            std::fs::read_to_string(path("cluster.csv")).expect("don't break"),
+            "representative read id,read id\n\
+             id_a,id_a\n\
+             id_a,\"id_b,x (rc)\"\n\
+             id_c,id_c\n\
+             id_a,id_d\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path("sizes.csv")).expect("don't break"),
+            "representative read id,cluster size\nid_a,3\nid_c,1\n"
+        );
+
+        // pairs keep their larger orientation, so the flipped pair isn't marked
+        {
+            let mut writer_r1 = fasta::Writer::to_file(path("R1.fasta")).expect("don't break");
+            let mut writer_r2 = fasta::Writer::to_file(path("R2.fasta")).expect("don't break");
+            writer_r1.write("id_a", None, seq).expect("don't break");
             writer_r2
-                .write("id_a", None, &seq, &seq)
+                .write("id_a", None, &seq[2..])
+                .expect("don't break");
+            writer_r1
+                .write("id_b", None, &rev_seq)
+                .expect("don't break");
+            writer_r2
+                .write("id_b", None, &revcomp(&seq[2..]))
                 .expect("don't break");
         }
-
         let args = [
             "executable",
             "-i",
-            &input_path_r1,
-            "-i",
-            &input_path_r2,
-            "-o",
-            &output_path_r1,
+            &path("R1.fasta"),
+            &path("R2.fasta"),
             "-o",
-            &output_path_r2,
+            &path("output-R1.fasta"),
+            &path("output-R2.fasta"),
             "-c",
-            &cluster_path,
+            &path("cluster.csv"),
+            "--legacy-semantics",
+            "-r",
         ];
-        let result = run_dedup(&args);
-        let message = result
-            .err()
-            .expect("should error on mismatched inputs")
-            .to_string();
+        let summary = run_dedup(args).expect("don't break");
+        assert_eq!(summary.parameters.pair_canonical, "lexmax");
         assert_eq!(
-            message,
-            "paired inputs have different file types r1: fasta, r2: fastq"
+            std::fs::read_to_string(path("cluster.csv")).expect("don't break"),
+            "representative read id,read id\nid_a,id_a\nid_a,id_b\n"
         );
+
+        let args = [
+            "executable",
+            "-i",
+            &path("input.fasta"),
+            "-o",
+            &path("output.fasta"),
+            "--legacy-semantics",
+            "--two-tier",
+        ];
+        assert!(run_dedup(args).is_err());
         dir.close().expect("don't break");
     }
 }
@@ -0,0 +1,673 @@
+use bio::io::{fasta, fastq};
+use flate2::read::MultiGzDecoder as GzReadDecoder;
+use flate2::write::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use super::clusters::{ClusterOptions, Clusters};
+use super::fastx::{self, FastxType};
+use super::paired::PairedRecords;
+use super::summary::DedupSummary;
+
+/// Settings for deduplicating reads held in memory
+#[derive(Clone, Debug, Default)]
+pub struct DedupConfig {
+    pub options: ClusterOptions,
+    pub use_revcomp: bool,
+    /// Skips invalid records with a warning instead of failing
+    pub skip_invalid: bool,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Decompresses gzipped input, leaving plain input as it is
+fn decompress(input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    if input.starts_with(&[0x1f, 0x8b]) {
+        GzReadDecoder::new(input).read_to_end(&mut decompressed)?;
+    } else {
+        decompressed.extend_from_slice(input);
+    }
+    Ok(decompressed)
+}
+
+fn new_clusters(config: &DedupConfig) -> io::Result<Clusters<io::Sink>> {
+    Ok(Clusters::from_writer_with_options(
+        None,
+        config.options.clone(),
+        0,
+    )?)
+}
+
+fn dedup_records<T, R, W>(
+    clusters: &mut Clusters<io::Sink>,
+    records_r1: R,
+    records_r2_opt: Option<R>,
+    (writer_r1, writer_r2): (&mut W, &mut W),
+    config: &DedupConfig,
+) -> io::Result<()>
+where
+    T: fastx::Record,
+    R: Iterator<Item = io::Result<T>>,
+    W: fastx::Writer<T>,
+{
+    match records_r2_opt {
+        Some(records_r2) => {
+            for result in PairedRecords::new(records_r1, records_r2) {
+                let record = result?;
+                if let Err(err) = record.check() {
+                    if config.skip_invalid {
+                        clusters.skip_invalid(record.id(), &err);
+                        continue;
+                    }
+                    return Err(invalid_data(err));
+                }
+                if clusters.insert_pair(&record, config.use_revcomp)? {
+                    writer_r1.write_record(record.r1())?;
+                    writer_r2.write_record(record.r2())?;
+                }
+            }
+        }
+        None => {
+            for result in records_r1 {
+                let record = result?;
+                if let Err(err) = record.check() {
+                    if config.skip_invalid {
+                        clusters.skip_invalid(record.id(), err);
+                        continue;
+                    }
+                    return Err(invalid_data(err.to_owned()));
+                }
+                if clusters.insert_single(&record, config.use_revcomp)? {
+                    writer_r1.write_record(&record)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deduplicates FASTA or FASTQ reads held in memory, plain or gzipped,
+/// returning the deduped r1 and r2 reads, r2 empty for single end input,
+/// and the run's summary
+pub fn dedup_bytes(
+    r1: &[u8],
+    r2: Option<&[u8]>,
+    config: &DedupConfig,
+) -> io::Result<(Vec<u8>, Vec<u8>, DedupSummary)> {
+    let r1 = decompress(r1)?;
+    let r2_opt = r2.map(decompress).transpose()?;
+    let mut clusters = new_clusters(config)?;
+    let mut outputs = (Vec::new(), Vec::new());
+    let fastx_type = input_type(&r1, r2_opt.as_deref())?;
+    dedup_chunk(
+        &mut clusters,
+        (&r1, r2_opt.as_deref()),
+        &mut outputs,
+        &fastx_type,
+        config,
+    )?;
+    Ok((outputs.0, outputs.1, clusters.summary(config.use_revcomp)))
+}
+
+fn input_type(r1: &[u8], r2_opt: Option<&[u8]>) -> io::Result<FastxType> {
+    let fastx_type = |input: &[u8]| match input.first() {
+        Some(b'>') => Ok(FastxType::Fasta),
+        Some(b'@') => Ok(FastxType::Fastq),
+        // empty input has no reads to write either way
+        None => Ok(FastxType::Fasta),
+        Some(_) => Err(invalid_data(
+            "input is not a valid FASTA or FASTQ file".to_owned(),
+        )),
+    };
+    let fastx_type_r1 = fastx_type(r1)?;
+    match r2_opt {
+        Some(r2) if !r1.is_empty() && fastx_type(r2)? != fastx_type_r1 => Err(invalid_data(
+            "paired inputs have different file types".to_owned(),
+        )),
+        _ => Ok(fastx_type_r1),
+    }
+}
+
+/// Deduplicates whole records, appending the unique ones to the outputs
+fn dedup_chunk(
+    clusters: &mut Clusters<io::Sink>,
+    (r1, r2_opt): (&[u8], Option<&[u8]>),
+    (output_r1, output_r2): &mut (Vec<u8>, Vec<u8>),
+    fastx_type: &FastxType,
+    config: &DedupConfig,
+) -> io::Result<()> {
+    if *fastx_type == FastxType::Fastq {
+        let mut writer_r1 = fastq::Writer::new(output_r1);
+        let mut writer_r2 = fastq::Writer::new(output_r2);
+        dedup_records(
+            clusters,
+            fastq::Reader::new(r1).records(),
+            r2_opt.map(|r2| fastq::Reader::new(r2).records()),
+            (&mut writer_r1, &mut writer_r2),
+            config,
+        )?;
+        writer_r1.flush()?;
+        writer_r2.flush()
+    } else {
+        let mut writer_r1 = fasta::Writer::new(output_r1);
+        let mut writer_r2 = fasta::Writer::new(output_r2);
+        dedup_records(
+            clusters,
+            fasta::Reader::new(r1).records(),
+            r2_opt.map(|r2| fasta::Reader::new(r2).records()),
+            (&mut writer_r1, &mut writer_r2),
+            config,
+        )?;
+        writer_r1.flush()?;
+        writer_r2.flush()
+    }
+}
+
+/// Offsets just past each record known to be complete in `bytes`, the last
+/// record only once the input has ended
+///
+/// FASTQ records are four lines and FASTA records end where the next header
+/// starts.
+fn record_ends(bytes: &[u8], fastx_type: &FastxType, at_end: bool) -> Vec<usize> {
+    let line_ends = bytes
+        .iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte == b'\n')
+        .map(|(i, _)| i + 1);
+    let mut ends = match fastx_type {
+        FastxType::Fastq => line_ends.skip(3).step_by(4).collect::<Vec<_>>(),
+        _ => line_ends
+            .filter(|end| bytes.get(*end) == Some(&b'>'))
+            .collect(),
+    };
+    let last_end = ends.last().copied().unwrap_or(0);
+    if at_end && last_end < bytes.len() {
+        ends.push(bytes.len());
+    }
+    ends
+}
+
+/// One input's bytes received but not yet deduplicated
+#[derive(Default)]
+struct InputBuffer {
+    /// the first bytes, held until there are enough to tell if the input is gzipped
+    head: Vec<u8>,
+    decoder_opt: Option<MultiGzDecoder<Vec<u8>>>,
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl InputBuffer {
+    fn push(&mut self, chunk: &[u8]) -> io::Result<()> {
+        if !self.started {
+            self.head.extend_from_slice(chunk);
+            if self.head.len() < 2 {
+                return Ok(());
+            }
+            self.started = true;
+            let head = std::mem::take(&mut self.head);
+            if head.starts_with(&[0x1f, 0x8b]) {
+                self.decoder_opt = Some(MultiGzDecoder::new(Vec::new()));
+            }
+            return self.push(&head);
+        }
+        match self.decoder_opt.as_mut() {
+            Some(decoder) => {
+                decoder.write_all(chunk)?;
+                self.pending.append(decoder.get_mut());
+            }
+            None => self.pending.extend_from_slice(chunk),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            self.pending = std::mem::take(&mut self.head);
+        }
+        if let Some(decoder) = self.decoder_opt.as_mut() {
+            decoder.try_finish()?;
+            self.pending.append(decoder.get_mut());
+        }
+        Ok(())
+    }
+}
+
+/// Deduplicates reads as they arrive in chunks, for callers streaming input
+/// from the network or an async reader
+///
+/// Chunks may split records anywhere. Each push returns the unique records
+/// completed by it, so output can be streamed on as input arrives. Paired
+/// input is pushed to each mate separately, and pairs are deduplicated once
+/// both mates have arrived.
+pub struct StreamDeduper {
+    clusters: Clusters<io::Sink>,
+    config: DedupConfig,
+    inputs: (InputBuffer, Option<InputBuffer>),
+    fastx_type_opt: Option<FastxType>,
+}
+
+impl StreamDeduper {
+    pub fn new(config: DedupConfig, paired: bool) -> io::Result<Self> {
+        Ok(StreamDeduper {
+            clusters: new_clusters(&config)?,
+            config,
+            inputs: (
+                InputBuffer::default(),
+                if paired {
+                    Some(InputBuffer::default())
+                } else {
+                    None
+                },
+            ),
+            fastx_type_opt: None,
+        })
+    }
+
+    /// Feeds the next chunk of r1, returning the unique r1 and r2 records it completed
+    pub fn push_r1(&mut self, chunk: &[u8]) -> io::Result<(Vec<u8>, Vec<u8>)> {
+        self.inputs.0.push(chunk)?;
+        self.dedup_pending(false)
+    }
+
+    /// Feeds the next chunk of r2, returning the unique r1 and r2 records it completed
+    pub fn push_r2(&mut self, chunk: &[u8]) -> io::Result<(Vec<u8>, Vec<u8>)> {
+        match self.inputs.1.as_mut() {
+            Some(input_r2) => input_r2.push(chunk)?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "r2 pushed to a single end deduper",
+                ))
+            }
+        }
+        self.dedup_pending(false)
+    }
+
+    /// Ends the input, returning the last unique records and the run's summary
+    pub fn finish(self) -> io::Result<(Vec<u8>, Vec<u8>, DedupSummary)> {
+        let use_revcomp = self.config.use_revcomp;
+        let (output_r1, output_r2, clusters) = self.finish_clusters()?;
+        Ok((output_r1, output_r2, clusters.summary(use_revcomp)))
+    }
+
+    /// Ends the input, returning the last unique records and the clusters
+    fn finish_clusters(mut self) -> io::Result<(Vec<u8>, Vec<u8>, Clusters<io::Sink>)> {
+        self.inputs.0.finish()?;
+        if let Some(input_r2) = self.inputs.1.as_mut() {
+            input_r2.finish()?;
+        }
+        let (output_r1, output_r2) = self.dedup_pending(true)?;
+        let pending_r2 = self
+            .inputs
+            .1
+            .as_ref()
+            .map_or(0, |input| input.pending.len());
+        if !self.inputs.0.pending.is_empty() || pending_r2 > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "paired inputs have different numbers of records",
+            ));
+        }
+        Ok((output_r1, output_r2, self.clusters))
+    }
+
+    fn dedup_pending(&mut self, at_end: bool) -> io::Result<(Vec<u8>, Vec<u8>)> {
+        let mut outputs = (Vec::new(), Vec::new());
+        let (input_r1, input_r2_opt) = &mut self.inputs;
+        if self.fastx_type_opt.is_none() {
+            let pending_r2_opt = input_r2_opt.as_ref().map(|input| input.pending.as_slice());
+            // the type is known from the first byte of each input
+            let waiting = input_r1.pending.is_empty()
+                || pending_r2_opt.is_some_and(|pending_r2| pending_r2.is_empty());
+            if waiting && !at_end {
+                return Ok(outputs);
+            }
+            self.fastx_type_opt = Some(input_type(&input_r1.pending, pending_r2_opt)?);
+        }
+        // checked above
+        let fastx_type = self.fastx_type_opt.as_ref().unwrap();
+        let ends_r1 = record_ends(&input_r1.pending, fastx_type, at_end);
+        let (end_r1, end_r2_opt) = match input_r2_opt.as_ref() {
+            Some(input_r2) => {
+                let ends_r2 = record_ends(&input_r2.pending, fastx_type, at_end);
+                let records = ends_r1.len().min(ends_r2.len());
+                if records == 0 {
+                    return Ok(outputs);
+                }
+                (ends_r1[records - 1], Some(ends_r2[records - 1]))
+            }
+            None => match ends_r1.last() {
+                Some(end) => (*end, None),
+                None => return Ok(outputs),
+            },
+        };
+        let rest_r1 = input_r1.pending.split_off(end_r1);
+        let chunk_r1 = std::mem::replace(&mut input_r1.pending, rest_r1);
+        let chunk_r2_opt = match (input_r2_opt.as_mut(), end_r2_opt) {
+            (Some(input_r2), Some(end_r2)) => {
+                let rest_r2 = input_r2.pending.split_off(end_r2);
+                Some(std::mem::replace(&mut input_r2.pending, rest_r2))
+            }
+            _ => None,
+        };
+        dedup_chunk(
+            &mut self.clusters,
+            (&chunk_r1, chunk_r2_opt.as_deref()),
+            &mut outputs,
+            fastx_type,
+            &self.config,
+        )?;
+        Ok(outputs)
+    }
+}
+
+/// Bytes read from each input at a time by `dedup_files`
+const FILE_CHUNK_BYTES: usize = 1 << 20;
+
+/// Deduplicates FASTA or FASTQ files, plain or gzipped, writing the unique
+/// reads uncompressed, for bindings to other languages
+///
+/// Returns the run's summary and each cluster's representative read id and
+/// size, in the order the clusters were found. Paired inputs are read a
+/// chunk of each mate at a time.
+pub fn dedup_files<P: AsRef<Path>>(
+    (input_r1, input_r2_opt): (P, Option<P>),
+    (output_r1, output_r2_opt): (P, Option<P>),
+    config: &DedupConfig,
+) -> io::Result<(DedupSummary, Vec<(String, u64)>)> {
+    let mut readers = (
+        Some(File::open(input_r1)?),
+        input_r2_opt.map(File::open).transpose()?,
+    );
+    let mut writer_r1 = io::BufWriter::new(File::create(output_r1)?);
+    let mut writer_r2_opt = match (output_r2_opt, readers.1.is_some()) {
+        (Some(output_r2), true) => Some(io::BufWriter::new(File::create(output_r2)?)),
+        (None, false) => None,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "paired inputs need paired outputs",
+            ))
+        }
+    };
+    let mut write = |(unique_r1, unique_r2): (Vec<u8>, Vec<u8>)| -> io::Result<()> {
+        writer_r1.write_all(&unique_r1)?;
+        match writer_r2_opt.as_mut() {
+            Some(writer_r2) => writer_r2.write_all(&unique_r2),
+            None => Ok(()),
+        }
+    };
+    let mut deduper = StreamDeduper::new(config.clone(), readers.1.is_some())?;
+    let mut chunk = vec![0; FILE_CHUNK_BYTES];
+    while readers.0.is_some() || readers.1.is_some() {
+        if let Some(reader_r1) = readers.0.as_mut() {
+            match reader_r1.read(&mut chunk)? {
+                0 => readers.0 = None,
+                n => write(deduper.push_r1(&chunk[..n])?)?,
+            }
+        }
+        if let Some(reader_r2) = readers.1.as_mut() {
+            match reader_r2.read(&mut chunk)? {
+                0 => readers.1 = None,
+                n => write(deduper.push_r2(&chunk[..n])?)?,
+            }
+        }
+    }
+    let (unique_r1, unique_r2, clusters) = deduper.finish_clusters()?;
+    write((unique_r1, unique_r2))?;
+    writer_r1.flush()?;
+    if let Some(writer_r2) = writer_r2_opt.as_mut() {
+        writer_r2.flush()?;
+    }
+    let cluster_sizes = clusters
+        .iter()
+        .map(|(id, size)| (id.to_owned(), size))
+        .collect();
+    Ok((clusters.summary(config.use_revcomp), cluster_sizes))
+}
+
+/// Deduplicates reads from tokio async readers, plain or gzipped, writing
+/// the unique reads uncompressed to async writers as they are completed
+///
+/// Paired inputs are read a chunk of each mate at a time, like
+/// `dedup_files`. Needs the `tokio` feature.
+#[cfg(feature = "tokio")]
+pub async fn dedup_async<R, W>(
+    (input_r1, input_r2_opt): (R, Option<R>),
+    (output_r1, output_r2_opt): (W, Option<W>),
+    config: &DedupConfig,
+) -> io::Result<DedupSummary>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut readers = (Some(input_r1), input_r2_opt);
+    let mut writers = match (output_r2_opt, readers.1.is_some()) {
+        (Some(output_r2), true) => (output_r1, Some(output_r2)),
+        (None, false) => (output_r1, None),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "paired inputs need paired outputs",
+            ))
+        }
+    };
+    let mut deduper = StreamDeduper::new(config.clone(), readers.1.is_some())?;
+    let mut chunk = vec![0; FILE_CHUNK_BYTES];
+    while readers.0.is_some() || readers.1.is_some() {
+        if let Some(reader_r1) = readers.0.as_mut() {
+            match reader_r1.read(&mut chunk).await? {
+                0 => readers.0 = None,
+                n => write_async(&mut writers, deduper.push_r1(&chunk[..n])?).await?,
+            }
+        }
+        if let Some(reader_r2) = readers.1.as_mut() {
+            match reader_r2.read(&mut chunk).await? {
+                0 => readers.1 = None,
+                n => write_async(&mut writers, deduper.push_r2(&chunk[..n])?).await?,
+            }
+        }
+    }
+    let (unique_r1, unique_r2, summary) = deduper.finish()?;
+    write_async(&mut writers, (unique_r1, unique_r2)).await?;
+    writers.0.flush().await?;
+    if let Some(writer_r2) = writers.1.as_mut() {
+        writer_r2.flush().await?;
+    }
+    Ok(summary)
+}
+
+#[cfg(feature = "tokio")]
+async fn write_async<W: tokio::io::AsyncWrite + Unpin>(
+    (writer_r1, writer_r2_opt): &mut (W, Option<W>),
+    (unique_r1, unique_r2): (Vec<u8>, Vec<u8>),
+) -> io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    writer_r1.write_all(&unique_r1).await?;
+    match writer_r2_opt.as_mut() {
+        Some(writer_r2) => writer_r2.write_all(&unique_r2).await,
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_dedup_bytes_single() {
+        let input = b"@id_a\nACGT\n+\nIIII\n@id_b\nACGT\n+\nIIII\n@id_c\nTTTT\n+\nIIII\n";
+        let (output_r1, output_r2, summary) =
+            dedup_bytes(input, None, &DedupConfig::default()).expect("don't break");
+        assert_eq!(
+            output_r1,
+            b"@id_a\nACGT\n+\nIIII\n@id_c\nTTTT\n+\nIIII\n".to_vec()
+        );
+        assert!(output_r2.is_empty());
+        assert_eq!(summary.total_records, 3);
+        assert_eq!(summary.unique_records, 2);
+    }
+
+    #[test]
+    fn test_dedup_bytes_paired_gzipped() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(b">id_a\nACGT\n>id_b\nACGT\n")
+            .expect("don't break");
+        let r1 = encoder.finish().expect("don't break");
+        let r2 = b">id_a\nGGGG\n>id_b\nCCCC\n";
+        let (output_r1, output_r2, summary) =
+            dedup_bytes(&r1, Some(r2), &DedupConfig::default()).expect("don't break");
+        assert_eq!(output_r1, b">id_a\nACGT\n>id_b\nACGT\n".to_vec());
+        assert_eq!(output_r2, b">id_a\nGGGG\n>id_b\nCCCC\n".to_vec());
+        assert_eq!(summary.unique_records, 2);
+    }
+
+    fn stream(
+        deduper: &mut StreamDeduper,
+        input: &[u8],
+        chunk_size: usize,
+        r2: bool,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let mut outputs = (Vec::new(), Vec::new());
+        for chunk in input.chunks(chunk_size) {
+            let (output_r1, output_r2) = if r2 {
+                deduper.push_r2(chunk)
+            } else {
+                deduper.push_r1(chunk)
+            }
+            .expect("don't break");
+            outputs.0.extend(output_r1);
+            outputs.1.extend(output_r2);
+        }
+        outputs
+    }
+
+    #[test]
+    fn test_record_ends() {
+        let fastq = b"@id_a\nACGT\n+\nIIII\n@id_b\nAC";
+        assert_eq!(record_ends(fastq, &FastxType::Fastq, false), vec![18]);
+        assert_eq!(record_ends(fastq, &FastxType::Fastq, true), vec![18, 26]);
+        let fasta = b">id_a\nACGT\nACGT\n>id_b\nAC";
+        assert_eq!(record_ends(fasta, &FastxType::Fasta, false), vec![16]);
+        assert_eq!(record_ends(fasta, &FastxType::Fasta, true), vec![16, 24]);
+    }
+
+    #[test]
+    fn test_stream_deduper_single() {
+        let input = b"@id_a\nACGT\n+\nIIII\n@id_b\nACGT\n+\nIIII\n@id_c\nTTTT\n+\nIIII\n";
+        let (expected, _, expected_summary) =
+            dedup_bytes(input, None, &DedupConfig::default()).expect("don't break");
+        for chunk_size in [1, 3, 7, 64].iter() {
+            let mut deduper =
+                StreamDeduper::new(DedupConfig::default(), false).expect("don't break");
+            let (mut output, _) = stream(&mut deduper, input, *chunk_size, false);
+            let (output_r1, _, summary) = deduper.finish().expect("don't break");
+            output.extend(output_r1);
+            assert_eq!(output, expected);
+            assert_eq!(summary, expected_summary);
+        }
+    }
+
+    #[test]
+    fn test_stream_deduper_paired_gzipped() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(b">id_a\nACGT\n>id_b\nACGT\n>id_c\nACGT\n")
+            .expect("don't break");
+        let r1 = encoder.finish().expect("don't break");
+        let r2 = b">id_a\nGGGG\n>id_b\nCCCC\n>id_c\nGGGG\n";
+        let mut deduper = StreamDeduper::new(DedupConfig::default(), true).expect("don't break");
+        let (mut output_r1, mut output_r2) = stream(&mut deduper, &r1, 5, false);
+        let outputs = stream(&mut deduper, r2, 5, true);
+        output_r1.extend(outputs.0);
+        output_r2.extend(outputs.1);
+        let (last_r1, last_r2, summary) = deduper.finish().expect("don't break");
+        output_r1.extend(last_r1);
+        output_r2.extend(last_r2);
+        assert_eq!(output_r1, b">id_a\nACGT\n>id_b\nACGT\n".to_vec());
+        assert_eq!(output_r2, b">id_a\nGGGG\n>id_b\nCCCC\n".to_vec());
+        assert_eq!(summary.total_records, 3);
+
+        let mut deduper = StreamDeduper::new(DedupConfig::default(), true).expect("don't break");
+        deduper
+            .push_r1(b">id_a\nACGT\n>id_b\nACGT\n")
+            .expect("don't break");
+        deduper.push_r2(b">id_a\nACGT\n").expect("don't break");
+        assert!(deduper.finish().is_err());
+    }
+
+    #[test]
+    fn test_dedup_bytes_invalid() {
+        let config = DedupConfig::default();
+        assert!(dedup_bytes(b"not fastx", None, &config).is_err());
+        assert!(dedup_bytes(b">id_a\nACGT\n", Some(b"@id_a\nACGT\n+\nIIII\n"), &config).is_err());
+        assert!(dedup_bytes(b">id_a\nACGT\n", Some(b">id_b\nACGT\n"), &config).is_err());
+    }
+
+    #[test]
+    fn test_dedup_files() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name);
+        std::fs::write(path("r1.fasta"), ">id_a\nACGT\n>id_b\nACGT\n>id_c\nACGT\n")
+            .expect("don't break");
+        std::fs::write(path("r2.fasta"), ">id_a\nGGGG\n>id_b\nCCCC\n>id_c\nGGGG\n")
+            .expect("don't break");
+        let (summary, cluster_sizes) = dedup_files(
+            (path("r1.fasta"), Some(path("r2.fasta"))),
+            (path("out_r1.fasta"), Some(path("out_r2.fasta"))),
+            &DedupConfig::default(),
+        )
+        .expect("don't break");
+        assert_eq!(summary.unique_records, 2);
+        assert_eq!(
+            cluster_sizes,
+            vec![("id_a".to_owned(), 2), ("id_b".to_owned(), 1)]
+        );
+        let output_r2 = std::fs::read(path("out_r2.fasta")).expect("don't break");
+        assert_eq!(output_r2, b">id_a\nGGGG\n>id_b\nCCCC\n".to_vec());
+
+        assert!(dedup_files(
+            (path("r1.fasta"), Some(path("r2.fasta"))),
+            (path("out_r1.fasta"), None),
+            &DedupConfig::default(),
+        )
+        .is_err());
+        dir.close().expect("don't break");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_dedup_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("don't break");
+        let input_r1: &[u8] = b">id_a\nACGT\n>id_b\nACGT\n>id_c\nACGT\n";
+        let input_r2: &[u8] = b">id_a\nGGGG\n>id_b\nCCCC\n>id_c\nGGGG\n";
+        let mut outputs = (Vec::new(), Vec::new());
+        let summary = runtime
+            .block_on(dedup_async(
+                (input_r1, Some(input_r2)),
+                (&mut outputs.0, Some(&mut outputs.1)),
+                &DedupConfig::default(),
+            ))
+            .expect("don't break");
+        assert_eq!(summary.unique_records, 2);
+        assert_eq!(outputs.0, b">id_a\nACGT\n>id_b\nACGT\n".to_vec());
+        assert_eq!(outputs.1, b">id_a\nGGGG\n>id_b\nCCCC\n".to_vec());
+    }
+}
@@ -0,0 +1,62 @@
+use bio::alphabets::dna::revcomp;
+use std::cmp;
+
+/// Minimum number of overlapping bases for mates to be merged
+pub const MIN_OVERLAP: usize = 12;
+/// Maximum fraction of mismatched bases allowed within the overlap
+pub const MAX_MISMATCH_RATE: f64 = 0.1;
+
+/// Merges r1 with the reverse complement of r2 when the end of r1 overlaps the
+/// start of it, returning the sequence of the whole fragment
+///
+/// The longest acceptable overlap wins. Bases in the overlap are taken from r1.
+pub fn merge_overlap(r1: &[u8], r2: &[u8]) -> Option<Vec<u8>> {
+    let r2_revcomp = revcomp(r2);
+    let max_overlap = cmp::min(r1.len(), r2_revcomp.len());
+    for overlap in (MIN_OVERLAP..=max_overlap).rev() {
+        let r1_suffix = &r1[r1.len() - overlap..];
+        let r2_prefix = &r2_revcomp[..overlap];
+        let mismatches = r1_suffix
+            .iter()
+            .zip(r2_prefix)
+            .filter(|(a, b)| a != b)
+            .count();
+        if mismatches as f64 <= overlap as f64 * MAX_MISMATCH_RATE {
+            let mut merged = r1.to_vec();
+            merged.extend_from_slice(&r2_revcomp[overlap..]);
+            return Some(merged);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FRAGMENT: &[u8] = b"ACGGTCATTGCAAGTCCTAGGATCCATGACGTTAGCAGTCATGCATAGGCTTACGATGCA";
+
+    #[test]
+    fn test_merge_overlap() {
+        let r1 = &FRAGMENT[..40];
+        let r2 = revcomp(&FRAGMENT[20..]);
+        assert_eq!(merge_overlap(r1, &r2), Some(FRAGMENT.to_vec()));
+    }
+
+    #[test]
+    fn test_merge_overlap_with_mismatch() {
+        let r1 = &FRAGMENT[..40];
+        let mut r2 = revcomp(&FRAGMENT[20..]);
+        // a sequencing error in the overlapping region of r2
+        let index = r2.len() - 5;
+        r2[index] = if r2[index] == b'A' { b'C' } else { b'A' };
+        assert_eq!(merge_overlap(r1, &r2), Some(FRAGMENT.to_vec()));
+    }
+
+    #[test]
+    fn test_merge_overlap_too_short() {
+        let r1 = &FRAGMENT[..30];
+        let r2 = revcomp(&FRAGMENT[25..]);
+        assert_eq!(merge_overlap(r1, &r2), None);
+    }
+}
@@ -27,11 +27,36 @@ impl<T: fastx::Record> PairedRecord<T> {
     pub fn r2(&self) -> &T {
         &self.r2
     }
+
+    /// The valid mate of a pair where exactly one mate fails its check,
+    /// otherwise the pair back
+    pub fn into_orphan(self) -> Result<(Mate, T), Self> {
+        match (self.r1.check().is_ok(), self.r2.check().is_ok()) {
+            (true, false) => Ok((Mate::R1, self.r1)),
+            (false, true) => Ok((Mate::R2, self.r2)),
+            _ => Err(self),
+        }
+    }
 }
 
-impl<T: fastx::Record> Into<(T, T)> for PairedRecord<T> {
-    fn into(self) -> (T, T) {
-        (self.r1, self.r2)
+/// Which input of a pair a read came from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mate {
+    R1,
+    R2,
+}
+
+/// A read pair, or a read whose mate is missing because the other input ended first
+pub enum Mates<T: fastx::Record> {
+    Pair(PairedRecord<T>),
+    Orphan(Mate, T),
+    /// r1 and r2 records whose ids differ
+    Mismatched(T, T),
+}
+
+impl<T: fastx::Record> From<PairedRecord<T>> for (T, T) {
+    fn from(record: PairedRecord<T>) -> (T, T) {
+        (record.r1, record.r2)
     }
 }
 
@@ -40,7 +65,7 @@ impl<T: fastx::Record> TryFrom<(T, T)> for PairedRecord<T> {
 
     fn try_from((r1, r2): (T, T)) -> Result<Self, Self::Error> {
         if r1.id() == r2.id() {
-            Ok(PairedRecord { r1: r1, r2: r2 })
+            Ok(PairedRecord { r1, r2 })
         } else {
             let message = format!(
                 "read pair had different read IDs: ({}, {})",
@@ -60,8 +85,27 @@ pub struct PairedRecords<T: fastx::Record, R: Iterator<Item = Result<T, std::io:
 impl<T: fastx::Record, R: Iterator<Item = Result<T, std::io::Error>>> PairedRecords<T, R> {
     pub fn new(records_r1: R, records_r2: R) -> Self {
         PairedRecords {
-            records_r1: records_r1,
-            records_r2: records_r2,
+            records_r1,
+            records_r2,
+        }
+    }
+}
+
+impl<A: fastx::Record, T: Iterator<Item = Result<A, std::io::Error>>> PairedRecords<A, T> {
+    /// The next pair, or once one input has ended the next read of the other as
+    /// an orphan. Mates with different ids are returned as mismatched rather
+    /// than an error so callers can skip them.
+    pub fn next_mates(&mut self) -> Option<Result<Mates<A>, Error>> {
+        match (self.records_r1.next(), self.records_r2.next()) {
+            (Some(Ok(r1_record)), Some(Ok(r2_record))) if r1_record.id() != r2_record.id() => {
+                Some(Ok(Mates::Mismatched(r1_record, r2_record)))
+            }
+            (Some(Ok(r1)), Some(Ok(r2))) => Some(Ok(Mates::Pair(PairedRecord { r1, r2 }))),
+            (None, None) => None,
+            (Some(Ok(record)), None) => Some(Ok(Mates::Orphan(Mate::R1, record))),
+            (None, Some(Ok(record))) => Some(Ok(Mates::Orphan(Mate::R2, record))),
+            (Some(Err(err)), _) => Some(Err(err)),
+            (_, Some(Err(err))) => Some(Err(err)),
         }
     }
 }
@@ -91,6 +135,91 @@ impl<A: fastx::Record, T: Iterator<Item = Result<A, std::io::Error>>> Iterator
     }
 }
 
+/// Synchronized records of any number of mates, like r1, r2, and an index read
+pub struct MateSet<T: fastx::Record> {
+    mates: Vec<T>,
+}
+
+impl<T: fastx::Record> MateSet<T> {
+    pub fn id(&self) -> &str {
+        self.mates[0].id()
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        self.mates
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, mate)| mate.check().map_err(|err| format!("r{}: {}", i + 1, err)))
+    }
+
+    pub fn mates(&self) -> &[T] {
+        &self.mates
+    }
+}
+
+impl<T: fastx::Record> From<PairedRecord<T>> for MateSet<T> {
+    fn from(record: PairedRecord<T>) -> Self {
+        MateSet {
+            mates: vec![record.r1, record.r2],
+        }
+    }
+}
+
+impl<T: fastx::Record> TryFrom<Vec<T>> for MateSet<T> {
+    type Error = Error;
+
+    fn try_from(mates: Vec<T>) -> Result<Self, Self::Error> {
+        if mates.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "a mate set needs a mate",
+            ));
+        }
+        if mates.iter().all(|mate| mate.id() == mates[0].id()) {
+            Ok(MateSet { mates })
+        } else {
+            let ids = mates.iter().map(|mate| mate.id()).collect::<Vec<_>>();
+            let message = format!("mates had different read IDs: ({})", ids.join(", "));
+            Err(Error::new(ErrorKind::InvalidData, message))
+        }
+    }
+}
+
+/// Reads the records of each mate's input in step, like `PairedRecords`
+/// for any number of mates
+pub struct MateSets<T: fastx::Record, R: Iterator<Item = Result<T, std::io::Error>>> {
+    records: Vec<R>,
+}
+
+impl<T: fastx::Record, R: Iterator<Item = Result<T, std::io::Error>>> MateSets<T, R> {
+    pub fn new(records: Vec<R>) -> Self {
+        MateSets { records }
+    }
+}
+
+impl<A: fastx::Record, T: Iterator<Item = Result<A, std::io::Error>>> Iterator for MateSets<A, T> {
+    type Item = Result<MateSet<A>, Error>;
+
+    fn next(&mut self) -> Option<Result<MateSet<A>, Error>> {
+        let results = self
+            .records
+            .iter_mut()
+            .map(|records| records.next())
+            .collect::<Vec<_>>();
+        if results.iter().all(Option::is_none) {
+            return None;
+        }
+        if let Some(ended) = results.iter().position(Option::is_none) {
+            // some other mate's input still has a record
+            let longer = results.iter().position(Option::is_some).unwrap();
+            let message = format!("reached the end of r{} before r{}", ended + 1, longer + 1);
+            return Some(Err(Error::new(ErrorKind::UnexpectedEof, message)));
+        }
+        let mates = results.into_iter().flatten().collect::<Result<Vec<_>, _>>();
+        Some(mates.and_then(MateSet::try_from))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -172,9 +301,8 @@ mod test {
     #[test]
     fn test_r1_error() {
         let records_r1 =
-            vec![Err(Error::new(ErrorKind::Other, "I'm broken")) as Result<fasta::Record, Error>]
-                .into_iter();
-        let records_r2 = vec![Err(Error::new(ErrorKind::Other, "I'm also broken"))].into_iter();
+            vec![Err(Error::other("I'm broken")) as Result<fasta::Record, Error>].into_iter();
+        let records_r2 = vec![Err(Error::other("I'm also broken"))].into_iter();
         let mut paired_iterator = PairedRecords::new(records_r1, records_r2);
         let result = paired_iterator.next();
 
@@ -190,7 +318,7 @@ mod test {
     fn test_r2_error() {
         let record_r1 = fasta::Record::with_attrs("id_a", None, &[]);
         let records_r1 = vec![Ok(record_r1)].into_iter();
-        let records_r2 = vec![Err(Error::new(ErrorKind::Other, "I'm broken"))].into_iter();
+        let records_r2 = vec![Err(Error::other("I'm broken"))].into_iter();
         let mut paired_iterator = PairedRecords::new(records_r1, records_r2);
         let result = paired_iterator.next();
 
@@ -201,4 +329,99 @@ mod test {
         assert_eq!(error.kind(), ErrorKind::Other, "should be of kind Other");
         assert_eq!(error.to_string(), "I'm broken");
     }
+
+    #[test]
+    fn test_next_mates_orphans() {
+        let records_r1 = vec![
+            Ok(fasta::Record::with_attrs("id_a", None, b"ACGT")),
+            Ok(fasta::Record::with_attrs("id_b", None, b"ACGT")),
+        ]
+        .into_iter();
+        let records_r2 = vec![Ok(fasta::Record::with_attrs("id_a", None, b"ACGT"))].into_iter();
+        let mut paired_iterator = PairedRecords::new(records_r1, records_r2);
+        match paired_iterator.next_mates() {
+            Some(Ok(Mates::Pair(record))) => assert_eq!(record.id(), "id_a"),
+            _ => panic!("should return a pair"),
+        }
+        match paired_iterator.next_mates() {
+            Some(Ok(Mates::Orphan(mate, record))) => {
+                assert_eq!((mate, record.id()), (Mate::R1, "id_b"))
+            }
+            _ => panic!("should return an orphan"),
+        }
+        assert!(paired_iterator.next_mates().is_none());
+    }
+
+    #[test]
+    fn test_next_mates_mismatched() {
+        let records_r1 = vec![Ok(fasta::Record::with_attrs("id_a", None, b"ACGT"))].into_iter();
+        let records_r2 = vec![Ok(fasta::Record::with_attrs("id_b", None, b"ACGT"))].into_iter();
+        let mut paired_iterator = PairedRecords::new(records_r1, records_r2);
+        match paired_iterator.next_mates() {
+            Some(Ok(Mates::Mismatched(r1, r2))) => assert_eq!((r1.id(), r2.id()), ("id_a", "id_b")),
+            _ => panic!("should return mismatched mates"),
+        }
+        assert!(paired_iterator.next_mates().is_none());
+    }
+
+    #[test]
+    fn test_mate_sets() {
+        let records = |ids: &[&str]| {
+            ids.iter()
+                .map(|id| Ok(fasta::Record::with_attrs(id, None, b"ACGT")))
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+        let mut mate_sets = MateSets::new(vec![
+            records(&["id_a", "id_b", "id_c"]),
+            records(&["id_a", "id_x", "id_c"]),
+            records(&["id_a", "id_b"]),
+        ]);
+        let mate_set = mate_sets
+            .next()
+            .expect("should return an element")
+            .expect("don't break");
+        assert_eq!(mate_set.id(), "id_a");
+        assert_eq!(mate_set.mates().len(), 3);
+        let error = mate_sets
+            .next()
+            .expect("should return an element")
+            .err()
+            .expect("should return an error");
+        assert_eq!(
+            error.to_string(),
+            "mates had different read IDs: (id_b, id_x, id_b)"
+        );
+        let error = mate_sets
+            .next()
+            .expect("should return an element")
+            .err()
+            .expect("should return an error");
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(error.to_string(), "reached the end of r3 before r1");
+        assert!(mate_sets.next().is_none());
+    }
+
+    #[test]
+    fn test_into_orphan() {
+        let valid = fasta::Record::with_attrs("id_a", None, b"ACGT");
+        let invalid = fasta::Record::with_attrs("id_a", None, "AC\u{e9}GT".as_bytes());
+        let record = PairedRecord::try_from((valid.clone(), invalid.clone())).expect("don't break");
+        assert_eq!(
+            record
+                .into_orphan()
+                .ok()
+                .map(|(mate, r)| (mate, r.seq().to_vec())),
+            Some((Mate::R1, b"ACGT".to_vec()))
+        );
+        let record = PairedRecord::try_from((invalid.clone(), valid.clone())).expect("don't break");
+        assert_eq!(
+            record.into_orphan().ok().map(|(mate, _)| mate),
+            Some(Mate::R2)
+        );
+        let record = PairedRecord::try_from((invalid.clone(), invalid)).expect("don't break");
+        assert!(record.into_orphan().is_err());
+        let record = PairedRecord::try_from((valid.clone(), valid)).expect("don't break");
+        assert!(record.into_orphan().is_err());
+    }
 }
@@ -0,0 +1,289 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use super::bam::{self, BamRecord};
+
+/// A read's 5' end: reference, coordinate, and whether it's reverse stranded
+type End = (i32, i64, bool);
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum PositionKey {
+    Fragment(End),
+    /// both mates' ends, lower first
+    Pair(End, End),
+}
+
+/// The reference coordinate of a read's 5' end, before any clipping
+///
+/// That's the start of forward reads less their leading clips, and the end
+/// of reverse reads plus their trailing clips.
+pub fn unclipped_five_prime(pos: i32, cigar: &[(u8, u32)], reverse: bool) -> i64 {
+    let is_clip = |op: &u8| *op == b'S' || *op == b'H';
+    let clipped = |ops: &mut dyn Iterator<Item = &(u8, u32)>| -> i64 {
+        ops.take_while(|(op, _)| is_clip(op))
+            .map(|(_, len)| *len as i64)
+            .sum()
+    };
+    if reverse {
+        let aligned: i64 = cigar
+            .iter()
+            .filter(|(op, _)| matches!(op, b'M' | b'D' | b'N' | b'=' | b'X'))
+            .map(|(_, len)| *len as i64)
+            .sum();
+        pos as i64 + aligned.max(1) - 1 + clipped(&mut cigar.iter().rev())
+    } else {
+        pos as i64 - clipped(&mut cigar.iter())
+    }
+}
+
+/// Picard's score for choosing among duplicates, the sum of a read's base
+/// qualities of at least 15
+pub fn quality_score(record: &BamRecord) -> u64 {
+    record
+        .quals()
+        .iter()
+        .filter(|qual| **qual >= 15)
+        .map(|qual| *qual as u64)
+        .sum()
+}
+
+/// The key duplicates share, `None` for reads that are never duplicates
+fn position_key(record: &BamRecord) -> Option<PositionKey> {
+    let flag = record.flag();
+    if flag & (bam::FLAG_UNMAPPED | bam::FLAG_SECONDARY | bam::FLAG_SUPPLEMENTARY) != 0 {
+        return None;
+    }
+    let reverse = flag & bam::FLAG_REVERSE != 0;
+    if flag & bam::FLAG_PAIRED == 0 || flag & bam::FLAG_MATE_UNMAPPED != 0 {
+        let end = (
+            record.ref_id(),
+            unclipped_five_prime(record.pos(), &record.cigar(), reverse),
+            reverse,
+        );
+        return Some(PositionKey::Fragment(end));
+    }
+    let mate_reverse = flag & bam::FLAG_MATE_REVERSE != 0;
+    let mate_cigar_opt = record.string_tag(b"MC").and_then(bam::parse_cigar);
+    let (position, mate_position) = match mate_cigar_opt {
+        Some(mate_cigar) => (
+            unclipped_five_prime(record.pos(), &record.cigar(), reverse),
+            unclipped_five_prime(record.next_pos(), &mate_cigar, mate_reverse),
+        ),
+        None => (record.pos() as i64, record.next_pos() as i64),
+    };
+    let end = (record.ref_id(), position, reverse);
+    let mate_end = (record.next_ref_id(), mate_position, mate_reverse);
+    Some(PositionKey::Pair(end.min(mate_end), end.max(mate_end)))
+}
+
+/// Finds duplicates among aligned reads by where they map, like Picard's
+/// MarkDuplicates: single reads by their 5' end and strand, and pairs by
+/// both mates' 5' ends and strands
+///
+/// Ends are unclipped when a pair's reads carry their mate's CIGAR in the
+/// `MC` tag, otherwise they're the mates' leftmost mapped positions, so both
+/// mates key the pair the same way. Unmapped, secondary, and supplementary
+/// reads are never duplicates.
+///
+/// Every read is scored with `score` first, then each is checked with
+/// `is_duplicate`. The read, or pair, with the highest `quality_score` at a
+/// position is kept, a pair scoring both mates together, and the first
+/// one on ties.
+#[derive(Default)]
+pub struct PositionDedup {
+    /// the read name kept at each key and its score
+    best: HashMap<PositionKey, (Vec<u8>, u64)>,
+    /// the key and score of pairs whose other mate hasn't been scored, by
+    /// read name
+    pending: HashMap<Vec<u8>, (PositionKey, u64)>,
+    total_records: u64,
+    duplicate_records: u64,
+}
+
+impl PositionDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scores a read in the first pass
+    pub fn score(&mut self, record: &BamRecord) {
+        let key = match position_key(record) {
+            Some(key) => key,
+            None => return,
+        };
+        let score = quality_score(record);
+        let name = record.name().to_vec();
+        if let PositionKey::Fragment(_) = key {
+            self.compete(key, name, score);
+        } else if let Some((_, mate_score)) = self.pending.remove(&name) {
+            self.compete(key, name, score + mate_score);
+        } else {
+            self.pending.insert(name, (key, score));
+        }
+    }
+
+    fn compete(&mut self, key: PositionKey, name: Vec<u8>, score: u64) {
+        match self.best.entry(key) {
+            Entry::Occupied(mut entry) => {
+                if score > entry.get().1 {
+                    entry.insert((name, score));
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((name, score));
+            }
+        }
+    }
+
+    /// Ends the first pass, scoring pairs whose other mate never turned up
+    /// by the one mate that did
+    pub fn finish_scoring(&mut self) {
+        let mut pending = self.pending.drain().collect::<Vec<_>>();
+        pending.sort_unstable_by(|(name, _), (other, _)| name.cmp(other));
+        for (name, (key, score)) in pending {
+            self.compete(key, name, score);
+        }
+    }
+
+    /// Whether a read isn't the one kept at its position, in the second
+    /// pass, counting it either way
+    pub fn is_duplicate(&mut self, record: &BamRecord) -> bool {
+        self.total_records += 1;
+        let is_duplicate = position_key(record)
+            .and_then(|key| self.best.get(&key))
+            .is_some_and(|(name, _)| name.as_slice() != record.name());
+        if is_duplicate {
+            self.duplicate_records += 1;
+        }
+        is_duplicate
+    }
+
+    pub fn total_records(&self) -> u64 {
+        self.total_records
+    }
+
+    pub fn duplicate_records(&self) -> u64 {
+        self.duplicate_records
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mc_tag(cigar: &str) -> Vec<u8> {
+        let mut tag = b"MCZ".to_vec();
+        tag.extend_from_slice(cigar.as_bytes());
+        tag.push(0);
+        tag
+    }
+
+    #[test]
+    fn test_unclipped_five_prime() {
+        let cigar = [(b'S', 5), (b'M', 10), (b'D', 2), (b'M', 8), (b'H', 3)];
+        assert_eq!(unclipped_five_prime(100, &cigar, false), 95);
+        assert_eq!(unclipped_five_prime(100, &cigar, true), 122);
+        assert_eq!(bam::parse_cigar("5S10M2D8M3H"), Some(cigar.to_vec()));
+        assert_eq!(bam::parse_cigar("5Q"), None);
+    }
+
+    #[test]
+    fn test_position_dedup() {
+        let mut dedup = PositionDedup::new();
+        let forward = bam::FLAG_PAIRED | bam::FLAG_MATE_REVERSE;
+        let reverse = bam::FLAG_PAIRED | bam::FLAG_REVERSE;
+        let records = [
+            // a pair, and a duplicate of it soft clipped differently
+            BamRecord::new(
+                "a",
+                forward,
+                (0, 100),
+                &[(b'M', 50)],
+                (0, 300),
+                &mc_tag("50M"),
+            ),
+            BamRecord::new(
+                "b",
+                forward,
+                (0, 102),
+                &[(b'S', 2), (b'M', 48)],
+                (0, 300),
+                &mc_tag("50M"),
+            ),
+            BamRecord::new(
+                "a",
+                reverse,
+                (0, 300),
+                &[(b'M', 50)],
+                (0, 100),
+                &mc_tag("50M"),
+            ),
+            BamRecord::new(
+                "b",
+                reverse,
+                (0, 300),
+                &[(b'M', 50)],
+                (0, 102),
+                &mc_tag("2S48M"),
+            ),
+            // same start, other strand
+            BamRecord::new("c", 0, (0, 100), &[(b'M', 50)], (-1, -1), &[]),
+            BamRecord::new(
+                "d",
+                bam::FLAG_REVERSE,
+                (0, 100),
+                &[(b'M', 50)],
+                (-1, -1),
+                &[],
+            ),
+            BamRecord::new("e", 0, (0, 100), &[(b'M', 50)], (-1, -1), &[]),
+            BamRecord::new("f", bam::FLAG_UNMAPPED, (-1, -1), &[], (-1, -1), &[]),
+            BamRecord::new("g", bam::FLAG_UNMAPPED, (-1, -1), &[], (-1, -1), &[]),
+        ];
+        for record in records.iter() {
+            dedup.score(record);
+        }
+        dedup.finish_scoring();
+        let duplicates = records
+            .iter()
+            .map(|record| dedup.is_duplicate(record))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            duplicates,
+            vec![false, true, false, true, false, false, true, false, false]
+        );
+        assert_eq!(dedup.total_records(), 9);
+        assert_eq!(dedup.duplicate_records(), 3);
+    }
+
+    #[test]
+    fn test_position_dedup_quality() {
+        let mut dedup = PositionDedup::new();
+        let forward = bam::FLAG_PAIRED | bam::FLAG_MATE_REVERSE;
+        let reverse = bam::FLAG_PAIRED | bam::FLAG_REVERSE;
+        let records = [
+            BamRecord::new("a", forward, (0, 100), &[(b'M', 4)], (0, 300), &[])
+                .with_quals(&[30, 30, 30, 30]),
+            // scores lower on the first mate but higher over the pair
+            BamRecord::new("b", forward, (0, 100), &[(b'M', 4)], (0, 300), &[])
+                .with_quals(&[30, 30, 30, 10]),
+            BamRecord::new("a", reverse, (0, 300), &[(b'M', 4)], (0, 100), &[])
+                .with_quals(&[20, 20, 20, 20]),
+            BamRecord::new("b", reverse, (0, 300), &[(b'M', 4)], (0, 100), &[])
+                .with_quals(&[40, 40, 40, 40]),
+            // qualities below 15 don't count, so this ties and the first wins
+            BamRecord::new("c", 0, (0, 500), &[(b'M', 2)], (-1, -1), &[]).with_quals(&[30, 14]),
+            BamRecord::new("d", 0, (0, 500), &[(b'M', 2)], (-1, -1), &[]).with_quals(&[30, 2]),
+        ];
+        for record in records.iter() {
+            dedup.score(record);
+        }
+        dedup.finish_scoring();
+        let duplicates = records
+            .iter()
+            .map(|record| dedup.is_duplicate(record))
+            .collect::<Vec<_>>();
+        assert_eq!(duplicates, vec![true, false, true, false, false, true]);
+        assert_eq!(quality_score(&records[4]), 30);
+    }
+}
@@ -0,0 +1,330 @@
+//! Checks a run's input and output paths before any work starts
+//!
+//! Every problem is reported at once, so a run doesn't stop on its second
+//! output an hour in, and again on the next once that's fixed. Problems that
+//! would fail the run are errors; too little free disk is only an estimate,
+//! so it's a warning.
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::archive;
+
+/// How many times larger than a gzipped input its reads are estimated to be
+/// once written uncompressed
+pub const GZIP_RATIO: u64 = 4;
+
+#[derive(Debug, Default)]
+pub struct RunPaths<'a> {
+    /// Inputs read for records, which no output may overwrite
+    pub inputs: Vec<&'a str>,
+    /// Inputs an output may replace, like a cluster state saved back over the
+    /// one loaded
+    pub replaceable_inputs: Vec<&'a str>,
+    pub outputs: Vec<&'a str>,
+}
+
+/// The file read for an input, the archive of an `archive::member` input
+fn input_file(input: &str) -> &str {
+    match archive::split_member(input) {
+        Some((path, _)) => path,
+        None => input,
+    }
+}
+
+/// Devices like `/dev/null` may be given for several outputs, or be read
+fn is_special(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| !metadata.is_file() && !metadata.is_dir())
+}
+
+/// The directory an output is created in
+fn output_dir(output: &str) -> PathBuf {
+    match Path::new(output).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn check_input(input: &str) -> Option<String> {
+    let path = Path::new(input_file(input));
+    match fs::metadata(path) {
+        Err(_) if !path.exists() => Some(format!("input {} does not exist", path.display())),
+        Err(err) => Some(format!("input {} can't be read: {}", path.display(), err)),
+        Ok(metadata) if metadata.is_dir() => {
+            Some(format!("input {} is a directory", path.display()))
+        }
+        Ok(_) => fs::File::open(path)
+            .err()
+            .map(|err| format!("input {} can't be read: {}", path.display(), err)),
+    }
+}
+
+fn check_output(output: &str) -> Option<String> {
+    let path = Path::new(output);
+    if path.is_dir() {
+        return Some(format!("output {} is a directory", output));
+    }
+    if path.exists() {
+        // opened without truncating, an existing output is only replaced once the run starts
+        return OpenOptions::new()
+            .append(true)
+            .open(path)
+            .err()
+            .map(|err| format!("output {} can't be written: {}", output, err));
+    }
+    let dir = output_dir(output);
+    if !dir.is_dir() {
+        return Some(format!(
+            "output {} is in {}, which does not exist",
+            output,
+            dir.display()
+        ));
+    }
+    tempfile::Builder::new()
+        .prefix(".czid-dedup-")
+        .tempfile_in(&dir)
+        .err()
+        .map(|err| {
+            format!(
+                "output {} can't be created in {}: {}",
+                output,
+                dir.display(),
+                err
+            )
+        })
+}
+
+impl RunPaths<'_> {
+    /// Every problem that would stop the run, empty if there are none
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = self
+            .inputs
+            .iter()
+            .chain(self.replaceable_inputs.iter())
+            .filter_map(|input| check_input(input))
+            .collect::<Vec<_>>();
+        problems.extend(
+            self.outputs
+                .iter()
+                .filter_map(|output| check_output(output)),
+        );
+
+        // outputs that exist already are compared by where they lead
+        let canonical = |path: &str| fs::canonicalize(path).unwrap_or_else(|_| path.into());
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| (canonical(input_file(input)), *input))
+            .collect::<HashMap<_, _>>();
+        let mut outputs = HashMap::new();
+        for output in self.outputs.iter() {
+            if is_special(Path::new(output)) {
+                continue;
+            }
+            let path = canonical(output);
+            if let Some(input) = inputs.get(&path) {
+                problems.push(format!("output {} would overwrite input {}", output, input));
+            }
+            if let Some(other) = outputs.insert(path, *output) {
+                problems.push(format!(
+                    "output {} is given more than once, also as {}",
+                    output, other
+                ));
+            }
+        }
+        problems
+    }
+
+    /// Bytes the outputs may need, at most the inputs' reads uncompressed
+    pub fn estimated_output_bytes(&self) -> u64 {
+        self.inputs
+            .iter()
+            .map(|input| input_file(input))
+            .filter_map(|input| {
+                let size = fs::metadata(input).ok()?.len();
+                Some(if is_gzipped(input) {
+                    size * GZIP_RATIO
+                } else {
+                    size
+                })
+            })
+            .sum()
+    }
+
+    /// Warnings for output directories without room for the outputs'
+    /// estimated size, skipped where free space can't be read
+    pub fn space_warnings(&self) -> Vec<String> {
+        let needed = self.estimated_output_bytes();
+        let mut dirs = self
+            .outputs
+            .iter()
+            .filter(|output| !is_special(Path::new(output)))
+            .map(|output| output_dir(output))
+            .collect::<Vec<_>>();
+        dirs.sort();
+        dirs.dedup();
+        dirs.into_iter()
+            .filter_map(|dir| {
+                let available = available_bytes(&dir)?;
+                if available >= needed {
+                    return None;
+                }
+                Some(format!(
+                    "outputs may need up to {} bytes but {} only has {} free",
+                    needed,
+                    dir.display(),
+                    available
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Removes the outputs a run created when it's dropped, unless `keep` was
+/// called, so a run that fails while setting up doesn't leave empty outputs
+/// behind
+///
+/// Outputs that existed before the run are left alone.
+pub struct PartialOutputs {
+    created: Vec<PathBuf>,
+}
+
+impl PartialOutputs {
+    /// Tracks the outputs that don't exist yet, before any is created
+    pub fn new(outputs: &[&str]) -> Self {
+        PartialOutputs {
+            created: outputs
+                .iter()
+                .map(PathBuf::from)
+                .filter(|path| !path.exists())
+                .collect(),
+        }
+    }
+
+    /// Keeps every output, once the run has started reading records
+    pub fn keep(mut self) {
+        self.created.clear();
+    }
+}
+
+impl Drop for PartialOutputs {
+    fn drop(&mut self) {
+        for path in self.created.iter() {
+            // an output that was never created has nothing to remove
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn is_gzipped(path: &str) -> bool {
+    let mut magic = [0u8; 2];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && magic == [0x1f, 0x8b]
+}
+
+/// Free bytes on the filesystem holding `dir`, from POSIX `df -Pk`
+fn available_bytes(dir: &Path) -> Option<u64> {
+    let output = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_df_available(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Reads the available column of `df -Pk`'s output, in bytes
+pub fn parse_df_available(df: &str) -> Option<u64> {
+    let kilobytes = df.lines().nth(1)?.split_whitespace().nth(3)?;
+    kilobytes
+        .parse::<u64>()
+        .ok()
+        .map(|kilobytes| kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_problems() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        fs::write(path("input.fasta"), ">id_a\nACGT\n").expect("don't break");
+        let (input, missing) = (path("input.fasta"), path("missing.fasta"));
+        let (output, nested) = (path("output.fasta"), path("missing/output.fasta"));
+        let paths = RunPaths {
+            inputs: vec![&input],
+            outputs: vec![&output, "/dev/null", "/dev/null"],
+            ..RunPaths::default()
+        };
+        assert!(paths.problems().is_empty());
+        // nothing is left behind by checking that outputs can be created
+        assert_eq!(fs::read_dir(dir.path()).expect("don't break").count(), 1);
+
+        let paths = RunPaths {
+            inputs: vec![&input, &missing],
+            outputs: vec![&output, &nested, &input, &output],
+            ..RunPaths::default()
+        };
+        assert_eq!(
+            paths.problems(),
+            vec![
+                format!("input {} does not exist", missing),
+                format!(
+                    "output {} is in {}, which does not exist",
+                    nested,
+                    path("missing")
+                ),
+                format!("output {} would overwrite input {}", input, input),
+                format!(
+                    "output {} is given more than once, also as {}",
+                    output, output
+                ),
+            ]
+        );
+
+        // a state may be saved over the one it was loaded from
+        let state = path("input.fasta");
+        let paths = RunPaths {
+            replaceable_inputs: vec![&state],
+            outputs: vec![&state],
+            ..RunPaths::default()
+        };
+        assert!(paths.problems().is_empty());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_partial_outputs() {
+        let dir = tempdir().expect("don't break");
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let (existing, created) = (path("existing.fasta"), path("created.fasta"));
+        fs::write(&existing, ">id_a\nACGT\n").expect("don't break");
+        let outputs = [existing.as_str(), created.as_str(), "/dev/null"];
+
+        let partial_outputs = PartialOutputs::new(&outputs);
+        fs::write(&created, "").expect("don't break");
+        drop(partial_outputs);
+        assert!(Path::new(&existing).exists());
+        assert!(!Path::new(&created).exists());
+
+        let partial_outputs = PartialOutputs::new(&outputs);
+        fs::write(&created, "").expect("don't break");
+        partial_outputs.keep();
+        assert!(Path::new(&created).exists());
+        dir.close().expect("don't break");
+    }
+
+    #[test]
+    fn test_parse_df_available() {
+        let df = "Filesystem     1024-blocks     Used Available Capacity Mounted on\n\
+                  /dev/sda1        102400000 51200000  51200000      50% /\n";
+        assert_eq!(parse_df_available(df), Some(51_200_000 * 1024));
+        assert_eq!(parse_df_available(""), None);
+    }
+}
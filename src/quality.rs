@@ -0,0 +1,169 @@
+//! Converts FASTQ qualities from the legacy Solexa and Illumina 1.3+
+//! encodings to standard Phred+33
+//!
+//! An input's encoding is detected from the range of its first records'
+//! qualities. Solexa and Illumina 1.3+ both offset their scores by 64, but
+//! Solexa scores go down to -5 (`;`), while no Phred+33 input has a quality
+//! over Q42 (`K`). An input whose qualities fit both Phred+33 and a legacy
+//! encoding is left as it is.
+use std::collections::VecDeque;
+
+use super::fastx;
+
+/// How many records' qualities an input's encoding is detected from
+pub const DETECT_RECORDS: usize = 1000;
+
+/// The lowest quality of Solexa's encoding, -5 offset by 64
+const SOLEXA_MIN: u8 = b';';
+/// The lowest quality of Illumina 1.3+'s encoding, 0 offset by 64
+const PHRED64_MIN: u8 = b'@';
+/// The highest quality of Phred+33 inputs, Q42
+const PHRED33_MAX: u8 = b'K';
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QualityEncoding {
+    Phred33,
+    /// Illumina 1.3 to 1.7, Phred scores offset by 64
+    Phred64,
+    /// Solexa's odds-based scores, offset by 64
+    Solexa,
+}
+
+impl QualityEncoding {
+    /// Detects the encoding of qualities, Phred+33 unless they can only be
+    /// a legacy encoding
+    pub fn detect<'a, I: IntoIterator<Item = &'a [u8]>>(quals: I) -> Self {
+        let (min, max) = quals
+            .into_iter()
+            .flatten()
+            .fold((u8::MAX, u8::MIN), |(min, max), q| {
+                (min.min(*q), max.max(*q))
+            });
+        if min < SOLEXA_MIN || max <= PHRED33_MAX {
+            QualityEncoding::Phred33
+        } else if min < PHRED64_MIN {
+            QualityEncoding::Solexa
+        } else {
+            QualityEncoding::Phred64
+        }
+    }
+
+    /// Converts a quality in this encoding to Phred+33
+    pub fn to_phred33(self, q: u8) -> u8 {
+        match self {
+            QualityEncoding::Phred33 => q,
+            QualityEncoding::Phred64 => q.saturating_sub(31),
+            QualityEncoding::Solexa => {
+                let solexa = f64::from(q) - 64.0;
+                let phred = 10.0 * (10f64.powf(solexa / 10.0) + 1.0).log10();
+                phred.round() as u8 + 33
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for QualityEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            QualityEncoding::Phred33 => "phred33",
+            QualityEncoding::Phred64 => "phred64",
+            QualityEncoding::Solexa => "solexa",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Rewrites an input's records to Phred+33 qualities, with --fix-legacy-quality
+///
+/// The first `DETECT_RECORDS` records are held back to detect the encoding.
+/// When disabled, or for FASTA, records pass through as they are.
+pub struct LegacyQuality<I: Iterator> {
+    records: I,
+    detecting: bool,
+    held: VecDeque<I::Item>,
+    encoding: QualityEncoding,
+}
+
+impl<I: Iterator> LegacyQuality<I> {
+    pub fn new(records: I, enabled: bool) -> Self {
+        LegacyQuality {
+            records,
+            detecting: enabled,
+            held: VecDeque::new(),
+            encoding: QualityEncoding::Phred33,
+        }
+    }
+}
+
+impl<R: fastx::Record, E, I: Iterator<Item = Result<R, E>>> Iterator for LegacyQuality<I> {
+    type Item = Result<R, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.detecting {
+            self.detecting = false;
+            self.held.extend(self.records.by_ref().take(DETECT_RECORDS));
+            self.encoding = QualityEncoding::detect(
+                self.held
+                    .iter()
+                    .filter_map(|result| result.as_ref().ok())
+                    .filter_map(|record| record.qual()),
+            );
+        }
+        let result = self.held.pop_front().or_else(|| self.records.next())?;
+        let encoding = self.encoding;
+        Some(result.map(|record| match (encoding, record.qual()) {
+            (QualityEncoding::Phred33, _) | (_, None) => record,
+            (_, Some(qual)) => {
+                let qual = qual
+                    .iter()
+                    .map(|q| encoding.to_phred33(*q))
+                    .collect::<Vec<_>>();
+                record.with_qual(&qual)
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bio::io::fastq;
+
+    #[test]
+    fn test_detect() {
+        let detect = |quals: &[&[u8]]| QualityEncoding::detect(quals.iter().cloned());
+        assert_eq!(detect(&[b"#,:FFF"]), QualityEncoding::Phred33);
+        // high quality Phred+33 fits Illumina 1.3+ too
+        assert_eq!(detect(&[b"FFFFJJ"]), QualityEncoding::Phred33);
+        assert_eq!(detect(&[b"BBh", b"@ab"]), QualityEncoding::Phred64);
+        assert_eq!(detect(&[b";@h"]), QualityEncoding::Solexa);
+        assert_eq!(detect(&[]), QualityEncoding::Phred33);
+    }
+
+    #[test]
+    fn test_to_phred33() {
+        assert_eq!(QualityEncoding::Phred64.to_phred33(b'h'), b'I');
+        assert_eq!(QualityEncoding::Phred64.to_phred33(b'@'), b'!');
+        // Solexa and Phred agree at high qualities, not at low ones
+        assert_eq!(QualityEncoding::Solexa.to_phred33(b'h'), b'I');
+        assert_eq!(QualityEncoding::Solexa.to_phred33(b'@'), b'$');
+        assert_eq!(QualityEncoding::Solexa.to_phred33(b';'), b'"');
+    }
+
+    #[test]
+    fn test_legacy_quality() {
+        let records = vec![
+            Ok::<_, ()>(fastq::Record::with_attrs("id_a", None, b"ACG", b"hhh")),
+            Ok(fastq::Record::with_attrs("id_b", None, b"ACG", b"@Th")),
+        ];
+        let fixed = LegacyQuality::new(records.clone().into_iter(), true)
+            .map(|result| result.unwrap().qual().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(fixed, vec![b"III".to_vec(), b"!5I".to_vec()]);
+        let unchanged = LegacyQuality::new(records.into_iter(), false)
+            .map(|result| result.unwrap().qual().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(unchanged, vec![b"hhh".to_vec(), b"@Th".to_vec()]);
+    }
+}
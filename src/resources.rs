@@ -0,0 +1,140 @@
+//! The CPU and memory a run may use, from its container's cgroup limits
+//!
+//! Inside a Kubernetes pod or other container the host's CPU count and
+//! memory are far more than a job was given. The limits are read from
+//! cgroup v2's `cpu.max` and `memory.max`, or cgroup v1's CFS quota and
+//! memory limit, falling back to the host when there are none.
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+/// Where the cgroup filesystem is mounted, the container's own cgroup
+/// inside a cgroup namespace
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// cgroup v1 reports no memory limit as a huge page aligned number rather
+/// than `max`, anything this large is treated as unlimited
+const UNLIMITED_MEMORY: u64 = 1 << 62;
+
+/// CPUs the container may use, possibly fractional, if it's limited
+pub fn cpu_limit() -> Option<f64> {
+    cpu_limit_in(Path::new(CGROUP_ROOT))
+}
+
+/// Bytes of memory the container may use, if it's limited
+pub fn memory_limit() -> Option<u64> {
+    memory_limit_in(Path::new(CGROUP_ROOT))
+}
+
+/// Threads to use when --threads isn't set, the CPUs available to this
+/// process rounded up to a whole thread
+pub fn default_threads() -> usize {
+    let available = thread::available_parallelism().map_or(1, usize::from);
+    match cpu_limit() {
+        Some(cpus) => available.min(cpus.ceil() as usize).max(1),
+        None => available,
+    }
+}
+
+fn read(root: &Path, file: &str) -> Option<String> {
+    fs::read_to_string(root.join(file)).ok()
+}
+
+/// `cpu_limit` of the cgroup filesystem at `root`
+pub fn cpu_limit_in(root: &Path) -> Option<f64> {
+    if let Some(cpu_max) = read(root, "cpu.max") {
+        return parse_cpu_max(&cpu_max);
+    }
+    ["cpu", "cpu,cpuacct"].iter().find_map(|controller| {
+        let quota = read(root, &format!("{}/cpu.cfs_quota_us", controller))?;
+        let period = read(root, &format!("{}/cpu.cfs_period_us", controller))?;
+        parse_cfs_quota(&quota, &period)
+    })
+}
+
+/// `memory_limit` of the cgroup filesystem at `root`
+pub fn memory_limit_in(root: &Path) -> Option<u64> {
+    let memory_max =
+        read(root, "memory.max").or_else(|| read(root, "memory/memory.limit_in_bytes"))?;
+    parse_memory_max(&memory_max)
+}
+
+/// Parses cgroup v2's `cpu.max`, `<quota> <period>` or `max <period>`
+pub fn parse_cpu_max(cpu_max: &str) -> Option<f64> {
+    let mut fields = cpu_max.split_whitespace();
+    let quota = fields.next()?;
+    let period = fields.next().unwrap_or("100000");
+    parse_cfs_quota(quota, period)
+}
+
+/// Parses a CFS quota and period in microseconds, a quota of `max` or -1
+/// meaning no limit
+pub fn parse_cfs_quota(quota: &str, period: &str) -> Option<f64> {
+    let quota = quota
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|quota| *quota > 0.0)?;
+    let period = period
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|period| *period > 0.0)?;
+    Some(quota / period)
+}
+
+/// Parses cgroup v2's `memory.max` or cgroup v1's `memory.limit_in_bytes`
+pub fn parse_memory_max(memory_max: &str) -> Option<u64> {
+    memory_max
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|limit| *limit < UNLIMITED_MEMORY)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(1.5));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cfs_quota("-1\n", "100000\n"), None);
+        assert_eq!(parse_cfs_quota("50000\n", "100000\n"), Some(0.5));
+        assert_eq!(parse_memory_max("536870912\n"), Some(536_870_912));
+        assert_eq!(parse_memory_max("max\n"), None);
+        assert_eq!(parse_memory_max("9223372036854771712\n"), None);
+    }
+
+    #[test]
+    fn test_limits_in() {
+        let dir = tempdir().expect("don't break");
+        assert_eq!(cpu_limit_in(dir.path()), None);
+        assert_eq!(memory_limit_in(dir.path()), None);
+
+        // cgroup v1
+        fs::create_dir_all(dir.path().join("cpu,cpuacct")).expect("don't break");
+        fs::create_dir_all(dir.path().join("memory")).expect("don't break");
+        fs::write(dir.path().join("cpu,cpuacct/cpu.cfs_quota_us"), "200000\n")
+            .expect("don't break");
+        fs::write(dir.path().join("cpu,cpuacct/cpu.cfs_period_us"), "100000\n")
+            .expect("don't break");
+        fs::write(
+            dir.path().join("memory/memory.limit_in_bytes"),
+            "1073741824\n",
+        )
+        .expect("don't break");
+        assert_eq!(cpu_limit_in(dir.path()), Some(2.0));
+        assert_eq!(memory_limit_in(dir.path()), Some(1 << 30));
+
+        // cgroup v2 takes precedence
+        fs::write(dir.path().join("cpu.max"), "max 100000\n").expect("don't break");
+        fs::write(dir.path().join("memory.max"), "268435456\n").expect("don't break");
+        assert_eq!(cpu_limit_in(dir.path()), None);
+        assert_eq!(memory_limit_in(dir.path()), Some(1 << 28));
+        dir.close().expect("don't break");
+    }
+}
@@ -0,0 +1,269 @@
+use bio::alphabets::dna::revcomp;
+
+/// Small, fast, seedable generator for synthetic reads, xorshift64*
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift can't leave the all zero state
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in [0, n)
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn base(&mut self) -> u8 {
+        b"ACGT"[self.below(4)]
+    }
+}
+
+/// Generates `reads` random sequences of `length` bases where about a
+/// `duplication` fraction repeat an earlier read
+pub fn synthetic_reads(reads: usize, length: usize, duplication: f64, seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(seed);
+    let mut seqs: Vec<Vec<u8>> = Vec::with_capacity(reads);
+    for _ in 0..reads {
+        let seq = if !seqs.is_empty() && rng.next_f64() < duplication {
+            seqs[rng.below(seqs.len())].clone()
+        } else {
+            (0..length).map(|_| rng.base()).collect()
+        };
+        seqs.push(seq);
+    }
+    seqs
+}
+
+/// The duplication structure of simulated reads
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulateOptions {
+    pub reads: usize,
+    pub length: usize,
+    /// (cluster size, relative weight) pairs clusters' sizes are drawn from
+    pub cluster_sizes: Vec<(usize, f64)>,
+    /// fraction of duplicates that are reverse complements of their cluster's fragment
+    pub revcomp_fraction: f64,
+    /// per-base chance a duplicate's base is substituted
+    pub error_rate: f64,
+    /// whether fragments are twice the read length, to be split into mates
+    pub paired: bool,
+    pub seed: u64,
+}
+
+impl Default for SimulateOptions {
+    fn default() -> Self {
+        SimulateOptions {
+            reads: 1000,
+            length: 150,
+            cluster_sizes: vec![(1, 0.7), (2, 0.2), (5, 0.1)],
+            revcomp_fraction: 0.0,
+            error_rate: 0.0,
+            paired: false,
+            seed: 1,
+        }
+    }
+}
+
+/// A simulated read, or fragment for pairs, and the cluster it was drawn from
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulatedRead {
+    pub cluster: u64,
+    pub seq: Vec<u8>,
+    pub is_revcomp: bool,
+    /// number of bases substituted from the cluster's fragment
+    pub errors: usize,
+}
+
+impl SimulatedRead {
+    /// The read, or for pairs the r1 and r2 mates cut from either end of the fragment
+    pub fn mates(&self, length: usize, paired: bool) -> (Vec<u8>, Option<Vec<u8>>) {
+        if paired {
+            let r2 = revcomp(&self.seq[length..]);
+            (self.seq[..length].to_vec(), Some(r2))
+        } else {
+            (self.seq.clone(), None)
+        }
+    }
+}
+
+fn cluster_size(rng: &mut Rng, cluster_sizes: &[(usize, f64)]) -> usize {
+    let total = cluster_sizes.iter().map(|(_, weight)| weight).sum::<f64>();
+    let mut target = rng.next_f64() * total;
+    for (size, weight) in cluster_sizes.iter() {
+        if target < *weight {
+            return *size;
+        }
+        target -= weight;
+    }
+    // rounding can leave a sliver past the last weight
+    cluster_sizes.last().map_or(1, |(size, _)| *size)
+}
+
+/// Simulates reads in clusters with sizes drawn from `cluster_sizes`, in a
+/// shuffled order
+///
+/// The first read of each cluster is its fragment, the rest are copies that
+/// may be reverse complemented and carry substitution errors. The last
+/// cluster is cut short to make exactly `reads` reads.
+pub fn simulate(options: &SimulateOptions) -> Vec<SimulatedRead> {
+    let mut rng = Rng::new(options.seed);
+    let fragment_length = if options.paired {
+        options.length * 2
+    } else {
+        options.length
+    };
+    let mut reads = Vec::with_capacity(options.reads);
+    let mut cluster = 0;
+    while reads.len() < options.reads {
+        let fragment = (0..fragment_length).map(|_| rng.base()).collect::<Vec<_>>();
+        let size = cluster_size(&mut rng, &options.cluster_sizes).max(1);
+        for i in 0..size.min(options.reads - reads.len()) {
+            let mut seq = fragment.clone();
+            let mut errors = 0;
+            let mut is_revcomp = false;
+            if i > 0 {
+                for base in seq.iter_mut() {
+                    if rng.next_f64() < options.error_rate {
+                        let substitute = b"ACGT"
+                            .iter()
+                            .copied()
+                            .filter(|b| b != base)
+                            .nth(rng.below(3))
+                            .unwrap_or(b'N');
+                        *base = substitute;
+                        errors += 1;
+                    }
+                }
+                if rng.next_f64() < options.revcomp_fraction {
+                    seq = revcomp(&seq);
+                    is_revcomp = true;
+                }
+            }
+            reads.push(SimulatedRead {
+                cluster,
+                seq,
+                is_revcomp,
+                errors,
+            });
+        }
+        cluster += 1;
+    }
+    // Fisher-Yates
+    for i in (1..reads.len()).rev() {
+        reads.swap(i, rng.below(i + 1));
+    }
+    reads
+}
+
+/// Parses cluster sizes and weights like `1:0.7,2:0.2,5:0.1`
+pub fn parse_cluster_sizes(s: &str) -> Result<Vec<(usize, f64)>, String> {
+    s.split(',')
+        .map(|entry| {
+            let invalid = || format!("invalid cluster size: {}, expected size:weight", entry);
+            let (size, weight) = entry.split_once(':').ok_or_else(invalid)?;
+            let size = size.trim().parse::<usize>().map_err(|_| invalid())?;
+            let weight = weight.trim().parse::<f64>().map_err(|_| invalid())?;
+            if size == 0 || weight.is_nan() || weight < 0.0 {
+                return Err(invalid());
+            }
+            Ok((size, weight))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_synthetic_reads() {
+        let seqs = synthetic_reads(1000, 50, 0.3, 7);
+        assert_eq!(seqs.len(), 1000);
+        assert!(seqs.iter().all(|seq| seq.len() == 50));
+        let mut unique = seqs.clone();
+        unique.sort();
+        unique.dedup();
+        // about 700 unique reads
+        assert!(unique.len() > 600 && unique.len() < 800, "{}", unique.len());
+        assert_eq!(seqs, synthetic_reads(1000, 50, 0.3, 7));
+    }
+
+    #[test]
+    fn test_simulate_cluster_sizes() {
+        let options = SimulateOptions {
+            reads: 100,
+            length: 20,
+            cluster_sizes: vec![(4, 1.0)],
+            ..SimulateOptions::default()
+        };
+        let reads = simulate(&options);
+        assert_eq!(reads.len(), 100);
+        let mut sizes = HashMap::new();
+        for read in reads.iter() {
+            *sizes.entry(read.cluster).or_insert(0) += 1;
+            assert_eq!(read.seq.len(), 20);
+        }
+        assert_eq!(sizes.len(), 25);
+        assert!(sizes.values().all(|size| *size == 4));
+        assert_eq!(reads, simulate(&options));
+    }
+
+    #[test]
+    fn test_simulate_revcomp_and_errors() {
+        let options = SimulateOptions {
+            reads: 1000,
+            length: 100,
+            cluster_sizes: vec![(10, 1.0)],
+            revcomp_fraction: 1.0,
+            error_rate: 0.01,
+            ..SimulateOptions::default()
+        };
+        let reads = simulate(&options);
+        let duplicates = reads.iter().filter(|read| read.is_revcomp).count();
+        assert_eq!(duplicates, 900);
+        let errors = reads.iter().map(|read| read.errors).sum::<usize>();
+        // about 900 errors over 90,000 duplicate bases
+        assert!(errors > 700 && errors < 1100, "{}", errors);
+    }
+
+    #[test]
+    fn test_mates() {
+        let read = SimulatedRead {
+            cluster: 0,
+            seq: b"AACCGGTT".to_vec(),
+            is_revcomp: false,
+            errors: 0,
+        };
+        assert_eq!(
+            read.mates(4, true),
+            (b"AACC".to_vec(), Some(b"AACC".to_vec()))
+        );
+        assert_eq!(read.mates(8, false), (b"AACCGGTT".to_vec(), None));
+    }
+
+    #[test]
+    fn test_parse_cluster_sizes() {
+        assert_eq!(
+            parse_cluster_sizes("1:0.7, 2:0.3"),
+            Ok(vec![(1, 0.7), (2, 0.3)])
+        );
+        assert!(parse_cluster_sizes("0:1").is_err());
+        assert!(parse_cluster_sizes("1").is_err());
+        assert!(parse_cluster_sizes("1:x").is_err());
+    }
+}
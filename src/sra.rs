@@ -0,0 +1,52 @@
+use std::io::{Error, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// Name of the SRA toolkit executable used to stream reads, looked up on `PATH`
+const FASTQ_DUMP: &str = "fastq-dump";
+
+/// Streams the FASTQ records of an SRA accession from `fastq-dump --stdout`
+///
+/// The child process is reaped when its output is exhausted so a failed
+/// download surfaces as a read error instead of a silently truncated input.
+pub struct SraReader {
+    child: Child,
+    stdout: ChildStdout,
+    accession: String,
+}
+
+impl SraReader {
+    pub fn open(accession: &str) -> Result<Self, Error> {
+        let mut child = Command::new(FASTQ_DUMP)
+            .arg("--stdout")
+            .arg(accession)
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| {
+                Error::new(err.kind(), format!("failed to run {}: {}", FASTQ_DUMP, err))
+            })?;
+        // guaranteed to be present since stdout is piped
+        let stdout = child.stdout.take().unwrap();
+        Ok(SraReader {
+            child,
+            stdout,
+            accession: accession.to_owned(),
+        })
+    }
+}
+
+impl Read for SraReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                let message = format!(
+                    "{} exited with {} while streaming {}",
+                    FASTQ_DUMP, status, self.accession
+                );
+                return Err(Error::other(message));
+            }
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,292 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::warnings::WarningKind;
+
+/// Fraction of records that were duplicates, 0 when there were no records
+pub fn duplication_rate(duplicate_records: u64, total_records: u64) -> f64 {
+    if total_records == 0 {
+        0.0
+    } else {
+        duplicate_records as f64 / total_records as f64
+    }
+}
+
+/// Above this fraction of records longer than the prefix length, clusters
+/// whose reads differ past the prefix are reported as a possible over-collapse
+pub const OVER_COLLAPSE_FRACTION: f64 = 0.5;
+
+/// Upper bounds of the read length bins the summary reports duplication
+/// for, binning reads under 50, 50-99, 100-149, and 150 or more bases
+pub const DEFAULT_LENGTH_BINS: [usize; 3] = [50, 100, 150];
+
+/// Whether a run finished, a failed, time limited, or cancelled run's
+/// summary only counts records read before it stopped
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    #[default]
+    Ok,
+    Failed,
+    /// reading stopped at --time-limit, the outputs hold what was read before
+    #[serde(rename = "time_limited")]
+    TimeLimited,
+    /// reading stopped when the run was cancelled, the outputs hold what was
+    /// read before
+    Cancelled,
+}
+
+/// An approximate number of unique reads, within its bounds 95% of the time
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UniqueEstimate {
+    pub estimate: u64,
+    pub lower_bound: u64,
+    pub upper_bound: u64,
+}
+
+/// Duplication among records whose length, or longest mate's length, is
+/// within a bin
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LengthBin {
+    pub min_length: usize,
+    /// `None` for the last bin, which holds every longer record
+    pub max_length: Option<usize>,
+    pub total_records: u64,
+    pub duplicate_records: u64,
+    pub duplication_rate: f64,
+}
+
+/// The reads of one member of a gzip input made by concatenating others,
+/// reported with --gz-members
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GzipMemberReads {
+    pub input: String,
+    /// 1-based, in the order the members are in the file
+    pub member: usize,
+    /// where the member starts in the compressed file
+    pub compressed_offset: u64,
+    /// 1-based position of the member's first record, or pair, in its input
+    pub first_record: u64,
+    /// records, or pairs, read from the member
+    pub records: u64,
+}
+
+/// Reads of paired input counted by mate, so a mate count asymmetry isn't
+/// hidden in the total
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PairCounts {
+    /// pairs read, whether they were kept, filtered, or skipped, not counting
+    /// those whose mates were left as orphans
+    pub pairs_read: u64,
+    pub pairs_kept: u64,
+    /// reads left without their r2, only reported with --allow-orphans
+    pub r1_only_records: Option<u64>,
+    /// reads left without their r1, only reported with --allow-orphans
+    pub r2_only_records: Option<u64>,
+}
+
+/// The version of the cluster and cluster size outputs' layout, raised when
+/// a change isn't visible in their metadata's column list
+pub const CLUSTER_SCHEMA_VERSION: u32 = 2;
+
+/// Describes a cluster or cluster size output, written beside it as
+/// `<output>.meta.json` so parsers can check its layout before reading it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClusterOutputMeta {
+    pub schema_version: u32,
+    /// `clusters` or `cluster sizes`
+    pub output: String,
+    pub columns: Vec<String>,
+    pub delimiter: String,
+    /// whether the first row names the columns
+    pub header: bool,
+    /// how the `cluster hash` column's values are computed
+    pub hash_algorithm: String,
+    pub parameters: DedupParameters,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DedupParameters {
+    pub prefix_length: Option<usize>,
+    /// whether the prefix length was chosen from a sample of reads
+    #[serde(default)]
+    pub auto_prefix: bool,
+    pub use_revcomp: bool,
+    pub merge_overlaps: bool,
+    pub two_tier: bool,
+    pub seed: u64,
+    pub strip_read_suffix: bool,
+    pub window: Option<u64>,
+    pub assume_sorted: bool,
+    pub pair_canonical: String,
+    pub short_mate: String,
+    #[serde(default)]
+    pub hash_region: String,
+    #[serde(default)]
+    pub compare_region: Option<String>,
+    #[serde(default)]
+    pub key: String,
+    #[serde(default)]
+    pub kmer_size: Option<usize>,
+    #[serde(default)]
+    pub max_mismatches: Option<usize>,
+    #[serde(default)]
+    pub allow_orphans: bool,
+    #[serde(default)]
+    pub filter_expr: Option<String>,
+    #[serde(default)]
+    pub id_filter: Option<String>,
+    #[serde(default)]
+    pub id_exclude: Option<String>,
+    #[serde(default)]
+    pub trim_to: Option<usize>,
+    #[serde(default)]
+    pub quality_trim: Option<u8>,
+    /// every input anomaly fails the run
+    #[serde(default)]
+    pub strict: bool,
+    /// input anomalies are skipped with a warning where safe
+    #[serde(default)]
+    pub lenient: bool,
+    /// how representatives' qualities combine their clusters'
+    #[serde(default)]
+    pub quality_aggregation: Option<String>,
+    #[serde(default)]
+    pub empty_reads: String,
+    #[serde(default)]
+    pub on_duplicate_ids: String,
+    /// which member represents each cluster
+    #[serde(default)]
+    pub representative: String,
+    #[serde(default)]
+    pub max_n_fraction: Option<f64>,
+    /// how IUPAC ambiguity codes are keyed
+    #[serde(default)]
+    pub ambiguity: String,
+    /// upper bounds of the read length bins
+    #[serde(default)]
+    pub length_bins: Vec<usize>,
+    /// whether hashes were salted with --key-salt, the salt itself isn't reported
+    #[serde(default)]
+    pub key_salted: bool,
+    /// what was done with concatenated gzip inputs' members
+    #[serde(default)]
+    pub gz_members: Option<String>,
+    /// whether Solexa and Illumina 1.3+ qualities were rewritten as Phred+33
+    #[serde(default)]
+    pub fix_legacy_quality: bool,
+    /// whether duplicates had to match on a second, independent hash too
+    #[serde(default)]
+    pub double_hash: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DedupSummary {
+    pub total_records: u64,
+    pub unique_records: u64,
+    pub duplicate_records: u64,
+    /// fraction of records that were duplicates, 0 when there were no records
+    pub duplication_rate: f64,
+    /// duplicates identical to an earlier read, only reported in two-tier mode
+    pub exact_duplicate_records: Option<u64>,
+    /// duplicates only sharing a prefix with earlier reads, only reported in two-tier mode
+    pub prefix_duplicate_records: Option<u64>,
+    /// cluster size -> number of clusters of that size
+    pub cluster_size_histogram: BTreeMap<u64, u64>,
+    /// pairs with a mate shorter than the prefix length
+    #[serde(default)]
+    pub short_mate_pairs: u64,
+    /// clusters holding reads whose full sequences differ past the prefix length
+    #[serde(default)]
+    pub prefix_divergent_clusters: u64,
+    /// warning kind -> number of reads it was raised for
+    #[serde(default)]
+    pub warning_counts: BTreeMap<String, u64>,
+    /// each input file holds every record, an r1 or r2 mate for paired input
+    #[serde(default)]
+    pub input_files: Vec<String>,
+    /// each deduped output file holds the unique records
+    #[serde(default)]
+    pub output_files: Vec<String>,
+    /// reads whose mate was missing or invalid, only reported with --allow-orphans
+    #[serde(default)]
+    pub orphan_records: Option<u64>,
+    /// orphans left after deduplicating them among themselves
+    #[serde(default)]
+    pub unique_orphan_records: Option<u64>,
+    /// reads of --single-input, only reported with it
+    #[serde(default)]
+    pub single_records: Option<u64>,
+    /// single reads left after deduplicating them against the pairs' r1s
+    /// and among themselves
+    #[serde(default)]
+    pub unique_single_records: Option<u64>,
+    /// single reads that were duplicates of a pair's r1
+    #[serde(default)]
+    pub paired_duplicate_single_records: Option<u64>,
+    /// pairs and leftover mates, only reported for paired input
+    #[serde(default)]
+    pub pair_counts: Option<PairCounts>,
+    /// records dropped by --filter-expr before clustering
+    #[serde(default)]
+    pub filtered_records: u64,
+    /// records with no bases, clustered only with --empty-reads keep
+    #[serde(default)]
+    pub empty_records: u64,
+    /// records dropped by --max-n-fraction before clustering
+    #[serde(default)]
+    pub n_filtered_records: u64,
+    /// records with IUPAC ambiguity codes other than N, however they were keyed
+    #[serde(default)]
+    pub ambiguous_records: u64,
+    /// distinct cluster keys estimated with a HyperLogLog, only reported with
+    /// --estimate-unique
+    #[serde(default)]
+    pub unique_estimate: Option<UniqueEstimate>,
+    /// duplication by read length, from shortest to longest
+    #[serde(default)]
+    pub length_bins: Vec<LengthBin>,
+    /// each gzip member's reads, with --gz-members
+    #[serde(default)]
+    pub gzip_members: Vec<GzipMemberReads>,
+    pub parameters: DedupParameters,
+    #[serde(default)]
+    pub status: RunStatus,
+    /// why the run failed
+    #[serde(default)]
+    pub error: Option<String>,
+    /// 1-based position of the record, or pair, being read when the run failed
+    #[serde(default)]
+    pub failed_at_record: Option<u64>,
+}
+
+impl DedupSummary {
+    /// Records longer than the prefix length, whose remaining bases were ignored
+    pub fn prefix_truncated_records(&self) -> u64 {
+        self.warning_counts
+            .get(WarningKind::PrefixTruncated.as_str())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Whether more than `OVER_COLLAPSE_FRACTION` of records were truncated
+    /// to the prefix length and some clusters joined reads that differ past it
+    pub fn possible_over_collapse(&self) -> bool {
+        let truncated_fraction =
+            duplication_rate(self.prefix_truncated_records(), self.total_records);
+        truncated_fraction > OVER_COLLAPSE_FRACTION && self.prefix_divergent_clusters > 0
+    }
+
+    /// Records read so far, whether they were clustered, filtered, skipped, or orphaned
+    pub fn processed_records(&self) -> u64 {
+        self.total_records
+            + self.filtered_records
+            + self.n_filtered_records
+            + self.orphan_records.unwrap_or(0)
+            + self.single_records.unwrap_or(0)
+            + [WarningKind::InvalidRecord, WarningKind::EmptyRead]
+                .iter()
+                .filter_map(|kind| self.warning_counts.get(kind.as_str()))
+                .sum::<u64>()
+    }
+}
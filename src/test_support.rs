@@ -0,0 +1,101 @@
+//! Helpers for tests of pipelines that embed this crate, built with the
+//! `test-support` feature
+//!
+//! `simulate` generates reproducible reads from a seed, these make throwaway
+//! inputs and check a run's results.
+use bio::io::{fasta, fastq};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+
+use super::fastx::{self, FastxType};
+use super::simulate::Rng;
+use super::summary::{self, DedupSummary};
+
+/// A random sequence of `len` bases, different on every call
+pub fn random_seq(len: usize) -> Vec<u8> {
+    // each RandomState is keyed differently, so hashing nothing gives a fresh seed
+    let mut rng = Rng::new(RandomState::new().build_hasher().finish());
+    (0..len).map(|_| b"ACGT"[rng.below(4)]).collect()
+}
+
+/// Writes reads to a FASTA file, as (id, sequence) pairs
+pub fn write_fasta<P: AsRef<Path>>(path: P, reads: &[(&str, &[u8])]) -> std::io::Result<()> {
+    let mut writer = fasta::Writer::to_file(path)?;
+    for (id, seq) in reads {
+        writer.write(id, None, seq)?;
+    }
+    writer.flush()
+}
+
+/// Writes reads to a FASTQ file, as (id, sequence) pairs with every base
+/// at quality 40
+pub fn write_fastq<P: AsRef<Path>>(path: P, reads: &[(&str, &[u8])]) -> std::io::Result<()> {
+    let mut writer = fastq::Writer::to_file(path)?;
+    for (id, seq) in reads {
+        writer.write(id, None, seq, &vec![b'I'; seq.len()])?;
+    }
+    writer.flush()
+}
+
+/// The read ids of a FASTA or FASTQ file, plain or gzipped, in order
+pub fn read_ids(path: &str) -> std::io::Result<Vec<String>> {
+    let (fastx_type, reader) = fastx::open_input(path)?;
+    match fastx_type {
+        FastxType::Fasta => fasta::Reader::new(reader)
+            .records()
+            .map(|result| result.map(|record| record.id().to_owned()))
+            .collect(),
+        FastxType::Fastq => fastq::Reader::new(reader)
+            .records()
+            .map(|result| result.map(|record| record.id().to_owned()))
+            .collect(),
+        FastxType::Invalid => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} is not a FASTA or FASTQ file", path),
+        )),
+    }
+}
+
+/// Asserts a run's totals, and that its duplicate count and duplication
+/// rate agree with them
+pub fn assert_counts(summary: &DedupSummary, total_records: u64, unique_records: u64) {
+    assert_eq!(summary.total_records, total_records, "total records");
+    assert_eq!(summary.unique_records, unique_records, "unique records");
+    let duplicate_records = total_records - unique_records;
+    assert_eq!(
+        summary.duplicate_records, duplicate_records,
+        "duplicate records"
+    );
+    assert_eq!(
+        summary.duplication_rate,
+        summary::duplication_rate(duplicate_records, total_records),
+        "duplication rate"
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_read_ids() {
+        let dir = tempdir().expect("don't break");
+        let seq = random_seq(20);
+        assert_eq!(seq.len(), 20);
+        assert_ne!(seq, random_seq(20));
+        let reads = [("id_a", seq.as_slice()), ("id_b", seq.as_slice())];
+        for name in ["input.fasta", "input.fastq"].iter() {
+            let path = dir.path().join(name).to_str().unwrap().to_string();
+            if name.ends_with(".fasta") {
+                write_fasta(&path, &reads).expect("don't break");
+            } else {
+                write_fastq(&path, &reads).expect("don't break");
+            }
+            assert_eq!(read_ids(&path).expect("don't break"), vec!["id_a", "id_b"]);
+        }
+        dir.close().expect("don't break");
+    }
+}
@@ -0,0 +1,107 @@
+use super::fastx;
+
+/// Trims representatives as they're written, after deduplication
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Trimmer {
+    /// keep at most this many bases
+    pub trim_to_opt: Option<usize>,
+    /// trim bases below this Phred quality from the 3' end
+    pub quality_trim_opt: Option<u8>,
+}
+
+impl Trimmer {
+    /// The number of bases kept of a read
+    pub fn trimmed_length(&self, seq: &[u8], qual_opt: Option<&[u8]>) -> usize {
+        let mut length = seq.len();
+        if let (Some(min_quality), Some(qual)) = (self.quality_trim_opt, qual_opt) {
+            length = qual
+                .iter()
+                .rposition(|quality| quality.saturating_sub(33) >= min_quality)
+                .map_or(0, |i| i + 1);
+        }
+        match self.trim_to_opt {
+            Some(trim_to) => length.min(trim_to),
+            None => length,
+        }
+    }
+}
+
+/// Wraps a writer, trimming every record written to it
+pub struct TrimWriter<W> {
+    inner: W,
+    trimmer: Trimmer,
+}
+
+impl<W> TrimWriter<W> {
+    pub fn new(inner: W, trimmer: Trimmer) -> Self {
+        TrimWriter { inner, trimmer }
+    }
+}
+
+impl<T: fastx::Record, W: fastx::Writer<T>> fastx::Writer<T> for TrimWriter<W> {
+    fn write_record(&mut self, record: &T) -> Result<(), std::io::Error> {
+        let length = self.trimmer.trimmed_length(record.seq(), record.qual());
+        if length == record.seq().len() {
+            self.inner.write_record(record)
+        } else {
+            self.inner.write_record(&record.truncated(length))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bio::io::fastq;
+
+    #[test]
+    fn test_trimmed_length() {
+        let trimmer = Trimmer::default();
+        assert_eq!(trimmer.trimmed_length(b"ACGTACGT", None), 8);
+
+        let trimmer = Trimmer {
+            trim_to_opt: Some(5),
+            quality_trim_opt: None,
+        };
+        assert_eq!(trimmer.trimmed_length(b"ACGTACGT", Some(b"IIIIIIII")), 5);
+        assert_eq!(trimmer.trimmed_length(b"ACG", None), 3);
+
+        // Q40 and Q2 bases
+        let trimmer = Trimmer {
+            trim_to_opt: None,
+            quality_trim_opt: Some(20),
+        };
+        assert_eq!(trimmer.trimmed_length(b"ACGTACGT", Some(b"III#I###")), 5);
+        assert_eq!(trimmer.trimmed_length(b"ACGT", Some(b"####")), 0);
+        // FASTA has no qualities to trim on
+        assert_eq!(trimmer.trimmed_length(b"ACGT", None), 4);
+    }
+
+    impl fastx::Writer<fastq::Record> for Vec<fastq::Record> {
+        fn write_record(&mut self, record: &fastq::Record) -> Result<(), std::io::Error> {
+            self.push(record.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trim_writer() {
+        let trimmer = Trimmer {
+            trim_to_opt: Some(3),
+            quality_trim_opt: Some(20),
+        };
+        let mut writer = TrimWriter::new(Vec::new(), trimmer);
+        let record = fastq::Record::with_attrs("id_a", None, b"ACGTA", b"IIIII");
+        fastx::Writer::write_record(&mut writer, &record).expect("don't break");
+        let record = fastq::Record::with_attrs("id_b", None, b"ACGTA", b"II###");
+        fastx::Writer::write_record(&mut writer, &record).expect("don't break");
+        assert_eq!(
+            writer.inner,
+            vec![
+                fastq::Record::with_attrs("id_a", None, b"ACG", b"III"),
+                fastq::Record::with_attrs("id_b", None, b"AC", b"II"),
+            ]
+        );
+    }
+}
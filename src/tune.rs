@@ -0,0 +1,170 @@
+use core::hash::{Hash, Hasher};
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{HashMap, HashSet};
+
+/// How clustering by one candidate prefix length would collapse the reads
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrefixStats {
+    pub prefix_length: usize,
+    /// distinct prefixes, the clusters a run with this prefix length would make
+    pub unique_keys: u64,
+    /// reads longer than the prefix, whose remaining bases would be ignored
+    pub truncated_reads: u64,
+    /// reads that would join a cluster whose first read differs past the prefix
+    pub over_collapsed_reads: u64,
+    /// clusters that would hold reads whose full sequences differ
+    pub divergent_clusters: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrefixReport {
+    pub total_records: u64,
+    /// distinct full sequences, the clusters a run without a prefix length would make
+    pub unique_sequences: u64,
+    /// one entry per candidate prefix length, in the order they were given
+    pub stats: Vec<PrefixStats>,
+}
+
+impl PrefixReport {
+    pub fn write_stats<W: std::io::Write>(
+        &self,
+        csv_writer: &mut csv::Writer<W>,
+    ) -> Result<(), csv::Error> {
+        csv_writer.write_record([
+            "prefix length",
+            "unique keys",
+            "truncated reads",
+            "over-collapsed reads",
+            "divergent clusters",
+        ])?;
+        for stats in self.stats.iter() {
+            csv_writer.write_record([
+                stats.prefix_length.to_string(),
+                stats.unique_keys.to_string(),
+                stats.truncated_reads.to_string(),
+                stats.over_collapsed_reads.to_string(),
+                stats.divergent_clusters.to_string(),
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys every read by each candidate prefix length in a single pass
+///
+/// Each candidate keeps its prefixes with the full sequence hash of the first
+/// read that had them, so a later read with the same prefix but a different
+/// full sequence is counted as over-collapsed, the same reads a run with that
+/// prefix length would report as prefix duplicates.
+#[derive(Debug)]
+pub struct PrefixTuner {
+    prefix_lengths: Vec<usize>,
+    /// per candidate, prefix hash -> (first read's full hash, whether it diverged)
+    keys: Vec<HashMap<u64, (u64, bool)>>,
+    stats: Vec<PrefixStats>,
+    sequences: HashSet<u64>,
+    total_records: u64,
+}
+
+impl PrefixTuner {
+    pub fn new(prefix_lengths: &[usize]) -> Self {
+        PrefixTuner {
+            prefix_lengths: prefix_lengths.to_vec(),
+            keys: vec![HashMap::new(); prefix_lengths.len()],
+            stats: prefix_lengths
+                .iter()
+                .map(|prefix_length| PrefixStats {
+                    prefix_length: *prefix_length,
+                    unique_keys: 0,
+                    truncated_reads: 0,
+                    over_collapsed_reads: 0,
+                    divergent_clusters: 0,
+                })
+                .collect(),
+            sequences: HashSet::new(),
+            total_records: 0,
+        }
+    }
+
+    pub fn add(&mut self, seq: &[u8]) {
+        self.total_records += 1;
+        let full_hash = hash(seq);
+        self.sequences.insert(full_hash);
+        for ((prefix_length, keys), stats) in self
+            .prefix_lengths
+            .iter()
+            .zip(self.keys.iter_mut())
+            .zip(self.stats.iter_mut())
+        {
+            if seq.len() > *prefix_length {
+                stats.truncated_reads += 1;
+            }
+            let prefix = &seq[..seq.len().min(*prefix_length)];
+            match keys.entry(hash(prefix)) {
+                Entry::Vacant(entry) => {
+                    entry.insert((full_hash, false));
+                    stats.unique_keys += 1;
+                }
+                Entry::Occupied(mut entry) => {
+                    let (first_hash, diverged) = entry.get_mut();
+                    if *first_hash != full_hash {
+                        stats.over_collapsed_reads += 1;
+                        if !*diverged {
+                            *diverged = true;
+                            stats.divergent_clusters += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn report(&self) -> PrefixReport {
+        PrefixReport {
+            total_records: self.total_records,
+            unique_sequences: self.sequences.len() as u64,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+fn hash(seq: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    Hash::hash_slice(seq, &mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prefix_tuner() {
+        let mut tuner = PrefixTuner::new(&[4, 8]);
+        for seq in [&b"ACGTACGT"[..], b"ACGTTTTT", b"ACGTTTTT", b"ACG"].iter() {
+            tuner.add(seq);
+        }
+        let report = tuner.report();
+        assert_eq!(report.total_records, 4);
+        assert_eq!(report.unique_sequences, 3);
+        assert_eq!(
+            report.stats,
+            vec![
+                PrefixStats {
+                    prefix_length: 4,
+                    unique_keys: 2,
+                    truncated_reads: 3,
+                    over_collapsed_reads: 2,
+                    divergent_clusters: 1,
+                },
+                PrefixStats {
+                    prefix_length: 8,
+                    unique_keys: 3,
+                    truncated_reads: 0,
+                    over_collapsed_reads: 0,
+                    divergent_clusters: 0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+
+/// Reads with more than this fraction of `N` bases are reported as N-heavy
+pub const N_HEAVY_FRACTION: f64 = 0.5;
+
+/// Inputs are checked for corruption in chunks of this many sequence bytes
+pub const COMPOSITION_CHUNK_BYTES: usize = 4096;
+
+/// A chunk of sequence with more than this fraction of bytes other than
+/// `ACGTN` is taken for a corrupt or mis-decoded input
+pub const CORRUPT_FRACTION: f64 = 0.5;
+
+/// How many warnings of each kind are kept as examples
+pub const MAX_EXAMPLES: usize = 10;
+
+/// A non-fatal issue with a read
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum WarningKind {
+    /// Bases past the prefix length were ignored when clustering the read
+    PrefixTruncated,
+    /// More than `N_HEAVY_FRACTION` of the read's bases are `N`
+    NHeavy,
+    /// The read failed validation and was skipped
+    InvalidRecord,
+    /// The pair's mates had different ids and was skipped
+    IdMismatch,
+    /// The read's mate was missing because the other input ended first, it was dropped
+    UnpairedRead,
+    /// The input ended part way through a compressed stream, reading stopped there
+    TruncatedInput,
+    /// The read had no bases and was dropped
+    EmptyRead,
+    /// The read had the same id as an earlier read
+    DuplicateId,
+}
+
+impl WarningKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WarningKind::PrefixTruncated => "prefix truncated",
+            WarningKind::NHeavy => "N-heavy",
+            WarningKind::InvalidRecord => "invalid record",
+            WarningKind::IdMismatch => "id mismatch",
+            WarningKind::UnpairedRead => "unpaired read",
+            WarningKind::TruncatedInput => "truncated input",
+            WarningKind::EmptyRead => "empty read",
+            WarningKind::DuplicateId => "duplicate id",
+        }
+    }
+}
+
+/// Which anomalies in the input are skipped with a warning rather than failing the run
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Leniency {
+    /// skip records that fail validation
+    pub skip_invalid: bool,
+    /// skip pairs whose mates have different ids
+    pub skip_id_mismatch: bool,
+    /// drop reads left over once one paired input ends
+    pub drop_unpaired: bool,
+    /// stop reading at a truncated compressed input, keeping the records before it
+    pub allow_truncated: bool,
+}
+
+impl Leniency {
+    /// Skips every anomaly that can be skipped safely
+    pub fn lenient() -> Self {
+        Leniency {
+            skip_invalid: true,
+            skip_id_mismatch: true,
+            drop_unpaired: true,
+            allow_truncated: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub read_id: String,
+    pub message: String,
+}
+
+/// Called with every warning as it is raised
+pub type WarningCallback = Box<dyn FnMut(&Warning)>;
+
+/// Counts of every warning raised and the first few of each kind
+#[derive(Debug, Default)]
+pub struct Warnings {
+    counts: BTreeMap<WarningKind, u64>,
+    examples: Vec<Warning>,
+}
+
+impl Warnings {
+    pub fn push(&mut self, warning: Warning) {
+        let count = self.counts.entry(warning.kind).or_insert(0);
+        *count += 1;
+        if *count <= MAX_EXAMPLES as u64 {
+            self.examples.push(warning);
+        }
+    }
+
+    pub fn count(&self, kind: WarningKind) -> u64 {
+        self.counts.get(&kind).copied().unwrap_or(0)
+    }
+
+    pub fn counts(&self) -> &BTreeMap<WarningKind, u64> {
+        &self.counts
+    }
+
+    /// Up to `MAX_EXAMPLES` warnings of each kind, in the order they were raised
+    pub fn examples(&self) -> &[Warning] {
+        &self.examples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+/// The fraction of a sequence's bases that are `N`, 0 for an empty sequence
+pub fn n_fraction(seq: &[u8]) -> f64 {
+    if seq.is_empty() {
+        return 0.0;
+    }
+    let n_bases = seq
+        .iter()
+        .filter(|base| matches!(base, b'N' | b'n'))
+        .count();
+    n_bases as f64 / seq.len() as f64
+}
+
+/// The number of a sequence's bytes that aren't `A`, `C`, `G`, `T` or `N`,
+/// in either case
+pub fn non_acgtn_bytes(seq: &[u8]) -> usize {
+    seq.iter()
+        .filter(|byte| !matches!(byte.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'N'))
+        .count()
+}
+
+/// Whether more than `N_HEAVY_FRACTION` of a sequence's bases are `N`
+pub fn is_n_heavy(seq: &[u8]) -> bool {
+    n_fraction(seq) > N_HEAVY_FRACTION
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_warnings() {
+        let mut warnings = Warnings::default();
+        assert!(warnings.is_empty());
+        for i in 0..(MAX_EXAMPLES + 2) {
+            warnings.push(Warning {
+                kind: WarningKind::NHeavy,
+                read_id: format!("id_{}", i),
+                message: "mostly N".to_owned(),
+            });
+        }
+        warnings.push(Warning {
+            kind: WarningKind::InvalidRecord,
+            read_id: "id_x".to_owned(),
+            message: "bad".to_owned(),
+        });
+        assert_eq!(warnings.count(WarningKind::NHeavy), MAX_EXAMPLES as u64 + 2);
+        assert_eq!(warnings.count(WarningKind::InvalidRecord), 1);
+        assert_eq!(warnings.count(WarningKind::PrefixTruncated), 0);
+        assert_eq!(warnings.examples().len(), MAX_EXAMPLES + 1);
+    }
+
+    #[test]
+    fn test_is_n_heavy() {
+        assert!(is_n_heavy(b"NNNNA"));
+        assert!(!is_n_heavy(b"NNAA"));
+        assert!(!is_n_heavy(b""));
+    }
+
+    #[test]
+    fn test_non_acgtn_bytes() {
+        assert_eq!(non_acgtn_bytes(b"ACGTNacgtn"), 0);
+        assert_eq!(non_acgtn_bytes(b"ACRY\x00\xff"), 4);
+        assert_eq!(non_acgtn_bytes(b""), 0);
+    }
+}